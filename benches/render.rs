@@ -0,0 +1,91 @@
+use std::hint::black_box;
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hyper_req_log::{LogConfig, LogRequest};
+
+fn corpora() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("token", b"my-domain.example.com".to_vec()),
+        (
+            "user_agent",
+            b"Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0".to_vec(),
+        ),
+        (
+            "utf8_emoji",
+            "caf\u{e9} \u{1f44d} na\u{ef}ve r\u{e9}sum\u{e9} \u{1f680}\u{1f680}"
+                .as_bytes()
+                .to_vec(),
+        ),
+        ("binary", (0..=255u8).cycle().take(256).collect()),
+        ("backslashes", b"\\".repeat(256)),
+        ("invalid_utf8", b"\\\xff\xc3\x28\\\"\xf0\x9f\x91".repeat(32)),
+    ]
+}
+
+fn escape(c: &mut Criterion) {
+    let mut group = c.benchmark_group("escape");
+    let mut out = String::with_capacity(4096);
+    for (name, input) in corpora() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| {
+                out.clear();
+                hyper_req_log::__private::write_escaped(black_box(input), &mut out).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn full_request(config: &Arc<LogConfig>) -> LogRequest<&'static str> {
+    let req = hyper::Request::post("/api/v1/items/12345?expand=owner&limit=50")
+        .header("host", "api.example.com")
+        .header(
+            "user-agent",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Firefox/128.0",
+        )
+        .header("referer", "https://www.example.com/items?page=2")
+        .header("x-forwarded-for", "203.0.113.7, 198.51.100.2")
+        .body(())
+        .unwrap();
+    let mut log = LogRequest::from_request_with_config(&req, config.clone());
+    log.set_remote("192.0.2.10:51234".parse().unwrap())
+        .set_user("alice@example.com")
+        .set_action("create_item")
+        .set_response(&hyper::Response::builder().status(201).body(()).unwrap())
+        .add_field_bytes("trace", bytes::Bytes::from_static(b"4bf92f3577b34da6"), 64);
+    log
+}
+
+fn render(c: &mut Criterion) {
+    let config = Arc::new(LogConfig::new().header_sizes(true));
+    let log = full_request(&config);
+    let entry = log.entry();
+    log.discard();
+
+    let mut out = String::with_capacity(1024);
+    c.bench_function("render_entry", |b| {
+        b.iter(|| {
+            out.clear();
+            black_box(&entry).write_to(&mut out).unwrap();
+        })
+    });
+}
+
+fn construct(c: &mut Criterion) {
+    let config = Arc::new(LogConfig::new().header_sizes(true));
+    c.bench_function("construct", |b| {
+        b.iter(|| black_box(full_request(&config)).discard())
+    });
+}
+
+fn write(c: &mut Criterion) {
+    let config = Arc::new(LogConfig::new().header_sizes(true));
+    c.bench_function("write_to_null", |b| {
+        b.iter(|| full_request(&config).write(std::io::sink()).unwrap())
+    });
+}
+
+criterion_group!(benches, escape, construct, render, write);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+use std::convert::Infallible;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper_util::rt::TokioIo;
+use hyper_req_log::LogRequest;
+
+/// Makes a logged outbound request to a small local server, which logs the same request on its
+/// side, so the two lines can be compared.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::task::spawn(async move {
+        let (stream, remote) = listener.accept().await.unwrap();
+        let _ = hyper::server::conn::http1::Builder::new()
+            .serve_connection(TokioIo::new(stream), hyper::service::service_fn(move |req| {
+                let mut log = LogRequest::<&str>::from_request(&req);
+                log.set_remote(remote).set_action("hello");
+                let resp = hyper::Response::new(Full::new(Bytes::from("hello")));
+                log.set_response(&resp);
+                async move { Ok::<_, Infallible>(resp) }
+            }))
+            .await;
+    });
+
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let peer = stream.peer_addr()?;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::task::spawn(conn);
+
+    let req = hyper::Request::get("/hello")
+        .header("host", addr.to_string())
+        .body(Empty::<Bytes>::new())?;
+    let mut log = LogRequest::<&str>::from_client_request(&req);
+    log.set_action("hello").set_peer(peer);
+    let resp = log.send(req, |req| sender.send_request(req)).await?;
+
+    let body = resp.into_body().collect().await?.to_bytes();
+    println!("got {:?}", body);
+    Ok(())
+}
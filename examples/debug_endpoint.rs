@@ -0,0 +1,56 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper_util::rt::TokioIo;
+use hyper_req_log::{RemoteAddr, RingBufferSink, SimpleLogRequest, StatusClass};
+
+// Serves the last 200 requests at /debug/requests, or only the failed ones at
+// /debug/requests?errors.
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let recent = Arc::new(RingBufferSink::new(200));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let recent = recent.clone();
+        tokio::task::spawn(async move {
+            let result = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), hyper::service::service_fn(|mut req| {
+                    // Server glue tags each request with its peer address, so whatever logs it
+                    // later doesn't need to be handed the address separately.
+                    req.extensions_mut().insert(RemoteAddr(remote));
+                    let mut log = SimpleLogRequest::from_request(&req);
+                    log.also_write_to(recent.clone());
+                    let recent = recent.clone();
+                    async move {
+                        let resp = match req.uri().path() {
+                            "/debug/requests" => {
+                                log.set_action("debug");
+                                let entries = if req.uri().query() == Some("errors") {
+                                    recent.entries_with_status(StatusClass::ServerError)
+                                } else {
+                                    recent.entries()
+                                };
+                                let body =
+                                    entries.iter().map(|e| e.to_string()).collect::<String>();
+                                hyper::Response::new(Full::new(Bytes::from(body)))
+                            }
+                            _ => {
+                                log.set_action("hello");
+                                hyper::Response::new(Full::new(Bytes::from("hello\n")))
+                            }
+                        };
+                        log.set_response(&resp);
+                        Ok::<_, Infallible>(resp)
+                    }
+                }))
+                .await;
+            if let Err(err) = result {
+                eprintln!("Error serving connection: {:?}", err);
+            }
+        });
+    }
+}
@@ -3,35 +3,34 @@ use bytes::Bytes;
 use http_body_util::Full;
 use hyper::{Method, StatusCode};
 use hyper_util::rt::TokioIo;
-use hyper_req_log::LogRequest;
+use hyper_req_log::{ConnectionLog, DynLogRequest};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
     loop {
         let (stream, remote) = listener.accept().await?;
-        let io = TokioIo::new(stream);
+        let conn = ConnectionLog::new(remote);
+        let io = conn.io(TokioIo::new(stream));
         tokio::task::spawn(async move {
-            if let Err(err) = hyper::server::conn::http1::Builder::new()
-                .serve_connection(io, hyper::service::service_fn(|req| async move {
-                    let mut log = LogRequest::from_request(&req);
-                    log.set_action("unset");
-                    log.set_remote(remote);
-
-                    let resp = handle_request(req, &mut log);
-
-                    log.set_response(&resp);
-                    Ok::<_, Infallible>(resp)
-                }))
-                .await
-            {
+            let result = hyper::server::conn::http1::Builder::new()
+                .serve_connection(io, hyper::service::service_fn(conn.logged_service(|req, log| {
+                    Box::pin(async move {
+                        log.set_action_dyn("unset");
+                        // The request is logged once the response body has been sent.
+                        Ok::<_, Infallible>(handle_request(req, log))
+                    })
+                })))
+                .await;
+            conn.finish(&result);
+            if let Err(err) = result {
                 eprintln!("Error serving connection: {:?}", err);
             }
         });
     }
 }
 
-fn handle_request(req: hyper::Request<hyper::body::Incoming>, log: &mut LogRequest<&'static str>)
+fn handle_request(req: hyper::Request<hyper::body::Incoming>, log: &mut DynLogRequest)
     -> hyper::Response<Full<Bytes>>
 {
     let resp = hyper::Response::builder()
@@ -41,10 +40,11 @@ fn handle_request(req: hyper::Request<hyper::body::Incoming>, log: &mut LogReque
     // this is bad, don't do this for real
     match req.headers().get("authorization").map(|v| v.as_bytes()).unwrap_or(b"") {
         b"Basic YWxpY2U6bG9va2dsYXNz" => { // "alice:lookglass"
-            log.set_user("alice@example.com".to_owned());
+            // Logs "alice", without the password.
+            log.set_user_from_basic_auth(&req);
         }
         _ => {
-            log.set_action("unauthorized");
+            log.set_action_dyn("unauthorized");
             return resp.status(401)
                 .body(Full::from("authorization required"))
                 .unwrap();
@@ -53,16 +53,17 @@ fn handle_request(req: hyper::Request<hyper::body::Incoming>, log: &mut LogReque
 
     match *req.method() {
         Method::GET => {
-            log.set_action("get");
+            log.set_action_dyn("get");
             let path = req.uri().path();
             resp.body(Full::from(format!("get from path {path}"))).unwrap()
         }
         Method::POST => {
-            log.set_action("post");
+            log.set_action_dyn("post");
             resp.body(Full::from("post ok")).unwrap()
         }
         _ => {
-            log.set_action("error");
+            // Actions of different types can be mixed.
+            log.set_action_dyn(format!("unsupported-{}", req.method()));
             resp.status(StatusCode::METHOD_NOT_ALLOWED)
                 .body(Full::from(""))
                 .unwrap()
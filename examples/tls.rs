@@ -0,0 +1,58 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper_util::rt::TokioIo;
+use hyper_req_log::{ConnectionLog, TlsConnectionInfo};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+// Serves HTTPS on 127.0.0.1:8443 with a self-signed certificate for localhost. Try:
+//   curl -k --http1.1 https://localhost:8443/hello
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cert = rcgen::generate_simple_self_signed(["localhost".to_owned()])?;
+    let key = PrivateKeyDer::try_from(cert.key_pair.serialize_der())?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(vec![CertificateDer::from(cert.cert)], key)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:8443").await?;
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        tokio::task::spawn(async move {
+            // Create the ConnectionLog before the handshake, so the time it takes is logged as
+            // setup=... on the first request.
+            let conn = ConnectionLog::new(remote);
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("TLS handshake with {remote} failed: {err}");
+                    return;
+                }
+            };
+            // The handshake is done, so the session's parameters are known.
+            let conn = conn.with_tls(TlsConnectionInfo::from(stream.get_ref().1));
+            let conn2 = conn.clone();
+            let service = hyper::service::service_fn(move |req| {
+                let mut log = conn2.request(&req);
+                async move {
+                    log.set_action("hello");
+                    let resp = hyper::Response::new(Full::new(Bytes::from("hello\n")));
+                    log.set_response(&resp);
+                    Ok::<_, Infallible>(resp)
+                }
+            });
+            let result = hyper::server::conn::http1::Builder::new()
+                .serve_connection(conn.io(TokioIo::new(stream)), service)
+                .await;
+            conn.finish(&result);
+        });
+    }
+}
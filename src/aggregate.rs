@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::entry::{LogEntry, StatusClass};
+use crate::sink::LogSink;
+
+/// Number of latency buckets. Bucket `i` counts durations below `2^(i+1)` microseconds (and at
+/// least `2^i` for `i > 0`); the last bucket also counts everything longer.
+const BUCKETS: usize = 32;
+
+type Key = (Option<String>, Option<StatusClass>);
+
+#[derive(Default)]
+struct Group {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS],
+}
+
+/// [AggregatingSink] is a sink which, instead of writing each entry, keeps per-(action, status
+/// class) request counts and a log2-bucketed latency histogram in memory, and writes one summary
+/// line per group to an inner sink when [report](Self::report) is called.
+///
+/// Counters are atomic, so recording an entry only takes a shared lock, except the first time a
+/// group is seen. Reporting resets the counters, so each summary covers the period since the
+/// previous report.
+///
+/// Summary lines look like:
+///
+/// `request-summary: [get:2xx] count=12 mean_ms=1.234 buckets=512us:2,1024us:7,2048us:3`
+///
+/// where each bucket is given by its (exclusive) upper bound in microseconds and only non-empty
+/// buckets are listed. Groups with no action omit it and the colon, and entries with no status
+/// are grouped under `???`, as in the text format.
+pub struct AggregatingSink {
+    inner: Arc<dyn LogSink>,
+    groups: RwLock<HashMap<Key, Arc<Group>>>,
+}
+
+impl AggregatingSink {
+    /// Create a new aggregating sink, which writes its summaries to the given sink.
+    pub fn new(inner: Arc<dyn LogSink>) -> Self {
+        Self {
+            inner,
+            groups: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn record(&self, entry: &LogEntry) {
+        let key = (entry.action.clone(), entry.status_class());
+        let group = self
+            .groups
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .cloned();
+        let group = match group {
+            Some(group) => group,
+            None => self
+                .groups
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(key)
+                .or_default()
+                .clone(),
+        };
+        let micros = u64::try_from(entry.duration.as_micros()).unwrap_or(u64::MAX);
+        group.count.fetch_add(1, Ordering::Relaxed);
+        group.total_micros.fetch_add(micros, Ordering::Relaxed);
+        group.buckets[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Write a summary line for each group which has seen requests since the last report, then
+    /// reset the counters. Groups are written in sorted order.
+    pub fn report(&self) -> io::Result<()> {
+        let mut groups = self
+            .groups
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(k, v)| (k.clone(), Arc::clone(v)))
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut result = Ok(());
+        for ((action, class), group) in groups {
+            let count = group.count.swap(0, Ordering::Relaxed);
+            let total = group.total_micros.swap(0, Ordering::Relaxed);
+            let buckets = group
+                .buckets
+                .iter()
+                .map(|b| b.swap(0, Ordering::Relaxed))
+                .collect::<Vec<_>>();
+            if count == 0 {
+                continue;
+            }
+
+            let mut line = String::from("request-summary: [");
+            if let Some(action) = action {
+                line.push_str(&action);
+                line.push(':');
+            }
+            match class {
+                Some(class) => write!(line, "{class}").unwrap(),
+                None => line.push_str("???"),
+            }
+            write!(
+                line,
+                "] count={count} mean_ms={:.3} buckets=",
+                total as f64 / count as f64 / 1000.
+            )
+            .unwrap();
+            let mut first = true;
+            for (i, n) in buckets.into_iter().enumerate() {
+                if n != 0 {
+                    if !first {
+                        line.push(',');
+                    }
+                    first = false;
+                    write!(line, "{}us:{n}", 1u64 << (i + 1)).unwrap();
+                }
+            }
+            line.push('\n');
+
+            if let Err(e) = self.inner.write_line(&line) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Start a background thread which calls [report](Self::report) on the given interval. The
+    /// thread exits after the sink is dropped.
+    pub fn spawn_reporter(self: &Arc<Self>, interval: Duration) -> thread::JoinHandle<()> {
+        let weak: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match weak.upgrade() {
+                Some(sink) => {
+                    let _ = sink.report();
+                }
+                None => return,
+            }
+        })
+    }
+}
+
+fn bucket(micros: u64) -> usize {
+    let log2 = 63 - (micros | 1).leading_zeros() as usize;
+    log2.min(BUCKETS - 1)
+}
+
+impl LogSink for AggregatingSink {
+    /// Lines which aren't request entries are passed through to the inner sink.
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        self.inner.write_line(line)
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        self.record(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::test_entry;
+    use std::sync::Mutex;
+
+    fn entry(action: Option<&str>, status: Option<u16>, micros: u64) -> LogEntry {
+        let mut entry = test_entry(&http::Request::new(()));
+        entry.action = action.map(str::to_owned);
+        entry.status = status;
+        entry.duration = Duration::from_micros(micros);
+        entry
+    }
+
+    #[test]
+    fn test_bucket() {
+        assert_eq!(bucket(0), 0);
+        assert_eq!(bucket(1), 0);
+        assert_eq!(bucket(2), 1);
+        assert_eq!(bucket(3), 1);
+        assert_eq!(bucket(1024), 10);
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_report() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sink = AggregatingSink::new(out.clone());
+        sink.write_entry(&entry(Some("get"), Some(200), 1000))
+            .unwrap();
+        sink.write_entry(&entry(Some("get"), Some(204), 1500))
+            .unwrap();
+        sink.write_entry(&entry(Some("get"), Some(200), 3000))
+            .unwrap();
+        sink.write_entry(&entry(Some("get"), Some(404), 10))
+            .unwrap();
+        sink.write_entry(&entry(None, None, 10)).unwrap();
+        sink.report().unwrap();
+
+        let text = String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap();
+        assert_eq!(
+            text,
+            "request-summary: [???] count=1 mean_ms=0.010 buckets=16us:1\n\
+             request-summary: [get:2xx] count=3 mean_ms=1.833 buckets=1024us:1,2048us:1,4096us:1\n\
+             request-summary: [get:4xx] count=1 mean_ms=0.010 buckets=16us:1\n"
+        );
+
+        // Counters are reset by reporting.
+        sink.write_entry(&entry(Some("get"), Some(500), 10))
+            .unwrap();
+        sink.report().unwrap();
+        let text = String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap();
+        assert_eq!(
+            text,
+            "request-summary: [get:5xx] count=1 mean_ms=0.010 buckets=16us:1\n"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use http::Response;
+
+/// [LogAnnotations] lets code which builds a response, but can't reach the
+/// [LogRequest](crate::LogRequest), add to the request's log entry. Insert it into the response's
+/// extensions, and [set_response](crate::LogRequest::set_response) merges it into the entry.
+///
+/// Anything set directly on the [LogRequest](crate::LogRequest) wins: the action and user are
+/// only used if they haven't been set, and fields are only added if there isn't already one with
+/// the same key. Fields count towards the limits on extra fields, and values are logged in full
+/// up to those limits.
+///
+/// ```
+/// # use hyper_req_log::{LogAnnotations, LogRequest};
+/// fn deep_in_the_handler() -> http::Response<()> {
+///     let mut resp = http::Response::new(());
+///     let annotations = LogAnnotations::of(&mut resp);
+///     annotations.action = Some("checkout".to_owned());
+///     annotations.fields.push(("cart".to_owned(), "3 items".to_owned()));
+///     resp
+/// }
+///
+/// let req = http::Request::get("/checkout").body(()).unwrap();
+/// let mut log = LogRequest::<&str>::from_request(&req);
+/// log.set_response(&deep_in_the_handler());
+/// assert!(log.to_string().contains(" request: [checkout:200] "));
+/// # log.discard();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogAnnotations {
+    /// The action, logged in place of one set with
+    /// [set_action](crate::LogRequest::set_action).
+    pub action: Option<String>,
+    /// The user, logged in place of one set with [set_user](crate::LogRequest::set_user).
+    pub user: Option<String>,
+    /// Extra fields, logged as `key=value` like ones added with
+    /// [add_field_bytes](crate::LogRequest::add_field_bytes).
+    pub fields: Vec<(String, String)>,
+}
+
+impl LogAnnotations {
+    /// The annotations in the response's extensions, inserting empty ones if there aren't any.
+    pub fn of<B>(response: &mut Response<B>) -> &mut Self {
+        response.extensions_mut().get_or_insert_default()
+    }
+}
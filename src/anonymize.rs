@@ -0,0 +1,162 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use http::HeaderValue;
+
+use crate::forwarded::{parse_chain, Chain, ForwardedEntry};
+
+/// Whether, and how much, client addresses are masked before they are logged, for
+/// [LogConfig::anonymize_ips](crate::LogConfig::anonymize_ips).
+///
+/// Masking zeroes the last octet of IPv4 addresses, so `203.0.113.7` is logged as `203.0.113.0`,
+/// and the last 80 bits of IPv6 addresses, so `2001:db8:cafe::17` is logged as `2001:db8:cafe::`.
+/// IPv4-mapped IPv6 addresses are masked as the IPv4 address they map. It applies to the remote
+/// address, the forwarded chain, the real client found in it, and `X-Real-IP`; the remote host
+/// name from [resolve_remote](crate::LogRequest::resolve_remote) is left out altogether. The
+/// lines logged for incomplete connections are masked with
+/// [ConnectionLog::with_ip_anonymization](crate::ConnectionLog::with_ip_anonymization).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpAnonymization {
+    /// Addresses are logged as they are.
+    #[default]
+    Off,
+    /// Addresses are masked, and ports kept.
+    Mask,
+    /// Addresses are masked, and ports left out.
+    MaskAndDropPort,
+}
+
+impl IpAnonymization {
+    /// The address, masked.
+    pub(crate) fn ip(self, ip: IpAddr) -> IpAddr {
+        if self == Self::Off {
+            return ip;
+        }
+        match ip.to_canonical() {
+            IpAddr::V4(v4) => {
+                let [a, b, c, _] = v4.octets();
+                Ipv4Addr::new(a, b, c, 0).into()
+            }
+            IpAddr::V6(v6) => Ipv6Addr::from(u128::from(v6) & !((1 << 80) - 1)).into(),
+        }
+    }
+
+    /// The address, masked, with its port set to 0 if it is to be left out. Port 0 is never the
+    /// port of a real connection, and isn't rendered.
+    pub(crate) fn addr(self, addr: SocketAddr) -> SocketAddr {
+        match self {
+            Self::Off => addr,
+            Self::Mask => (self.ip(addr.ip()), addr.port()).into(),
+            Self::MaskAndDropPort => (self.ip(addr.ip()), 0).into(),
+        }
+    }
+
+    /// A comma-separated chain of addresses, as in `X-Forwarded-For`, parsed, with each address
+    /// masked.
+    pub(crate) fn chain(self, chain: &[u8]) -> Vec<ForwardedEntry> {
+        let mut entries = parse_chain(chain);
+        if self != Self::Off {
+            for entry in &mut entries {
+                *entry = self.hop(std::mem::replace(entry, ForwardedEntry::Unknown));
+            }
+        }
+        entries
+    }
+
+    /// A header value holding forwarded addresses, such as `X-Real-IP`, masked.
+    pub(crate) fn header(self, value: &HeaderValue) -> HeaderValue {
+        if self == Self::Off {
+            return value.clone();
+        }
+        let masked = Chain(&self.chain(value.as_bytes()), 0, false).to_string();
+        HeaderValue::try_from(masked).expect("masked addresses are valid header values")
+    }
+
+    /// An entry of a forwarded chain, masked. Anything which isn't an address is replaced with
+    /// `unknown`, since it could be anything, including an address in some other notation.
+    fn hop(self, entry: ForwardedEntry) -> ForwardedEntry {
+        match entry {
+            ForwardedEntry::Ip(ip) => ForwardedEntry::Ip(self.ip(ip)),
+            ForwardedEntry::Socket(addr) => match self.addr(addr) {
+                addr if addr.port() == 0 => ForwardedEntry::Ip(addr.ip()),
+                addr => ForwardedEntry::Socket(addr),
+            },
+            _ => ForwardedEntry::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MASK: IpAnonymization = IpAnonymization::Mask;
+    const DROP: IpAnonymization = IpAnonymization::MaskAndDropPort;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip() {
+        assert_eq!(MASK.ip(ip("203.0.113.7")), ip("203.0.113.0"));
+        assert_eq!(MASK.ip(ip("10.0.0.255")), ip("10.0.0.0"));
+        assert_eq!(
+            MASK.ip(ip("2001:db8:cafe:1:2:3:4:5")),
+            ip("2001:db8:cafe::")
+        );
+        assert_eq!(MASK.ip(ip("::1")), ip("::"));
+        assert_eq!(MASK.ip(ip("::ffff:203.0.113.7")), ip("203.0.113.0"));
+        assert_eq!(
+            IpAnonymization::Off.ip(ip("203.0.113.7")),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn test_addr() {
+        assert_eq!(
+            MASK.addr(addr("203.0.113.7:4000")),
+            addr("203.0.113.0:4000")
+        );
+        assert_eq!(DROP.addr(addr("203.0.113.7:4000")), addr("203.0.113.0:0"));
+        assert_eq!(
+            MASK.addr(addr("[2001:db8:cafe::17]:443")),
+            addr("[2001:db8:cafe::]:443")
+        );
+        assert_eq!(
+            MASK.addr(addr("[::ffff:198.51.100.9]:4000")),
+            addr("198.51.100.0:4000")
+        );
+        assert_eq!(
+            IpAnonymization::Off.addr(addr("203.0.113.7:4000")),
+            addr("203.0.113.7:4000")
+        );
+    }
+
+    #[test]
+    fn test_chain() {
+        let chain =
+            |anon: IpAnonymization, chain: &[u8]| Chain(&anon.chain(chain), 0, false).to_string();
+        assert_eq!(
+            chain(MASK, b"203.0.113.7, 10.0.0.2:8080,[2001:db8:cafe::17]:4711"),
+            "203.0.113.0,10.0.0.0:8080,[2001:db8:cafe::]:4711"
+        );
+        assert_eq!(
+            chain(DROP, b"203.0.113.7:5000, [2001:db8::1]:4711, [2001:db8::2]"),
+            "203.0.113.0,2001:db8::,2001:db8::"
+        );
+        assert_eq!(chain(MASK, b"::ffff:192.0.2.60"), "192.0.2.0");
+        assert_eq!(
+            chain(MASK, b"_hidden, not an address, caf\xc3\xa9, 192.0.2.60"),
+            "unknown,unknown,unknown,192.0.2.0"
+        );
+        assert_eq!(
+            chain(IpAnonymization::Off, b"_hidden,192.0.2.60"),
+            "_hidden,192.0.2.60"
+        );
+    }
+}
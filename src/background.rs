@@ -0,0 +1,312 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use http::Request;
+
+use crate::display::LogDisplay;
+use crate::entry::LogEntry;
+use crate::request::LogRequest;
+use crate::sink::LogSink;
+use crate::write_errors;
+
+/// What [BackgroundLogger] does with an entry when its queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the entry, and count it in [dropped](BackgroundLogger::dropped). The thread writing
+    /// the entry never waits.
+    #[default]
+    Drop,
+    /// Wait until there is room in the queue. No entries are lost, but a writer which can't keep
+    /// up eventually holds up the threads writing entries.
+    Block,
+}
+
+enum Message {
+    Entry(Box<LogEntry>),
+    Line(String),
+    Flush(SyncSender<()>),
+}
+
+/// [BackgroundLogger] is a sink which hands entries to a dedicated thread to be written to
+/// another sink, so that a slow destination, such as stderr going to journald under load,
+/// doesn't hold up the threads serving requests.
+///
+/// Requests created with [request](Self::request) are written to it when they are dropped,
+/// instead of being written to stderr inline. The entry is captured when the request is written,
+/// and passed to the thread over a queue of `capacity` entries; it is rendered and written by the
+/// thread. What happens when the queue is full depends on the [Overflow] policy. It can also be
+/// set as the [sink](crate::LogConfig::sink) of a config, or as the
+/// [default sink](crate::set_default_sink).
+///
+/// Entries are written in the order they were queued. Errors from the inner sink can't be
+/// returned to the thread which queued the entry, so they are counted in
+/// [failed_writes](crate::failed_writes) and passed to the
+/// [write error handler](crate::set_write_error_handler).
+/// [shutdown](Self::shutdown) writes everything queued and stops the thread; it is also done when
+/// the logger is dropped. Entries written after that are written to the inner sink inline.
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use hyper_req_log::{BackgroundLogger, Overflow};
+/// let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+/// let logger = Arc::new(BackgroundLogger::new(out.clone(), 1024, Overflow::Drop));
+///
+/// let req = http::Request::get("/hello").body(()).unwrap();
+/// let mut log = logger.request::<&str, _>(&req);
+/// log.set_response(&http::Response::new(()));
+/// drop(log);
+///
+/// logger.flush();
+/// assert!(String::from_utf8_lossy(&out.lock().unwrap()).contains(" GET /hello "));
+/// ```
+pub struct BackgroundLogger {
+    sink: Arc<dyn LogSink>,
+    overflow: Overflow,
+    sender: RwLock<Option<SyncSender<Message>>>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+    dropped: AtomicU64,
+}
+
+impl BackgroundLogger {
+    /// Start a thread which writes entries to `sink`, with a queue of up to `capacity` entries
+    /// waiting to be written.
+    pub fn new(sink: Arc<dyn LogSink>, capacity: usize, overflow: Overflow) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let thread = {
+            let sink = sink.clone();
+            thread::Builder::new()
+                .name("hyper-req-log-writer".to_owned())
+                .spawn(move || run(&*sink, receiver))
+                .expect("failed to start writer thread")
+        };
+        Self {
+            sink,
+            overflow,
+            sender: RwLock::new(Some(sender)),
+            thread: Mutex::new(Some(thread)),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a [LogRequest] from the given Hyper [Request], which is written to this logger
+    /// when it is dropped, as if the default configuration had it as its
+    /// [sink](crate::LogConfig::sink).
+    pub fn request<A: LogDisplay, B>(self: &Arc<Self>, req: &Request<B>) -> LogRequest<A> {
+        LogRequest::from_request_with_sink(req, self.clone())
+    }
+
+    /// How many entries have been dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until every entry queued so far has been written to the inner sink.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::sync_channel(1);
+        if self.send(Message::Flush(done), Overflow::Block).is_none() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// Write everything queued and stop the thread. Entries written after this are written to
+    /// the inner sink inline.
+    pub fn shutdown(&self) {
+        self.sender
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let thread = self.thread.lock().unwrap_or_else(|e| e.into_inner()).take();
+        // An entry's config can hold the last reference to the logger, in which case the logger
+        // is dropped on its own thread, which can't wait for itself. It writes the rest of the
+        // queue and stops once this returns.
+        if let Some(thread) = thread.filter(|t| t.thread().id() != thread::current().id()) {
+            let _ = thread.join();
+        }
+    }
+
+    /// Queue a message, returning it if the thread has been stopped.
+    fn send(&self, message: Message, overflow: Overflow) -> Option<Message> {
+        let sender = self.sender.read().unwrap_or_else(|e| e.into_inner());
+        let Some(sender) = &*sender else {
+            return Some(message);
+        };
+        match overflow {
+            Overflow::Drop => match sender.try_send(message) {
+                Ok(()) => None,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+                Err(TrySendError::Disconnected(message)) => Some(message),
+            },
+            Overflow::Block => sender.send(message).err().map(|e| e.0),
+        }
+    }
+}
+
+impl Drop for BackgroundLogger {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl LogSink for BackgroundLogger {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        match self.send(Message::Line(line.to_owned()), self.overflow) {
+            Some(_) => self.sink.write_line(line),
+            None => Ok(()),
+        }
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        match self.send(Message::Entry(Box::new(entry.clone())), self.overflow) {
+            Some(_) => self.sink.write_entry(entry),
+            None => Ok(()),
+        }
+    }
+}
+
+fn run(sink: &dyn LogSink, receiver: Receiver<Message>) {
+    for message in receiver {
+        let result = match message {
+            Message::Entry(entry) => sink.write_entry(&entry),
+            Message::Line(line) => sink.write_line(&line),
+            Message::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+        if let Err(err) = result {
+            write_errors::report(&err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// A sink which signals when it starts writing, then waits to be let go.
+    struct Blocked {
+        started: Mutex<mpsc::Sender<()>>,
+        release: Mutex<Receiver<()>>,
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl LogSink for Blocked {
+        fn write_line(&self, line: &str) -> io::Result<()> {
+            let _ = self.started.lock().unwrap().send(());
+            let _ = self.release.lock().unwrap().recv();
+            self.lines.lock().unwrap().push(line.to_owned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_blocked_writer() {
+        let (started, started_rx) = mpsc::channel();
+        let (release_tx, release) = mpsc::channel();
+        let sink = Arc::new(Blocked {
+            started: Mutex::new(started),
+            release: Mutex::new(release),
+            lines: Mutex::new(vec![]),
+        });
+        let logger = Arc::new(BackgroundLogger::new(sink.clone(), 1, Overflow::Drop));
+        let req = http::Request::get("/slow").body(()).unwrap();
+
+        // The first entry is taken by the writer, which then blocks. The second waits in the
+        // queue, and the rest are dropped, without the dropping thread waiting for the writer.
+        let begin = Instant::now();
+        drop(logger.request::<&str, _>(&req));
+        started_rx.recv().unwrap();
+        for _ in 0..4 {
+            drop(logger.request::<&str, _>(&req));
+        }
+        assert!(begin.elapsed() < Duration::from_secs(5));
+        assert_eq!(logger.dropped(), 3);
+
+        for _ in 0..2 {
+            release_tx.send(()).unwrap();
+        }
+        logger.flush();
+        let lines = sink.lines.lock().unwrap().clone();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.contains(" GET /slow ")));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let logger = Arc::new(BackgroundLogger::new(out.clone(), 2, Overflow::Block));
+        let req = http::Request::get("/queued").body(()).unwrap();
+        for _ in 0..10 {
+            drop(logger.request::<&str, _>(&req));
+        }
+        logger.shutdown();
+        let text = String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap();
+        assert_eq!(text.lines().count(), 10);
+        assert_eq!(logger.dropped(), 0);
+
+        // Once the thread is stopped, entries are written inline.
+        drop(logger.request::<&str, _>(&req));
+        logger.write_line("connection: closed\n").unwrap();
+        let text = String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap();
+        assert!(text.contains(" GET /queued "), "{text}");
+        assert!(text.ends_with("connection: closed\n"), "{text}");
+    }
+
+    #[test]
+    fn test_dropped_by_queued_entry() {
+        /// Waits to be let go before each line, and says whether it was dropped by a panic.
+        struct Held {
+            release: Mutex<Receiver<()>>,
+            lines: Arc<Mutex<Vec<String>>>,
+            dropped: Mutex<mpsc::Sender<bool>>,
+        }
+
+        impl LogSink for Held {
+            fn write_line(&self, line: &str) -> io::Result<()> {
+                let _ = self.release.lock().unwrap().recv();
+                self.lines.lock().unwrap().push(line.to_owned());
+                Ok(())
+            }
+        }
+
+        impl Drop for Held {
+            fn drop(&mut self) {
+                let _ = self.dropped.lock().unwrap().send(thread::panicking());
+            }
+        }
+
+        let (release_tx, release) = mpsc::channel();
+        let (dropped_tx, dropped) = mpsc::channel();
+        let lines = Arc::new(Mutex::new(vec![]));
+        let sink = Arc::new(Held {
+            release: Mutex::new(release),
+            lines: lines.clone(),
+            dropped: Mutex::new(dropped_tx),
+        });
+        let logger = Arc::new(BackgroundLogger::new(sink, 4, Overflow::Block));
+        let config = Arc::new(crate::LogConfig::new().sink(logger.clone()));
+        let req = http::Request::get("/last").body(()).unwrap();
+        for _ in 0..2 {
+            drop(LogRequest::<&str>::from_request_with_config(
+                &req,
+                config.clone(),
+            ));
+        }
+
+        // The queued entries now hold the only references to the logger, through their config.
+        drop((logger, config));
+        for _ in 0..2 {
+            release_tx.send(()).unwrap();
+        }
+        let panicked = dropped.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(!panicked);
+        assert_eq!(lines.lock().unwrap().len(), 2);
+    }
+}
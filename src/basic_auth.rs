@@ -0,0 +1,104 @@
+use bytes::Bytes;
+use http::HeaderValue;
+
+/// The longest `Authorization` header which is decoded. Usernames are short, so anything longer
+/// isn't worth the work.
+const MAX_LEN: usize = 1024;
+
+/// The username from an `Authorization: Basic` header, if it has one. The password is decoded
+/// along with it, since they're encoded together, but it is dropped here and never kept.
+///
+/// This has its own small base64 decoder rather than pulling in a crate for it. The username is
+/// kept as the bytes it decodes to, which may not be valid UTF-8, for the formats to escape like
+/// any other value, and an empty username counts as none.
+pub(crate) fn username(value: &HeaderValue) -> Option<Bytes> {
+    let value = value.as_bytes();
+    if value.len() > MAX_LEN {
+        return None;
+    }
+    let (scheme, credentials) = value.split_at(value.iter().position(|&b| b == b' ')?);
+    if !scheme.eq_ignore_ascii_case(b"basic") {
+        return None;
+    }
+    let decoded = decode(credentials.trim_ascii())?;
+    let mut user = Bytes::from(decoded);
+    user.truncate(user.iter().position(|&b| b == b':')?);
+    if user.is_empty() {
+        return None;
+    }
+    Some(user)
+}
+
+/// Decode standard base64, with or without its padding.
+fn decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let encoded = match encoded {
+        [rest @ .., b'=', b'='] | [rest @ .., b'='] => rest,
+        _ => encoded,
+    };
+    if encoded.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(encoded.len() / 4 * 3 + 2);
+    let (mut bits, mut n) = (0u32, 0);
+    for &b in encoded {
+        let sextet = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(sextet);
+        n += 6;
+        if n >= 8 {
+            n -= 8;
+            out.push((bits >> n) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"").unwrap(), b"");
+        assert_eq!(decode(b"YQ==").unwrap(), b"a");
+        assert_eq!(decode(b"YQ").unwrap(), b"a");
+        assert_eq!(decode(b"YWI=").unwrap(), b"ab");
+        assert_eq!(decode(b"YWJj").unwrap(), b"abc");
+        assert_eq!(decode(b"YWxpY2U6bG9va2dsYXNz").unwrap(), b"alice:lookglass");
+        assert_eq!(decode(b"+/+/").unwrap(), [0xfb, 0xff, 0xbf]);
+        assert_eq!(decode(b"Y"), None);
+        assert_eq!(decode(b"YW*j"), None);
+        assert_eq!(decode(b"YQ==="), None);
+    }
+
+    #[test]
+    fn test_username() {
+        let user = |s: &str| username(&HeaderValue::from_str(s).unwrap());
+        assert_eq!(
+            user("Basic YWxpY2U6bG9va2dsYXNz").as_deref(),
+            Some(&b"alice"[..])
+        );
+        assert_eq!(
+            user("basic  YWxpY2U6bG9va2dsYXNz ").as_deref(),
+            Some(&b"alice"[..])
+        );
+        // Only the first colon separates the username; the password may contain more.
+        assert_eq!(user("Basic Ym9iOmE6Yg==").as_deref(), Some(&b"bob"[..]));
+        // "\xffbob:pw"
+        assert_eq!(user("Basic /2JvYjpwdw==").as_deref(), Some(&b"\xffbob"[..]));
+        // No colon.
+        assert_eq!(user("Basic YWxpY2U="), None);
+        // ":pw"
+        assert_eq!(user("Basic OnB3"), None);
+        assert_eq!(user("Basic not*base64"), None);
+        assert_eq!(user("Basic"), None);
+        assert_eq!(user("Bearer YWxpY2U6bG9va2dsYXNz"), None);
+        assert_eq!(user(&format!("Basic {}", "A".repeat(MAX_LEN))), None);
+    }
+}
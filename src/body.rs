@@ -0,0 +1,321 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+
+use bytes::Buf;
+use http_body::{Body, Frame, SizeHint};
+
+use crate::entry::LogEntry;
+use crate::sink::LogSink;
+
+/// Body wrapper returned by
+/// [LogRequest::count_request_body](crate::LogRequest::count_request_body) and
+/// [LogRequest::count_response_body](crate::LogRequest::count_response_body), which counts the
+/// bytes of body data polled from it.
+///
+/// For a response body, if the request is dropped while the body is still being sent, which is
+/// the usual case when the handler returns the response, the entry is written once the body
+/// finishes or is dropped instead, so that it has the full count. A body which is dropped before
+/// its end, such as when the client disconnects part way through a download, or which fails, is
+/// logged with `body=incomplete`.
+pub struct CountingBody<B: Body> {
+    inner: B,
+    count: Arc<BodyCount>,
+}
+
+/// The state shared between a [CountingBody] and its request.
+#[derive(Default)]
+pub(crate) struct BodyCount {
+    bytes: AtomicU64,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    end: Option<BodyEnd>,
+    deferred: Option<Deferred>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BodyEnd {
+    Complete,
+    Incomplete,
+}
+
+/// An entry waiting for its body to end before it is written.
+pub(crate) struct Deferred {
+    pub(crate) entry: LogEntry,
+    pub(crate) sinks: Vec<Arc<dyn LogSink>>,
+    /// The size set with `set_response_size`, which takes precedence over the count.
+    pub(crate) response_size: Option<u64>,
+}
+
+impl<B: Body> CountingBody<B> {
+    pub(crate) fn new(inner: B, count: Arc<BodyCount>) -> Self {
+        Self { inner, count }
+    }
+
+    /// The number of bytes of body data polled so far.
+    pub fn bytes_counted(&self) -> u64 {
+        self.count.bytes()
+    }
+
+    /// Get a reference to the wrapped body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+}
+
+impl BodyCount {
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes counted, unless none have been and the body hasn't been read to its
+    /// end, so a body which was never read doesn't hide its `Content-Length`.
+    pub(crate) fn counted(&self) -> Option<u64> {
+        let bytes = self.bytes();
+        (bytes != 0 || self.end() == Some(BodyEnd::Complete)).then_some(bytes)
+    }
+
+    /// How the body ended, or `None` if it is still being sent.
+    pub(crate) fn end(&self) -> Option<BodyEnd> {
+        self.lock().end
+    }
+
+    /// Write the entry when the body ends, or now if it already has.
+    pub(crate) fn defer(&self, deferred: Deferred) {
+        let mut state = self.lock();
+        match state.end {
+            Some(end) => {
+                drop(state);
+                self.write(deferred, end);
+            }
+            None => state.deferred = Some(deferred),
+        }
+    }
+
+    fn finish(&self, end: BodyEnd) {
+        let mut state = self.lock();
+        if state.end.is_some() {
+            return;
+        }
+        state.end = Some(end);
+        let deferred = state.deferred.take();
+        drop(state);
+        if let Some(deferred) = deferred {
+            self.write(deferred, end);
+        }
+    }
+
+    fn write(&self, mut deferred: Deferred, end: BodyEnd) {
+        deferred.entry.response_bytes = deferred.response_size.or(Some(self.bytes()));
+        deferred.entry.body_end = Some(end);
+        if let Err(err) = crate::request::emit_default(&deferred.entry, &deferred.sinks) {
+            crate::write_errors::report(&err);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<B: Body + Unpin> Body for CountingBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    self.count
+                        .bytes
+                        .fetch_add(data.remaining() as u64, Ordering::Relaxed);
+                }
+                // hyper stops polling once the body says it has ended, without waiting for the
+                // final `None`.
+                if self.inner.is_end_stream() {
+                    self.count.finish(BodyEnd::Complete);
+                }
+            }
+            Some(Err(_)) => self.count.finish(BodyEnd::Incomplete),
+            None => self.count.finish(BodyEnd::Complete),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B: Body> Drop for CountingBody<B> {
+    fn drop(&mut self) {
+        // A body which is empty from the start is never polled.
+        self.count.finish(match self.inner.is_end_stream() {
+            true => BodyEnd::Complete,
+            false => BodyEnd::Incomplete,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    use crate::LogRequest;
+
+    fn request(out: &Arc<Mutex<Vec<u8>>>) -> LogRequest<&'static str> {
+        let req = http::Request::get("/download").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .also_write_to(out.clone());
+        log
+    }
+
+    /// A body of `n` chunks of 10 bytes, which doesn't know its end until it is polled past it.
+    struct Chunks(u32);
+
+    impl Body for Chunks {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            if self.0 == 0 {
+                return Poll::Ready(None);
+            }
+            self.0 -= 1;
+            Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(b"0123456789")))))
+        }
+    }
+
+    fn text(out: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_full() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let mut log = request(&out);
+        let resp = http::Response::new(Full::new(Bytes::from("hello\n")));
+        log.set_response(&resp);
+        let body = log.count_response_body(resp.into_body());
+        drop(log);
+        // Not written until the body has been sent.
+        assert_eq!(text(&out), "");
+
+        let collected = body.collect().await.unwrap().to_bytes();
+        assert_eq!(collected, "hello\n");
+        let line = text(&out);
+        assert!(line.ends_with(" bytes=6\n"), "{line}");
+    }
+
+    #[tokio::test]
+    async fn test_streamed() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let mut log = request(&out);
+        log.set_response(&http::Response::new(()));
+        let body = log.count_response_body(Chunks(3));
+        drop(log);
+        body.collect().await.unwrap();
+        let line = text(&out);
+        assert!(line.ends_with(" bytes=30\n"), "{line}");
+
+        // Dropped part way through, like a client disconnecting.
+        let mut log = request(&out);
+        log.set_response(&http::Response::new(()));
+        let mut body = log.count_response_body(Chunks(3));
+        drop(log);
+        body.frame().await.unwrap().unwrap();
+        assert_eq!(body.bytes_counted(), 10);
+        drop(body);
+        let line = text(&out);
+        assert!(line.ends_with(" bytes=10 body=incomplete\n"), "{line}");
+    }
+
+    #[tokio::test]
+    async fn test_written_first() {
+        // If the request is written before the body ends, it has the count so far.
+        let out = Arc::new(Mutex::new(vec![]));
+        let mut log = request(&out);
+        log.set_response(&http::Response::new(()));
+        let body = log.count_response_body(Full::new(Bytes::from("abc")));
+        log.write(std::io::sink()).unwrap();
+        let line = text(&out);
+        assert!(line.ends_with(" bytes=0\n"), "{line}");
+        body.collect().await.unwrap();
+        assert_eq!(text(&out), "");
+    }
+
+    #[tokio::test]
+    async fn test_request_body() {
+        let req = http::Request::post("/upload")
+            .header("content-length", "30")
+            .body(Chunks(2))
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        let line = log.to_string();
+        assert!(line.ends_with(" bytes_in=30\n"), "{line}");
+
+        // The counted size wins over the header, once anything has been read.
+        let mut body = log.count_request_body(req.into_body());
+        let line = log.to_string();
+        assert!(line.ends_with(" bytes_in=30\n"), "{line}");
+        body.frame().await.unwrap().unwrap();
+        let line = log.to_string();
+        assert!(line.ends_with(" bytes_in=10\n"), "{line}");
+        body.collect().await.unwrap();
+        let entry = log.entry();
+        log.discard();
+        assert_eq!(entry.request_bytes(), Some(20));
+
+        // Dropping a body without reading it doesn't make it empty.
+        let req = http::Request::post("/upload")
+            .header("content-length", "30")
+            .body(Chunks(3))
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        drop(log.count_request_body(req.into_body()));
+        assert!(log.to_string().ends_with(" bytes_in=30\n"));
+        log.discard();
+
+        // An empty body is counted as empty.
+        let req = http::Request::post("/upload")
+            .header("content-length", "30")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        log.count_request_body(req.into_body())
+            .collect()
+            .await
+            .unwrap();
+        assert!(log.to_string().ends_with(" bytes_in=0\n"));
+        log.discard();
+
+        let bad = http::Request::post("/upload")
+            .header("content-length", "lots")
+            .body(())
+            .unwrap();
+        let log = LogRequest::<&str>::from_request(&bad);
+        assert!(!log.to_string().contains("bytes_in="));
+        log.discard();
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Whether a response was served from a cache in front of the handler, in the terms of nginx's
+/// `$upstream_cache_status`. Set with [set_cache_status](crate::LogRequest::set_cache_status),
+/// and logged as a token at the end of the bracketed section, as in `[get:200:HIT]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CacheStatus {
+    /// The response was served from the cache.
+    Hit,
+    /// The response wasn't in the cache, so the handler produced it.
+    Miss,
+    /// A stale cached response was served, such as while the handler was failing.
+    Stale,
+    /// A stale cached response was found to still be current, and served.
+    Revalidated,
+    /// The cache was skipped for this request.
+    Bypass,
+    /// The cached response had expired, so the handler produced a fresh one.
+    Expired,
+    /// Some other state, logged as given (escaped as needed).
+    Other(String),
+}
+
+impl CacheStatus {
+    /// The token it is logged as, such as `HIT`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Hit => "HIT",
+            Self::Miss => "MISS",
+            Self::Stale => "STALE",
+            Self::Revalidated => "REVALIDATED",
+            Self::Bypass => "BYPASS",
+            Self::Expired => "EXPIRED",
+            Self::Other(other) => other,
+        }
+    }
+}
+
+impl Display for CacheStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::{self, Display, Formatter, Write};
+use std::net::SocketAddr;
+
+use crate::entry::LogEntry;
+use crate::fields::header_value;
+use crate::format::{http_version, LogMethod};
+use crate::query::LogUri;
+
+/// Render an entry in the Apache combined log format, for
+/// [Format::Combined](crate::Format::Combined).
+pub(crate) fn write_combined<W: Write + ?Sized>(entry: &LogEntry, f: &mut W) -> fmt::Result {
+    match entry.remote {
+        Some(remote) => write!(f, "{}", Host(remote))?,
+        None => f.write_char('-')?,
+    }
+    f.write_str(" - ")?;
+    match &entry.user {
+        Some(user) => write!(f, "{}", ClfEscaped(user))?,
+        None => f.write_char('-')?,
+    }
+    write!(f, " [{}] \"", entry.timestamp().apache())?;
+    let method = LogMethod(&entry.method, entry.config.method_allowlist).to_string();
+    let uri = LogUri(entry).to_string();
+    write!(
+        f,
+        "{} {} {}\" ",
+        ClfEscaped(method.as_bytes()),
+        ClfEscaped(uri.as_bytes()),
+        http_version(entry.version),
+    )?;
+    match entry.status {
+        Some(status) => write!(f, "{status}")?,
+        None => f.write_char('-')?,
+    }
+    // Like Apache's `%b`, which is `-` rather than 0 when no body was sent.
+    match entry.response_bytes.filter(|&n| n != 0) {
+        Some(bytes) => write!(f, " {bytes}")?,
+        None => f.write_str(" -")?,
+    }
+    for value in [&entry.referer, &entry.user_agent] {
+        match value {
+            Some(value) => {
+                let value = header_value(entry, value.as_bytes());
+                write!(f, " \"{}\"", ClfEscaped(&value))?
+            }
+            None => f.write_str(" \"-\"")?,
+        }
+    }
+    f.write_char('\n')
+}
+
+/// Renders the IP address of a socket address, without the port, as Apache's `%h`.
+pub(crate) struct Host(pub(crate) SocketAddr);
+
+impl Display for Host {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            SocketAddr::V4(v4) => write!(f, "{}", v4.ip()),
+            SocketAddr::V6(v6) => match v6.ip().octets() {
+                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                    write!(f, "{a}.{b}.{c}.{d}")
+                }
+                _ => write!(f, "{}", v6.ip()),
+            },
+        }
+    }
+}
+
+/// Renders bytes escaped the way Apache escapes log items: quotes and backslashes with a
+/// backslash, the usual C escapes for whitespace controls, and any other byte which isn't
+/// printable ASCII as `\xhh`.
+struct ClfEscaped<'a>(&'a [u8]);
+
+impl Display for ClfEscaped<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for &b in self.0 {
+            match b {
+                b'"' => f.write_str("\\\"")?,
+                b'\\' => f.write_str("\\\\")?,
+                b'\x08' => f.write_str("\\b")?,
+                b'\n' => f.write_str("\\n")?,
+                b'\r' => f.write_str("\\r")?,
+                b'\t' => f.write_str("\\t")?,
+                b'\x0b' => f.write_str("\\v")?,
+                b' '..=b'~' => f.write_char(b as char)?,
+                _ => write!(f, "\\x{b:02x}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use http::HeaderValue;
+
+    use crate::config::LogConfig;
+    use crate::entry::test_entry;
+    use crate::time::Timezone;
+
+    fn combined(entry: &LogEntry) -> String {
+        let mut line = String::new();
+        write_combined(entry, &mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn test_missing() {
+        let mut entry = test_entry(&http::Request::new(()));
+        entry.start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(971_211_336);
+        assert_eq!(
+            combined(&entry),
+            "- - - [10/Oct/2000:20:55:36 +0000] \"GET / HTTP/1.1\" - - \"-\" \"-\"\n"
+        );
+
+        entry.status = Some(304);
+        entry.response_bytes = Some(0);
+        entry.remote = Some("[::ffff:192.0.2.1]:4000".parse().unwrap());
+        entry.config = Arc::new(LogConfig::new().timezone(Timezone::FixedOffset(-7 * 3600)));
+        assert_eq!(
+            combined(&entry),
+            "192.0.2.1 - - [10/Oct/2000:13:55:36 -0700] \"GET / HTTP/1.1\" 304 - \"-\" \"-\"\n"
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        let req = http::Request::get("/a%20b?q=x")
+            .header(
+                "user-agent",
+                HeaderValue::from_bytes(b"agent \"quoted\" back\\slash\t\xff").unwrap(),
+            )
+            .header("referer", "caf\u{e9}")
+            .body(())
+            .unwrap();
+        let mut entry = test_entry(&req);
+        entry.remote = Some("[2001:db8::1]:443".parse().unwrap());
+        entry.user = Some("bob \"b\"\n".into());
+        entry.status = Some(200);
+        entry.response_bytes = Some(6);
+        let line = combined(&entry);
+        let (_, rest) = line.split_once(']').unwrap();
+        assert!(
+            line.starts_with("2001:db8::1 - bob \\\"b\\\"\\n ["),
+            "{line}"
+        );
+        assert_eq!(
+            rest,
+            " \"GET /a%20b?q=x HTTP/1.1\" 200 6 \"caf\\xc3\\xa9\" \
+             \"agent \\\"quoted\\\" back\\\\slash\\t\\xff\"\n"
+        );
+    }
+}
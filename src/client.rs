@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::future::Future;
+
+use http::{Request, Response};
+
+use crate::display::LogDisplay;
+use crate::error::ErrorKind;
+use crate::request::LogRequest;
+
+/// Marks the request as cancelled if the call is dropped before it finishes.
+struct CancelGuard<A: LogDisplay>(Option<LogRequest<A>>);
+
+impl<A: LogDisplay> Drop for CancelGuard<A> {
+    fn drop(&mut self) {
+        if let Some(log) = &mut self.0 {
+            log.mark_cancelled();
+        }
+    }
+}
+
+impl<A: LogDisplay> LogRequest<A> {
+    /// Make an outbound request with the given function, such as hyper's
+    /// `SendRequest::send_request` or a client's `request` method, and log it
+    /// when it finishes.
+    ///
+    /// The entry is finished with the response's status, or with the
+    /// [ErrorKind] of the error if the request failed, and written when this
+    /// instance is dropped at the end of the call. If the returned future is
+    /// dropped before the request finishes, it is logged as cancelled.
+    ///
+    /// ```no_run
+    /// # use hyper_req_log::LogRequest;
+    /// # async fn call(
+    /// #     sender: &mut hyper::client::conn::http1::SendRequest<String>,
+    /// #     peer: std::net::SocketAddr,
+    /// # ) -> Result<(), hyper::Error> {
+    /// let req = http::Request::get("/status").body(String::new()).unwrap();
+    /// let mut log = LogRequest::<&str>::from_client_request(&req);
+    /// log.set_action("status").set_peer(peer);
+    /// let resp = log.send(req, |req| sender.send_request(req)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send<B, R, E, F, Fut>(self, req: Request<B>, send: F) -> Result<Response<R>, E>
+    where
+        F: FnOnce(Request<B>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, E>>,
+        E: Error + 'static,
+    {
+        let mut guard = CancelGuard(Some(self));
+        let result = send(req).await;
+        if let Some(mut log) = guard.0.take() {
+            match &result {
+                Ok(resp) => log.set_response(resp),
+                Err(e) => log.set_error_kind(ErrorKind::from_error(e)),
+            };
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::untimed;
+    use std::convert::Infallible;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use hyper::client::conn::http1;
+    use hyper_util::rt::TokioIo;
+
+    fn sink() -> Arc<Mutex<Vec<u8>>> {
+        Arc::new(Mutex::new(vec![]))
+    }
+
+    fn text(out: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(out.lock().unwrap().clone()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+            TokioIo::new(server),
+            hyper::service::service_fn(|_req| async {
+                let mut resp = Response::new(String::new());
+                *resp.status_mut() = http::StatusCode::NOT_FOUND;
+                Ok::<_, Infallible>(resp)
+            }),
+        ));
+        let (mut sender, conn) = http1::handshake::<_, String>(TokioIo::new(client))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let out = sink();
+        let req = Request::get("/missing")
+            .header("host", "upstream.example")
+            .header("x-forwarded-for", "10.9.9.9")
+            .body(String::new())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_client_request(&req);
+        log.set_action("lookup")
+            .set_peer("127.0.0.1:8080".parse().unwrap())
+            .also_write_to(out.clone());
+        let resp = log.send(req, |req| sender.send_request(req)).await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with(
+                "client-request: [lookup:404] 127.0.0.1:8080 upstream.example GET /missing "
+            ),
+            "{line}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_error() {
+        let out = sink();
+        let req = Request::get("/").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_client_request(&req);
+        log.also_write_to(out.clone());
+        let result = log
+            .send(req, |_req| async {
+                Err::<Response<()>, _>(io::Error::from(io::ErrorKind::ConnectionReset))
+            })
+            .await;
+        assert!(result.is_err());
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with("client-request: [???] <unknown-peer> "),
+            "{line}"
+        );
+        assert!(line.ends_with(" err_kind=client-disconnect\n"), "{line}");
+    }
+
+    #[tokio::test]
+    async fn test_send_cancelled() {
+        let out = sink();
+        let req = Request::get("/").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_client_request(&req);
+        log.also_write_to(out.clone());
+        let call = log.send(req, |_req| {
+            std::future::pending::<Result<Response<()>, io::Error>>()
+        });
+        let timeout = tokio::time::timeout(std::time::Duration::from_millis(10), call).await;
+        assert!(timeout.is_err());
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with("client-request: [!cancelled] "),
+            "{line}"
+        );
+    }
+}
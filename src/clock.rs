@@ -0,0 +1,119 @@
+use std::time::{Instant, SystemTime};
+
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::{Arc, Mutex};
+#[cfg(any(test, feature = "test-util"))]
+use std::time::Duration;
+
+/// Where a request's times come from: the system's clocks, unless a test swaps in a
+/// [MockClock].
+pub(crate) trait Clock: Send + Sync {
+    /// The current time, for measuring durations.
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time, for timestamps.
+    fn system_now(&self) -> SystemTime;
+}
+
+/// The system's clocks.
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which only moves when it is [advanced](Self::advance), for tests which check rendered
+/// lines, whose durations would otherwise differ from run to run. Give it to a request with
+/// [LogRequest::set_clock](crate::LogRequest::set_clock); clones share the same time.
+///
+/// ```
+/// # use std::time::{Duration, SystemTime};
+/// # use hyper_req_log::{LogConfig, LogRequest, MockClock, TimestampStyle};
+/// let clock = MockClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+/// let req = http::Request::get("/").body(()).unwrap();
+/// let mut log = LogRequest::<&str>::from_request(&req);
+/// log.set_clock(clock.clone());
+/// clock.advance(Duration::from_millis(12));
+/// log.set_status(200);
+/// clock.advance(Duration::from_millis(3));
+/// assert_eq!(
+///     log.render(),
+///     "2023-11-14T22:13:20.000Z request: [200] <unknown-remote> \"\" GET / HTTP/1.1 \"\" \"\" \
+///      12.000/15.000\n",
+/// );
+/// # log.discard();
+/// ```
+///
+/// This is only available with the `test-util` feature.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<(Instant, SystemTime)>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    /// A clock stopped at the given wall-clock time.
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new((Instant::now(), start))),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.lock();
+        now.0 += by;
+        now.1 += by;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (Instant, SystemTime)> {
+        self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    /// A clock stopped at the Unix epoch.
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.lock().0
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.lock().1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::default();
+        let (start, system_start) = (clock.now(), clock.system_now());
+        assert_eq!(system_start, SystemTime::UNIX_EPOCH);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(clock.now(), start);
+
+        clock.clone().advance(Duration::from_millis(5));
+        assert_eq!(clock.now() - start, Duration::from_millis(5));
+        assert_eq!(
+            clock.system_now(),
+            SystemTime::UNIX_EPOCH + Duration::from_millis(5)
+        );
+    }
+}
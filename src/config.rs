@@ -0,0 +1,952 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use http::header::HeaderName;
+use http::uri::Scheme;
+
+use crate::anonymize::IpAnonymization;
+use crate::dump::DumpPolicy;
+use crate::field_set::FieldSet;
+use crate::fields::FieldNames;
+#[cfg(feature = "fingerprint")]
+use crate::fingerprint::Fingerprint;
+use crate::format::Format;
+use crate::formatter::{ConfigFormatter, LogFormatter};
+#[cfg(feature = "identity")]
+use crate::identity::{IdentitySource, TrustedIdentityHeader};
+use crate::metrics::{ConfigMetricsSink, MetricsSink};
+use crate::policy::LogPolicy;
+use crate::privacy::Privacy;
+use crate::proxies::TrustedProxies;
+use crate::route::RouteRules;
+use crate::sample::SamplingDecision;
+use crate::sink::{ConfigSink, LogSink};
+use crate::time::{DurationStyle, TimestampStyle, Timezone};
+
+/// Options controlling what a [LogRequest](crate::LogRequest) captures and how its entries are
+/// rendered.
+///
+/// A configuration is built with its chaining setters and then shared between requests in an
+/// `Arc`:
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{LogConfig, LogRequest};
+/// let config = Arc::new(LogConfig::new().method_allowlist(true));
+/// # let req = http::Request::new(());
+/// let log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+/// # log.discard();
+/// ```
+///
+/// [build](Self::build) does the same, and in [strict](Self::strict) mode first checks the
+/// configuration with [validate](Self::validate), so that mistakes fail at startup:
+///
+/// ```
+/// # use hyper_req_log::{DumpPolicy, LogConfig};
+/// let result = LogConfig::new()
+///     .dump(DumpPolicy::new().ratio(5.))
+///     .strict(true)
+///     .build();
+/// assert_eq!(result.unwrap_err()[0].to_string(), "dump.ratio: ratio 5 is not between 0 and 1");
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub(crate) method_allowlist: bool,
+    pub(crate) status_style: StatusStyle,
+    pub(crate) status_reason: bool,
+    pub(crate) placeholder: Placeholder,
+    pub(crate) format: Option<Format>,
+    pub(crate) formatter: Option<ConfigFormatter>,
+    pub(crate) sink: Option<ConfigSink>,
+    pub(crate) metrics_sink: Option<ConfigMetricsSink>,
+    pub(crate) timezone: Timezone,
+    pub(crate) timestamp_style: Option<TimestampStyle>,
+    pub(crate) duration_style: DurationStyle,
+    pub(crate) field_names: FieldNames,
+    pub(crate) header_sizes: bool,
+    pub(crate) negotiation: bool,
+    pub(crate) content_headers: bool,
+    pub(crate) normalize_host: bool,
+    pub(crate) normalize_content_type: bool,
+    pub(crate) normalize_content_encoding: bool,
+    pub(crate) scheme: Option<Scheme>,
+    pub(crate) max_extras: usize,
+    pub(crate) max_forwarded_hops: usize,
+    pub(crate) max_extras_bytes: usize,
+    pub(crate) max_header_len: Option<usize>,
+    pub(crate) ascii_headers: bool,
+    pub(crate) max_line_len: Option<usize>,
+    pub(crate) routes: RouteRules,
+    #[cfg(feature = "identity")]
+    pub(crate) identity: Vec<IdentitySource>,
+    #[cfg(feature = "identity")]
+    pub(crate) trusted_identity: Option<TrustedIdentityHeader>,
+    #[cfg(feature = "watchdog")]
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) duration_header: Option<HeaderName>,
+    pub(crate) generate_request_ids: bool,
+    pub(crate) trace_context: bool,
+    pub(crate) trace_state: bool,
+    pub(crate) trusted_proxies: Option<TrustedProxies>,
+    pub(crate) real_ip_mismatch: bool,
+    pub(crate) anonymize_ips: IpAnonymization,
+    pub(crate) privacy: Privacy,
+    pub(crate) redact_query_params: Vec<String>,
+    pub(crate) log_query: Option<bool>,
+    pub(crate) field_set: Option<FieldSet>,
+    pub(crate) origin_form: bool,
+    pub(crate) capture_headers: Vec<HeaderName>,
+    pub(crate) capture_response_headers: Vec<HeaderName>,
+    pub(crate) dump: Option<DumpPolicy>,
+    #[cfg(feature = "fingerprint")]
+    pub(crate) fingerprint: Option<Fingerprint>,
+    pub(crate) keep_if_status: Option<fn(u16) -> bool>,
+    pub(crate) policy: Option<LogPolicy>,
+    pub(crate) slow_threshold: Option<Duration>,
+    pub(crate) strict: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            method_allowlist: false,
+            status_style: StatusStyle::default(),
+            status_reason: false,
+            placeholder: Placeholder::default(),
+            format: None,
+            formatter: None,
+            sink: None,
+            metrics_sink: None,
+            timezone: Timezone::default(),
+            timestamp_style: None,
+            duration_style: DurationStyle::default(),
+            field_names: FieldNames::default(),
+            header_sizes: false,
+            negotiation: false,
+            content_headers: false,
+            normalize_host: false,
+            normalize_content_type: false,
+            normalize_content_encoding: false,
+            scheme: None,
+            max_extras: 32,
+            max_forwarded_hops: 16,
+            max_extras_bytes: 4096,
+            max_header_len: None,
+            ascii_headers: false,
+            max_line_len: None,
+            routes: RouteRules::default(),
+            #[cfg(feature = "identity")]
+            identity: vec![],
+            #[cfg(feature = "identity")]
+            trusted_identity: None,
+            #[cfg(feature = "watchdog")]
+            max_age: None,
+            duration_header: None,
+            generate_request_ids: false,
+            trace_context: false,
+            trace_state: false,
+            trusted_proxies: None,
+            real_ip_mismatch: false,
+            anonymize_ips: IpAnonymization::Off,
+            privacy: Privacy::Off,
+            redact_query_params: vec![],
+            log_query: None,
+            field_set: None,
+            origin_form: false,
+            capture_headers: vec![],
+            capture_response_headers: vec![],
+            dump: None,
+            #[cfg(feature = "fingerprint")]
+            fingerprint: None,
+            keep_if_status: None,
+            policy: None,
+            slow_threshold: None,
+            strict: false,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Create a new configuration with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// If enabled, request methods other than the ones defined in RFC 9110 (plus `PATCH`) are
+    /// logged as `OTHER(method)` instead of as themselves.
+    ///
+    /// Disabled by default.
+    pub fn method_allowlist(mut self, enable: bool) -> Self {
+        self.method_allowlist = enable;
+        self
+    }
+
+    /// If enabled, the serialized sizes of the request and response headers are logged as
+    /// `hdr_in=N hdr_out=N`. This walks every header of the request and the response.
+    ///
+    /// Disabled by default.
+    pub fn header_sizes(mut self, enable: bool) -> Self {
+        self.header_sizes = enable;
+        self
+    }
+
+    /// Capture the request's `Accept` header and the response's `Content-Type`, and log them as
+    /// `accept=application/json -> text/html`: the media range the client prefers most, and the
+    /// type it was served without its parameters. If the served type isn't acceptable, `!mismatch`
+    /// is added. Nothing is logged for requests without an `Accept` header.
+    ///
+    /// Disabled by default.
+    pub fn negotiation(mut self, enable: bool) -> Self {
+        self.negotiation = enable;
+        self
+    }
+
+    /// Capture the request's `Content-Type`, `Content-Encoding`, and `Accept` headers, for APIs
+    /// which do content negotiation, and log them as they were sent, as
+    /// `content_type=application/json`, `content_encoding=gzip`, and `accept_header=...`. In the
+    /// text format, they are in the `details` group, and are cut short like the other headers by
+    /// [max_header_len](Self::max_header_len). They are also available from
+    /// [LogRequest::content_type](crate::LogRequest::content_type),
+    /// [LogRequest::content_encoding](crate::LogRequest::content_encoding), and
+    /// [LogRequest::accept](crate::LogRequest::accept).
+    ///
+    /// Disabled by default.
+    pub fn content_headers(mut self, enable: bool) -> Self {
+        self.content_headers = enable;
+        self
+    }
+
+    /// If enabled, the host is normalized when it is captured, so that the same host is always
+    /// logged the same way: it is lower-cased, a trailing dot is removed, and the port is removed
+    /// if it is the default for the request's scheme. The scheme is taken from the request URI,
+    /// or [scheme](Self::scheme) if the URI doesn't have one; if neither is known, the port is
+    /// kept. Hosts which aren't ASCII are logged as they are.
+    ///
+    /// Disabled by default, so the raw header is logged.
+    pub fn normalize_host(mut self, enable: bool) -> Self {
+        self.normalize_host = enable;
+        self
+    }
+
+    /// If enabled, the served `Content-Type` captured for [negotiation](Self::negotiation) and the
+    /// request's `Content-Type` captured with [content_headers](Self::content_headers) are
+    /// lower-cased.
+    ///
+    /// Disabled by default.
+    pub fn normalize_content_type(mut self, enable: bool) -> Self {
+        self.normalize_content_type = enable;
+        self
+    }
+
+    /// If enabled, the request's `Content-Encoding` captured with
+    /// [content_headers](Self::content_headers) is lower-cased.
+    ///
+    /// Disabled by default.
+    pub fn normalize_content_encoding(mut self, enable: bool) -> Self {
+        self.normalize_content_encoding = enable;
+        self
+    }
+
+    /// The scheme requests are received with, for servers which know it, such as one listening
+    /// for HTTPS. Requests usually have no scheme in their URI, so this is used instead when
+    /// [normalizing the host](Self::normalize_host).
+    ///
+    /// Unknown by default.
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = Some(scheme);
+        self
+    }
+
+    /// The name of the response header which
+    /// [apply_duration_header](crate::LogRequest::apply_duration_header) sets to the time taken so
+    /// far, such as `x-server-duration`, for clients to correlate with the latency they observe.
+    ///
+    /// Disabled by default.
+    pub fn duration_header(mut self, name: HeaderName) -> Self {
+        self.duration_header = Some(name);
+        self
+    }
+
+    /// If enabled, requests which don't come with an `x-request-id` header are given a
+    /// [generated](crate::LogRequest::generate_request_id) ID, so every entry has one.
+    ///
+    /// Disabled by default, so only requests with the header, or whose ID is set by the caller,
+    /// have one.
+    pub fn generate_request_ids(mut self, enable: bool) -> Self {
+        self.generate_request_ids = enable;
+        self
+    }
+
+    /// The reverse proxies whose `X-Forwarded-For` or `Forwarded` headers are believed. For
+    /// requests from one of them, the real client found in the header is logged in place of the
+    /// remote address; for requests from anyone else, the header is marked `!untrusted`. See
+    /// [TrustedProxies].
+    ///
+    /// Unset by default, so the header is logged as it is, after the remote address, without
+    /// being interpreted.
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(proxies);
+        self
+    }
+
+    /// If enabled, a request's `X-Real-IP` header is logged as `real_ip_mismatch=...` when it
+    /// isn't one of the addresses in its `X-Forwarded-For` (or `Forwarded`) header, which usually
+    /// means one of them was set by someone other than the proxy. `X-Real-IP` is always used in
+    /// place of the forwarded chain when there isn't one.
+    ///
+    /// Disabled by default.
+    pub fn real_ip_mismatch(mut self, enable: bool) -> Self {
+        self.real_ip_mismatch = enable;
+        self
+    }
+
+    /// Mask client addresses before they are logged, for privacy: the remote address, the
+    /// addresses in `X-Forwarded-For` or `Forwarded`, and `X-Real-IP`. Entries in the forwarded
+    /// chain which aren't addresses are logged as `unknown`, since they could be anything. See
+    /// [IpAnonymization].
+    ///
+    /// The unmasked addresses are still used to find the real client behind
+    /// [trusted_proxies](Self::trusted_proxies), and
+    /// [LogRequest::forwarded_client](crate::LogRequest::forwarded_client) still returns the
+    /// unmasked address, for the application's own use.
+    ///
+    /// Disabled by default.
+    pub fn anonymize_ips(mut self, anonymization: IpAnonymization) -> Self {
+        self.anonymize_ips = anonymization;
+        self
+    }
+
+    /// Reduce the `Referer` to its origin, and with [Privacy::Coarse], the `User-Agent` to the
+    /// kind of client, before they are logged, for services which mustn't store them in full. See
+    /// [Privacy].
+    ///
+    /// [LogRequest::referer](crate::LogRequest::referer) and
+    /// [LogRequest::user_agent](crate::LogRequest::user_agent) still return the headers as they
+    /// were sent, for the application's own use.
+    ///
+    /// Disabled by default.
+    pub fn privacy(mut self, privacy: Privacy) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
+    /// Query parameters whose values are replaced with `[REDACTED]` wherever the request URI is
+    /// logged, such as `token` or `api_key`, so `/a?token=abc&b=1` is logged as
+    /// `/a?token=[REDACTED]&b=1`. Names are matched without regard to case, and every occurrence
+    /// of a repeated parameter is redacted. Each call adds to the list.
+    ///
+    /// Only the logged URI is changed: [LogEntry::uri](crate::LogEntry::uri) and
+    /// [LogEntry::query](crate::LogEntry::query) still return the URI as it was received.
+    ///
+    /// Empty by default.
+    pub fn redact_query_params<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.redact_query_params
+            .extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// If disabled, the query string is left out of the logged URI altogether, along with its
+    /// `?`, so `/search?q=secret` is logged as `/search`, and the W3C `cs-uri-query` field is
+    /// left empty. The scheme and authority of absolute-form URIs are still logged.
+    ///
+    /// Requests can override this with
+    /// [LogRequest::set_log_query](crate::LogRequest::set_log_query).
+    /// Unset by default, which uses the process default from
+    /// [set_default_log_query](crate::set_default_log_query), which is to log the query.
+    pub fn log_query(mut self, enable: bool) -> Self {
+        self.log_query = Some(enable);
+        self
+    }
+
+    /// Which of the optional fields, such as the user agent and referer, are logged.
+    ///
+    /// Requests can override this with
+    /// [LogRequest::set_field_set](crate::LogRequest::set_field_set). Unset by default, which
+    /// uses the process default from [set_default_field_set](crate::set_default_field_set),
+    /// which is to log all of them.
+    pub fn field_set(mut self, fields: FieldSet) -> Self {
+        self.field_set = Some(fields);
+        self
+    }
+
+    /// If enabled, absolute-form request targets, which clients send to proxies, are logged in
+    /// origin-form: `http://example.com/a?b` is logged as `/a?b`, as if it had been sent to the
+    /// origin server. The authority is still logged as the host when the request has no `Host`
+    /// header. Authority-form targets, which CONNECT requests use, and the asterisk-form `*` of
+    /// `OPTIONS *` have no path, so they're logged as they are.
+    ///
+    /// Disabled by default.
+    pub fn origin_form(mut self, enable: bool) -> Self {
+        self.origin_form = enable;
+        self
+    }
+
+    /// Capture a request header, beyond the `Host`, `User-Agent`, and `Referer` which always are,
+    /// and log it as `name=value`, such as `x-api-version=2`, or as `req.name=value` if the name is
+    /// already the key of another field, such as `host` or `traceparent`. A header sent more than
+    /// once is logged once, with its values joined by `, `, as they would be in a single header.
+    /// Each call adds to the list. In the text format, captured headers are in the `details` group
+    /// which [max_line_len](Self::max_line_len) can leave out; in the structured formats, each is a
+    /// key of its own.
+    ///
+    /// None by default.
+    pub fn capture_header(mut self, name: HeaderName) -> Self {
+        self.capture_headers.push(name);
+        self
+    }
+
+    /// Capture a response header in [set_response](crate::LogRequest::set_response), and log it
+    /// as `resp.name=value`, such as `resp.location=/login`, to see where a redirect pointed or
+    /// what `Content-Type` was served. It is logged like a header captured with
+    /// [capture_header](Self::capture_header), after those.
+    ///
+    /// None by default.
+    pub fn capture_response_header(mut self, name: HeaderName) -> Self {
+        self.capture_response_headers.push(name);
+        self
+    }
+
+    /// If enabled, the request's W3C `traceparent` header is captured, and its trace ID and the
+    /// caller's span ID are logged as `trace_id=4bf92f3577b34da6a3ce929d0e0e4736
+    /// span_id=00f067aa0ba902b7`, for matching entries up with distributed traces. A header which
+    /// isn't valid is logged as it is, escaped, as `traceparent=...`.
+    ///
+    /// Disabled by default.
+    pub fn trace_context(mut self, enable: bool) -> Self {
+        self.trace_context = enable;
+        self
+    }
+
+    /// If enabled, the request's W3C `tracestate` header is captured and logged as
+    /// `tracestate=...`. It can be up to several hundred bytes, so it is in the `details` group
+    /// which [max_line_len](Self::max_line_len) can leave out.
+    ///
+    /// Disabled by default.
+    pub fn trace_state(mut self, enable: bool) -> Self {
+        self.trace_state = enable;
+        self
+    }
+
+    /// Log an anonymous ID for each request as `fp=f7fe52887124fbba`, derived from the fields
+    /// chosen in the [Fingerprint]. See its documentation for the privacy tradeoffs.
+    ///
+    /// Disabled by default.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint(mut self, fingerprint: Fingerprint) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// Log some requests with all of their request and response headers, in a fenced block
+    /// around the usual entry, chosen by the given [DumpPolicy].
+    ///
+    /// Disabled by default.
+    pub fn dump(mut self, policy: DumpPolicy) -> Self {
+        self.dump = Some(policy);
+        self
+    }
+
+    /// The most `X-Forwarded-For` hops kept for an entry. Beyond this, the first hop and the rest
+    /// from the right are kept, and the hops in between are logged as a count:
+    /// `1.2.3.4,…(+4021),10.0.0.1`. The rightmost hops, which were added by the proxies nearest
+    /// to this server, are always kept intact. At least two hops are kept.
+    ///
+    /// Defaults to 16.
+    pub fn max_forwarded_hops(mut self, hops: usize) -> Self {
+        self.max_forwarded_hops = hops.max(2);
+        self
+    }
+
+    /// The most extra fields an entry can have. Fields added beyond this are dropped, and the
+    /// number dropped is logged as `extras_dropped=N` after the others.
+    ///
+    /// Defaults to 32.
+    pub fn max_extras(mut self, count: usize) -> Self {
+        self.max_extras = count;
+        self
+    }
+
+    /// The most bytes the extra fields of an entry can add up to, counting each as `key=value`
+    /// before escaping. Fields which would take an entry past this are dropped, like ones beyond
+    /// [max_extras](Self::max_extras).
+    ///
+    /// Defaults to 4096.
+    pub fn max_extras_bytes(mut self, bytes: usize) -> Self {
+        self.max_extras_bytes = bytes;
+        self
+    }
+
+    /// The most characters of each header value logged: the host, user agent, referer, and
+    /// captured headers. Longer values are cut short and followed by `…(+N)` for the N bytes left
+    /// out, so a client can't flood the log with huge headers. The text and pretty formats count
+    /// the characters after escaping, and the others, which escape values their own way, before.
+    ///
+    /// None by default.
+    pub fn max_header_len(mut self, len: usize) -> Self {
+        self.max_header_len = Some(len);
+        self
+    }
+
+    /// If enabled, every non-ASCII character in the host, user agent, referer, and captured
+    /// headers is escaped as `\u{...}`, such as `caf\u{e9}`, instead of being logged as UTF-8,
+    /// for log pipelines which only accept ASCII. Formats which escape values their own way, such
+    /// as [Format::Json](crate::Format::Json), escape the backslash of this escape in turn. The
+    /// `…` marking values cut short and left out forwarded hops is logged as `\u{2026}` too.
+    ///
+    /// Disabled by default.
+    pub fn ascii_headers(mut self, enable: bool) -> Self {
+        self.ascii_headers = enable;
+        self
+    }
+
+    /// The longest a line of the text format should be, not counting the newline. Longer lines
+    /// are rendered again without some of their fields, dropping groups of the least important
+    /// ones first until the line fits:
+    ///
+    /// 1. `extras`: the extra fields.
+    /// 2. `ua`: the user agent.
+    /// 3. `referer`: the referer.
+    /// 4. `details`: the diagnostic fields, such as `mw=`, `hdr_in=`, `conn=`, `setup=`, `tls=`,
+    ///    `geo=`, and `remote_host=`.
+    /// 5. `fwd`: the forwarded-for address.
+    /// 6. `host`: the host.
+    ///
+    /// The groups left out are listed at the end of the line, as `elided=extras,ua`, and the user
+    /// agent, referer, and host columns are `-` rather than being removed. The action, status,
+    /// user, remote address, method, URI, version, duration, and the fields which explain a
+    /// failure (`err_kind=`, `late=`, `over=`) are never left out, so lines can still be longer
+    /// than this.
+    ///
+    /// There is no limit by default.
+    pub fn max_line_len(mut self, len: usize) -> Self {
+        self.max_line_len = Some(len);
+        self
+    }
+
+    /// Where entries are written when they aren't written explicitly: when the request is
+    /// dropped or [reset](crate::LogRequest::reset), when a counted response body ends, and when
+    /// the watchdog finds a request stuck. Entries are still written to the extra sinks as well.
+    ///
+    /// Defaults to the sink set with [set_default_sink](crate::set_default_sink), or stderr if
+    /// there isn't one.
+    pub fn sink(mut self, sink: Arc<dyn LogSink>) -> Self {
+        self.sink = Some(ConfigSink(sink));
+        self
+    }
+
+    /// Where metrics about each request are recorded, when it is dropped, for requests which
+    /// don't set their own with
+    /// [LogRequest::set_metrics_sink](crate::LogRequest::set_metrics_sink).
+    ///
+    /// Defaults to the metrics sink set with
+    /// [set_default_metrics_sink](crate::set_default_metrics_sink), if there is one.
+    pub fn metrics_sink(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics_sink = Some(ConfigMetricsSink(sink));
+        self
+    }
+
+    /// The format entries are rendered in.
+    ///
+    /// Defaults to the formatter set with
+    /// [set_default_formatter](crate::set_default_formatter), or [Format::Text] if there isn't
+    /// one.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// How the keys of the structured formats are named: the crate's own names, or those of the
+    /// OpenTelemetry HTTP semantic conventions, for collectors which expect them.
+    ///
+    /// Defaults to [FieldNames::Native].
+    pub fn field_names(mut self, names: FieldNames) -> Self {
+        self.field_names = names;
+        self
+    }
+
+    /// A formatter of the application's own to render entries with, in place of the
+    /// [format](Self::format).
+    ///
+    /// None by default.
+    pub fn formatter(mut self, formatter: Arc<dyn LogFormatter>) -> Self {
+        self.formatter = Some(ConfigFormatter(formatter));
+        self
+    }
+
+    /// The timezone wall-clock timestamps are rendered in, by [TimestampStyle::Clf], the
+    /// [Combined](Format::Combined) format, and [LogEntry::timestamp](crate::LogEntry::timestamp).
+    ///
+    /// Defaults to [Timezone::Utc].
+    pub fn timezone(mut self, timezone: Timezone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// How the time each request started is shown, unless the request sets its own with
+    /// [LogRequest::set_timestamp_style](crate::LogRequest::set_timestamp_style).
+    ///
+    /// Defaults to the one set with
+    /// [set_default_timestamp_style](crate::set_default_timestamp_style), or
+    /// [TimestampStyle::Rfc3339Utc].
+    pub fn timestamp_style(mut self, style: TimestampStyle) -> Self {
+        self.timestamp_style = Some(style);
+        self
+    }
+
+    /// How durations, such as how long each request took, are shown in the text and pretty
+    /// formats.
+    ///
+    /// Defaults to [DurationStyle::Millis].
+    pub fn duration_style(mut self, style: DurationStyle) -> Self {
+        self.duration_style = style;
+        self
+    }
+
+    /// How the status is shown in the bracketed prefix of the text format.
+    ///
+    /// Defaults to [StatusStyle::Numeric].
+    pub fn status_style(mut self, style: StatusStyle) -> Self {
+        self.status_style = style;
+        self
+    }
+
+    /// Show the canonical reason phrase after the status code, as in `[get:404 Not Found]`, for
+    /// people reading the log. Codes without one are shown as just the number. Structured
+    /// formats add it as `status_text`.
+    ///
+    /// Disabled by default, since it puts a space inside the brackets.
+    pub fn status_reason(mut self, enable: bool) -> Self {
+        self.status_reason = enable;
+        self
+    }
+
+    /// What the text format shows in place of fields which weren't captured.
+    ///
+    /// Defaults to [Placeholder::Verbose].
+    pub fn placeholder(mut self, placeholder: Placeholder) -> Self {
+        self.placeholder = placeholder;
+        self
+    }
+
+    /// Per-route overrides, chosen by the request's path or a predicate when it is created.
+    ///
+    /// There are no routes by default.
+    pub fn routes(mut self, routes: RouteRules) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Add a way of identifying the user to the end of the identity chain. When a request is
+    /// created, the sources are tried in the order they were added, and the first one which
+    /// identifies the user sets it, as if by [set_user](crate::LogRequest::set_user).
+    ///
+    /// The chain is empty by default.
+    #[cfg(feature = "identity")]
+    pub fn identity(mut self, source: IdentitySource) -> Self {
+        self.identity.push(source);
+        self
+    }
+
+    /// Take the client's identity from a header added by a trusted proxy, instead of from
+    /// `X-Forwarded-For` and the identity chain. See [TrustedIdentityHeader].
+    ///
+    /// Disabled by default.
+    #[cfg(feature = "identity")]
+    pub fn trusted_identity_header(mut self, header: TrustedIdentityHeader) -> Self {
+        self.trusted_identity = Some(header);
+        self
+    }
+
+    /// Write a provisional entry for any request which hasn't been logged or discarded within the
+    /// given age, such as one held by a leaked task. It has what was captured when the request was
+    /// created, plus its remote address and sinks, and is tagged `[!stuck]` in place of the
+    /// status. If the request is logged after all, its entry has `late=+time` for how long after
+    /// the provisional one it came.
+    ///
+    /// Requests are tracked by a single background thread, started the first time one is created
+    /// with this set. Disabled by default.
+    #[cfg(feature = "watchdog")]
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Log requests which sampling dropped anyway if they end with a status for which the given
+    /// function returns true, such as `|status| status >= 400` to keep every error however few
+    /// successes are kept. Their `sample_rate` is then 1. They have already been reported as
+    /// dropped if [apply_sampling_header](crate::LogRequest::apply_sampling_header) was used.
+    ///
+    /// None by default.
+    pub fn keep_if_status(mut self, keep: fn(u16) -> bool) -> Self {
+        self.keep_if_status = Some(keep);
+        self
+    }
+
+    /// Only log requests which match the given [LogPolicy], such as errors and slow requests.
+    /// A request's own [set_policy](crate::LogRequest::set_policy) takes precedence.
+    ///
+    /// None by default, so every request is logged.
+    pub fn policy(mut self, policy: LogPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Flag requests which take longer than the given time, so they stand out when tailing the
+    /// log: the text format ends their lines with `SLOW>500.000`, giving the threshold, and the
+    /// structured formats add `slow` and `slow_threshold_ms`. A request's own
+    /// [set_slow_threshold](crate::LogRequest::set_slow_threshold) takes precedence.
+    ///
+    /// None by default.
+    pub fn slow_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_threshold = Some(threshold);
+        self
+    }
+
+    /// Make [build](Self::build) fail if [validate](Self::validate) finds any errors.
+    ///
+    /// Disabled by default.
+    pub fn strict(mut self, enable: bool) -> Self {
+        self.strict = enable;
+        self
+    }
+
+    /// Check the configuration, including the configurations of its routes, for options which
+    /// are set but can't work as intended, such as a sampling ratio above 1 or a route which can
+    /// never match. Every error found is returned, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = vec![];
+        self.check("", &mut errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    pub(crate) fn check(&self, path: &str, errors: &mut Vec<ConfigError>) {
+        if let Some(dump) = &self.dump {
+            dump.check(&format!("{path}dump"), errors);
+        }
+        if self.duration_header.as_ref().map(HeaderName::as_str) == Some(SamplingDecision::HEADER) {
+            errors.push(ConfigError::ReservedHeader {
+                option: format!("{path}duration_header"),
+                name: SamplingDecision::HEADER.to_owned(),
+            });
+        }
+        #[cfg(feature = "watchdog")]
+        if self.max_age == Some(Duration::ZERO) {
+            errors.push(ConfigError::ZeroDuration {
+                option: format!("{path}max_age"),
+            });
+        }
+        self.routes.check(&format!("{path}routes"), errors);
+    }
+
+    /// Finish the configuration, ready to be shared between requests.
+    ///
+    /// In [strict](Self::strict) mode, this returns the errors [validate](Self::validate) finds
+    /// instead, so that a bad configuration can stop a server from starting. Otherwise it always
+    /// succeeds, and options with errors keep their lenient behavior: ratios outside 0 to 1 act
+    /// as the nearest end of the range, and routes which can't match are ignored.
+    pub fn build(self) -> Result<Arc<Self>, Vec<ConfigError>> {
+        if self.strict {
+            self.validate()?;
+        }
+        Ok(Arc::new(self))
+    }
+}
+
+/// A problem with a [LogConfig], found by [LogConfig::validate].
+///
+/// Each error names the option it is about as a path from the top-level configuration, such as
+/// `dump.ratio` or `routes["/api"].config.max_age`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// A ratio isn't between 0 and 1.
+    Ratio {
+        /// The option.
+        option: String,
+        /// The ratio it was given.
+        value: f64,
+    },
+    /// A route prefix doesn't start with `/`, so it never matches a request.
+    RoutePrefix {
+        /// The option.
+        option: String,
+        /// The prefix.
+        prefix: String,
+    },
+    /// The same route prefix was added more than once, so only the last one is ever used.
+    DuplicateRoute {
+        /// The option.
+        option: String,
+        /// The prefix.
+        prefix: String,
+    },
+    /// A duration which has to be more than zero is zero.
+    ZeroDuration {
+        /// The option.
+        option: String,
+    },
+    /// A header name is one the crate already uses for something else.
+    ReservedHeader {
+        /// The option.
+        option: String,
+        /// The header name.
+        name: String,
+    },
+}
+
+impl ConfigError {
+    /// The option the error is about, such as `routes["/api"].sample`.
+    pub fn option(&self) -> &str {
+        match self {
+            Self::Ratio { option, .. }
+            | Self::RoutePrefix { option, .. }
+            | Self::DuplicateRoute { option, .. }
+            | Self::ZeroDuration { option }
+            | Self::ReservedHeader { option, .. } => option,
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.option())?;
+        match self {
+            Self::Ratio { value, .. } => write!(f, "ratio {value} is not between 0 and 1"),
+            Self::RoutePrefix { prefix, .. } => {
+                write!(
+                    f,
+                    "prefix {prefix:?} doesn't start with '/', so it never matches"
+                )
+            }
+            Self::DuplicateRoute { prefix, .. } => write!(
+                f,
+                "prefix {prefix:?} is added more than once, so only the last one is used"
+            ),
+            Self::ZeroDuration { .. } => f.write_str("must be more than zero"),
+            Self::ReservedHeader { name, .. } => {
+                write!(
+                    f,
+                    "header {name} is already used by LogRequest::apply_sampling_header"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How the status is shown in the bracketed prefix of the text format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatusStyle {
+    /// The numeric status code: `[get:404]`.
+    #[default]
+    Numeric,
+    /// The [Outcome](crate::Outcome) instead of the status code: `[get:client-error]`. The
+    /// numeric status is added to the end of the line as `status=404`.
+    Outcome,
+    /// The status code followed by the outcome: `[get:404:client-error]`.
+    Both,
+}
+
+/// What the text format shows in place of a missing status, user, remote address, host, user
+/// agent, or referer. Placeholders which explain why there's no status, like `!cancelled`, are
+/// always shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Placeholder {
+    /// A placeholder for each field which says what's missing: `???` for the status,
+    /// `<unknown-remote>` for the remote address, and `""` for the host, user agent, and referer.
+    /// A missing user is left out.
+    #[default]
+    Verbose,
+    /// `-` for every missing field, so that every line has the same number of columns.
+    Dash,
+    /// Leave out missing fields.
+    Omit,
+}
+
+/// The configuration used by requests which weren't given one explicitly.
+pub(crate) fn default_config() -> Arc<LogConfig> {
+    static DEFAULT: OnceLock<Arc<LogConfig>> = OnceLock::new();
+    DEFAULT.get_or_init(Default::default).clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::route::Route;
+    use crate::sample::SamplePolicy;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(LogConfig::new().validate(), Ok(()));
+
+        let bad = LogConfig::new()
+            .dump(DumpPolicy::new().ratio(1.5))
+            .duration_header(HeaderName::from_static("x-log-sampled"))
+            .routes(
+                RouteRules::new()
+                    .prefix("api", Route::new())
+                    .prefix("/static", Route::new().sample(SamplePolicy::Ratio(-0.1)))
+                    .prefix("/static/", Route::new())
+                    .prefix(
+                        "/upload",
+                        Route::new().config(Arc::new(
+                            LogConfig::new().dump(DumpPolicy::new().ratio(f64::NAN)),
+                        )),
+                    )
+                    .when(|_| false, Route::new().sample(SamplePolicy::Ratio(2.))),
+            );
+        let errors = bad.validate().unwrap_err();
+        let messages = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "dump.ratio: ratio 1.5 is not between 0 and 1",
+                "duration_header: header x-log-sampled is already used by \
+                 LogRequest::apply_sampling_header",
+                "routes[\"api\"]: prefix \"api\" doesn't start with '/', so it never matches",
+                "routes[\"/static\"]: prefix \"/static\" is added more than once, so only the last \
+                 one is used",
+                "routes[\"/static\"].sample: ratio -0.1 is not between 0 and 1",
+                "routes[\"/upload\"].config.dump.ratio: ratio NaN is not between 0 and 1",
+                "routes[when #0].sample: ratio 2 is not between 0 and 1",
+            ]
+        );
+        assert_eq!(
+            errors[2],
+            ConfigError::RoutePrefix {
+                option: "routes[\"api\"]".to_owned(),
+                prefix: "api".to_owned(),
+            }
+        );
+
+        // Lenient unless strict.
+        assert!(bad.clone().build().is_ok());
+        assert_eq!(bad.strict(true).build().unwrap_err().len(), 7);
+        assert!(LogConfig::new().strict(true).build().is_ok());
+    }
+
+    #[cfg(feature = "watchdog")]
+    #[test]
+    fn test_zero_duration() {
+        let errors = LogConfig::new()
+            .max_age(Duration::ZERO)
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            [ConfigError::ZeroDuration {
+                option: "max_age".to_owned()
+            }]
+        );
+        assert_eq!(errors[0].to_string(), "max_age: must be more than zero");
+    }
+}
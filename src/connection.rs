@@ -0,0 +1,513 @@
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+use http::{Request, Response};
+use http_body::Body;
+use hyper::rt::{Read, ReadBuf, ReadBufCursor};
+
+use crate::anonymize::IpAnonymization;
+use crate::body::CountingBody;
+use crate::display::LogDisplay;
+use crate::entry::{ConnSeq, Setup};
+use crate::escaped::Escaped;
+use crate::format::Remote;
+use crate::logged::{logged_with, LoggedFuture};
+use crate::request::LogRequest;
+use crate::request_id;
+use crate::sink::{LogSink, StderrSink};
+use crate::tls::TlsConnectionInfo;
+use crate::write_errors;
+
+/// [ConnectionLog] tracks an accepted connection, so that connections which never produce a
+/// complete request (because they timed out or were closed while the request headers were still
+/// being sent) still show up in the log.
+///
+/// Create one per accepted connection, wrap the connection's IO with [io](Self::io) so received
+/// bytes can be counted, create each request's [LogRequest] with [request](Self::request), and
+/// pass the result of serving the connection to [finish](Self::finish):
+///
+/// ```no_run
+/// # use hyper_req_log::{ConnectionLog, LogRequest};
+/// # async fn serve(stream: tokio::net::TcpStream, remote: std::net::SocketAddr) {
+/// let conn = ConnectionLog::new(remote);
+/// let io = conn.io(hyper_util::rt::TokioIo::new(stream));
+/// let conn2 = conn.clone();
+/// let result = hyper::server::conn::http1::Builder::new()
+///     .serve_connection(io, hyper::service::service_fn(move |req| {
+///         let mut log: LogRequest<&str> = conn2.request(&req);
+///         async move {
+///             let resp = http::Response::new(String::new());
+///             log.set_response(&resp);
+///             Ok::<_, std::convert::Infallible>(resp)
+///         }
+///     }))
+///     .await;
+/// conn.finish(&result);
+/// # }
+/// ```
+///
+/// Each request is logged with `conn=ID#N`: the connection's [id](Self::id), and which request
+/// on the connection it was, counting from 1. This shows which requests shared a keep-alive or
+/// HTTP/2 connection, and in what order they arrived.
+///
+/// Incomplete connections are logged as a line like:
+///
+/// `connection: [incomplete] remote bytes_in=N requests=N elapsed conn=ID err=error`
+///
+/// where `err` is only present if serving the connection failed. Connections which are closed
+/// without sending anything are not logged.
+#[derive(Clone)]
+pub struct ConnectionLog {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    remote: SocketAddr,
+    id: OnceLock<Arc<str>>,
+    accepted: Instant,
+    handshake: OnceLock<Instant>,
+    tls: OnceLock<Arc<TlsConnectionInfo>>,
+    anonymize_ips: OnceLock<IpAnonymization>,
+    bytes_in: AtomicU64,
+    requests: AtomicU64,
+    finished: AtomicBool,
+    sink: Arc<dyn LogSink>,
+}
+
+impl ConnectionLog {
+    /// Start tracking a connection from the given remote address. Incomplete connections are
+    /// logged to stderr.
+    pub fn new(remote: SocketAddr) -> Self {
+        Self::with_sink(remote, Arc::new(StderrSink))
+    }
+
+    /// Start tracking a connection from the given remote address. Incomplete connections are
+    /// logged to the given sink.
+    pub fn with_sink(remote: SocketAddr, sink: Arc<dyn LogSink>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                remote,
+                id: OnceLock::new(),
+                accepted: Instant::now(),
+                handshake: OnceLock::new(),
+                tls: OnceLock::new(),
+                anonymize_ips: OnceLock::new(),
+                bytes_in: AtomicU64::new(0),
+                requests: AtomicU64::new(0),
+                finished: AtomicBool::new(false),
+                sink,
+            }),
+        }
+    }
+
+    /// The address of the remote end of the connection.
+    pub fn remote(&self) -> SocketAddr {
+        self.shared.remote
+    }
+
+    /// Use the given ID for the connection, such as one the application already logs it by,
+    /// rather than generating one. Only the first call has any effect, and only before the ID
+    /// is first used.
+    pub fn with_id(self, id: impl Into<String>) -> Self {
+        let _ = self.shared.id.set(id.into().into());
+        self
+    }
+
+    /// Mask the remote address in the line logged for an incomplete connection, as
+    /// [LogConfig::anonymize_ips](crate::LogConfig::anonymize_ips) does for requests. The
+    /// connection's requests are masked by their own config, so this should usually be the same
+    /// setting. Only the first call has any effect.
+    pub fn with_ip_anonymization(self, anonymization: IpAnonymization) -> Self {
+        let _ = self.shared.anonymize_ips.set(anonymization);
+        self
+    }
+
+    /// The connection's ID. Unless one was given with [with_id](Self::with_id), it is 64 random
+    /// bits, as 16 lowercase hex digits.
+    pub fn id(&self) -> &str {
+        self.shared.id()
+    }
+
+    /// The number of bytes received on the connection so far. This is only counted if the
+    /// connection's IO is wrapped with [io](Self::io).
+    pub fn bytes_in(&self) -> u64 {
+        self.shared.bytes_in.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests started on the connection so far.
+    pub fn requests(&self) -> u64 {
+        self.shared.requests.load(Ordering::Relaxed)
+    }
+
+    /// Record that the connection's setup (such as a TLS handshake) has
+    /// finished. Only the first call has any effect.
+    pub fn handshake_complete(&self) {
+        let _ = self.shared.handshake.set(Instant::now());
+    }
+
+    /// Record the connection's TLS session, once the handshake has finished, so that every
+    /// request on the connection is logged with it as if by
+    /// [LogRequest::set_tls](crate::LogRequest::set_tls). If it has a client certificate common
+    /// name, that is given to each request with
+    /// [set_client_cert_cn](crate::LogRequest::set_client_cert_cn) too.
+    ///
+    /// This also calls [handshake_complete](Self::handshake_complete). Only the first call has
+    /// any effect.
+    pub fn with_tls(self, info: TlsConnectionInfo) -> Self {
+        self.handshake_complete();
+        let _ = self.shared.tls.set(Arc::new(info));
+        self
+    }
+
+    /// Wrap the connection's IO so the bytes received on it are counted.
+    pub fn io<T>(&self, io: T) -> CountingIo<T> {
+        CountingIo {
+            inner: io,
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Create a [LogRequest] for a request received on this connection, with the remote address
+    /// already set, and the connection's ID and the request's position on it to be logged as
+    /// `conn=ID#N`.
+    ///
+    /// The first request on the connection is logged with `setup=elapsed`, the time from when
+    /// the connection was accepted until [handshake_complete](Self::handshake_complete) was
+    /// called, or until this request if it wasn't. Later requests are logged with
+    /// `setup=reused`.
+    pub fn request<A: LogDisplay, B>(&self, req: &Request<B>) -> LogRequest<A> {
+        let seq = self.shared.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut log = LogRequest::from_request(req);
+        log.set_remote(self.shared.remote);
+        log.set_conn(ConnSeq {
+            id: self.shared.id().clone(),
+            seq,
+        });
+        if seq == 1 {
+            let ready = self
+                .shared
+                .handshake
+                .get()
+                .copied()
+                .unwrap_or_else(Instant::now);
+            log.set_setup(Setup::New(ready.duration_since(self.shared.accepted)));
+        } else {
+            log.set_setup(Setup::Reused);
+        }
+        if let Some(tls) = self.shared.tls.get() {
+            #[cfg(feature = "identity")]
+            if let Some(cn) = &tls.client_cert_cn {
+                log.set_client_cert_cn(cn.clone());
+            }
+            log.set_tls(tls.clone());
+        }
+        log
+    }
+
+    /// Wrap a handler so that each request it handles is logged, for use with hyper's
+    /// `service_fn`, as [logged_service](crate::logged_service) does, but with each
+    /// [LogRequest] created by [request](Self::request).
+    pub fn logged_service<A, B, R, E, F>(
+        &self,
+        handler: F,
+    ) -> impl Fn(Request<B>) -> LoggedFuture<'static, Result<Response<CountingBody<R>>, E>> + Clone
+    where
+        A: LogDisplay + Send + 'static,
+        B: Send + 'static,
+        R: Body + Send + 'static,
+        E: Send + 'static,
+        F: for<'a> Fn(
+                Request<B>,
+                &'a mut LogRequest<A>,
+            ) -> LoggedFuture<'a, Result<Response<R>, E>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let conn = self.clone();
+        logged_with(move |req| conn.request(req), handler)
+    }
+
+    /// Record that the connection has ended, with the result of serving it.
+    ///
+    /// The connection is logged as incomplete if serving it failed to parse a request, timed
+    /// out, or was closed in the middle of a message, or if data was received but no request
+    /// was ever started. If this isn't called, the last clone of the [ConnectionLog] does the
+    /// same check, without an error, when it is dropped.
+    pub fn finish(&self, result: &Result<(), hyper::Error>) {
+        if self.shared.finished.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let err = result.as_ref().err().filter(|e| {
+            e.is_parse() || e.is_parse_status() || e.is_timeout() || e.is_incomplete_message()
+        });
+        self.shared.log_incomplete(err);
+    }
+}
+
+/// A new connection ID: 64 random bits, as 16 lowercase hex digits. Connection IDs only need to
+/// tell apart connections which are open around the same time, so they're half the length of a
+/// [request ID](request_id::generate).
+fn new_id() -> Arc<str> {
+    request_id::generate()[..16].into()
+}
+
+impl Shared {
+    fn id(&self) -> &Arc<str> {
+        self.id.get_or_init(new_id)
+    }
+
+    fn log_incomplete(&self, err: Option<&hyper::Error>) {
+        let bytes_in = self.bytes_in.load(Ordering::Relaxed);
+        let requests = self.requests.load(Ordering::Relaxed);
+        if err.is_none() && (requests != 0 || bytes_in == 0) {
+            return;
+        }
+        let anonymize_ips = self.anonymize_ips.get().copied().unwrap_or_default();
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "connection: [incomplete] {} bytes_in={bytes_in} requests={requests} {:?}",
+            Remote(anonymize_ips.addr(self.remote)),
+            self.accepted.elapsed(),
+        );
+        let _ = write!(line, " conn={}", Escaped::from(&**self.id()));
+        if let Some(err) = err {
+            let _ = write!(line, " err={}", Escaped::from(&err.to_string()));
+        }
+        line.push('\n');
+        if let Err(err) = self.sink.write_line(&line) {
+            write_errors::report(&err);
+        }
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if !*self.finished.get_mut() {
+            self.log_incomplete(None);
+        }
+    }
+}
+
+/// IO wrapper returned by [ConnectionLog::io], which counts the bytes read from the connection.
+pub struct CountingIo<T> {
+    inner: T,
+    shared: Arc<Shared>,
+}
+
+impl<T: Read + Unpin> Read for CountingIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        // SAFETY: the inner reader only writes initialized bytes into the buffer, and `filled`
+        // reports exactly how many it wrote, so advancing the cursor by that much is sound.
+        let n = unsafe {
+            let mut tmp = ReadBuf::uninit(buf.as_mut());
+            ready!(Pin::new(&mut self.inner).poll_read(cx, tmp.unfilled()))?;
+            tmp.filled().len()
+        };
+        // SAFETY: see above.
+        unsafe { buf.advance(n) };
+        self.shared.bytes_in.fetch_add(n as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: hyper::rt::Write + Unpin> hyper::rt::Write for CountingIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use hyper_util::rt::{TokioIo, TokioTimer};
+    use tokio::io::AsyncWriteExt;
+
+    async fn serve(client_sends: &[u8]) -> String {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let (client, server) = tokio::io::duplex(1024);
+        let conn = ConnectionLog::with_sink("10.0.0.1:5555".parse().unwrap(), out.clone());
+        let conn2 = conn.clone();
+        let server = tokio::spawn(async move {
+            let result = hyper::server::conn::http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(Duration::from_millis(50))
+                .serve_connection(
+                    conn2.io(TokioIo::new(server)),
+                    hyper::service::service_fn(move |req| {
+                        let log: LogRequest<&str> = conn2.request(&req);
+                        log.discard();
+                        async { Ok::<_, Infallible>(http::Response::new(String::new())) }
+                    }),
+                )
+                .await;
+            conn.finish(&result);
+        });
+
+        let mut client = client;
+        client.write_all(client_sends).await.unwrap();
+        server.await.unwrap();
+        let out = out.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_header_timeout() {
+        let line = serve(b"GET / HTTP/1.1\r\nHost: exa").await;
+        assert!(
+            line.starts_with("connection: [incomplete] 10.0.0.1:5555 bytes_in=25 requests=0 "),
+            "{line}"
+        );
+        assert!(line.contains(" err="), "{line}");
+    }
+
+    #[tokio::test]
+    async fn test_complete() {
+        let line = serve(b"GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n").await;
+        assert_eq!(line, "");
+    }
+
+    #[test]
+    fn test_setup() {
+        let conn = ConnectionLog::new("10.0.0.1:5555".parse().unwrap());
+        std::thread::sleep(Duration::from_millis(5));
+        conn.handshake_complete();
+        std::thread::sleep(Duration::from_millis(200));
+        conn.handshake_complete();
+
+        let req = Request::new(());
+        let entries = (0..2)
+            .map(|_| {
+                let log: LogRequest<&str> = conn.request(&req);
+                let entry = log.entry();
+                log.discard();
+                entry
+            })
+            .collect::<Vec<_>>();
+        let (first, second) = (&entries[0], &entries[1]);
+        let setup = first.connection_setup().unwrap();
+        assert!(setup >= Duration::from_millis(5), "{setup:?}");
+        assert!(setup < Duration::from_millis(200), "{setup:?}");
+        assert!(first.to_string().contains(&format!(" setup={setup:?}")));
+        assert_eq!(second.connection_setup(), None);
+        assert!(second.to_string().ends_with(" setup=reused\n"));
+        conn.finish(&Ok(()));
+    }
+
+    #[test]
+    fn test_conn_seq() {
+        let conn = ConnectionLog::new("10.0.0.1:5555".parse().unwrap());
+        let id = conn.id().to_owned();
+        assert_eq!(id.len(), 16);
+        assert!(id.bytes().all(|b| b.is_ascii_hexdigit()), "{id}");
+        assert_ne!(ConnectionLog::new(conn.remote()).id(), id);
+
+        let conn2 = conn.clone();
+        let req = Request::new(());
+        for seq in 1..=3 {
+            let log: LogRequest<&str> = match seq % 2 {
+                0 => conn.request(&req),
+                _ => conn2.request(&req),
+            };
+            let entry = log.entry();
+            log.discard();
+            assert_eq!(entry.connection_id(), Some(&*id));
+            assert_eq!(entry.connection_seq(), Some(seq));
+            assert_eq!(entry.remote(), Some(conn.remote()));
+            let line = entry.to_string();
+            assert!(line.contains(&format!(" conn={id}#{seq} setup=")), "{line}");
+            let mut json = String::new();
+            crate::format::write_entry(&entry, crate::Format::Json, &mut json).unwrap();
+            assert!(
+                json.contains(&format!(r#""conn_id":"{id}","conn_seq":{seq},"#)),
+                "{json}"
+            );
+        }
+        assert_eq!(conn.requests(), 3);
+        conn.finish(&Ok(()));
+
+        let conn = ConnectionLog::new("10.0.0.1:5555".parse().unwrap()).with_id("lb-7 a");
+        let log: LogRequest<&str> = conn.request(&Request::new(()));
+        assert!(log.to_string().contains(r##" conn="lb-7 a"#1 "##));
+        log.discard();
+        let conn = conn.with_id("ignored");
+        assert_eq!(conn.id(), "lb-7 a");
+        conn.finish(&Ok(()));
+
+        let req = Request::new(());
+        let log = LogRequest::<&str>::from_request(&req);
+        assert_eq!(log.entry().connection_seq(), None);
+        assert!(!log.to_string().contains("conn="));
+        log.discard();
+    }
+
+    #[test]
+    fn test_drop() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let conn = ConnectionLog::with_sink("[::1]:5555".parse().unwrap(), out.clone());
+        conn.shared.bytes_in.store(3, Ordering::Relaxed);
+        drop(conn);
+        let line = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(
+            line.starts_with("connection: [incomplete] ::1:5555 bytes_in=3 requests=0 "),
+            "{line}"
+        );
+        assert!(!line.contains("err="), "{line}");
+        assert!(line.contains(" conn="), "{line}");
+    }
+
+    #[test]
+    fn test_ip_anonymization() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let conn = ConnectionLog::with_sink("203.0.113.7:5555".parse().unwrap(), out.clone())
+            .with_ip_anonymization(IpAnonymization::Mask)
+            .with_ip_anonymization(IpAnonymization::Off);
+        conn.shared.bytes_in.store(3, Ordering::Relaxed);
+        assert_eq!(conn.remote(), "203.0.113.7:5555".parse().unwrap());
+        drop(conn);
+        let line = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(
+            line.starts_with("connection: [incomplete] 203.0.113.0:5555 bytes_in=3 "),
+            "{line}"
+        );
+    }
+}
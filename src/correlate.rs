@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::entry::LogEntry;
+use crate::escaped::Escaped;
+use crate::sink::LogSink;
+use crate::time::{StyledDuration, Timestamp};
+
+type KeyFn = dyn Fn(&LogEntry) -> Option<String> + Send + Sync;
+
+/// [CorrelatedSink] is a sink which combines entries for the same operation, such as the attempts
+/// of a request which is retried internally, into one record.
+///
+/// Entries are grouped by a key, which by default is their [request ID](LogEntry::request_id), or
+/// failing that the value of their `request_id` extra field; it can be changed with
+/// [key](Self::key). Entries without a key are passed through unchanged. A group is written once
+/// the window (5 seconds by default) has passed since its first entry, or sooner if it is the
+/// oldest of more than [max_groups](Self::max_groups) open groups, as a line like:
+///
+/// `request-group: [get:200] id=abc123 attempts=2 first=2023-11-14T22:13:20.000Z last=2023-11-14T22:13:20.250Z durations=12.000,30.000`
+///
+/// where the action and status are those of the last attempt, `first` and `last` are when the
+/// first and last attempts started, and `durations` lists how long each attempt took, in order.
+///
+/// Groups are only checked for expiry when an entry is written, so a group can be held for longer
+/// than the window if nothing else is logged; [flush](Self::flush) writes every group straight
+/// away. The individual entries are only written if [keep_individual](Self::keep_individual) is
+/// enabled. Lines which aren't request entries are passed through unchanged.
+pub struct CorrelatedSink {
+    inner: Arc<dyn LogSink>,
+    key: Box<KeyFn>,
+    window: Duration,
+    keep_individual: bool,
+    flush_on_drop: bool,
+    max_groups: usize,
+    groups: Mutex<HashMap<String, Group>>,
+}
+
+struct Group {
+    opened: Instant,
+    first: Timestamp,
+    last: LogEntry,
+    durations: Vec<Duration>,
+}
+
+impl CorrelatedSink {
+    /// Create a new correlating sink, which writes to the given sink.
+    pub fn new(inner: Arc<dyn LogSink>) -> Self {
+        Self {
+            inner,
+            key: Box::new(|entry| {
+                entry
+                    .request_id()
+                    .or_else(|| entry.extra("request_id"))
+                    .map(str::to_owned)
+            }),
+            window: Duration::from_secs(5),
+            keep_individual: false,
+            flush_on_drop: true,
+            max_groups: 10_000,
+            groups: Mutex::default(),
+        }
+    }
+
+    /// Use the given function to compute the key entries are grouped by. Entries it returns
+    /// `None` for are passed through.
+    pub fn key(
+        mut self,
+        key: impl Fn(&LogEntry) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.key = Box::new(key);
+        self
+    }
+
+    /// How long after its first entry a group is written.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// If enabled, each entry is also written on its own when it arrives, as well as in its
+    /// group's record.
+    ///
+    /// Disabled by default.
+    pub fn keep_individual(mut self, enable: bool) -> Self {
+        self.keep_individual = enable;
+        self
+    }
+
+    /// Whether groups which are still open are written when the sink is dropped. If disabled,
+    /// they are discarded.
+    ///
+    /// Enabled by default.
+    pub fn flush_on_drop(mut self, enable: bool) -> Self {
+        self.flush_on_drop = enable;
+        self
+    }
+
+    /// The most groups which can be open at once. When an entry would open another, the oldest
+    /// group is written straight away to make room, so a flood of distinct keys can't hold an
+    /// unbounded number of entries in memory.
+    ///
+    /// Defaults to 10,000.
+    pub fn max_groups(mut self, max_groups: usize) -> Self {
+        self.max_groups = max_groups.max(1);
+        self
+    }
+
+    /// Write out every group which is currently open.
+    pub fn flush(&self) -> io::Result<()> {
+        let groups = std::mem::take(&mut *self.lock());
+        self.write_groups(groups.into_iter().collect())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Group>> {
+        self.groups.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_groups(&self, mut groups: Vec<(String, Group)>) -> io::Result<()> {
+        groups.sort_by_key(|(_, group)| group.opened);
+        let mut result = Ok(());
+        for (id, group) in groups {
+            if let Err(e) = self.inner.write_line(&group.record(&id)) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+impl Group {
+    fn record(&self, id: &str) -> String {
+        let mut line = String::from("request-group: [");
+        if let Some(action) = &self.last.action {
+            write!(line, "{}:", Escaped::from(action)).unwrap();
+        }
+        match self.last.status {
+            Some(status) => write!(line, "{status}").unwrap(),
+            None => line.push_str("???"),
+        }
+        write!(
+            line,
+            "] id={} attempts={} first={} last={} durations=",
+            Escaped::from(id),
+            self.durations.len(),
+            self.first,
+            self.last.timestamp(),
+        )
+        .unwrap();
+        for (i, duration) in self.durations.iter().enumerate() {
+            if i != 0 {
+                line.push(',');
+            }
+            let style = self.last.config.duration_style;
+            write!(line, "{}", StyledDuration(*duration, style)).unwrap();
+        }
+        line.push('\n');
+        line
+    }
+}
+
+impl LogSink for CorrelatedSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        self.inner.write_line(line)
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        let key = (self.key)(entry);
+        let mut groups = self.lock();
+        let mut expired = groups
+            .extract_if(|_, group| group.opened.elapsed() >= self.window)
+            .collect::<Vec<_>>();
+        if let Some(key) = key {
+            if groups.len() >= self.max_groups && !groups.contains_key(&key) {
+                let oldest = groups
+                    .iter()
+                    .min_by_key(|(_, group)| group.opened)
+                    .map(|(key, _)| key.clone());
+                expired.extend(oldest.and_then(|key| groups.remove_entry(&key)));
+            }
+            groups
+                .entry(key)
+                .and_modify(|group| {
+                    group.last = entry.clone();
+                    group.durations.push(entry.duration);
+                })
+                .or_insert_with(|| Group {
+                    opened: Instant::now(),
+                    first: entry.timestamp(),
+                    last: entry.clone(),
+                    durations: vec![entry.duration],
+                });
+            drop(groups);
+            let result = self.write_groups(expired);
+            if self.keep_individual {
+                return result.and(self.inner.write_entry(entry));
+            }
+            result
+        } else {
+            drop(groups);
+            let result = self.write_groups(expired);
+            result.and(self.inner.write_entry(entry))
+        }
+    }
+}
+
+impl Drop for CorrelatedSink {
+    fn drop(&mut self) {
+        if self.flush_on_drop {
+            let _ = self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::SystemTime;
+
+    use bytes::Bytes;
+
+    use crate::entry::{test_entry, Extra};
+
+    fn attempt(id: Option<&'static str>, status: u16, millis: u64, offset: u64) -> LogEntry {
+        let mut entry = test_entry(&http::Request::get("/upstream").body(()).unwrap());
+        entry.status = Some(status);
+        entry.action = Some("proxy".to_owned());
+        entry.duration = Duration::from_millis(millis);
+        entry.start_time =
+            SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000 + offset);
+        if let Some(id) = id {
+            entry.extras.push((
+                "request_id".into(),
+                Extra::Bytes {
+                    value: Bytes::from_static(id.as_bytes()),
+                    max_len: 64,
+                },
+            ));
+        }
+        entry
+    }
+
+    fn text(out: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_retried() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let sink = CorrelatedSink::new(out.clone());
+        sink.write_entry(&attempt(Some("abc123"), 502, 12, 0))
+            .unwrap();
+        let ungrouped = attempt(None, 200, 1, 100);
+        sink.write_entry(&ungrouped).unwrap();
+        sink.write_entry(&attempt(Some("abc123"), 200, 30, 250))
+            .unwrap();
+        // Only the entry without an ID has been written so far.
+        assert_eq!(text(&out), ungrouped.to_string());
+
+        sink.flush().unwrap();
+        assert_eq!(
+            text(&out),
+            "request-group: [proxy:200] id=abc123 attempts=2 first=2023-11-14T22:13:20.000Z \
+             last=2023-11-14T22:13:20.250Z durations=12.000,30.000\n"
+        );
+        sink.flush().unwrap();
+        assert_eq!(text(&out), "");
+    }
+
+    #[test]
+    fn test_window() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let sink = CorrelatedSink::new(out.clone())
+            .window(Duration::from_millis(20))
+            .keep_individual(true);
+        let first = attempt(Some("a"), 503, 5, 0);
+        sink.write_entry(&first).unwrap();
+        assert_eq!(text(&out), first.to_string());
+
+        std::thread::sleep(Duration::from_millis(50));
+        let second = attempt(Some("b"), 200, 5, 60);
+        sink.write_entry(&second).unwrap();
+        let lines = text(&out);
+        assert!(
+            lines.starts_with("request-group: [proxy:503] id=a attempts=1 "),
+            "{lines}"
+        );
+        assert!(lines.ends_with(&second.to_string()), "{lines}");
+
+        drop(sink);
+        assert!(text(&out).starts_with("request-group: [proxy:200] id=b "));
+    }
+
+    #[test]
+    fn test_max_groups() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let sink = CorrelatedSink::new(out.clone()).max_groups(2);
+        for (id, offset) in [("a", 0), ("b", 10), ("a", 20)] {
+            sink.write_entry(&attempt(Some(id), 200, 5, offset))
+                .unwrap();
+        }
+        assert_eq!(text(&out), "");
+
+        // A third group makes the oldest one be written early.
+        sink.write_entry(&attempt(Some("c"), 200, 5, 30)).unwrap();
+        let lines = text(&out);
+        assert!(
+            lines.starts_with("request-group: [proxy:200] id=a attempts=2 "),
+            "{lines}"
+        );
+        assert_eq!(lines.lines().count(), 1, "{lines}");
+        assert_eq!(sink.lock().len(), 2);
+    }
+
+    #[test]
+    fn test_key() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let sink = CorrelatedSink::new(out.clone())
+            .key(|entry| Some(entry.path().to_owned()))
+            .flush_on_drop(false);
+        sink.write_entry(&attempt(None, 200, 5, 0)).unwrap();
+        sink.write_entry(&attempt(None, 200, 7, 10)).unwrap();
+        drop(sink);
+        assert_eq!(text(&out), "");
+    }
+}
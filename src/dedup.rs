@@ -0,0 +1,234 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::entry::LogEntry;
+use crate::sink::LogSink;
+use crate::time::StyledDuration;
+
+type KeyFn = dyn Fn(&LogEntry) -> String + Send + Sync;
+
+/// [DedupSink] is a sink which collapses runs of identical consecutive entries, such as a broken
+/// client retrying in a tight loop.
+///
+/// Entries are identical if their keys are equal. By default the key is the remote IP address
+/// (not the port, since retries often use new connections), method, URI, and status; it can be
+/// changed with [key](Self::key).
+///
+/// The first entry of a run is written straight away. Following entries with the same key are
+/// suppressed and counted, until one of:
+///
+/// * an entry with a different key is written,
+/// * an entry is written more than the maximum interval (10 seconds by default) after the first
+///   suppressed entry,
+/// * [flush](Self::flush) is called, or the sink is dropped.
+///
+/// At that point the last suppressed entry is written once, with the number of suppressed
+/// entries and the range of their durations appended, e.g.
+/// `... 1.200 (repeated 3841× min=1.100 max=9.800)`.
+///
+/// Lines which aren't request entries are passed through unchanged.
+pub struct DedupSink {
+    inner: Arc<dyn LogSink>,
+    key: Box<KeyFn>,
+    max_interval: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    key: Option<String>,
+    repeated: Option<Repeated>,
+}
+
+struct Repeated {
+    last: LogEntry,
+    count: u64,
+    min: Duration,
+    max: Duration,
+    since: Instant,
+}
+
+impl DedupSink {
+    /// Create a new deduplicating sink, which writes to the given sink.
+    pub fn new(inner: Arc<dyn LogSink>) -> Self {
+        Self {
+            inner,
+            key: Box::new(default_key),
+            max_interval: Duration::from_secs(10),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Use the given function to compute the key entries are compared by.
+    pub fn key(mut self, key: impl Fn(&LogEntry) -> String + Send + Sync + 'static) -> Self {
+        self.key = Box::new(key);
+        self
+    }
+
+    /// The longest time a run of suppressed entries is held before it is written.
+    pub fn max_interval(mut self, interval: Duration) -> Self {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Write out any entries which are currently being suppressed.
+    pub fn flush(&self) -> io::Result<()> {
+        let repeated = self
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .repeated
+            .take();
+        match repeated {
+            Some(r) => self.write_repeated(r),
+            None => Ok(()),
+        }
+    }
+
+    fn write_repeated(&self, r: Repeated) -> io::Result<()> {
+        let mut line = r.last.to_string();
+        line.truncate(line.trim_end().len());
+        let style = r.last.config.duration_style;
+        line.push_str(&format!(
+            " (repeated {}× min={} max={})\n",
+            r.count,
+            StyledDuration(r.min, style),
+            StyledDuration(r.max, style),
+        ));
+        self.inner.write_line(&line)
+    }
+}
+
+fn default_key(entry: &LogEntry) -> String {
+    format!(
+        "{:?} {} {} {:?}",
+        entry.remote.map(|addr| addr.ip()),
+        entry.method,
+        entry.uri,
+        entry.status
+    )
+}
+
+impl LogSink for DedupSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        self.inner.write_line(line)
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        let key = (self.key)(entry);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.key.as_ref() == Some(&key) {
+            match &mut state.repeated {
+                Some(r) if r.since.elapsed() <= self.max_interval => {
+                    r.count += 1;
+                    r.min = r.min.min(entry.duration);
+                    r.max = r.max.max(entry.duration);
+                    r.last = entry.clone();
+                    return Ok(());
+                }
+                Some(_) => {
+                    // Held for too long: write it out, and start a new run with this entry.
+                    let r = state.repeated.take().unwrap();
+                    drop(state);
+                    self.write_repeated(r)?;
+                    return self.inner.write_entry(entry);
+                }
+                None => {
+                    state.repeated = Some(Repeated {
+                        last: entry.clone(),
+                        count: 1,
+                        min: entry.duration,
+                        max: entry.duration,
+                        since: Instant::now(),
+                    });
+                    return Ok(());
+                }
+            }
+        }
+
+        state.key = Some(key);
+        let repeated = state.repeated.take();
+        drop(state);
+        if let Some(r) = repeated {
+            self.write_repeated(r)?;
+        }
+        self.inner.write_entry(entry)
+    }
+}
+
+impl Drop for DedupSink {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::test_entry;
+
+    fn entry(uri: &str, millis: u64) -> LogEntry {
+        let mut entry = test_entry(&http::Request::get(uri).body(()).unwrap());
+        entry.status = Some(503);
+        entry.duration = Duration::from_millis(millis);
+        entry
+    }
+
+    fn take(out: &Mutex<Vec<u8>>) -> Vec<String> {
+        let out = std::mem::take(&mut *out.lock().unwrap());
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| l.split_once(" HTTP/1.1 ").unwrap().1.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_dedup() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sink = DedupSink::new(out.clone());
+        sink.write_entry(&entry("/a", 5)).unwrap();
+        sink.write_entry(&entry("/a", 3)).unwrap();
+        sink.write_entry(&entry("/a", 9)).unwrap();
+        sink.write_entry(&entry("/a", 4)).unwrap();
+        sink.write_entry(&entry("/b", 1)).unwrap();
+        sink.write_entry(&entry("/a", 2)).unwrap();
+        assert_eq!(
+            take(&out),
+            vec![
+                "\"\" \"\" 5.000",
+                "\"\" \"\" 4.000 (repeated 3× min=3.000 max=9.000)",
+                "\"\" \"\" 1.000",
+                "\"\" \"\" 2.000",
+            ]
+        );
+
+        sink.write_entry(&entry("/a", 7)).unwrap();
+        assert!(take(&out).is_empty());
+        drop(sink);
+        assert_eq!(
+            take(&out),
+            vec!["\"\" \"\" 7.000 (repeated 1× min=7.000 max=7.000)"]
+        );
+    }
+
+    #[test]
+    fn test_max_interval() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sink = DedupSink::new(out.clone()).max_interval(Duration::ZERO);
+        sink.write_entry(&entry("/a", 1)).unwrap();
+        sink.write_entry(&entry("/a", 2)).unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        sink.write_entry(&entry("/a", 3)).unwrap();
+        assert_eq!(
+            take(&out),
+            vec![
+                "\"\" \"\" 1.000",
+                "\"\" \"\" 2.000 (repeated 1× min=2.000 max=2.000)",
+                "\"\" \"\" 3.000",
+            ]
+        );
+    }
+}
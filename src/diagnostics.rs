@@ -0,0 +1,109 @@
+//! Warnings about likely misuse of the API. These are only checked in debug builds, and each
+//! kind of warning is printed to stderr at most once per process.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A pattern of API usage which usually indicates an integration bug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Misuse {
+    /// Many entries have been logged without a status.
+    MissingStatus,
+    /// `set_response` was called more than once on the same request.
+    DuplicateResponse,
+    /// An entry was logged without the remote address having been set.
+    MissingRemote,
+}
+
+const KINDS: usize = 3;
+
+static OCCURRENCES: [AtomicU32; KINDS] = [const { AtomicU32::new(0) }; KINDS];
+static EMITTED: [AtomicU32; KINDS] = [const { AtomicU32::new(0) }; KINDS];
+
+impl Misuse {
+    /// How many times the pattern has to occur before it is warned about. Some patterns are
+    /// legitimate occasionally, and only suspicious if they keep happening.
+    fn threshold(self) -> u32 {
+        match self {
+            Self::MissingStatus => 10,
+            Self::DuplicateResponse | Self::MissingRemote => 1,
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::MissingStatus => {
+                "10 requests have been logged without a status; is set_response being called?"
+            }
+            Self::DuplicateResponse => {
+                "set_response was called more than once for a request; only the last one is logged"
+            }
+            Self::MissingRemote => {
+                "a request was logged without a remote address; is set_remote being called?"
+            }
+        }
+    }
+}
+
+/// Record an occurrence of a misuse pattern, warning about it if it has reached its threshold
+/// and hasn't been warned about before.
+pub(crate) fn report(misuse: Misuse) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let i = misuse as usize;
+    if OCCURRENCES[i].fetch_add(1, Ordering::Relaxed) + 1 == misuse.threshold() {
+        EMITTED[i].fetch_add(1, Ordering::Relaxed);
+        eprintln!("hyper-req-log: warning: {}", misuse.message());
+    }
+}
+
+/// How many times a warning has been emitted for the given misuse pattern.
+#[cfg(test)]
+pub(crate) fn emitted(misuse: Misuse) -> u32 {
+    EMITTED[misuse as usize].load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::LogRequest;
+    use http::{Request, Response};
+
+    // Other tests trigger these patterns too, so these only check that however many times they
+    // occur, each warning is emitted exactly once.
+
+    #[test]
+    fn test_duplicate_response() {
+        let req = Request::new(());
+        for _ in 0..3 {
+            let mut log = LogRequest::<&str>::from_request(&req);
+            log.set_response(&Response::new(()));
+            log.set_response(&Response::new(()));
+            log.set_response(&Response::new(()));
+            log.discard();
+        }
+        assert_eq!(emitted(Misuse::DuplicateResponse), 1);
+    }
+
+    #[test]
+    fn test_missing_status() {
+        let req = Request::new(());
+        for _ in 0..20 {
+            let mut log = LogRequest::<&str>::from_request(&req);
+            log.set_remote("127.0.0.1:1234".parse().unwrap());
+            log.write(std::io::sink()).unwrap();
+        }
+        assert_eq!(emitted(Misuse::MissingStatus), 1);
+    }
+
+    #[test]
+    fn test_missing_remote() {
+        let req = Request::new(());
+        for _ in 0..3 {
+            let mut log = LogRequest::<&str>::from_request(&req);
+            log.set_response(&Response::new(()));
+            log.write(std::io::sink()).unwrap();
+        }
+        assert_eq!(emitted(Misuse::MissingRemote), 1);
+    }
+}
@@ -1,14 +1,156 @@
-use std::fmt::{self, Debug, Formatter};
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::Arc;
 
-/// How to write a value to logs. Defaults to using the Debug impl, but can be overridden.
+/// How to write a value to logs, as the action of a [LogRequest](crate::LogRequest). Defaults to
+/// using the Debug impl, but can be overridden.
+///
+/// The default means an enum of actions only needs `#[derive(Debug)]` and an empty impl:
+///
+/// ```
+/// # use hyper_req_log::{LogDisplay, LogRequest};
+/// #[derive(Debug)]
+/// enum Action {
+///     ListItems,
+///     GetItem(u32),
+/// }
+///
+/// impl LogDisplay for Action {}
+///
+/// # let req = http::Request::new(());
+/// let mut log = LogRequest::from_request(&req);
+/// log.set_action(Action::GetItem(42));
+/// assert!(log.to_string().contains("[GetItem(42):"));
+/// # log.discard();
+/// ```
+///
+/// Strings are written as they are, without the quotes their Debug impls add, and so are
+/// characters. Integers and `bool` are written as usual.
+///
+/// An action type which only implements `Display`, as they had to before actions were written
+/// with this trait, can be wrapped in [ViaDisplay] instead.
 pub trait LogDisplay: Debug {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         <Self as Debug>::fmt(self, f)
     }
 }
 
-impl<'a> LogDisplay for &'a str {
+impl LogDisplay for &str {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_str(self)
     }
 }
+
+impl LogDisplay for String {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl LogDisplay for Cow<'_, str> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl LogDisplay for Box<str> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl LogDisplay for Arc<str> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl LogDisplay for char {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl LogDisplay for Box<dyn LogDisplay + Send> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        LogDisplay::fmt(&**self, f)
+    }
+}
+
+macro_rules! debug_is_display {
+    ($($ty:ty),*) => {
+        $(impl LogDisplay for $ty {})*
+    };
+}
+
+debug_is_display!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool);
+
+/// Wraps a type which implements `Display` so that it is written to logs with its `Display` impl,
+/// for using it as an action.
+///
+/// ```
+/// # use hyper_req_log::{LogRequest, ViaDisplay};
+/// # let req = http::Request::new(());
+/// let mut log = LogRequest::<ViaDisplay<std::net::Ipv4Addr>>::from_request(&req);
+/// log.set_action(ViaDisplay(std::net::Ipv4Addr::LOCALHOST));
+/// assert!(log.to_string().contains("[127.0.0.1:"));
+/// # log.discard();
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ViaDisplay<T>(pub T);
+
+impl<T: Display> Debug for ViaDisplay<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl<T: Display> LogDisplay for ViaDisplay<T> {}
+
+/// Adapter which formats a [LogDisplay] value using its `Display` impl, so it can be written to
+/// any `fmt::Write`.
+pub(crate) struct Displayed<'a, T: ?Sized>(pub &'a T);
+
+impl<T: LogDisplay + ?Sized> Display for Displayed<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        LogDisplay::fmt(self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shown<T: LogDisplay>(value: T) -> String {
+        Displayed(&value).to_string()
+    }
+
+    #[derive(Debug)]
+    #[allow(dead_code)] // The fields are only read by Debug.
+    enum Action {
+        Search,
+        Get { id: u32 },
+    }
+
+    impl LogDisplay for Action {}
+
+    #[test]
+    fn test_impls() {
+        assert_eq!(shown("get"), "get");
+        assert_eq!(shown("get".to_owned()), "get");
+        assert_eq!(shown(Cow::Borrowed("get")), "get");
+        assert_eq!(shown(Box::<str>::from("get")), "get");
+        assert_eq!(shown(Arc::<str>::from("get")), "get");
+        assert_eq!(shown('g'), "g");
+        assert_eq!(shown(42u16), "42");
+        assert_eq!(shown(-7i64), "-7");
+        assert_eq!(shown(true), "true");
+        assert_eq!(shown(ViaDisplay(1.5)), "1.5");
+        assert_eq!(shown(Action::Search), "Search");
+        assert_eq!(shown(Action::Get { id: 7 }), "Get { id: 7 }");
+        let boxed: Box<dyn LogDisplay + Send> = Box::new("get");
+        assert_eq!(shown(boxed), "get");
+        let boxed: Box<dyn LogDisplay + Send> = Box::new(Action::Search);
+        assert_eq!(shown(boxed), "Search");
+    }
+}
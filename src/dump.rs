@@ -0,0 +1,249 @@
+use std::fmt::{self, Debug, Formatter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use http::header::{HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE};
+use http::HeaderMap;
+
+use crate::config::{ConfigError, LogConfig};
+use crate::escaped::Escaped;
+use crate::head::RequestHead;
+use crate::sample::random_unit;
+
+type Predicate = dyn Fn(&RequestHead<'_>) -> bool + Send + Sync;
+
+/// [DumpPolicy] chooses requests to log with all of their headers, for
+/// [LogConfig::dump](crate::LogConfig::dump). It is meant to be switched on briefly, while
+/// debugging.
+///
+/// A dumped request's entry is fenced, so that anything parsing ordinary lines can skip it:
+///
+/// ```text
+/// --- begin dump id=7 ---
+/// > host: example.com
+/// > authorization: <redacted>
+/// < content-type: text/plain
+/// request: [hello:200] 192.0.2.1:4000 example.com GET /hello HTTP/1.1 "" "" 1.2ms
+/// --- end dump ---
+/// ```
+///
+/// Request headers are prefixed with `>` and response headers with `<`. Values are escaped as in
+/// the text format, and the values of credential headers (`Authorization`, `Proxy-Authorization`,
+/// `Cookie`, `Set-Cookie`, any header hashed by an
+/// [identity source](crate::IdentitySource::HashedHeader), and any added with
+/// [redact](Self::redact)) are replaced by `<redacted>`.
+///
+/// The policy is applied when a request is created, and the headers are only copied for requests
+/// which are dumped.
+#[derive(Clone, Default)]
+pub struct DumpPolicy {
+    ratio: f64,
+    predicates: Vec<Arc<Predicate>>,
+    redact: Vec<HeaderName>,
+}
+
+impl DumpPolicy {
+    /// A policy which dumps nothing until a ratio or predicate is added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dump a random fraction of requests, between 0 and 1.
+    pub fn ratio(mut self, ratio: f64) -> Self {
+        self.ratio = ratio;
+        self
+    }
+
+    /// Dump requests which the given predicate accepts, in addition to the random fraction. This
+    /// can be called multiple times, and a request matching any predicate is dumped.
+    pub fn when(
+        mut self,
+        predicate: impl Fn(&RequestHead<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Also redact the values of the given header.
+    pub fn redact(mut self, name: HeaderName) -> Self {
+        self.redact.push(name);
+        self
+    }
+
+    pub(crate) fn check(&self, path: &str, errors: &mut Vec<ConfigError>) {
+        if !(0. ..=1.).contains(&self.ratio) {
+            errors.push(ConfigError::Ratio {
+                option: format!("{path}.ratio"),
+                value: self.ratio,
+            });
+        }
+    }
+
+    pub(crate) fn decide(&self, head: &RequestHead<'_>) -> bool {
+        self.predicates.iter().any(|p| p(head)) || random_unit() < self.ratio
+    }
+}
+
+impl Debug for DumpPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DumpPolicy")
+            .field("ratio", &self.ratio)
+            .field("predicates", &self.predicates.len())
+            .field("redact", &self.redact)
+            .finish()
+    }
+}
+
+/// The headers of a dumped request, and its response once it has one.
+#[derive(Debug, Clone)]
+pub(crate) struct Dump {
+    id: u64,
+    request: HeaderMap,
+    pub(crate) response: Option<HeaderMap>,
+}
+
+impl Dump {
+    pub(crate) fn new(headers: &HeaderMap) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            request: headers.clone(),
+            response: None,
+        }
+    }
+
+    pub(crate) fn write_begin<W: Write + ?Sized>(
+        &self,
+        config: &LogConfig,
+        f: &mut W,
+    ) -> fmt::Result {
+        writeln!(f, "--- begin dump id={} ---", self.id)?;
+        write_headers(config, '>', &self.request, f)?;
+        if let Some(response) = &self.response {
+            write_headers(config, '<', response, f)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_end<W: Write + ?Sized>(f: &mut W) -> fmt::Result {
+        f.write_str("--- end dump ---\n")
+    }
+}
+
+fn write_headers<W: Write + ?Sized>(
+    config: &LogConfig,
+    prefix: char,
+    headers: &HeaderMap,
+    f: &mut W,
+) -> fmt::Result {
+    for (name, value) in headers {
+        if is_redacted(config, name) {
+            writeln!(f, "{prefix} {name}: <redacted>")?;
+        } else if let Some(value) = config.privacy.header(name, value) {
+            writeln!(f, "{prefix} {name}: {}", Escaped::from(&value))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_redacted(config: &LogConfig, name: &HeaderName) -> bool {
+    if [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE].contains(name) {
+        return true;
+    }
+    if config
+        .dump
+        .as_ref()
+        .is_some_and(|d| d.redact.contains(name))
+    {
+        return true;
+    }
+    #[cfg(feature = "identity")]
+    if config.identity.iter().any(
+        |source| matches!(source, crate::IdentitySource::HashedHeader(hashed) if hashed == name),
+    ) {
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::untimed;
+    use std::sync::Mutex;
+
+    use http::{Request, Response};
+
+    use crate::request::LogRequest;
+
+    #[test]
+    fn test_dump() {
+        let config = Arc::new(
+            LogConfig::new().dump(
+                DumpPolicy::new()
+                    .when(|head| head.uri().path() == "/debug")
+                    .redact(HeaderName::from_static("x-api-key")),
+            ),
+        );
+        let out = Arc::new(Mutex::new(vec![]));
+
+        let req = Request::get("/debug")
+            .header("host", "example.com")
+            .header("authorization", "Bearer hunter2")
+            .header("cookie", "session=hunter2")
+            .header("x-api-key", "hunter2")
+            .header("x-note", "two words")
+            .body(())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .also_write_to(out.clone());
+        let resp = Response::builder()
+            .status(200)
+            .header("set-cookie", "session=hunter2")
+            .header("content-type", "text/plain")
+            .body(())
+            .unwrap();
+        log.set_response(&resp);
+        log.write(std::io::sink()).unwrap();
+
+        let text = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(!text.contains("hunter2"), "{text}");
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("--- begin dump id="), "{text}");
+        assert_eq!(
+            &lines[1..8],
+            [
+                "> host: example.com",
+                "> authorization: <redacted>",
+                "> cookie: <redacted>",
+                "> x-api-key: <redacted>",
+                "> x-note: \"two words\"",
+                "< set-cookie: <redacted>",
+                "< content-type: text/plain",
+            ]
+        );
+        assert!(
+            untimed(lines[8]).starts_with("request: [200] 192.0.2.1:4000 "),
+            "{text}"
+        );
+        assert_eq!(lines[9], "--- end dump ---");
+        assert_eq!(lines.len(), 10);
+
+        // Requests which aren't chosen are logged as usual.
+        let req = Request::get("/other").body(()).unwrap();
+        let log = LogRequest::<&str>::from_request_with_config(&req, config);
+        let line = log.entry().to_string();
+        log.discard();
+        assert!(untimed(&line).starts_with("request: "), "{line}");
+        assert_eq!(line.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_ratio() {
+        let head = |policy: &DumpPolicy| policy.decide(&RequestHead::new(&Request::new(())));
+        assert!(!head(&DumpPolicy::new()));
+        assert!(head(&DumpPolicy::new().ratio(1.)));
+        assert!(head(&DumpPolicy::new().when(|_| true)));
+    }
+}
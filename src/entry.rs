@@ -0,0 +1,996 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt::{self, Display, Formatter, Write};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Method, StatusCode, Uri, Version};
+
+use crate::body::BodyEnd;
+use crate::cache::CacheStatus;
+use crate::config::LogConfig;
+use crate::dump::Dump;
+use crate::error::ErrorKind;
+use crate::escaped::Escaped;
+use crate::field_set::{default_field_set, FieldSet};
+use crate::format::{self, Format};
+use crate::formatter::{default_formatter, LogFormatter, WriteAdapter};
+use crate::forwarded::ForwardedEntry;
+use crate::geo::Geo;
+use crate::negotiation::Negotiation;
+use crate::query::{default_log_query, LogUri, TargetForm};
+use crate::request_id;
+use crate::time::{default_timestamp_style, Timestamp, TimestampStyle, Timezone};
+use crate::tls::TlsConnectionInfo;
+use crate::trace_context::TraceParent;
+
+/// The capacity lines are rendered into: enough for a typical entry.
+const LINE_CAPACITY: usize = 512;
+
+/// The largest capacity a thread's line buffer keeps between entries.
+const MAX_LINE_CAPACITY: usize = 16 * 1024;
+
+thread_local! {
+    static LINE: RefCell<String> = RefCell::new(String::with_capacity(LINE_CAPACITY));
+}
+
+/// [LogEntry] is an owned snapshot of a [LogRequest](crate::LogRequest), taken at the point where
+/// it is logged. The action has already been rendered to a string and the elapsed time is fixed,
+/// so every output format derives its values from the same data.
+///
+/// Like [LogRequest](crate::LogRequest), the `Debug` representation is the rendered log line.
+#[derive(Clone)]
+pub struct LogEntry {
+    pub(crate) start_time: SystemTime,
+    pub(crate) timestamp_style: Option<TimestampStyle>,
+    pub(crate) log_query: Option<bool>,
+    pub(crate) field_set: Option<FieldSet>,
+    pub(crate) request_id: Option<String>,
+    pub(crate) trace_parent: Option<TraceParent>,
+    pub(crate) trace_state: Option<HeaderValue>,
+    pub(crate) user: Option<Bytes>,
+    pub(crate) client: bool,
+    pub(crate) remote: Option<SocketAddr>,
+    pub(crate) remote_host: Option<String>,
+    pub(crate) geo: Option<Geo>,
+    pub(crate) fwd: Vec<ForwardedEntry>,
+    pub(crate) fwd_elided: usize,
+    pub(crate) fwd_proto: Option<HeaderValue>,
+    pub(crate) fwd_host: Option<HeaderValue>,
+    pub(crate) real_ip: Option<IpAddr>,
+    pub(crate) fwd_untrusted: bool,
+    pub(crate) real_ip_mismatch: Option<HeaderValue>,
+    pub(crate) host: Option<HeaderValue>,
+    pub(crate) authority_mismatch: Option<HeaderValue>,
+    pub(crate) server_name: Option<String>,
+    pub(crate) method: Method,
+    pub(crate) uri: Uri,
+    pub(crate) version: Version,
+    pub(crate) user_agent: Option<HeaderValue>,
+    pub(crate) referer: Option<HeaderValue>,
+    pub(crate) headers: Vec<(HeaderName, HeaderValue)>,
+    pub(crate) response_headers: Vec<(HeaderName, HeaderValue)>,
+    pub(crate) upgrade: Option<HeaderValue>,
+    pub(crate) negotiation: Option<Negotiation>,
+    pub(crate) content_type: Option<HeaderValue>,
+    pub(crate) content_encoding: Option<HeaderValue>,
+    pub(crate) accept: Option<HeaderValue>,
+    pub(crate) dump: Option<Box<Dump>>,
+    #[cfg(feature = "fingerprint")]
+    pub(crate) fingerprint: Option<String>,
+    pub(crate) formatter: Option<Arc<dyn LogFormatter>>,
+    pub(crate) action: Option<String>,
+    /// The actions `action` was joined from, if there was more than one.
+    pub(crate) action_chain: Vec<String>,
+    pub(crate) status: Option<u16>,
+    pub(crate) grpc_status: Option<i32>,
+    pub(crate) cache_status: Option<CacheStatus>,
+    pub(crate) budget: Option<Duration>,
+    /// The threshold set on the request, in place of the config's.
+    pub(crate) slow_threshold: Option<Duration>,
+    pub(crate) middleware: Option<Duration>,
+    pub(crate) handler: Option<Duration>,
+    pub(crate) upstream: Option<Duration>,
+    pub(crate) sent_duration: Option<Duration>,
+    pub(crate) header_bytes_in: Option<u32>,
+    pub(crate) header_bytes_out: Option<u32>,
+    pub(crate) request_bytes: Option<u64>,
+    pub(crate) response_bytes: Option<u64>,
+    pub(crate) body_end: Option<BodyEnd>,
+    pub(crate) setup: Option<Setup>,
+    pub(crate) conn: Option<ConnSeq>,
+    pub(crate) tls: Option<Arc<TlsConnectionInfo>>,
+    pub(crate) extras: Vec<(Cow<'static, str>, Extra)>,
+    pub(crate) extras_dropped: u32,
+    pub(crate) error_kind: Option<ErrorKind>,
+    pub(crate) error: Option<String>,
+    pub(crate) lifecycle: Lifecycle,
+    pub(crate) sample_rate: Option<f64>,
+    pub(crate) duration: Duration,
+    pub(crate) stuck_after: Option<Duration>,
+    pub(crate) config: Arc<LogConfig>,
+}
+
+impl LogEntry {
+    /// The request's ID, if it had one.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    /// The trace ID from the request's `traceparent` header, if it was captured and valid.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_parent.as_ref()?.trace_id()
+    }
+
+    /// The caller's span ID from the request's `traceparent` header, if it was captured and
+    /// valid.
+    pub fn span_id(&self) -> Option<&str> {
+        self.trace_parent.as_ref()?.span_id()
+    }
+
+    /// The address of the remote endpoint, if it was set.
+    pub fn remote(&self) -> Option<SocketAddr> {
+        self.remote
+    }
+
+    /// The IP address of the client, with IPv4-mapped IPv6 addresses converted to IPv4.
+    ///
+    /// This is the address of the connection, if it was set, unless the request came from one
+    /// of the [LogConfig::trusted_proxies] and the client was found in its `X-Forwarded-For` or
+    /// `Forwarded` header. Without trusted proxies, forwarded addresses are never used.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.real_ip
+            .or_else(|| self.remote.map(|remote| remote.ip().to_canonical()))
+    }
+
+    /// The chain of forwarded addresses from the request's forwarding headers, parsed as it is
+    /// logged. If [LogConfig::max_forwarded_hops](crate::LogConfig::max_forwarded_hops) left
+    /// some out, they are missing from it too.
+    pub fn forwarded_chain(&self) -> &[ForwardedEntry] {
+        &self.fwd
+    }
+
+    /// The best guess at the client's address from the request's forwarding headers, as with
+    /// [LogRequest::forwarded_client](crate::LogRequest::forwarded_client).
+    pub fn forwarded_client(&self) -> Option<IpAddr> {
+        self.real_ip.or_else(|| self.fwd.first()?.ip())
+    }
+
+    /// The name of the site which handled the request, if it was set with
+    /// [set_server_name](crate::LogRequest::set_server_name).
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// The request method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The request URI, as it was sent. It isn't redacted: a formatter logging the URI should
+    /// use [logged_uri](Self::logged_uri) instead.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The request URI as the built-in formats log it, with the query left out or its
+    /// parameters redacted, and in origin form, as configured by
+    /// [LogConfig::redact_query_params](crate::LogConfig::redact_query_params),
+    /// [LogConfig::origin_form](crate::LogConfig::origin_form), and
+    /// [log_query](Self::log_query).
+    pub fn logged_uri(&self) -> impl Display + '_ {
+        LogUri(self)
+    }
+
+    /// The path of the request URI.
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+
+    /// The path and query of the request URI, as a custom formatter would log a request sent
+    /// to the origin server. It's `/` for an absolute-form URI without a path, and empty for an
+    /// authority-form URI, which CONNECT requests use. Like [uri](Self::uri), it isn't redacted.
+    pub fn path_and_query(&self) -> &str {
+        match (self.uri.path_and_query(), self.target_form()) {
+            (Some(pq), _) => pq.as_str(),
+            (None, TargetForm::Absolute) => "/",
+            (None, _) => "",
+        }
+    }
+
+    /// The form of the request URI: whether it's a path, a whole URI, an authority, or `*`.
+    pub fn target_form(&self) -> TargetForm {
+        TargetForm::of(&self.uri)
+    }
+
+    /// The query of the request URI, without the `?`, if it has one. Like [uri](Self::uri), it
+    /// isn't redacted.
+    pub fn query(&self) -> Option<&str> {
+        self.uri.query()
+    }
+
+    /// The request's HTTP version.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The request's `Host` header, or the authority of its URI if it had no `Host` header, as it
+    /// is logged.
+    pub fn host(&self) -> Option<&HeaderValue> {
+        self.host.as_ref()
+    }
+
+    /// The authority of the request's URI, if the request also had a `Host` header naming a
+    /// different host.
+    pub fn authority_mismatch(&self) -> Option<&HeaderValue> {
+        self.authority_mismatch.as_ref()
+    }
+
+    /// The request's `User-Agent` header.
+    pub fn user_agent(&self) -> Option<&HeaderValue> {
+        self.user_agent.as_ref()
+    }
+
+    /// The request's `Referer` header.
+    pub fn referer(&self) -> Option<&HeaderValue> {
+        self.referer.as_ref()
+    }
+
+    /// The authenticated user, if one was set and it is valid UTF-8. A username from
+    /// [set_user_from_basic_auth](crate::LogRequest::set_user_from_basic_auth) may not be, and
+    /// is logged all the same.
+    pub fn user(&self) -> Option<&str> {
+        std::str::from_utf8(self.user.as_deref()?).ok()
+    }
+
+    /// The request's `Content-Type` header, if it had one and [LogConfig::content_headers] is
+    /// enabled.
+    pub fn content_type(&self) -> Option<&HeaderValue> {
+        self.content_type.as_ref()
+    }
+
+    /// The request's `Content-Encoding` header, if it had one and [LogConfig::content_headers] is
+    /// enabled.
+    pub fn content_encoding(&self) -> Option<&HeaderValue> {
+        self.content_encoding.as_ref()
+    }
+
+    /// The request's `Accept` header, if it had one and [LogConfig::content_headers] is enabled.
+    pub fn accept(&self) -> Option<&HeaderValue> {
+        self.accept.as_ref()
+    }
+
+    /// The value of a header captured with [LogConfig::capture_header], if the request had it.
+    pub fn captured_header(&self, name: &str) -> Option<&HeaderValue> {
+        find_header(&self.headers, name)
+    }
+
+    /// The value of a response header captured with [LogConfig::capture_response_header], if
+    /// the response had it.
+    pub fn captured_response_header(&self, name: &str) -> Option<&HeaderValue> {
+        find_header(&self.response_headers, name)
+    }
+
+    /// The HTTP status of the response, if one was set.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// The gRPC status of the response, if it was set with
+    /// [set_grpc_status](crate::LogRequest::set_grpc_status) or
+    /// [set_response_grpc](crate::LogRequest::set_response_grpc).
+    pub fn grpc_status(&self) -> Option<i32> {
+        self.grpc_status
+    }
+
+    /// Whether the response was served from a cache, if it was set with
+    /// [set_cache_status](crate::LogRequest::set_cache_status).
+    pub fn cache_status(&self) -> Option<&CacheStatus> {
+        self.cache_status.as_ref()
+    }
+
+    /// The canonical reason phrase of the status, if [LogConfig::status_reason] is enabled and
+    /// the status has one.
+    pub(crate) fn status_reason(&self) -> Option<&'static str> {
+        if !self.config.status_reason {
+            return None;
+        }
+        StatusCode::from_u16(self.status?).ok()?.canonical_reason()
+    }
+
+    /// The class of the HTTP status of the response, if one was set.
+    pub fn status_class(&self) -> Option<StatusClass> {
+        self.status.and_then(StatusClass::from_status)
+    }
+
+    /// The lifecycle state of the request when it was logged.
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle
+    }
+
+    /// Whether this is an outbound request made by a client, rather than one received by a
+    /// server. For client requests, the remote endpoint is the peer the request was sent to.
+    pub fn is_client(&self) -> bool {
+        self.client
+    }
+
+    /// The hostname of the remote endpoint, if it was resolved.
+    pub fn remote_host(&self) -> Option<&str> {
+        self.remote_host.as_deref()
+    }
+
+    /// Where the request came from, if it was set with
+    /// [set_geo](crate::LogRequest::set_geo).
+    pub fn geo(&self) -> Option<&Geo> {
+        self.geo.as_ref()
+    }
+
+    /// The anonymous ID derived for the request, if
+    /// [LogConfig::fingerprint](crate::LogConfig::fingerprint) is set.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+
+    /// The protocol the connection switched to, from the request's `Upgrade`
+    /// header, if the response was `101 Switching Protocols`.
+    pub fn upgraded_to(&self) -> Option<&HeaderValue> {
+        match self.status {
+            Some(101) => self.upgrade.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The age at which the request was logged provisionally as [stuck](Lifecycle::Stuck), if
+    /// it was. This entry is then the late, final one.
+    pub fn stuck_after(&self) -> Option<Duration> {
+        self.stuck_after
+    }
+
+    /// The time taken to set up the connection the request came in on, if
+    /// this was the first request on a connection tracked by a
+    /// [ConnectionLog](crate::ConnectionLog).
+    pub fn connection_setup(&self) -> Option<Duration> {
+        match self.setup {
+            Some(Setup::New(d)) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The ID of the connection the request came in on, if it is tracked by a
+    /// [ConnectionLog](crate::ConnectionLog).
+    pub fn connection_id(&self) -> Option<&str> {
+        self.conn.as_ref().map(|conn| &*conn.id)
+    }
+
+    /// The request's position on its connection, counting from 1, if the connection is tracked
+    /// by a [ConnectionLog](crate::ConnectionLog).
+    pub fn connection_seq(&self) -> Option<u64> {
+        self.conn.as_ref().map(|conn| conn.seq)
+    }
+
+    /// The TLS session the request came in on, if it was set.
+    pub fn tls(&self) -> Option<&TlsConnectionInfo> {
+        self.tls.as_deref()
+    }
+
+    /// The coarse outcome of the request.
+    pub fn outcome(&self) -> Outcome {
+        Outcome::new(self.status, self.lifecycle)
+    }
+
+    /// Whether the request failed: it has a 4xx or 5xx status, or didn't complete, or has an
+    /// [error kind](Self::error_kind) or an [error](Self::error).
+    pub fn is_error(&self) -> bool {
+        self.error_kind.is_some()
+            || self.error.is_some()
+            || matches!(
+                self.outcome(),
+                Outcome::ClientError | Outcome::ServerError | Outcome::Incomplete
+            )
+    }
+
+    /// The classification of why the request failed, if one was set.
+    pub fn error_kind(&self) -> Option<ErrorKind> {
+        self.error_kind
+    }
+
+    /// The description of why the request failed, if one was set.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// The rendered action, if one was set. A chain of actions is joined with `>`.
+    pub fn action_str(&self) -> Option<&str> {
+        self.action.as_deref()
+    }
+
+    /// The rendered actions of a chain made with
+    /// [push_action](crate::LogRequest::push_action), oldest first, or none if there weren't
+    /// more than one.
+    pub fn action_chain(&self) -> &[String] {
+        &self.action_chain
+    }
+
+    /// The value of the extra field with the given key, in full and unescaped, if there is one
+    /// and it is UTF-8.
+    pub fn extra(&self, key: &str) -> Option<&str> {
+        let (_, value) = self.extras.iter().find(|(k, _)| k == key)?;
+        match value {
+            Extra::Bytes { value, .. } => std::str::from_utf8(value).ok(),
+        }
+    }
+
+    /// The wall-clock time when the request was created.
+    pub fn start_time(&self) -> SystemTime {
+        self.start_time
+    }
+
+    /// The wall-clock time when the request was created, as milliseconds since the Unix epoch,
+    /// or 0 if the clock is set before it.
+    pub fn start_unix_millis(&self) -> u64 {
+        unix_millis(self.start_time)
+    }
+
+    /// A key identifying the request by when it started and its method and path, as with
+    /// [LogRequest::correlation_key](crate::LogRequest::correlation_key).
+    pub fn correlation_key(&self) -> String {
+        request_id::correlation_key(self.start_unix_millis(), &self.method, self.uri.path())
+    }
+
+    /// The wall-clock time when the request was created, for rendering in the configured
+    /// timezone.
+    pub fn timestamp(&self) -> Timestamp {
+        Timestamp::new(self.start_time, self.config.timezone)
+    }
+
+    /// The wall-clock time when the request was created, for rendering in UTC whatever the
+    /// configured timezone.
+    pub fn start_time_utc(&self) -> Timestamp {
+        Timestamp::new(self.start_time, Timezone::Utc)
+    }
+
+    /// How the time the request started is shown: the style set on the request, or in its
+    /// config, or the process default.
+    pub fn timestamp_style(&self) -> TimestampStyle {
+        self.timestamp_style
+            .or(self.config.timestamp_style)
+            .unwrap_or_else(default_timestamp_style)
+    }
+
+    /// Whether the query string is logged: the setting made on the request, or in its config, or
+    /// the process default.
+    pub fn log_query(&self) -> bool {
+        self.log_query
+            .or(self.config.log_query)
+            .unwrap_or_else(default_log_query)
+    }
+
+    /// Which of the optional fields are logged: the setting made on the request, or in its
+    /// config, or the process default.
+    pub fn field_set(&self) -> FieldSet {
+        self.field_set
+            .or(self.config.field_set)
+            .unwrap_or_else(default_field_set)
+    }
+
+    /// The time the request started, rendered in its [timestamp_style](Self::timestamp_style),
+    /// unless that is [TimestampStyle::None].
+    pub(crate) fn styled_timestamp(&self) -> Option<impl Display> {
+        Timestamp::new(self.start_time, self.config.timezone).styled(self.timestamp_style())
+    }
+
+    /// The time between when the request was created and when this snapshot was taken.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The latency budget for the request, if one was set.
+    pub fn budget(&self) -> Option<Duration> {
+        self.budget
+    }
+
+    /// The time between when the request was created and when the response was produced, if it
+    /// was: when [set_response](crate::LogRequest::set_response) or
+    /// [set_status](crate::LogRequest::set_status) was first called, or
+    /// [mark_response_started](crate::LogRequest::mark_response_started) was last called. The
+    /// rest of the [duration](Self::duration) was spent sending the response.
+    pub fn handler_duration(&self) -> Option<Duration> {
+        self.handler
+    }
+
+    /// The total time spent waiting on upstream servers, if any was
+    /// [added](crate::LogRequest::add_upstream_duration).
+    pub fn upstream_duration(&self) -> Option<Duration> {
+        self.upstream
+    }
+
+    /// The time between when the request was created and when the handler was called, if
+    /// [mark_handler_start](crate::LogRequest::mark_handler_start) was called.
+    pub fn middleware(&self) -> Option<Duration> {
+        self.middleware
+    }
+
+    /// How much the request's [duration](Self::duration) exceeded its budget by, if it had a
+    /// budget and exceeded it.
+    pub fn over_budget(&self) -> Option<Duration> {
+        self.budget
+            .and_then(|budget| self.duration.checked_sub(budget))
+            .filter(|over| !over.is_zero())
+    }
+
+    /// The slow threshold the request's [duration](Self::duration) exceeded, if it had one and
+    /// exceeded it. This compares the same duration which is logged, so the two always agree.
+    pub fn slow_threshold_exceeded(&self) -> Option<Duration> {
+        self.slow_threshold
+            .or(self.config.slow_threshold)
+            .filter(|&threshold| self.duration > threshold)
+    }
+
+    /// Whether the request took longer than its slow threshold, as with
+    /// [LogConfig::slow_threshold].
+    pub fn is_slow(&self) -> bool {
+        self.slow_threshold_exceeded().is_some()
+    }
+
+    /// The serialized size of the request headers, if header sizes are enabled in the
+    /// configuration.
+    pub fn header_bytes_in(&self) -> Option<u32> {
+        self.header_bytes_in
+    }
+
+    /// The serialized size of the response headers, if header sizes are enabled in the
+    /// configuration and a response was set.
+    pub fn header_bytes_out(&self) -> Option<u32> {
+        self.header_bytes_out
+    }
+
+    /// The size of the request body in bytes, if it was counted with
+    /// [count_request_body](crate::LogRequest::count_request_body) or the request had a valid
+    /// `Content-Length` header.
+    pub fn request_bytes(&self) -> Option<u64> {
+        self.request_bytes
+    }
+
+    /// The size of the response body in bytes, if it was set with
+    /// [set_response_size](crate::LogRequest::set_response_size) or the response had a
+    /// `Content-Length` header.
+    pub fn response_bytes(&self) -> Option<u64> {
+        self.response_bytes
+    }
+
+    /// Whether the whole response body was sent, if it was counted with
+    /// [count_response_body](crate::LogRequest::count_response_body) and has ended.
+    pub fn body_complete(&self) -> Option<bool> {
+        self.body_end.map(|end| end == BodyEnd::Complete)
+    }
+
+    /// The duration in milliseconds, with microsecond precision. Structured formats use this
+    /// value instead of the unit-suffixed text rendering.
+    pub fn duration_ms(&self) -> f64 {
+        self.duration.as_micros() as f64 / 1000.
+    }
+
+    /// Render the entry into a new string, which starts out big enough for a typical line so it
+    /// rarely has to grow while being written. A [formatter](crate::LogFormatter) which returns
+    /// an error fails the write, rather than panicking as `to_string` would.
+    pub(crate) fn render(&self) -> io::Result<String> {
+        let mut line = String::with_capacity(LINE_CAPACITY);
+        self.write_to(&mut line).map_err(format_error)?;
+        Ok(line)
+    }
+
+    /// Render the entry into this thread's line buffer, and pass it to `f`. The buffer is reused
+    /// from one entry to the next, so this usually doesn't allocate. A sink which writes another
+    /// entry from inside `f` gets a new string instead.
+    pub(crate) fn with_rendered<R>(&self, f: impl FnOnce(&str) -> io::Result<R>) -> io::Result<R> {
+        LINE.with(|line| match line.try_borrow_mut() {
+            Ok(mut line) => {
+                line.clear();
+                let result = match self.write_to(&mut *line) {
+                    Ok(()) => f(&line),
+                    Err(err) => Err(format_error(err)),
+                };
+                // Don't hold on to the memory of an unusually long line.
+                if line.capacity() > MAX_LINE_CAPACITY {
+                    *line = String::with_capacity(LINE_CAPACITY);
+                }
+                result
+            }
+            Err(_) => f(&self.render()?),
+        })
+    }
+
+    /// Render the entry, with the [formatter](crate::LogFormatter) or [Format](crate::Format) of
+    /// its request or config, into the given formatting sink.
+    pub fn write_to<W: Write + ?Sized>(&self, f: &mut W) -> fmt::Result {
+        let formatter = self
+            .formatter
+            .as_ref()
+            .or(self.config.formatter.as_ref().map(|c| &c.0));
+        match (formatter, self.config.format) {
+            (Some(formatter), _) => formatter.format(self, &mut WriteAdapter(f)),
+            (None, Some(format)) => format::write_entry(self, format, f),
+            (None, None) => match default_formatter() {
+                Some(formatter) => formatter.format(self, &mut WriteAdapter(f)),
+                None => format::write_entry(self, Format::Text, f),
+            },
+        }
+    }
+
+    /// Render the entry in the given [Format](crate::Format), regardless of the configured one.
+    pub fn write_as<W: Write + ?Sized>(&self, format: Format, f: &mut W) -> fmt::Result {
+        format::write_entry(self, format, f)
+    }
+}
+
+/// Milliseconds since the Unix epoch, or 0 for times before it.
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Connection setup time, for requests on a tracked connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "connection"), allow(dead_code))]
+pub(crate) enum Setup {
+    /// The first request on the connection, which took this long to set up.
+    New(Duration),
+    /// A later request on an already set up connection.
+    Reused,
+}
+
+impl Display for Setup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Setup::New(d) => write!(f, "{d:?}"),
+            Setup::Reused => f.write_str("reused"),
+        }
+    }
+}
+
+/// The connection a request came in on, and which request on it this was, logged as
+/// `conn=ID#SEQ`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(not(feature = "connection"), allow(dead_code))]
+pub(crate) struct ConnSeq {
+    pub(crate) id: Arc<str>,
+    /// Counting from 1.
+    pub(crate) seq: u64,
+}
+
+impl Display for ConnSeq {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", Escaped::from(&*self.id), self.seq)
+    }
+}
+
+/// The value of an extra field added to a request by the application.
+#[derive(Clone)]
+pub(crate) enum Extra {
+    /// Bytes, of which at most `max_len` are logged.
+    Bytes { value: Bytes, max_len: usize },
+}
+
+impl Extra {
+    /// The size of the field `key=value`, not counting any escaping, for enforcing the limit on
+    /// the total size of extras.
+    pub(crate) fn size(&self, key: &str) -> usize {
+        match self {
+            Self::Bytes { value, max_len } => key.len() + 1 + value.len().min(*max_len),
+        }
+    }
+}
+
+impl Extra {
+    /// The part of the value which is logged, and how many bytes are left out.
+    pub(crate) fn shown(&self) -> (&[u8], usize) {
+        match self {
+            Self::Bytes { value, max_len } => {
+                let shown = &value[..value.len().min(*max_len)];
+                (shown, value.len() - shown.len())
+            }
+        }
+    }
+}
+
+impl Display for Extra {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (shown, omitted) = self.shown();
+        write!(f, "{}", Escaped::from(shown))?;
+        if omitted != 0 {
+            write!(f, "…(+{omitted})")?;
+        }
+        Ok(())
+    }
+}
+
+/// How far a request got before it was logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lifecycle {
+    /// No response has been recorded. If the request is logged in this state, it is either
+    /// because the integration never called `set_response`, or for a reason that couldn't be
+    /// detected.
+    InFlight,
+    /// A response status was recorded.
+    Responded,
+    /// The request was cancelled before a response was produced.
+    Cancelled,
+    /// The handler panicked.
+    Panicked,
+    /// The request hadn't finished within the configured
+    /// [max_age](crate::LogConfig::max_age), so what had been captured when it started was
+    /// logged provisionally.
+    Stuck,
+}
+
+/// A coarse classification of how a request turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Outcome {
+    /// A 1xx or 2xx status.
+    Ok,
+    /// A 3xx status.
+    Redirect,
+    /// A 4xx status.
+    ClientError,
+    /// A 5xx status, or one outside the standard ranges.
+    ServerError,
+    /// No status was recorded, or the request was cancelled, panicked, or got stuck.
+    Incomplete,
+}
+
+impl Outcome {
+    pub(crate) fn new(status: Option<u16>, lifecycle: Lifecycle) -> Self {
+        if matches!(
+            lifecycle,
+            Lifecycle::Cancelled | Lifecycle::Panicked | Lifecycle::Stuck
+        ) {
+            return Self::Incomplete;
+        }
+        match status.map(StatusClass::from_status) {
+            None => Self::Incomplete,
+            Some(Some(StatusClass::Informational | StatusClass::Success)) => Self::Ok,
+            Some(Some(StatusClass::Redirection)) => Self::Redirect,
+            Some(Some(StatusClass::ClientError)) => Self::ClientError,
+            Some(Some(StatusClass::ServerError) | None) => Self::ServerError,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::Redirect => "redirect",
+            Self::ClientError => "client-error",
+            Self::ServerError => "server-error",
+            Self::Incomplete => "incomplete",
+        })
+    }
+}
+
+/// The class of an HTTP status code, given by its first digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StatusClass {
+    /// 1xx
+    Informational,
+    /// 2xx
+    Success,
+    /// 3xx
+    Redirection,
+    /// 4xx
+    ClientError,
+    /// 5xx
+    ServerError,
+}
+
+impl StatusClass {
+    /// Classify the given status code. Returns `None` for codes outside 100-599.
+    pub fn from_status(status: u16) -> Option<Self> {
+        Some(match status {
+            100..=199 => Self::Informational,
+            200..=299 => Self::Success,
+            300..=399 => Self::Redirection,
+            400..=499 => Self::ClientError,
+            500..=599 => Self::ServerError,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for StatusClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Informational => "1xx",
+            Self::Success => "2xx",
+            Self::Redirection => "3xx",
+            Self::ClientError => "4xx",
+            Self::ServerError => "5xx",
+        })
+    }
+}
+
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.write_to(f)
+    }
+}
+
+impl LogEntry {
+    /// Debug-format the entry as a tuple struct of the given name, containing the rendered line.
+    /// Nothing is included which wouldn't also be in the line.
+    pub(crate) fn debug_fmt(&self, name: &str, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut line = String::new();
+        if self.write_to(&mut line).is_err() {
+            line = "<formatter error>".to_owned();
+        }
+        f.debug_tuple(name).field(&line.trim_end()).finish()
+    }
+}
+
+impl fmt::Debug for LogEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.debug_fmt("LogEntry", f)
+    }
+}
+
+/// The value of a captured header, by name.
+fn find_header<'a>(
+    headers: &'a [(HeaderName, HeaderValue)],
+    name: &str,
+) -> Option<&'a HeaderValue> {
+    headers
+        .iter()
+        .find(|(captured, _)| captured.as_str().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+/// The error a write fails with when the entry's [formatter](crate::LogFormatter) fails.
+fn format_error(_: fmt::Error) -> io::Error {
+    io::Error::other("the log formatter returned an error")
+}
+
+/// A line rendered in the text format, without the timestamp it starts with.
+#[cfg(test)]
+pub(crate) fn untimed(line: &str) -> &str {
+    line.split_once(' ').map_or(line, |(_, rest)| rest)
+}
+
+/// Snapshot a request, without logging it.
+#[cfg(test)]
+pub(crate) fn test_entry<B>(req: &http::Request<B>) -> LogEntry {
+    let log = crate::LogRequest::<&str>::from_request(req);
+    let entry = log.entry();
+    log.discard();
+    entry
+}
+
+/// The canonical entry which the golden files in `testdata/golden` are rendered from, with every
+/// field that is always available set to a fixed value.
+#[cfg(test)]
+pub(crate) fn fixture_entry() -> LogEntry {
+    let req = http::Request::post("/api/items/42?expand=owner")
+        .header("host", "api.example.com")
+        .header("content-length", "512")
+        .header("user-agent", "fixture-agent/1.0 (test)")
+        .header("referer", "https://www.example.com/items")
+        .header("x-forwarded-for", "203.0.113.7")
+        .body(())
+        .unwrap();
+    let config = std::sync::Arc::new(LogConfig::new().header_sizes(true));
+    let mut log = crate::LogRequest::<&str>::from_request_with_config(&req, config);
+    log.set_remote("192.0.2.10:51234".parse().unwrap())
+        .set_user("alice".to_owned())
+        .set_action("create")
+        .set_deadline(Duration::from_millis(10))
+        .set_geo(
+            Geo::new("NZ")
+                .unwrap()
+                .with_subdivision("AUK")
+                .with_asn(64496),
+        )
+        .set_response(
+            &http::Response::builder()
+                .status(201)
+                .header("content-length", "2048")
+                .body(())
+                .unwrap(),
+        )
+        .add_field_bytes("trace", Bytes::from_static(b"4bf92f3577b34da6"), 64);
+    let mut entry = log.entry();
+    log.discard();
+    entry.start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    entry.duration = Duration::from_micros(12_345);
+    entry.middleware = Some(Duration::from_micros(3_400));
+    entry.handler = Some(Duration::from_micros(4_567));
+    entry
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::StatusStyle;
+
+    #[test]
+    fn test_duration_ms() {
+        let mut entry = test_entry(&http::Request::new(()));
+        entry.duration = Duration::from_nanos(12_345_678);
+        assert_eq!(entry.duration_ms(), 12.345);
+        entry.duration = Duration::from_secs(2);
+        assert_eq!(entry.duration_ms(), 2000.);
+    }
+
+    #[test]
+    fn test_getters() {
+        let req = http::Request::get("/search?q=rust&page=2")
+            .body(())
+            .unwrap();
+        let mut log = crate::LogRequest::<&str>::from_request(&req);
+        log.set_remote("[::ffff:192.0.2.1]:4000".parse().unwrap())
+            .set_action("search")
+            .add_field_bytes("cache", Bytes::from_static(b"hit"), 1)
+            .add_field_bytes("raw", Bytes::from_static(b"\xff"), 10)
+            .set_response(&http::Response::builder().status(404).body(()).unwrap());
+        let entry = log.entry();
+        log.discard();
+
+        assert_eq!(entry.path(), "/search");
+        assert_eq!(entry.query(), Some("q=rust&page=2"));
+        assert_eq!(entry.client_ip(), Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(entry.status(), Some(404));
+        assert_eq!(entry.status_class(), Some(StatusClass::ClientError));
+        assert!(entry.is_error());
+        assert_eq!(entry.action_str(), Some("search"));
+        // Extras are returned in full, even if they're truncated in the log.
+        assert_eq!(entry.extra("cache"), Some("hit"));
+        assert_eq!(entry.extra("raw"), None);
+        assert_eq!(entry.extra("missing"), None);
+
+        let mut entry = test_entry(&http::Request::new(()));
+        assert_eq!(entry.query(), None);
+        assert_eq!(entry.client_ip(), None);
+        assert!(entry.is_error());
+        entry.status = Some(302);
+        assert!(!entry.is_error());
+        entry.error_kind = Some(ErrorKind::Timeout);
+        assert!(entry.is_error());
+    }
+
+    #[test]
+    fn test_outcome() {
+        use Lifecycle::*;
+        assert_eq!(Outcome::new(Some(101), Responded), Outcome::Ok);
+        assert_eq!(Outcome::new(Some(204), Responded), Outcome::Ok);
+        assert_eq!(Outcome::new(Some(302), Responded), Outcome::Redirect);
+        assert_eq!(Outcome::new(Some(404), Responded), Outcome::ClientError);
+        assert_eq!(Outcome::new(Some(503), Responded), Outcome::ServerError);
+        assert_eq!(Outcome::new(Some(200), Panicked), Outcome::Incomplete);
+        assert_eq!(Outcome::new(None, InFlight), Outcome::Incomplete);
+        assert_eq!(Outcome::new(None, Cancelled), Outcome::Incomplete);
+
+        let req = http::Request::new(());
+        let mut entry = test_entry(&req);
+        entry.action = Some("get".to_owned());
+        entry.status = Some(404);
+        let mut config = LogConfig::new().status_style(StatusStyle::Outcome);
+        entry.config = Arc::new(config.clone());
+        let line = entry.to_string();
+        assert!(
+            untimed(&line).starts_with("request: [get:client-error] "),
+            "{line}"
+        );
+        assert!(line.ends_with(" status=404\n"), "{line}");
+
+        config = config.status_style(StatusStyle::Both);
+        entry.config = Arc::new(config);
+        let line = entry.to_string();
+        assert!(
+            untimed(&line).starts_with("request: [get:404:client-error] "),
+            "{line}"
+        );
+        assert!(!line.contains("status="), "{line}");
+    }
+}
@@ -0,0 +1,162 @@
+#[cfg(feature = "hyper")]
+use std::error::Error as _;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// A machine-readable classification of why a request failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The client went away: the connection was reset or closed, or the request was cancelled.
+    ClientDisconnect,
+    /// Something took too long.
+    Timeout,
+    /// The peer violated the HTTP protocol.
+    ProtocolError,
+    /// Reading or writing a message body failed.
+    BodyError,
+    /// The application failed.
+    Internal,
+    /// Anything else.
+    Other,
+}
+
+impl ErrorKind {
+    /// Classify an IO error.
+    pub fn from_io_error(err: &io::Error) -> Self {
+        use io::ErrorKind::*;
+        match err.kind() {
+            BrokenPipe | ConnectionReset | ConnectionAborted | NotConnected | UnexpectedEof => {
+                Self::ClientDisconnect
+            }
+            TimedOut => Self::Timeout,
+            InvalidData => Self::ProtocolError,
+            _ => Self::Other,
+        }
+    }
+
+    /// Classify an error from hyper. If hyper doesn't say what kind of error it is, the IO error
+    /// which caused it is classified instead, if there is one.
+    #[cfg(feature = "hyper")]
+    pub fn from_hyper_error(err: &hyper::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout
+        } else if err.is_canceled()
+            || err.is_closed()
+            || err.is_incomplete_message()
+            || err.is_body_write_aborted()
+        {
+            Self::ClientDisconnect
+        } else if err.is_parse() || err.is_parse_status() {
+            Self::ProtocolError
+        } else if err.is_user() {
+            Self::Internal
+        } else {
+            let mut source = err.source();
+            while let Some(err) = source {
+                if let Some(io) = err.downcast_ref::<io::Error>() {
+                    return Self::from_io_error(io);
+                }
+                source = err.source();
+            }
+            Self::Other
+        }
+    }
+}
+
+impl ErrorKind {
+    /// Classify any error, by the first hyper or IO error found in it or its chain of sources.
+    /// This suits errors from HTTP clients, which usually wrap one of those.
+    pub fn from_error(err: &(dyn std::error::Error + 'static)) -> Self {
+        let mut source = Some(err);
+        while let Some(err) = source {
+            #[cfg(feature = "hyper")]
+            if let Some(err) = err.downcast_ref::<hyper::Error>() {
+                return Self::from_hyper_error(err);
+            }
+            if let Some(err) = err.downcast_ref::<io::Error>() {
+                return Self::from_io_error(err);
+            }
+            source = err.source();
+        }
+        Self::Other
+    }
+}
+
+impl From<&io::Error> for ErrorKind {
+    fn from(err: &io::Error) -> Self {
+        Self::from_io_error(err)
+    }
+}
+
+#[cfg(feature = "hyper")]
+impl From<&hyper::Error> for ErrorKind {
+    fn from(err: &hyper::Error) -> Self {
+        Self::from_hyper_error(err)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ClientDisconnect => "client-disconnect",
+            Self::Timeout => "timeout",
+            Self::ProtocolError => "protocol-error",
+            Self::BodyError => "body-error",
+            Self::Internal => "internal",
+            Self::Other => "other",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_io() {
+        let kind = |k| ErrorKind::from(&io::Error::from(k));
+        assert_eq!(kind(io::ErrorKind::BrokenPipe), ErrorKind::ClientDisconnect);
+        assert_eq!(
+            kind(io::ErrorKind::ConnectionReset),
+            ErrorKind::ClientDisconnect
+        );
+        assert_eq!(kind(io::ErrorKind::TimedOut), ErrorKind::Timeout);
+        assert_eq!(kind(io::ErrorKind::InvalidData), ErrorKind::ProtocolError);
+        assert_eq!(kind(io::ErrorKind::PermissionDenied), ErrorKind::Other);
+    }
+
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn test_hyper() {
+        use hyper_util::rt::TokioIo;
+        use tokio::io::AsyncWriteExt;
+
+        async fn serve(client_sends: &'static [u8], close: bool) -> hyper::Error {
+            let (mut client, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                TokioIo::new(server),
+                hyper::service::service_fn(|_| async {
+                    Ok::<_, io::Error>(http::Response::new(String::new()))
+                }),
+            ));
+            client.write_all(client_sends).await.unwrap();
+            if close {
+                drop(client);
+                server.await.unwrap().unwrap_err()
+            } else {
+                let err = server.await.unwrap().unwrap_err();
+                drop(client);
+                err
+            }
+        }
+
+        assert_eq!(
+            ErrorKind::from(&serve(b"GET / HTTP/1.1\r\nHo", true).await),
+            ErrorKind::ClientDisconnect
+        );
+        assert_eq!(
+            ErrorKind::from(&serve(b"GET / \xff\r\n\r\n", false).await),
+            ErrorKind::ProtocolError
+        );
+    }
+}
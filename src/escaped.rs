@@ -1,72 +1,362 @@
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter, Write};
 
+/// A value escaped the way header values are in the text formats, for logging values of your own
+/// with the same conventions.
+///
+/// Values made of printable ASCII other than `\` and `"` are written as they are; anything else
+/// is double-quoted, with `\` and `"` backslash-escaped, unprintable characters escaped as by
+/// [char::escape_debug], and bytes which aren't valid UTF-8 written as `\xDD`. So a value which
+/// starts or ends with whitespace is always quoted, and an empty value is `""`. A value which is
+/// nothing but whitespace has its spaces escaped as well, as in `"\u{20}\t"`, so that it can't be
+/// mistaken for an empty value or for the space between fields.
+///
+/// ```
+/// use hyper_req_log::{escape_to_string, Escaped};
+///
+/// assert_eq!(Escaped::from("plain").to_string(), "plain");
+/// assert_eq!(escape_to_string(b"two words\xff"), r#""two words\xff""#);
+/// assert_eq!(Escaped::json("a\tb").to_string(), r#""a\tb""#);
+/// ```
+///
+/// Escaping a borrowed value doesn't allocate: it is escaped as it is written, and plain values
+/// are written straight through. Converting from a `String` or `Vec<u8>`, or
+/// [into_owned](Self::into_owned), gives an `Escaped<'static>` which owns a copy of the value,
+/// for when the source doesn't live long enough; [escape_to_string] allocates the result.
+#[derive(Debug, Clone)]
 pub struct Escaped<'a> {
-    bytes: &'a [u8],
+    bytes: Cow<'a, [u8]>,
+    max_len: Option<usize>,
+    ascii_only: bool,
+    json: bool,
 }
 
 impl<'a, T: AsRef<[u8]> + ?Sized> From<&'a T> for Escaped<'a> {
     fn from(value: &'a T) -> Self {
         Self {
-            bytes: value.as_ref(),
+            bytes: Cow::Borrowed(value.as_ref()),
+            max_len: None,
+            ascii_only: false,
+            json: false,
         }
     }
 }
 
 impl<'a, T: AsRef<[u8]>> From<Option<&'a T>> for Escaped<'a> {
     fn from(value: Option<&'a T>) -> Self {
+        Self::from(value.map(AsRef::as_ref).unwrap_or(&[]))
+    }
+}
+
+impl From<Vec<u8>> for Escaped<'static> {
+    fn from(value: Vec<u8>) -> Self {
         Self {
-            bytes: value.map(AsRef::as_ref).unwrap_or(&[]),
+            bytes: Cow::Owned(value),
+            max_len: None,
+            ascii_only: false,
+            json: false,
         }
     }
 }
 
-impl<'a> Display for Escaped<'a> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if self.bytes.is_empty() {
-            return f.write_str("\"\"");
-        }
-        let mut range = 0..self.bytes.len();
-        while range.start != self.bytes.len() {
-            match std::str::from_utf8(&self.bytes[range.clone()]) {
-                Ok(s) => {
-                    if range == (0..self.bytes.len()) {
-                        if s.chars().all(|c| c.is_ascii_graphic() && c != '\\') {
-                            return f.write_str(s);
-                        } else {
-                            f.write_char('"')?;
-                        }
-                    }
-                    for c in s.chars() {
-                        if c == '\\' {
-                            f.write_str("\\\\")?;
-                        } else if !c.is_ascii_graphic() {
-                            write!(f, "{}", c.escape_debug())?;
-                        } else {
-                            f.write_char(c)?;
-                        }
-                    }
+impl From<String> for Escaped<'static> {
+    fn from(value: String) -> Self {
+        Self::from(value.into_bytes())
+    }
+}
+
+/// Escape bytes into a new string, as [Escaped] does.
+pub fn escape_to_string(bytes: impl AsRef<[u8]>) -> String {
+    Escaped::from(bytes.as_ref()).to_string()
+}
+
+impl<'a> Escaped<'a> {
+    /// Write the value as a JSON string: always quoted, using JSON's escapes, with control
+    /// characters, U+2028 and U+2029 written as `\uXXXX`. JSON has no way to write invalid
+    /// UTF-8, so each invalid sequence is replaced with U+FFFD instead of being escaped.
+    pub fn json(value: impl Into<Escaped<'a>>) -> Self {
+        Self {
+            json: true,
+            ..value.into()
+        }
+    }
+
+    /// Write at most `max_len` characters of the escaped value, not counting the quotes around
+    /// it, followed by `…(+N)` for the N bytes of the value which were left out. The value is cut
+    /// between characters or `\xDD` escapes, never inside one. For JSON, the marker goes inside
+    /// the quotes, so the result is still one string.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Escape every character outside of ASCII as `\u{...}` (or `\uXXXX` for JSON), as well as
+    /// the ones which are always escaped, so the output is plain ASCII.
+    pub fn ascii_only(mut self) -> Self {
+        self.ascii_only = true;
+        self
+    }
+
+    /// Copy the value if it is borrowed, so it can outlive its source.
+    pub fn into_owned(self) -> Escaped<'static> {
+        Escaped {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+            max_len: self.max_len,
+            ascii_only: self.ascii_only,
+            json: self.json,
+        }
+    }
+
+    /// Write the escaped value to `out`. Like formatting with `{}`, this doesn't allocate.
+    pub fn write_to<W: Write + ?Sized>(&self, out: &mut W) -> std::fmt::Result {
+        write!(out, "{self}")
+    }
+
+    /// Whether the value is nothing but whitespace, so its spaces are escaped too.
+    fn blank(&self) -> bool {
+        std::str::from_utf8(&self.bytes)
+            .is_ok_and(|s| !s.is_empty() && s.chars().all(char::is_whitespace))
+    }
+
+    /// The part of the value which fits in the maximum length, and how many bytes are left out.
+    fn shown(&self) -> (&[u8], usize) {
+        let bytes = &*self.bytes;
+        let Some(max_len) = self.max_len else {
+            return (bytes, 0);
+        };
+        let mut cut = Cut {
+            max_len,
+            len: 0,
+            pos: 0,
+        };
+        let fits = if self.json {
+            bytes.utf8_chunks().all(|chunk| {
+                chunk
+                    .valid()
+                    .chars()
+                    .all(|c| cut.take(c.len_utf8(), json_len(c, self.ascii_only)))
+                    && (chunk.invalid().is_empty()
+                        || cut.take(chunk.invalid().len(), json_len('\u{fffd}', self.ascii_only)))
+            })
+        } else {
+            let blank = self.blank();
+            let len = |c| match (c, blank) {
+                (' ', true) => BLANK_SPACE.len(),
+                (c, _) => escaped_len(c, self.ascii_only),
+            };
+            Pieces(bytes).all(|piece| match piece {
+                Ok(s) => s.chars().all(|c| cut.take(c.len_utf8(), len(c))),
+                Err(bytes) => bytes.iter().all(|_| cut.take(1, 4)),
+            })
+        };
+        if fits {
+            (bytes, 0)
+        } else {
+            (&bytes[..cut.pos], bytes.len() - cut.pos)
+        }
+    }
+
+    fn write_json(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (bytes, omitted) = self.shown();
+        f.write_char('"')?;
+        for chunk in bytes.utf8_chunks() {
+            write_runs(
+                f,
+                chunk.valid(),
+                |c| json_len(c, self.ascii_only) != 1,
+                |f, c| self.write_json_char(f, c),
+            )?;
+            if !chunk.invalid().is_empty() {
+                self.write_json_char(f, '\u{fffd}')?;
+            }
+        }
+        if omitted != 0 {
+            self.write_json_char(f, '…')?;
+            write!(f, "(+{omitted})")?;
+        }
+        f.write_char('"')
+    }
+
+    fn write_json_char(&self, f: &mut Formatter<'_>, c: char) -> std::fmt::Result {
+        match c {
+            '"' => f.write_str("\\\""),
+            '\\' => f.write_str("\\\\"),
+            '\n' => f.write_str("\\n"),
+            '\r' => f.write_str("\\r"),
+            '\t' => f.write_str("\\t"),
+            // Valid JSON, but not valid JavaScript.
+            '\u{2028}' | '\u{2029}' => write!(f, "\\u{:04x}", c as u32),
+            c if c.is_control() || (self.ascii_only && !c.is_ascii()) => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    write!(f, "\\u{unit:04x}")?;
                 }
-                Err(e) => {
-                    if range == (0..self.bytes.len()) {
-                        f.write_char('"')?;
-                    }
-                    if e.valid_up_to() == 0 {
-                        if let Some(len) = e.error_len() {
-                            range.end = range.start + len + 1;
-                        }
-                        // We've isolated the garbage, print it escaped
-                        for byte in &self.bytes[range.clone()] {
-                            write!(f, "\\x{byte:02x}")?;
+                Ok(())
+            }
+            c => f.write_char(c),
+        }
+    }
+}
+
+/// Tracks how much of a value fits in a maximum length.
+struct Cut {
+    max_len: usize,
+    len: usize,
+    pos: usize,
+}
+
+impl Cut {
+    /// Take `bytes` bytes of the value which are `len` characters once escaped, if they fit.
+    fn take(&mut self, bytes: usize, len: usize) -> bool {
+        self.len += len;
+        if self.len > self.max_len {
+            return false;
+        }
+        self.pos += bytes;
+        true
+    }
+}
+
+/// How a space is escaped in a value which is nothing but whitespace.
+const BLANK_SPACE: &str = "\\u{20}";
+
+/// The length of a character once escaped.
+fn escaped_len(c: char, ascii_only: bool) -> usize {
+    match c {
+        '\\' | '"' => 2,
+        c if c.is_ascii_graphic() || c == ' ' => 1,
+        c if ascii_only && !c.is_ascii() => c.escape_unicode().count(),
+        c => c.escape_debug().count(),
+    }
+}
+
+/// The length of a character once escaped for JSON.
+fn json_len(c: char, ascii_only: bool) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' => 2,
+        '\u{2028}' | '\u{2029}' => 6,
+        c if c.is_control() || (ascii_only && !c.is_ascii()) => 6 * c.len_utf16(),
+        _ => 1,
+    }
+}
+
+/// Write `s`, escaping the characters for which `needs_escape` is true with `escape`, and
+/// writing each run of characters between them with a single `write_str`.
+fn write_runs(
+    f: &mut Formatter<'_>,
+    s: &str,
+    needs_escape: impl Fn(char) -> bool,
+    escape: impl Fn(&mut Formatter<'_>, char) -> std::fmt::Result,
+) -> std::fmt::Result {
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if needs_escape(c) {
+            f.write_str(&s[start..i])?;
+            escape(f, c)?;
+            start = i + c.len_utf8();
+        }
+    }
+    f.write_str(&s[start..])
+}
+
+/// Write a byte as `\xDD`, without going through the formatting machinery.
+fn write_hex_escape(f: &mut Formatter<'_>, byte: u8) -> std::fmt::Result {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let escape = [
+        b'\\',
+        b'x',
+        HEX[usize::from(byte >> 4)],
+        HEX[usize::from(byte & 0xf)],
+    ];
+    f.write_str(std::str::from_utf8(&escape).expect("hex escapes are ASCII"))
+}
+
+/// Splits bytes into runs of valid UTF-8, and runs of invalid bytes which are written as `\xDD`
+/// escapes.
+struct Pieces<'a>(&'a [u8]);
+
+impl<'a> Iterator for Pieces<'a> {
+    type Item = Result<&'a str, &'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let len = match std::str::from_utf8(self.0) {
+            Ok(s) => {
+                self.0 = &[];
+                return Some(Ok(s));
+            }
+            Err(e) if e.valid_up_to() != 0 => e.valid_up_to(),
+            // An invalid sequence: take it and the byte following it.
+            Err(e) if e.error_len().is_some() => {
+                let len = (e.error_len().unwrap() + 1).min(self.0.len());
+                let (bad, rest) = self.0.split_at(len);
+                self.0 = rest;
+                return Some(Err(bad));
+            }
+            // A sequence truncated by the end of the input: take everything left.
+            Err(_) => {
+                let bad = std::mem::take(&mut self.0);
+                return Some(Err(bad));
+            }
+        };
+        let (valid, rest) = self.0.split_at(len);
+        self.0 = rest;
+        // SAFETY: from_utf8 found these bytes to be valid.
+        Some(Ok(unsafe { std::str::from_utf8_unchecked(valid) }))
+    }
+}
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.json {
+            return self.write_json(f);
+        }
+        let (bytes, omitted) = self.shown();
+        let plain = match std::str::from_utf8(bytes) {
+            Ok(s) if !s.is_empty() => s
+                .chars()
+                .all(|c| c.is_ascii_graphic() && c != '\\' && c != '"')
+                .then_some(s),
+            _ => None,
+        };
+        if let Some(s) = plain {
+            f.write_str(s)?;
+        } else {
+            let blank = self.blank();
+            f.write_char('"')?;
+            for piece in Pieces(bytes) {
+                match piece {
+                    Ok(s) => write_runs(
+                        f,
+                        s,
+                        |c| escaped_len(c, self.ascii_only) != 1 || (blank && c == ' '),
+                        |f, c| match c {
+                            ' ' => f.write_str(BLANK_SPACE),
+                            '\\' => f.write_str("\\\\"),
+                            '"' => f.write_str("\\\""),
+                            c if self.ascii_only && !c.is_ascii() => {
+                                write!(f, "{}", c.escape_unicode())
+                            }
+                            c => write!(f, "{}", c.escape_debug()),
+                        },
+                    )?,
+                    Err(bad) => {
+                        for &byte in bad {
+                            write_hex_escape(f, byte)?;
                         }
-                    } else {
-                        range.end = range.start + e.valid_up_to();
-                        continue;
                     }
                 }
             }
-            range = range.end..self.bytes.len();
+            f.write_char('"')?;
         }
-        f.write_char('"')
+        if omitted != 0 {
+            match self.ascii_only {
+                true => write!(f, "\\u{{2026}}(+{omitted})")?,
+                false => write!(f, "…(+{omitted})")?,
+            }
+        }
+        Ok(())
     }
 }
 
@@ -85,6 +375,14 @@ mod test {
             Escaped::from("back\\slash").to_string(),
             "\"back\\\\slash\""
         );
+        assert_eq!(
+            Escaped::from("\"quoted\"").to_string(),
+            "\"\\\"quoted\\\"\""
+        );
+        assert_eq!(
+            Escaped::from("Mozilla \"compatible\"").to_string(),
+            "\"Mozilla \\\"compatible\\\"\""
+        );
         assert_eq!(
             Escaped::from(b"bad utf8 \xc3\x28!").to_string(),
             "\"bad utf8 \\xc3\\x28!\""
@@ -98,4 +396,287 @@ mod test {
             "\"\\xc3\\x28 bad utf8\""
         );
     }
+
+    #[test]
+    fn test_whitespace() {
+        let cases: [(&str, &str); 14] = [
+            // Empty.
+            ("", r#""""#),
+            // Nothing but whitespace.
+            (" ", r#""\u{20}""#),
+            ("   ", r#""\u{20}\u{20}\u{20}""#),
+            ("\t\t", r#""\t\t""#),
+            (" \t", r#""\u{20}\t""#),
+            ("\r\n", r#""\r\n""#),
+            ("\u{a0}", r#""\u{a0}""#),
+            ("\u{3000} ", r#""\u{3000}\u{20}""#),
+            // Leading, trailing, and interior whitespace.
+            (" abc", r#"" abc""#),
+            ("abc ", r#""abc ""#),
+            ("\tabc", r#""\tabc""#),
+            ("abc\u{a0}", r#""abc\u{a0}""#),
+            ("a b", r#""a b""#),
+            ("abc", "abc"),
+        ];
+        for (value, expected) in cases {
+            let escaped = Escaped::from(value).to_string();
+            assert_eq!(escaped, expected, "{value:?}");
+            assert_eq!(unescape(&escaped), value.as_bytes(), "{value:?}");
+            let ascii = Escaped::from(value).ascii_only().to_string();
+            assert_eq!(ascii, expected, "{value:?}");
+        }
+
+        // The escapes count towards the maximum length.
+        assert_eq!(
+            Escaped::from("  ").max_len(6).to_string(),
+            r#""\u{20}"…(+1)"#
+        );
+        assert_eq!(Escaped::from("  ").max_len(5).to_string(), r#"""…(+2)"#);
+        // Only whole values of whitespace are escaped, not what's left of a longer one.
+        assert_eq!(Escaped::from("  ab").max_len(2).to_string(), r#""  "…(+2)"#);
+        // JSON has its own quoting.
+        assert_eq!(Escaped::json(" ").to_string(), r#"" ""#);
+    }
+
+    #[test]
+    fn test_ascii_only() {
+        let ascii = |value: &[u8]| Escaped::from(value).ascii_only().to_string();
+        assert_eq!(ascii(b"hello"), "hello");
+        assert_eq!(ascii(b"hello world"), "\"hello world\"");
+        assert_eq!(
+            ascii("non-åsçïï".as_bytes()),
+            "\"non-\\u{e5}s\\u{e7}\\u{ef}\\u{ef}\""
+        );
+        assert_eq!(ascii("emoji 👍".as_bytes()), "\"emoji \\u{1f44d}\"");
+        // "é" as "e" and a combining acute accent.
+        assert_eq!(ascii("cafe\u{301}".as_bytes()), "\"cafe\\u{301}\"");
+        assert_eq!(ascii(b"tab\there"), "\"tab\\there\"");
+        assert_eq!(ascii(b"bad \xc3\x28"), "\"bad \\xc3\\x28\"");
+        // The default is unchanged.
+        assert_eq!(Escaped::from("emoji 👍").to_string(), "\"emoji 👍\"");
+
+        let cut = Escaped::from("ab👍cd").ascii_only().max_len(11).to_string();
+        assert_eq!(cut, "\"ab\\u{1f44d}\"\\u{2026}(+2)");
+        let cut = Escaped::from("ab👍cd").ascii_only().max_len(10).to_string();
+        assert_eq!(cut, "ab\\u{2026}(+6)");
+
+        for value in ["non-åsçïï", "emoji 👍", "cafe\u{301}", "\u{1b}[0m"] {
+            let escaped = ascii(value.as_bytes());
+            assert!(escaped.is_ascii(), "{escaped}");
+            assert_eq!(unescape(&escaped), value.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let json = |value: &[u8]| Escaped::json(value).to_string();
+        let parse = |s: &str| serde_json::from_str::<String>(s).unwrap();
+        assert_eq!(json(b"hello"), "\"hello\"");
+        assert_eq!(json(b""), "\"\"");
+        assert_eq!(json(b"say \"hi\"\\"), "\"say \\\"hi\\\"\\\\\"");
+        assert_eq!(json(b"a\tb\r\nc"), "\"a\\tb\\r\\nc\"");
+        assert_eq!(json(b"\x1b[0m\x7f"), "\"\\u001b[0m\\u007f\"");
+        assert_eq!(json("\u{2028}\u{2029}".as_bytes()), "\"\\u2028\\u2029\"");
+        assert_eq!(json("emoji 👍".as_bytes()), "\"emoji 👍\"");
+        // Never Rust's escapes.
+        assert_eq!(json(b"bad \xc3\x28"), "\"bad \u{fffd}(\"");
+        assert_eq!(json(b"bad \xff"), "\"bad \u{fffd}\"");
+
+        let ascii = Escaped::json("é👍").ascii_only().to_string();
+        assert_eq!(ascii, "\"\\u00e9\\ud83d\\udc4d\"");
+        assert_eq!(parse(&ascii), "é👍");
+
+        for value in [
+            &b"plain"[..],
+            b"with spaces and \"quotes\" and \\",
+            b"\x00\x01\x1f\x7f control",
+            b"bad \xc3\x28 \xf0\x9f\x91 utf8 \xff",
+            "\u{2028}\u{2029} non-åsçïï 👍".as_bytes(),
+        ] {
+            let lossy = String::from_utf8_lossy(value);
+            assert_eq!(parse(&json(value)), lossy);
+            let ascii = Escaped::json(value).ascii_only().to_string();
+            assert!(ascii.is_ascii(), "{ascii}");
+            assert_eq!(parse(&ascii), lossy);
+        }
+    }
+
+    #[test]
+    fn test_json_max_len() {
+        let cut = |value: &[u8], max_len| Escaped::json(value).max_len(max_len).to_string();
+        assert_eq!(cut(b"abcdef", 6), "\"abcdef\"");
+        assert_eq!(cut(b"abcdef", 3), "\"abc…(+3)\"");
+        assert_eq!(cut(b"a\"bc", 2), "\"a…(+3)\"");
+        assert_eq!(cut(b"a\"bc", 3), "\"a\\\"…(+2)\"");
+        assert_eq!(cut(b"\x1bz", 5), "\"…(+2)\"");
+        // Invalid UTF-8 is one character, however many bytes it was.
+        assert_eq!(cut(b"a\xf0\x9f\x91b", 2), "\"a\u{fffd}…(+1)\"");
+        assert_eq!(cut(b"a\xf0\x9f\x91b", 1), "\"a…(+4)\"");
+
+        let ascii = Escaped::json("a👍b").ascii_only().max_len(12).to_string();
+        assert_eq!(ascii, "\"a\\u2026(+5)\"");
+        let ascii = Escaped::json("a👍b").ascii_only().max_len(13).to_string();
+        assert_eq!(ascii, "\"a\\ud83d\\udc4d\\u2026(+1)\"");
+        assert_eq!(serde_json::from_str::<String>(&ascii).unwrap(), "a👍…(+1)");
+    }
+
+    #[test]
+    fn test_owned() {
+        fn escape_in_closure() -> Escaped<'static> {
+            let value = format!("{} {}", "computed", "value");
+            Escaped::from(value)
+        }
+        assert_eq!(escape_in_closure().to_string(), "\"computed value\"");
+        assert_eq!(Escaped::from(b"a\xff".to_vec()).to_string(), "\"a\\xff\"");
+
+        let owned = {
+            let value = String::from("long value");
+            Escaped::from(&value).max_len(4).into_owned()
+        };
+        assert_eq!(owned.to_string(), "long…(+6)");
+        let owned = Escaped::json(String::from("x\"y")).into_owned();
+        assert_eq!(owned.to_string(), "\"x\\\"y\"");
+    }
+
+    #[test]
+    fn test_write_to() {
+        let mut out = String::from("key=");
+        Escaped::from("two words").write_to(&mut out).unwrap();
+        assert_eq!(out, "key=\"two words\"");
+        assert_eq!(escape_to_string("two words"), "\"two words\"");
+        assert_eq!(escape_to_string(b"plain"), "plain");
+        assert_eq!(escape_to_string(Vec::from(&b"\xff"[..])), "\"\\xff\"");
+    }
+
+    #[test]
+    fn test_escape_truncated() {
+        // lone invalid bytes
+        assert_eq!(Escaped::from(b"\xff").to_string(), "\"\\xff\"");
+        assert_eq!(Escaped::from(b"a\xff").to_string(), "\"a\\xff\"");
+        // truncated sequences at the end
+        assert_eq!(Escaped::from(b"a\xc3").to_string(), "\"a\\xc3\"");
+        assert_eq!(Escaped::from(b"a\xe2\x82").to_string(), "\"a\\xe2\\x82\"");
+        assert_eq!(
+            Escaped::from(b"a\xf0\x9f\x91").to_string(),
+            "\"a\\xf0\\x9f\\x91\""
+        );
+        assert_eq!(
+            Escaped::from(b"\xf0\x9f\x91").to_string(),
+            "\"\\xf0\\x9f\\x91\""
+        );
+        // truncated sequences in the middle
+        assert_eq!(Escaped::from(b"a\xc3 b").to_string(), "\"a\\xc3\\x20b\"");
+        assert_eq!(
+            Escaped::from(b"a\xe2\x82 b").to_string(),
+            "\"a\\xe2\\x82\\x20b\""
+        );
+        assert_eq!(
+            Escaped::from(b"a\xf0\x9f\x91 b").to_string(),
+            "\"a\\xf0\\x9f\\x91\\x20b\""
+        );
+    }
+
+    #[test]
+    fn test_max_len() {
+        let cut = |value: &[u8], max_len| Escaped::from(value).max_len(max_len).to_string();
+        assert_eq!(cut(b"short", 5), "short");
+        assert_eq!(cut(b"shorter", 5), "short…(+2)");
+        assert_eq!(cut(b"", 0), "\"\"");
+        assert_eq!(cut(b"abc", 0), "\"\"…(+3)");
+        // Quoting depends on the part that's shown.
+        assert_eq!(cut(b"abc def", 3), "abc…(+4)");
+        assert_eq!(cut(b"ab cdef", 3), "\"ab \"…(+4)");
+
+        // Multi-byte characters are never split, and count once.
+        let long = "é".repeat(300);
+        assert_eq!(
+            cut(long.as_bytes(), 256),
+            format!("\"{}\"…(+88)", "é".repeat(256))
+        );
+        // Escapes take their full length, and aren't split either.
+        assert_eq!(cut(b"a\\b", 2), "a…(+2)");
+        assert_eq!(cut(b"a\\b", 3), "\"a\\\\\"…(+1)");
+        assert_eq!(cut(b"a\"b", 2), "a…(+2)");
+
+        // Invalid bytes near the cut point are escaped whole.
+        let xs = "x".repeat(250);
+        let mut bad = xs.clone().into_bytes();
+        bad.extend_from_slice(b"\xff\xfe tail");
+        assert_eq!(cut(&bad, 256), format!("\"{xs}\\xff\"…(+6)"));
+        assert_eq!(cut(&bad, 257), format!("\"{xs}\\xff\"…(+6)"));
+        assert_eq!(cut(&bad, 258), format!("\"{xs}\\xff\\xfe\"…(+5)"));
+        let xs = "x".repeat(254);
+        let mut euro = xs.clone().into_bytes();
+        euro.extend_from_slice(b"\xe2\x82\xac\xe2\x82");
+        assert_eq!(cut(&euro, 254), format!("{xs}…(+5)"));
+        assert_eq!(cut(&euro, 255), format!("\"{xs}€\"…(+2)"));
+        assert_eq!(cut(&euro, 256), format!("\"{xs}€\"…(+2)"));
+
+        // Whatever is shown round-trips.
+        let value = b"a \"b\" \\ \xc3\x28 \xe2\x82\xac \t end";
+        for max_len in 0..40 {
+            let escaped = cut(value, max_len);
+            let (shown, marker) = escaped.rsplit_once('…').unwrap_or((&escaped, ""));
+            let shown = unescape(shown);
+            assert!(value.starts_with(&shown), "{max_len}: {escaped}");
+            if !marker.is_empty() {
+                assert_eq!(marker, format!("(+{})", value.len() - shown.len()));
+            }
+        }
+    }
+
+    /// Reverse the escaping, for the subset of output `Escaped` produces.
+    fn unescape(s: &str) -> Vec<u8> {
+        let Some(s) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+            return s.as_bytes().to_vec();
+        };
+        let mut out = vec![];
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+            let c = match chars.next().unwrap() {
+                'x' => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    out.push(u8::from_str_radix(&hex, 16).unwrap());
+                    continue;
+                }
+                'u' => {
+                    assert_eq!(chars.next(), Some('{'));
+                    let hex = chars.by_ref().take_while(|&c| c != '}').collect::<String>();
+                    char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap()
+                }
+                't' => '\t',
+                'r' => '\r',
+                'n' => '\n',
+                '0' => '\0',
+                c => c,
+            };
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_escape_round_trip() {
+        let mut inputs = (0..=0xffff_u32)
+            .map(|n| n.to_be_bytes()[2..].to_vec())
+            .collect::<Vec<_>>();
+        inputs.extend((0..=0xff).map(|b| vec![b]));
+        for prefix in [&b"\xf0\x9f\x91"[..], b"\xe2\x82", b"\xc3", b"\xf0\x9f"] {
+            for suffix in [&b""[..], b"!", b"\xc3\xa9", b"\xff"] {
+                inputs.push([b"x", prefix, suffix].concat());
+            }
+        }
+        inputs.push(b"Mozilla \"compatible\"".to_vec());
+        inputs.push(b"\"".to_vec());
+        inputs.push(b"\\\"".to_vec());
+        for input in inputs {
+            let escaped = Escaped::from(&input).to_string();
+            assert!(escaped.len() >= input.len(), "{input:x?} => {escaped}");
+            assert_eq!(unescape(&escaped), input, "{input:x?} => {escaped}");
+        }
+    }
 }
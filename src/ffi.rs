@@ -0,0 +1,291 @@
+//! A C interface to [LogEntryInput::render](crate::LogEntryInput::render), for programs in other
+//! languages which want to produce the same lines.
+//!
+//! The function is exported unmangled, so it can be called from C when this crate is linked into
+//! a `staticlib` or `cdylib`. The matching declarations are:
+//!
+//! ```c
+//! struct hrl_extra {
+//!     const char *key;
+//!     const char *value;
+//! };
+//!
+//! struct hrl_entry_input {
+//!     const char *action;
+//!     const char *user;
+//!     const char *remote;
+//!     const char *forwarded_for;
+//!     const char *host;
+//!     const char *method;
+//!     const char *uri;
+//!     const char *version;
+//!     const char *user_agent;
+//!     const char *referer;
+//!     const char *request_id;
+//!     const char *server_name;
+//!     const char *error;
+//!     const struct hrl_extra *extras;
+//!     size_t extras_len;
+//!     uint64_t start_unix_nanos;
+//!     uint64_t duration_nanos;
+//!     int64_t handler_nanos;
+//!     int64_t response_bytes;
+//!     uint16_t status;
+//!     uint8_t client;
+//! };
+//!
+//! ptrdiff_t hyper_req_log_render(const struct hrl_entry_input *input, char *buf, size_t cap);
+//! ```
+
+use std::ffi::{c_char, CStr};
+use std::time::SystemTime;
+
+use crate::input::LogEntryInput;
+
+/// An extra field, for [HrlEntryInput::extras].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HrlExtra {
+    /// The key, as NUL-terminated UTF-8.
+    pub key: *const c_char,
+    /// The value, as NUL-terminated UTF-8.
+    pub value: *const c_char,
+}
+
+/// The C layout of a [LogEntryInput]. Strings are NUL-terminated UTF-8, and may be null where
+/// the field is optional. A null `method`, `uri`, or `version` takes the default.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HrlEntryInput {
+    /// [LogEntryInput::action].
+    pub action: *const c_char,
+    /// [LogEntryInput::user].
+    pub user: *const c_char,
+    /// [LogEntryInput::remote].
+    pub remote: *const c_char,
+    /// [LogEntryInput::forwarded_for].
+    pub forwarded_for: *const c_char,
+    /// [LogEntryInput::host].
+    pub host: *const c_char,
+    /// [LogEntryInput::method].
+    pub method: *const c_char,
+    /// [LogEntryInput::uri].
+    pub uri: *const c_char,
+    /// [LogEntryInput::version].
+    pub version: *const c_char,
+    /// [LogEntryInput::user_agent].
+    pub user_agent: *const c_char,
+    /// [LogEntryInput::referer].
+    pub referer: *const c_char,
+    /// [LogEntryInput::request_id].
+    pub request_id: *const c_char,
+    /// [LogEntryInput::server_name].
+    pub server_name: *const c_char,
+    /// [LogEntryInput::error].
+    pub error: *const c_char,
+    /// [LogEntryInput::extras], as an array of `extras_len` fields. May be null if there are
+    /// none.
+    pub extras: *const HrlExtra,
+    /// The length of `extras`.
+    pub extras_len: usize,
+    /// [LogEntryInput::start_unix_nanos], or 0 for the time of the call.
+    pub start_unix_nanos: u64,
+    /// [LogEntryInput::duration_nanos].
+    pub duration_nanos: u64,
+    /// [LogEntryInput::handler_nanos], or -1 if it isn't known.
+    pub handler_nanos: i64,
+    /// [LogEntryInput::response_bytes], or -1 if it isn't known.
+    pub response_bytes: i64,
+    /// [LogEntryInput::status], or 0 if there isn't one.
+    pub status: u16,
+    /// [LogEntryInput::client]: 1 for a client request, 0 otherwise.
+    pub client: u8,
+}
+
+/// Render a request in the text format, like `snprintf`: the line and a terminating NUL are
+/// written to `buf` if they fit in `cap` bytes, and the length of the line without the NUL is
+/// returned either way. Nothing is written if it doesn't fit. Returns -1 if the input is invalid.
+///
+/// # Safety
+///
+/// `input` must point to a valid [HrlEntryInput], whose non-null strings are NUL-terminated and
+/// whose `extras` points to `extras_len` valid [HrlExtra] if it isn't null. `buf` must be valid
+/// for writes of `cap` bytes, or may be null if `cap` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn hyper_req_log_render(
+    input: *const HrlEntryInput,
+    buf: *mut u8,
+    cap: usize,
+) -> isize {
+    let Some(input) = (unsafe { input.as_ref() }) else {
+        return -1;
+    };
+    let Some(input) = (unsafe { convert(input) }) else {
+        return -1;
+    };
+    let mut line = String::new();
+    if input.render(&mut line).is_err() {
+        return -1;
+    }
+    if line.len() < cap {
+        // SAFETY: the caller guarantees `buf` is valid for `cap` bytes, which is more than the
+        // line.
+        unsafe {
+            std::ptr::copy_nonoverlapping(line.as_ptr(), buf, line.len());
+            *buf.add(line.len()) = 0;
+        }
+    }
+    line.len() as isize
+}
+
+/// Convert the C input, or return `None` if any of its strings aren't UTF-8.
+///
+/// # Safety
+///
+/// As for [hyper_req_log_render].
+unsafe fn convert(input: &HrlEntryInput) -> Option<LogEntryInput> {
+    let extras = match input.extras.is_null() {
+        true => &[][..],
+        // SAFETY: the caller guarantees `extras` points to `extras_len` fields.
+        false => unsafe { std::slice::from_raw_parts(input.extras, input.extras_len) },
+    };
+    let defaults = LogEntryInput::default();
+    Some(LogEntryInput {
+        client: input.client != 0,
+        request_id: unsafe { string(input.request_id)? },
+        action: unsafe { string(input.action)? },
+        status: Some(input.status).filter(|&s| s != 0),
+        user: unsafe { string(input.user)? },
+        remote: unsafe { string(input.remote)? },
+        forwarded_for: unsafe { string(input.forwarded_for)? },
+        host: unsafe { string(input.host)? },
+        server_name: unsafe { string(input.server_name)? },
+        method: unsafe { string(input.method)? }.unwrap_or(defaults.method),
+        uri: unsafe { string(input.uri)? }.unwrap_or(defaults.uri),
+        version: unsafe { string(input.version)? }.unwrap_or(defaults.version),
+        user_agent: unsafe { string(input.user_agent)? },
+        referer: unsafe { string(input.referer)? },
+        duration_nanos: input.duration_nanos,
+        handler_nanos: u64::try_from(input.handler_nanos).ok(),
+        start_unix_nanos: match input.start_unix_nanos {
+            0 => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos() as u64),
+            start => start,
+        },
+        response_bytes: u64::try_from(input.response_bytes).ok(),
+        error: unsafe { string(input.error)? },
+        extras: extras
+            .iter()
+            .map(|extra| unsafe { Some((string(extra.key)??, string(extra.value)??)) })
+            .collect::<Option<_>>()?,
+    })
+}
+
+/// Read a nullable C string: `None` if it isn't UTF-8, and `Some(None)` if it is null.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string.
+unsafe fn string(s: *const c_char) -> Option<Option<String>> {
+    if s.is_null() {
+        return Some(None);
+    }
+    // SAFETY: the caller guarantees `s` is NUL-terminated.
+    let s = unsafe { CStr::from_ptr(s) };
+    s.to_str().ok().map(|s| Some(s.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr::null;
+
+    #[test]
+    fn test_render() {
+        let extras = [HrlExtra {
+            key: c"trace".as_ptr(),
+            value: c"abc".as_ptr(),
+        }];
+        let c_input = HrlEntryInput {
+            action: c"get".as_ptr(),
+            user: null(),
+            remote: c"192.0.2.1:4000".as_ptr(),
+            forwarded_for: null(),
+            host: c"example.com".as_ptr(),
+            method: null(),
+            uri: c"/hello".as_ptr(),
+            version: null(),
+            user_agent: null(),
+            referer: null(),
+            request_id: c"req-1".as_ptr(),
+            server_name: c"www".as_ptr(),
+            error: c"upstream reset".as_ptr(),
+            extras: extras.as_ptr(),
+            extras_len: extras.len(),
+            start_unix_nanos: 1_700_000_000_000_000_000,
+            duration_nanos: 1_500_000,
+            handler_nanos: 1_000_000,
+            response_bytes: 6,
+            status: 200,
+            client: 0,
+        };
+        let input = LogEntryInput {
+            action: Some("get".to_owned()),
+            status: Some(200),
+            remote: Some("192.0.2.1:4000".to_owned()),
+            host: Some("example.com".to_owned()),
+            uri: "/hello".to_owned(),
+            request_id: Some("req-1".to_owned()),
+            server_name: Some("www".to_owned()),
+            error: Some("upstream reset".to_owned()),
+            start_unix_nanos: 1_700_000_000_000_000_000,
+            duration_nanos: 1_500_000,
+            handler_nanos: Some(1_000_000),
+            response_bytes: Some(6),
+            extras: vec![("trace".to_owned(), "abc".to_owned())],
+            ..Default::default()
+        };
+        let mut expected = String::new();
+        input.render(&mut expected).unwrap();
+
+        let len = unsafe { hyper_req_log_render(&c_input, std::ptr::null_mut(), 0) };
+        assert_eq!(len as usize, expected.len());
+        let mut buf = vec![0xff; expected.len()];
+        // Too small for the NUL, so nothing is written.
+        let len = unsafe { hyper_req_log_render(&c_input, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(len as usize, expected.len());
+        assert!(buf.iter().all(|&b| b == 0xff));
+
+        let mut buf = vec![0xff; 256];
+        let len = unsafe { hyper_req_log_render(&c_input, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(&buf[..len as usize], expected.as_bytes());
+        assert_eq!(buf[len as usize], 0);
+        assert!(
+            expected.starts_with("2023-11-14T22:13:20.000Z "),
+            "{expected}"
+        );
+
+        // A start time of 0 is the time of the call.
+        let now = HrlEntryInput {
+            start_unix_nanos: 0,
+            ..c_input
+        };
+        let len = unsafe { hyper_req_log_render(&now, buf.as_mut_ptr(), buf.len()) };
+        let line = std::str::from_utf8(&buf[..len as usize]).unwrap();
+        assert!(!line.starts_with("1970-") && line[..4] >= *"2024", "{line}");
+
+        let bad = HrlEntryInput {
+            method: c"GE T".as_ptr(),
+            ..c_input
+        };
+        assert_eq!(
+            unsafe { hyper_req_log_render(&bad, buf.as_mut_ptr(), 256) },
+            -1
+        );
+        assert_eq!(
+            unsafe { hyper_req_log_render(null(), buf.as_mut_ptr(), 256) },
+            -1
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static DEFAULT_FIELD_SET: AtomicU8 = AtomicU8::new(FieldSet::ALL.0);
+
+/// Set which optional fields are logged for entries whose request and config don't say, in
+/// place of all of them. It applies to every entry rendered afterwards.
+pub fn set_default_field_set(fields: FieldSet) {
+    DEFAULT_FIELD_SET.store(fields.0, Ordering::Relaxed);
+}
+
+/// The setting made with [set_default_field_set].
+pub(crate) fn default_field_set() -> FieldSet {
+    FieldSet(DEFAULT_FIELD_SET.load(Ordering::Relaxed))
+}
+
+/// Which of the optional fields of an entry are logged, for slimming down lines by leaving out
+/// ones which are never looked at:
+///
+/// ```
+/// use hyper_req_log::{FieldSet, LogConfig};
+///
+/// let fields = FieldSet::default().without_user_agent().without_referer();
+/// let config = LogConfig::new().field_set(fields);
+/// ```
+///
+/// The status, method, URI, and duration are always logged. The fields which aren't selected are
+/// left out of the text, pretty, and structured formats entirely, with no placeholder, so the
+/// columns of the text format which remain keep their order. In the text format, the forwarded
+/// chain is part of the remote address's column, so it is left out along with it.
+///
+/// The default is [FieldSet::ALL], unless changed with [set_default_field_set].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldSet(u8);
+
+impl FieldSet {
+    const USER: u8 = 1 << 0;
+    const REMOTE: u8 = 1 << 1;
+    const FORWARDED: u8 = 1 << 2;
+    const HOST: u8 = 1 << 3;
+    const USER_AGENT: u8 = 1 << 4;
+    const REFERER: u8 = 1 << 5;
+    const VERSION: u8 = 1 << 6;
+
+    /// Every field.
+    pub const ALL: Self = Self(0x7f);
+
+    /// None of the optional fields: just the status, method, URI, and duration, and whichever
+    /// other fields, like the action or request ID, were set.
+    pub const MINIMAL: Self = Self(0);
+
+    /// Leave out the user.
+    pub fn without_user(self) -> Self {
+        Self(self.0 & !Self::USER)
+    }
+
+    /// Leave out the remote address, and in the text format, the forwarded chain with it.
+    pub fn without_remote(self) -> Self {
+        Self(self.0 & !Self::REMOTE)
+    }
+
+    /// Leave out the chain of forwarded addresses from `Forwarded` or `X-Forwarded-For`.
+    pub fn without_forwarded(self) -> Self {
+        Self(self.0 & !Self::FORWARDED)
+    }
+
+    /// Leave out the `Host` header.
+    pub fn without_host(self) -> Self {
+        Self(self.0 & !Self::HOST)
+    }
+
+    /// Leave out the `User-Agent` header.
+    pub fn without_user_agent(self) -> Self {
+        Self(self.0 & !Self::USER_AGENT)
+    }
+
+    /// Leave out the `Referer` header.
+    pub fn without_referer(self) -> Self {
+        Self(self.0 & !Self::REFERER)
+    }
+
+    /// Leave out the HTTP version.
+    pub fn without_version(self) -> Self {
+        Self(self.0 & !Self::VERSION)
+    }
+
+    /// Log the user, for building up from [FieldSet::MINIMAL].
+    pub fn with_user(self) -> Self {
+        Self(self.0 | Self::USER)
+    }
+
+    /// Log the remote address.
+    pub fn with_remote(self) -> Self {
+        Self(self.0 | Self::REMOTE)
+    }
+
+    /// Log the chain of forwarded addresses.
+    pub fn with_forwarded(self) -> Self {
+        Self(self.0 | Self::FORWARDED)
+    }
+
+    /// Log the `Host` header.
+    pub fn with_host(self) -> Self {
+        Self(self.0 | Self::HOST)
+    }
+
+    /// Log the `User-Agent` header.
+    pub fn with_user_agent(self) -> Self {
+        Self(self.0 | Self::USER_AGENT)
+    }
+
+    /// Log the `Referer` header.
+    pub fn with_referer(self) -> Self {
+        Self(self.0 | Self::REFERER)
+    }
+
+    /// Log the HTTP version.
+    pub fn with_version(self) -> Self {
+        Self(self.0 | Self::VERSION)
+    }
+
+    pub(crate) fn user(self) -> bool {
+        self.0 & Self::USER != 0
+    }
+
+    pub(crate) fn remote(self) -> bool {
+        self.0 & Self::REMOTE != 0
+    }
+
+    pub(crate) fn forwarded(self) -> bool {
+        self.0 & Self::FORWARDED != 0
+    }
+
+    pub(crate) fn host(self) -> bool {
+        self.0 & Self::HOST != 0
+    }
+
+    pub(crate) fn user_agent(self) -> bool {
+        self.0 & Self::USER_AGENT != 0
+    }
+
+    pub(crate) fn referer(self) -> bool {
+        self.0 & Self::REFERER != 0
+    }
+
+    pub(crate) fn version(self) -> bool {
+        self.0 & Self::VERSION != 0
+    }
+}
+
+impl Default for FieldSet {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let fields = FieldSet::default().without_user_agent().without_referer();
+        assert!(fields.user() && fields.remote() && fields.host() && fields.version());
+        assert!(!fields.user_agent() && !fields.referer());
+        assert_eq!(fields.with_user_agent().with_referer(), FieldSet::ALL);
+        let minimal = FieldSet::MINIMAL.with_host();
+        assert!(minimal.host() && !minimal.user() && !minimal.version());
+        assert_eq!(minimal.without_host(), FieldSet::MINIMAL);
+    }
+}
@@ -0,0 +1,561 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Write};
+use std::time::Duration;
+
+use http::HeaderName;
+
+use crate::entry::{Lifecycle, LogEntry};
+use crate::format::{http_version, LogMethod, Remote, RemoteIp, SCHEMA_VERSION};
+use crate::forwarded::Chain;
+use crate::query::{log_query, LogUri};
+use crate::time::TimestampStyle;
+use crate::trace_context::TraceParent;
+
+/// How the keys of the structured formats ([Format::Json](crate::Format::Json),
+/// [Format::Logfmt](crate::Format::Logfmt), and `serde::Serialize`) are named, set with
+/// [LogConfig::field_names](crate::LogConfig::field_names).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldNames {
+    /// This crate's own names, such as `status`, `uri`, and `duration_ms`.
+    #[default]
+    Native,
+    /// The names of the OpenTelemetry HTTP semantic conventions, where there is one, such as
+    /// `http.response.status_code`, `url.path`, and `client.address`, with their values as the
+    /// conventions give them: the URI is split into its path and query, the remote address into
+    /// its IP and port, the HTTP version is just the number, as in `1.1`, the duration is a
+    /// number of seconds, and captured headers are `http.request.header.<name>` and
+    /// `http.response.header.<name>`. Fields the conventions don't cover keep their native
+    /// names.
+    Otel,
+}
+
+/// The [FieldNames::Otel] names of the keys [write_fields] writes, for those which have one. Some
+/// of these native keys are only written with [FieldNames::Otel], for values it splits up or
+/// gives in other units.
+const OTEL_KEYS: &[(&str, &str)] = &[
+    ("status", "http.response.status_code"),
+    ("grpc_status", "rpc.grpc.status_code"),
+    ("action", "http.route"),
+    ("user", "user.name"),
+    ("remote", "client.address"),
+    ("remote_port", "client.port"),
+    ("host", "server.address"),
+    ("method", "http.request.method"),
+    ("path", "url.path"),
+    ("query", "url.query"),
+    ("version", "network.protocol.version"),
+    ("user_agent", "user_agent.original"),
+    ("duration", "http.server.request.duration"),
+    ("bytes_in", "http.request.body.size"),
+    ("bytes", "http.response.body.size"),
+    ("err_kind", "error.type"),
+    ("cipher", "tls.cipher"),
+    ("alpn", "tls.next_protocol"),
+];
+
+/// Whether a key is one [write_fields] can write under either naming scheme, other than those
+/// of captured headers. Formats which write extra fields alongside them use this to keep an
+/// extra field from being mistaken for one of these.
+pub(crate) fn is_key(key: &str) -> bool {
+    KEYS.contains(&key) || OTEL_KEYS.iter().any(|&(_, otel)| otel == key)
+}
+
+/// The key a captured request header is logged under: its name, or `req.<name>` if that is one
+/// of the [keys](is_key) of an entry's own fields, such as `host` or `traceparent`, as extra
+/// fields are kept apart with `extra.`.
+pub(crate) fn header_key(name: &HeaderName) -> Cow<'_, str> {
+    match is_key(name.as_str()) {
+        true => Cow::Owned(format!("req.{name}")),
+        false => Cow::Borrowed(name.as_str()),
+    }
+}
+
+/// A header value as the formats other than text and pretty log it, before the format escapes
+/// it: with every non-ASCII character written as `\u{...}` and every byte which isn't valid
+/// UTF-8 as `\x..` if [LogConfig::ascii_headers](crate::LogConfig::ascii_headers) is enabled, and
+/// cut to [LogConfig::max_header_len](crate::LogConfig::max_header_len) characters, counting
+/// those escapes, and followed by `…(+N)` for the N bytes left out, as in the text format.
+pub(crate) fn header_value<'a>(entry: &LogEntry, value: &'a [u8]) -> Cow<'a, [u8]> {
+    let ascii = entry.config.ascii_headers;
+    let max_len = entry.config.max_header_len;
+    if (!ascii || value.is_ascii()) && max_len.is_none_or(|max_len| value.len() <= max_len) {
+        return Cow::Borrowed(value);
+    }
+    let max_len = max_len.unwrap_or(usize::MAX);
+    // The characters written so far, and the bytes of the value they stand for.
+    let (mut out, mut len, mut taken) = (Vec::with_capacity(value.len()), 0, 0);
+    'cut: for chunk in value.utf8_chunks() {
+        let invalid = chunk.invalid().iter().copied().map(Err);
+        for unit in chunk.valid().chars().map(Ok).chain(invalid) {
+            let start = out.len();
+            let (chars, width) = match unit {
+                Ok(c) if ascii && !c.is_ascii() => {
+                    write!(ByteWriter(&mut out), "\\u{{{:x}}}", c as u32).unwrap();
+                    (out.len() - start, c.len_utf8())
+                }
+                Ok(c) => {
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    (1, c.len_utf8())
+                }
+                Err(b) if ascii => {
+                    write!(ByteWriter(&mut out), "\\x{b:02x}").unwrap();
+                    (out.len() - start, 1)
+                }
+                Err(b) => {
+                    out.push(b);
+                    (1, 1)
+                }
+            };
+            if len + chars > max_len {
+                out.truncate(start);
+                break 'cut;
+            }
+            len += chars;
+            taken += width;
+        }
+    }
+    if taken < value.len() {
+        let marker = match ascii {
+            true => "\\u{2026}",
+            false => "…",
+        };
+        write!(ByteWriter(&mut out), "{marker}(+{})", value.len() - taken).unwrap();
+    }
+    Cow::Owned(out)
+}
+
+/// A structured format, such as [Format::Json](crate::Format::Json), which the fields of an
+/// entry are written to one at a time by [write_fields].
+pub(crate) trait Fields {
+    /// A string, which may not be valid UTF-8.
+    fn string(&mut self, key: &str, value: &[u8]) -> fmt::Result;
+
+    /// A number, as rendered by its `Display` impl.
+    fn number(&mut self, key: &str, value: &dyn Display) -> fmt::Result;
+
+    /// The extra fields, whose keys are chosen by the application.
+    fn extras(&mut self, extras: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result;
+
+    /// A list of strings, for formats which have arrays. Others leave it out, so it should only
+    /// be used for something already written another way.
+    fn strings(&mut self, _key: &str, _values: &[String]) -> fmt::Result {
+        Ok(())
+    }
+
+    /// A string, as rendered by a `Display` impl.
+    fn display(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        self.string(key, value.to_string().as_bytes())
+    }
+}
+
+/// The keys [write_fields] can write with [FieldNames::Native], other than those of captured
+/// headers.
+pub(crate) const KEYS: &[&str] = &[
+    "timestamp",
+    "schema",
+    "request_id",
+    "trace_id",
+    "span_id",
+    "traceparent",
+    "status",
+    "status_text",
+    "grpc_status",
+    "outcome",
+    "lifecycle",
+    "cache_status",
+    "sample_rate",
+    "action",
+    "actions",
+    "user",
+    "remote",
+    "peer",
+    "remote_host",
+    "geo",
+    "asn",
+    "fp",
+    "tracestate",
+    "forwarded_for",
+    "client_ip",
+    "forwarded_trusted",
+    "real_ip_mismatch",
+    "forwarded_proto",
+    "forwarded_host",
+    "host",
+    "authority_mismatch",
+    "server",
+    "method",
+    "uri",
+    "version",
+    "user_agent",
+    "referer",
+    "handler_ms",
+    "duration_ms",
+    "bytes_in",
+    "bytes",
+    "body",
+    "err_kind",
+    "err",
+    "late_ms",
+    "budget_ms",
+    "over_ms",
+    "slow",
+    "slow_threshold_ms",
+    "upstream_ms",
+    "mw_ms",
+    "sent_duration_ms",
+    "hdr_in",
+    "hdr_out",
+    "conn_id",
+    "conn_seq",
+    "setup",
+    "tls",
+    "cipher",
+    "sni",
+    "alpn",
+    "client_cn",
+    "upgrade",
+    "accept",
+    "content_type",
+    "content_encoding",
+    "accept_header",
+    "extras_dropped",
+];
+
+/// Write the fields of an entry which were captured, in a fixed order, named by its
+/// [FieldNames]. Durations are numbers of milliseconds under keys ending in `_ms`.
+pub(crate) fn write_fields(entry: &LogEntry, out: &mut dyn Fields) -> fmt::Result {
+    match entry.config.field_names {
+        FieldNames::Native => write_named(entry, out),
+        FieldNames::Otel => write_named(entry, &mut Otel(out)),
+    }
+}
+
+/// Write the fields of an entry under their native keys, with the values [FieldNames::Otel]
+/// calls for if the entry uses it.
+fn write_named(entry: &LogEntry, out: &mut dyn Fields) -> fmt::Result {
+    let otel = entry.config.field_names == FieldNames::Otel;
+    let fields = entry.field_set();
+    if let Some(timestamp) = entry.styled_timestamp() {
+        match entry.timestamp_style() {
+            TimestampStyle::EpochMillis => out.number("timestamp", &timestamp)?,
+            _ => out.display("timestamp", &timestamp)?,
+        }
+    }
+    out.number("schema", &SCHEMA_VERSION)?;
+    if let Some(id) = &entry.request_id {
+        out.string("request_id", id.as_bytes())?;
+    }
+    match &entry.trace_parent {
+        Some(TraceParent::Valid { trace_id, span_id }) => {
+            out.string("trace_id", trace_id.as_bytes())?;
+            out.string("span_id", span_id.as_bytes())?;
+        }
+        Some(TraceParent::Malformed(header)) => out.string("traceparent", header.as_bytes())?,
+        None => {}
+    }
+    if let Some(status) = entry.status {
+        out.number("status", &status)?;
+    }
+    if let Some(reason) = entry.status_reason() {
+        out.string("status_text", reason.as_bytes())?;
+    }
+    if let Some(grpc) = entry.grpc_status {
+        out.number("grpc_status", &grpc)?;
+    }
+    out.display("outcome", &entry.outcome())?;
+    let lifecycle = match entry.lifecycle {
+        Lifecycle::Responded => None,
+        Lifecycle::InFlight => Some("in-flight"),
+        Lifecycle::Cancelled => Some("cancelled"),
+        Lifecycle::Panicked => Some("panicked"),
+        Lifecycle::Stuck => Some("stuck"),
+    };
+    if let Some(lifecycle) = lifecycle {
+        out.string("lifecycle", lifecycle.as_bytes())?;
+    }
+    if let Some(cache) = &entry.cache_status {
+        out.string("cache_status", cache.as_str().as_bytes())?;
+    }
+    if let Some(rate) = entry.sample_rate {
+        out.number("sample_rate", &rate)?;
+    }
+    if let Some(act) = &entry.action {
+        out.string("action", act.as_bytes())?;
+    }
+    if !entry.action_chain.is_empty() {
+        out.strings("actions", &entry.action_chain)?;
+    }
+    if let Some(user) = entry.user.as_ref().filter(|_| fields.user()) {
+        out.string("user", user)?;
+    }
+    if let Some(remote) = entry.remote.filter(|_| fields.remote()) {
+        match (entry.client, otel) {
+            (false, true) => {
+                out.display("remote", &RemoteIp(remote.ip()))?;
+                if remote.port() != 0 {
+                    out.number("remote_port", &remote.port())?;
+                }
+            }
+            (false, false) => out.display("remote", &Remote(remote))?,
+            (true, _) => out.display("peer", &Remote(remote))?,
+        }
+    }
+    if let Some(name) = &entry.remote_host {
+        out.string("remote_host", name.as_bytes())?;
+    }
+    if let Some(geo) = &entry.geo {
+        out.display("geo", geo)?;
+        if let Some(asn) = geo.asn() {
+            out.number("asn", &asn)?;
+        }
+    }
+    #[cfg(feature = "fingerprint")]
+    if let Some(fp) = &entry.fingerprint {
+        out.string("fp", fp.as_bytes())?;
+    }
+    if let Some(state) = &entry.trace_state {
+        out.string("tracestate", state.as_bytes())?;
+    }
+    if !entry.fwd.is_empty() && fields.forwarded() {
+        out.display(
+            "forwarded_for",
+            &Chain(&entry.fwd, entry.fwd_elided, entry.config.ascii_headers),
+        )?;
+    }
+    if let Some(ip) = entry.real_ip.filter(|_| fields.remote()) {
+        out.display("client_ip", &ip)?;
+    }
+    if entry.real_ip.is_some() || entry.fwd_untrusted {
+        out.number("forwarded_trusted", &!entry.fwd_untrusted)?;
+    }
+    if let Some(real_ip) = &entry.real_ip_mismatch {
+        out.string("real_ip_mismatch", real_ip.as_bytes())?;
+    }
+    if let Some(proto) = &entry.fwd_proto {
+        out.string("forwarded_proto", proto.as_bytes())?;
+    }
+    if let Some(host) = &entry.fwd_host {
+        out.string("forwarded_host", host.as_bytes())?;
+    }
+    if let Some(host) = entry.host.as_ref().filter(|_| fields.host()) {
+        out.string("host", &header_value(entry, host.as_bytes()))?;
+    }
+    if let Some(authority) = &entry.authority_mismatch {
+        out.string("authority_mismatch", authority.as_bytes())?;
+    }
+    if let Some(name) = &entry.server_name {
+        out.string("server", name.as_bytes())?;
+    }
+    out.display(
+        "method",
+        &LogMethod(&entry.method, entry.config.method_allowlist),
+    )?;
+    if otel {
+        let path = entry.uri.path();
+        if !path.is_empty() {
+            out.string("path", path.as_bytes())?;
+        }
+        if let Some(query) = log_query(entry) {
+            out.string("query", query.as_bytes())?;
+        }
+    } else {
+        out.display("uri", &LogUri(entry))?;
+    }
+    if fields.version() {
+        let version = http_version(entry.version);
+        let version = match otel {
+            true => version.trim_start_matches("HTTP/"),
+            false => version,
+        };
+        out.string("version", version.as_bytes())?;
+    }
+    if let Some(agent) = entry.user_agent.as_ref().filter(|_| fields.user_agent()) {
+        out.string("user_agent", &header_value(entry, agent.as_bytes()))?;
+    }
+    if let Some(referer) = entry.referer.as_ref().filter(|_| fields.referer()) {
+        out.string("referer", &header_value(entry, referer.as_bytes()))?;
+    }
+    for (name, value) in &entry.headers {
+        let value = header_value(entry, value.as_bytes());
+        match otel {
+            true => out.string(&format!("http.request.header.{name}"), &value)?,
+            false => out.string(&header_key(name), &value)?,
+        }
+    }
+    for (name, value) in &entry.response_headers {
+        let value = header_value(entry, value.as_bytes());
+        match otel {
+            true => out.string(&format!("http.response.header.{name}"), &value)?,
+            false => out.string(&format!("resp.{name}"), &value)?,
+        }
+    }
+    if let Some(handler) = entry.handler {
+        out.number("handler_ms", &ms(handler))?;
+    }
+    match otel {
+        true => out.number("duration", &secs(entry.duration))?,
+        false => out.number("duration_ms", &entry.duration_ms())?,
+    }
+    if let Some(bytes) = entry.request_bytes {
+        out.number("bytes_in", &bytes)?;
+    }
+    if let Some(bytes) = entry.response_bytes {
+        out.number("bytes", &bytes)?;
+    }
+    if entry.body_complete() == Some(false) {
+        out.string("body", b"incomplete")?;
+    }
+    if let Some(kind) = &entry.error_kind {
+        out.display("err_kind", kind)?;
+    }
+    if let Some(err) = &entry.error {
+        out.string("err", err.as_bytes())?;
+    }
+    if let Some(age) = entry.stuck_after {
+        out.number("late_ms", &ms(entry.duration.saturating_sub(age)))?;
+    }
+    if let (Some(budget), Some(over)) = (entry.budget, entry.over_budget()) {
+        out.number("budget_ms", &ms(budget))?;
+        out.number("over_ms", &ms(over))?;
+    }
+    if let Some(threshold) = entry.slow_threshold_exceeded() {
+        out.number("slow", &true)?;
+        out.number("slow_threshold_ms", &ms(threshold))?;
+    }
+    if let Some(upstream) = entry.upstream {
+        out.number("upstream_ms", &ms(upstream))?;
+    }
+    if let Some(mw) = entry.middleware {
+        out.number("mw_ms", &ms(mw))?;
+    }
+    if let Some(sent) = entry.sent_duration {
+        out.number("sent_duration_ms", &ms(sent))?;
+    }
+    if let Some(n) = entry.header_bytes_in {
+        out.number("hdr_in", &n)?;
+    }
+    if let Some(n) = entry.header_bytes_out {
+        out.number("hdr_out", &n)?;
+    }
+    if let Some(conn) = &entry.conn {
+        out.string("conn_id", conn.id.as_bytes())?;
+        out.number("conn_seq", &conn.seq)?;
+    }
+    if let Some(setup) = &entry.setup {
+        out.display("setup", setup)?;
+    }
+    if let Some(tls) = &entry.tls {
+        if let Some(version) = &tls.version {
+            out.string("tls", version.as_bytes())?;
+        }
+        if let Some(cipher) = &tls.cipher {
+            out.string("cipher", cipher.as_bytes())?;
+        }
+        if let Some(sni) = &tls.sni {
+            out.string("sni", sni.as_bytes())?;
+        }
+        if let Some(alpn) = &tls.alpn {
+            out.string("alpn", alpn)?;
+        }
+        if let Some(cn) = &tls.client_cert_cn {
+            out.string("client_cn", cn.as_bytes())?;
+        }
+    }
+    if let Some(upgrade) = entry.upgraded_to() {
+        out.string("upgrade", upgrade.as_bytes())?;
+    }
+    if let Some(negotiation) = &entry.negotiation {
+        out.display("accept", negotiation)?;
+    }
+    if let Some(content_type) = &entry.content_type {
+        out.string(
+            "content_type",
+            &header_value(entry, content_type.as_bytes()),
+        )?;
+    }
+    if let Some(encoding) = &entry.content_encoding {
+        out.string(
+            "content_encoding",
+            &header_value(entry, encoding.as_bytes()),
+        )?;
+    }
+    if let Some(accept) = &entry.accept {
+        out.string("accept_header", &header_value(entry, accept.as_bytes()))?;
+    }
+    if !entry.extras.is_empty() {
+        let extras = entry
+            .extras
+            .iter()
+            .map(|(key, value)| {
+                let value = match value.shown() {
+                    (shown, 0) => Cow::Borrowed(shown),
+                    (shown, omitted) => {
+                        let mut value = shown.to_vec();
+                        write!(ByteWriter(&mut value), "…(+{omitted})").unwrap();
+                        Cow::Owned(value)
+                    }
+                };
+                (key.as_ref(), value)
+            })
+            .collect::<Vec<_>>();
+        out.extras(&extras)?;
+    }
+    if entry.extras_dropped != 0 {
+        out.number("extras_dropped", &entry.extras_dropped)?;
+    }
+    Ok(())
+}
+
+/// A duration in milliseconds, as a number.
+fn ms(d: Duration) -> f64 {
+    d.as_micros() as f64 / 1000.
+}
+
+/// A duration in seconds, as a number.
+fn secs(d: Duration) -> f64 {
+    d.as_micros() as f64 / 1e6
+}
+
+/// Renames the keys written through it to their [FieldNames::Otel] names, where they have one.
+struct Otel<'a>(&'a mut dyn Fields);
+
+impl Otel<'_> {
+    fn key(key: &str) -> &str {
+        OTEL_KEYS
+            .iter()
+            .find(|&&(native, _)| native == key)
+            .map_or(key, |&(_, otel)| otel)
+    }
+}
+
+impl Fields for Otel<'_> {
+    fn string(&mut self, key: &str, value: &[u8]) -> fmt::Result {
+        self.0.string(Self::key(key), value)
+    }
+
+    fn number(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        self.0.number(Self::key(key), value)
+    }
+
+    fn extras(&mut self, extras: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result {
+        self.0.extras(extras)
+    }
+
+    fn strings(&mut self, key: &str, values: &[String]) -> fmt::Result {
+        self.0.strings(Self::key(key), values)
+    }
+
+    fn display(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        self.0.display(Self::key(key), value)
+    }
+}
+
+/// Adapts a byte buffer for `write!`.
+struct ByteWriter<'a>(&'a mut Vec<u8>);
+
+impl Write for ByteWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
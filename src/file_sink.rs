@@ -0,0 +1,111 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::sink::LogSink;
+
+/// [FileSink] is a sink which appends lines to a file, and can reopen it, so that the file can be
+/// rotated by logrotate or the like without restarting the server:
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{FileSink, LogConfig};
+/// let sink = Arc::new(FileSink::open("/var/log/app/access.log")?);
+/// let config = LogConfig::new().sink(sink.clone());
+///
+/// // When the server gets SIGHUP, from logrotate's postrotate script:
+/// sink.reopen()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Once the file has been renamed away, lines keep going to it until [reopen](Self::reopen) is
+/// called, and then go to a new file at the original path. Each line is written with a single
+/// write to a file opened for appending, so lines from several processes sharing the file aren't
+/// interleaved.
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    /// Open the file at `path` for appending, creating it if it doesn't exist.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = Mutex::new(open(&path)?);
+        Ok(Self { path, file })
+    }
+
+    /// Close the file and open the one at the same path again, creating it if it doesn't exist.
+    ///
+    /// If it can't be opened, the error is returned, and lines keep going to the old file.
+    pub fn reopen(&self) -> io::Result<()> {
+        let file = open(&self.path)?;
+        *self.file.lock().unwrap_or_else(|e| e.into_inner()) = file;
+        Ok(())
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+impl LogSink for FileSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+
+    use crate::{LogConfig, LogRequest};
+
+    #[test]
+    fn test_reopen() {
+        let dir = std::env::temp_dir().join(format!("hrl-file-sink-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let rotated = dir.join("access.log.1");
+        let _ = fs::remove_file(&path);
+
+        let sink = Arc::new(FileSink::open(&path).unwrap());
+        assert_eq!(sink.path(), path);
+        let config = Arc::new(LogConfig::new().sink(sink.clone()));
+        let log = |uri: &str| {
+            let req = http::Request::get(uri).body(()).unwrap();
+            let mut log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+            log.set_response(&http::Response::new(()));
+        };
+
+        log("/first");
+        fs::rename(&path, &rotated).unwrap();
+        log("/second");
+        sink.reopen().unwrap();
+        log("/third");
+
+        let old = fs::read_to_string(&rotated).unwrap();
+        let new = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(old.lines().count(), 2, "{old}");
+        assert!(
+            old.contains(" GET /first ") && old.contains(" GET /second "),
+            "{old}"
+        );
+        assert_eq!(new.lines().count(), 1, "{new}");
+        assert!(new.contains(" GET /third "), "{new}");
+
+        // If the file can't be opened again, the old one is kept.
+        assert!(sink.reopen().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug, Formatter, Write};
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use http::header::{HeaderName, ACCEPT_LANGUAGE, USER_AGENT};
+use http::HeaderValue;
+use sha2::{Digest, Sha256};
+
+use crate::head::RequestHead;
+
+/// A part of the request which goes into a [Fingerprint].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FingerprintField {
+    /// The network of the remote address: the /24 of an IPv4 address, or the /48 of an IPv6
+    /// address.
+    ClientNetwork,
+    /// The `User-Agent` header.
+    UserAgent,
+    /// The `Accept-Language` header.
+    AcceptLanguage,
+    /// Any other request header.
+    Header(HeaderName),
+}
+
+/// [Fingerprint] derives an anonymous ID for each request from a salted hash of some of its
+/// fields, for [LogConfig::fingerprint](crate::LogConfig::fingerprint). It is logged as
+/// `fp=f7fe52887124fbba`, and is meant for rough counts of distinct visitors without cookies.
+///
+/// The fields are whatever is passed to [new](Self::new); nothing is included by default.
+///
+/// # Privacy
+///
+/// A fingerprint is pseudonymous, not anonymous. Within a salt period, every request with the
+/// same field values gets the same ID, so the requests of someone whose combination of fields is
+/// unusual can be linked together, and anyone who knows their field values and the salt can
+/// recognize them. Adding fields makes IDs more distinct, which makes counts more accurate and
+/// people easier to single out. The address is reduced to its network for the same reason, at
+/// the cost of counting visitors behind the same NAT or proxy as one.
+///
+/// The salt is derived from a secret and the current period, and changes at the start of each
+/// period, so IDs can't be linked across periods by anyone who doesn't know the secret. The secret
+/// is random unless [secret](Self::secret) is used; a random one is lost when the process exits,
+/// so that instances can't agree on IDs, but neither can anyone who reads the logs later. Shorter
+/// periods limit how long anyone can be followed, and make counts over longer spans impossible.
+#[derive(Clone)]
+pub struct Fingerprint {
+    fields: Vec<FingerprintField>,
+    secret: Vec<u8>,
+    period: Duration,
+}
+
+impl Fingerprint {
+    /// Derive IDs from the given fields, in order, with a random secret and a salt which changes
+    /// daily.
+    pub fn new(fields: impl IntoIterator<Item = FingerprintField>) -> Self {
+        let state = RandomState::new();
+        let secret = (0..4u64)
+            .flat_map(|i| {
+                let mut hasher = state.build_hasher();
+                hasher.write_u64(i);
+                hasher.finish().to_be_bytes()
+            })
+            .collect();
+        Self {
+            fields: fields.into_iter().collect(),
+            secret,
+            period: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Use the given secret instead of a random one, so that several processes derive the same
+    /// IDs. Anyone who learns it can derive the salt for any period.
+    pub fn secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.secret = secret.into();
+        self
+    }
+
+    /// How often the salt changes. Periods start at multiples of this since the Unix epoch.
+    ///
+    /// Defaults to one day.
+    pub fn rotate_every(mut self, period: Duration) -> Self {
+        self.period = period.max(Duration::from_secs(1));
+        self
+    }
+
+    /// Capture the headers among the fields, in order, with `None` in place of the address.
+    pub(crate) fn capture(&self, head: &RequestHead<'_>) -> Vec<Option<HeaderValue>> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let name = match field {
+                    FingerprintField::ClientNetwork => return None,
+                    FingerprintField::UserAgent => &USER_AGENT,
+                    FingerprintField::AcceptLanguage => &ACCEPT_LANGUAGE,
+                    FingerprintField::Header(name) => name,
+                };
+                head.headers().get(name).cloned()
+            })
+            .collect()
+    }
+
+    /// Derive the ID from the captured headers and the remote address, with the salt for the
+    /// period containing the given time.
+    pub(crate) fn derive(
+        &self,
+        captured: &[Option<HeaderValue>],
+        remote: Option<IpAddr>,
+        at: SystemTime,
+    ) -> String {
+        let since_epoch = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let period = since_epoch.as_secs() / self.period.as_secs();
+        let salt = Sha256::new()
+            .chain_update(&self.secret)
+            .chain_update(period.to_be_bytes())
+            .finalize();
+
+        let mut hash = Sha256::new().chain_update(salt);
+        for (field, value) in self.fields.iter().zip(captured) {
+            let network;
+            let value = match field {
+                FingerprintField::ClientNetwork => {
+                    network = remote.map(|ip| network_of(ip.to_canonical()));
+                    network.as_deref()
+                }
+                _ => value.as_ref().map(HeaderValue::as_bytes),
+            };
+            // Length-prefix each value, so that values can't run into each other, and mark
+            // missing ones differently from empty ones.
+            match value {
+                Some(value) => {
+                    hash.update([1]);
+                    hash.update((value.len() as u64).to_be_bytes());
+                    hash.update(value);
+                }
+                None => hash.update([0]),
+            }
+        }
+
+        let mut out = String::with_capacity(16);
+        for b in &hash.finalize()[..8] {
+            write!(out, "{b:02x}").unwrap();
+        }
+        out
+    }
+}
+
+/// The network prefix of an address, as bytes.
+fn network_of(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(v4) => v4.octets()[..3].to_vec(),
+        IpAddr::V6(v6) => v6.octets()[..6].to_vec(),
+    }
+}
+
+impl Debug for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Fingerprint")
+            .field("fields", &self.fields)
+            .field("period", &self.period)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    use http::Request;
+
+    use crate::config::LogConfig;
+    use crate::request::LogRequest;
+
+    fn fingerprint() -> Fingerprint {
+        Fingerprint::new([
+            FingerprintField::ClientNetwork,
+            FingerprintField::UserAgent,
+            FingerprintField::AcceptLanguage,
+        ])
+        .secret("fixed")
+        .rotate_every(Duration::from_secs(3600))
+    }
+
+    fn derive(fp: &Fingerprint, req: &Request<()>, ip: &str, secs: u64) -> String {
+        let captured = fp.capture(&RequestHead::new(req));
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        fp.derive(&captured, Some(ip.parse().unwrap()), at)
+    }
+
+    #[test]
+    fn test_derive() {
+        let fp = fingerprint();
+        let req = Request::get("/")
+            .header("user-agent", "curl/8.0")
+            .header("accept-language", "en-US")
+            .body(())
+            .unwrap();
+        let id = derive(&fp, &req, "192.0.2.10", 1_700_000_000);
+        assert_eq!(id, "f7fe52887124fbba");
+        // Same network, same period.
+        assert_eq!(derive(&fp, &req, "192.0.2.99", 1_700_000_001), id);
+        assert_eq!(derive(&fp, &req, "::ffff:192.0.2.1", 1_700_000_000), id);
+        // Different network, period, or secret.
+        assert_ne!(derive(&fp, &req, "192.0.3.10", 1_700_000_000), id);
+        assert_ne!(derive(&fp, &req, "192.0.2.10", 1_700_003_600), id);
+        assert_ne!(
+            derive(
+                &fp.clone().secret("other"),
+                &req,
+                "192.0.2.10",
+                1_700_000_000
+            ),
+            id
+        );
+
+        // A missing header is different from an empty one.
+        let missing = Request::get("/")
+            .header("user-agent", "curl/8.0")
+            .body(())
+            .unwrap();
+        let empty = Request::get("/")
+            .header("user-agent", "curl/8.0")
+            .header("accept-language", "")
+            .body(())
+            .unwrap();
+        assert_ne!(
+            derive(&fp, &missing, "192.0.2.10", 1_700_000_000),
+            derive(&fp, &empty, "192.0.2.10", 1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn test_only_configured_fields() {
+        let fp = Fingerprint::new([FingerprintField::UserAgent]).secret("fixed");
+        let a = Request::get("/")
+            .header("user-agent", "x")
+            .body(())
+            .unwrap();
+        let b = Request::get("/other")
+            .header("user-agent", "x")
+            .header("accept-language", "fr")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            derive(&fp, &a, "192.0.2.1", 0),
+            derive(&fp, &b, "198.51.100.1", 0)
+        );
+    }
+
+    #[test]
+    fn test_logged() {
+        let config = Arc::new(LogConfig::new().fingerprint(fingerprint()));
+        let req = Request::get("/")
+            .header("user-agent", "curl/8.0")
+            .header("accept-language", "en-US")
+            .body(())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+        log.set_remote("192.0.2.10:4000".parse().unwrap());
+        let entry = log.entry();
+        log.discard();
+        let id = entry.fingerprint().unwrap();
+        assert_eq!(id.len(), 16);
+        assert!(entry.to_string().ends_with(&format!(" fp={id}\n")));
+    }
+}
@@ -0,0 +1,1377 @@
+use std::fmt::{self, Display, Formatter, Write};
+use std::net::{IpAddr, SocketAddr};
+
+use http::{Method, Version};
+
+use crate::clf;
+use crate::config::{Placeholder, StatusStyle};
+use crate::dump::Dump;
+use crate::entry::{Lifecycle, LogEntry};
+use crate::escaped::Escaped;
+use crate::fields::header_key;
+use crate::forwarded::Chain;
+use crate::json;
+use crate::logfmt;
+use crate::query::LogUri;
+use crate::time::StyledDuration;
+use crate::trace_context::TraceParent;
+
+/// The version of the schema of structured outputs, such as the columns written by
+/// `ParquetSink`. The structured formats write it with every entry, under the key `schema`.
+///
+/// Within a version, fields are only ever added: existing ones keep their name, type, and meaning,
+/// and are never removed or renamed. Anything else is a new version. Consumers should ignore
+/// fields they don't know about.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The shape of rendered log entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A single line per entry:
+    ///
+    /// `timestamp request: [action:status] user remote host method uri version agent referer elapsed`
+    ///
+    /// The elapsed time is preceded by the time the handler took to produce the response, if it
+    /// is known, as in `12.300/450.100`.
+    ///
+    /// The timestamp is shown in the entry's [TimestampStyle](crate::TimestampStyle), and left out
+    /// for [TimestampStyle::None](crate::TimestampStyle::None).
+    ///
+    /// Outbound requests logged by a client start with `client-request:` instead, and have the
+    /// peer they were sent to in place of the remote address.
+    #[default]
+    Text,
+    /// A multi-line block per entry, with one aligned `key: value` field per line and a blank
+    /// line after each entry. Intended for humans reading low-traffic logs; anything which
+    /// expects one line per entry will not be able to handle it.
+    Pretty,
+    /// A single-line JSON object per entry, for log pipelines which parse structured input. The
+    /// keys follow the field names of [Pretty](Self::Pretty), except that the forwarded address is
+    /// `forwarded_for`, durations are numbers of milliseconds under keys ending in `_ms`, such as
+    /// `duration_ms`, the extra fields are in an object under `extras`, and a
+    /// [chain](crate::LogRequest::push_action) of actions is also an array under `actions`. Fields
+    /// which weren't captured are left out, rather than being `null`. Values which aren't valid
+    /// UTF-8 have the invalid sequences replaced with U+FFFD.
+    ///
+    /// Request [dumps](crate::LogConfig::dump) aren't written in this format. Its keys are
+    /// covered by [SCHEMA_VERSION].
+    Json,
+    /// The Apache combined log format, which many log analyzers understand without any
+    /// configuration:
+    ///
+    /// `remote - user [timestamp] "method uri version" status bytes "referer" "agent"`
+    ///
+    /// The remote address is logged without its port, the timestamp is the start of the request
+    /// in [LogConfig::timezone](crate::LogConfig::timezone), and missing fields are `-`, as is a
+    /// size of 0. Values are escaped the way Apache escapes them rather than as in the other
+    /// formats. Nothing else is logged, including the action, extra fields, and request
+    /// [dumps](crate::LogConfig::dump).
+    Combined,
+    /// A single line of logfmt `key=value` pairs per entry, which log stores like Loki can pick
+    /// fields out of without any parsing configuration. It has the same fields, with the same
+    /// keys, as [Json](Self::Json), except that the extra fields are pairs of their own. An extra
+    /// field with the same key as a built-in one, such as `status`, has its key prefixed with
+    /// `extra.`, so it can't be mistaken for it.
+    ///
+    /// Values are quoted if they are empty or have spaces, `=`, quotes, backslashes, or control
+    /// characters in them, with quotes and backslashes escaped by a backslash. Values which
+    /// aren't valid UTF-8 have the invalid sequences replaced with U+FFFD. Characters which
+    /// can't be in a key are replaced with `_`.
+    ///
+    /// Request [dumps](crate::LogConfig::dump) aren't written in this format.
+    Logfmt,
+}
+
+pub(crate) fn write_entry<W: Write + ?Sized>(
+    entry: &LogEntry,
+    format: Format,
+    f: &mut W,
+) -> fmt::Result {
+    let dump = (entry.dump.as_ref())
+        .filter(|_| !matches!(format, Format::Json | Format::Combined | Format::Logfmt));
+    if let Some(dump) = dump {
+        dump.write_begin(&entry.config, f)?;
+    }
+    match (format, entry.config.max_line_len) {
+        (Format::Text, None) => write_text(entry, Elided::NONE, f)?,
+        (Format::Text, Some(max)) => write_text_capped(entry, max, f)?,
+        (Format::Pretty, _) => write_pretty(entry, f)?,
+        (Format::Json, _) => json::write_json(entry, f)?,
+        (Format::Combined, _) => clf::write_combined(entry, f)?,
+        (Format::Logfmt, _) => logfmt::write_logfmt(entry, f)?,
+    }
+    if dump.is_some() {
+        Dump::write_end(f)?;
+    }
+    Ok(())
+}
+
+/// The groups of fields the text format can leave out to fit under
+/// [LogConfig::max_line_len](crate::LogConfig::max_line_len), least important first.
+const ELIDABLE: [&str; 6] = ["extras", "ua", "referer", "details", "fwd", "host"];
+
+/// The set of [ELIDABLE] groups left out of a line, by index.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Elided(u8);
+
+impl Elided {
+    const NONE: Self = Self(0);
+
+    fn has(self, group: &str) -> bool {
+        let i = ELIDABLE.iter().position(|g| *g == group).unwrap();
+        self.0 & (1 << i) != 0
+    }
+
+    fn with(self, i: usize) -> Self {
+        Self(self.0 | (1 << i))
+    }
+}
+
+impl Display for Elided {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        for (i, group) in ELIDABLE.iter().enumerate() {
+            if self.0 & (1 << i) != 0 {
+                write!(f, "{sep}{group}")?;
+                sep = ",";
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render the text format, leaving out groups of fields, least important first, until it fits
+/// in `max` bytes. The highest-priority fields are never left out, so the line can still be
+/// longer than that.
+fn write_text_capped<W: Write + ?Sized>(entry: &LogEntry, max: usize, f: &mut W) -> fmt::Result {
+    let mut elided = Elided::NONE;
+    let mut line = String::new();
+    write_text(entry, elided, &mut line)?;
+    for (i, _) in present(entry).iter().enumerate().filter(|(_, p)| **p) {
+        if line.len() - 1 <= max {
+            break;
+        }
+        elided = elided.with(i);
+        line.clear();
+        write_text(entry, elided, &mut line)?;
+    }
+    f.write_str(&line)
+}
+
+/// Render the text format with every [ELIDABLE] group the entry has left out.
+pub(crate) fn write_minimal<W: Write + ?Sized>(entry: &LogEntry, f: &mut W) -> fmt::Result {
+    let elided = present(entry)
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| **p)
+        .fold(Elided::NONE, |elided, (i, _)| elided.with(i));
+    write_text(entry, elided, f)
+}
+
+/// Which of the [ELIDABLE] groups the entry has any fields in which it logs.
+fn present(entry: &LogEntry) -> [bool; ELIDABLE.len()] {
+    let fields = entry.field_set();
+    [
+        !entry.extras.is_empty(),
+        entry.user_agent.is_some() && fields.user_agent(),
+        entry.referer.is_some() && fields.referer(),
+        has_details(entry),
+        !entry.fwd.is_empty() && fields.remote() && fields.forwarded(),
+        entry.host.is_some() && fields.host(),
+    ]
+}
+
+/// Whether the entry has any of the fields in the `details` group.
+fn has_details(entry: &LogEntry) -> bool {
+    #[cfg(feature = "fingerprint")]
+    if entry.fingerprint.is_some() {
+        return true;
+    }
+    entry.middleware.is_some()
+        || entry.sent_duration.is_some()
+        || entry.header_bytes_in.is_some()
+        || entry.header_bytes_out.is_some()
+        || entry.setup.is_some()
+        || entry.conn.is_some()
+        || entry.tls.is_some()
+        || entry.upgraded_to().is_some()
+        || entry.negotiation.is_some()
+        || entry.content_type.is_some()
+        || entry.content_encoding.is_some()
+        || entry.accept.is_some()
+        || entry.remote_host.is_some()
+        || entry.geo.is_some()
+        || entry.trace_state.is_some()
+        || !entry.headers.is_empty()
+        || !entry.response_headers.is_empty()
+        || entry.fwd_proto.is_some()
+        || entry.fwd_host.is_some()
+        || entry.real_ip.is_some()
+        || entry.real_ip_mismatch.is_some()
+        || entry.authority_mismatch.is_some()
+}
+
+fn write_text<W: Write + ?Sized>(entry: &LogEntry, elided: Elided, f: &mut W) -> fmt::Result {
+    let placeholder = entry.config.placeholder;
+    if let Some(timestamp) = entry.styled_timestamp() {
+        write!(f, "{timestamp} ")?;
+    }
+    f.write_str(match entry.client {
+        false => "request: [",
+        true => "client-request: [",
+    })?;
+    let mut sep = "";
+    if let Some(act) = &entry.action {
+        f.write_str(act)?;
+        sep = ":";
+    }
+    let style = entry.config.status_style;
+    let known_status = entry.status.is_some()
+        || !matches!(entry.lifecycle, Lifecycle::InFlight | Lifecycle::Responded);
+    if style != StatusStyle::Outcome && (known_status || placeholder != Placeholder::Omit) {
+        write!(f, "{sep}{}", Status(entry, placeholder))?;
+        sep = ":";
+    }
+    if style != StatusStyle::Numeric {
+        write!(f, "{sep}{}", entry.outcome())?;
+        sep = ":";
+    }
+    if let Some(cache) = &entry.cache_status {
+        write!(f, "{sep}{}", Escaped::from(cache.as_str()))?;
+    }
+    f.write_str("]")?;
+
+    let fields = entry.field_set();
+    if fields.user() {
+        let user = entry.user.as_deref().map(Escaped::from);
+        column(f, placeholder, user.as_ref(), None)?;
+    }
+    if fields.remote() {
+        let unknown = match entry.client {
+            false => "<unknown-remote>",
+            true => "<unknown-peer>",
+        };
+        match entry.real_ip {
+            Some(ip) => write!(f, " {ip}")?,
+            None => column(
+                f,
+                placeholder,
+                entry.remote.map(Remote).as_ref(),
+                Some(unknown),
+            )?,
+        }
+        if !entry.fwd.is_empty() && fields.forwarded() && !elided.has("fwd") {
+            write!(f, "/{}", Forwarded(entry))?;
+        }
+    }
+    if fields.host() {
+        let host = entry.host.as_ref().map(|host| header(entry, host));
+        elidable_column(f, placeholder, host.as_ref(), elided.has("host"))?;
+    }
+
+    write!(
+        f,
+        " {method} {uri}",
+        method = LogMethod(&entry.method, entry.config.method_allowlist),
+        uri = LogUri(entry),
+    )?;
+    if fields.version() {
+        write!(f, " {}", http_version(entry.version))?;
+    }
+    if fields.user_agent() {
+        let agent = entry.user_agent.as_ref().map(|agent| header(entry, agent));
+        elidable_column(f, placeholder, agent.as_ref(), elided.has("ua"))?;
+    }
+    if fields.referer() {
+        let referer = entry.referer.as_ref().map(|referer| header(entry, referer));
+        elidable_column(f, placeholder, referer.as_ref(), elided.has("referer"))?;
+    }
+    let dur = |d| StyledDuration(d, entry.config.duration_style);
+    match entry.handler {
+        Some(handler) => write!(f, " {}/{}", dur(handler), dur(entry.duration))?,
+        None => write!(f, " {}", dur(entry.duration))?,
+    }
+
+    if let Some(id) = &entry.request_id {
+        write!(f, " request_id={}", Escaped::from(id))?;
+    }
+    if let Some(trace) = &entry.trace_parent {
+        write!(f, " {trace}")?;
+    }
+    if let Some(name) = &entry.server_name {
+        write!(f, " server={}", Escaped::from(name))?;
+    }
+    if let (StatusStyle::Outcome, Some(status)) = (style, entry.status) {
+        write!(f, " status={status}")?;
+        if let Some(grpc) = entry.grpc_status {
+            write!(f, " grpc_status={grpc}")?;
+        }
+    }
+    if let Some(bytes) = entry.request_bytes {
+        write!(f, " bytes_in={bytes}")?;
+    }
+    if let Some(bytes) = entry.response_bytes {
+        write!(f, " bytes={bytes}")?;
+    }
+    if entry.body_complete() == Some(false) {
+        f.write_str(" body=incomplete")?;
+    }
+    if let Some(kind) = entry.error_kind {
+        write!(f, " err_kind={kind}")?;
+    }
+    if let Some(age) = entry.stuck_after {
+        write!(f, " late=+{}", dur(entry.duration.saturating_sub(age)))?;
+    }
+    if let (Some(budget), Some(over)) = (entry.budget, entry.over_budget()) {
+        write!(f, " budget={} over=+{}", dur(budget), dur(over))?;
+    }
+    if let Some(upstream) = entry.upstream {
+        write!(f, " upstream={}", dur(upstream))?;
+    }
+    if !elided.has("details") {
+        write_details(entry, f)?;
+    }
+    if !elided.has("extras") {
+        for (key, value) in &entry.extras {
+            write!(f, " {}={value}", Escaped::from(key.as_ref()))?;
+        }
+    }
+    if entry.extras_dropped != 0 {
+        write!(f, " extras_dropped={}", entry.extras_dropped)?;
+    }
+    if let Some(err) = &entry.error {
+        write!(f, " err={}", Escaped::from(err))?;
+    }
+    if elided != Elided::NONE {
+        write!(f, " elided={elided}")?;
+    }
+    if let Some(threshold) = entry.slow_threshold_exceeded() {
+        write!(f, " SLOW>{}", dur(threshold))?;
+    }
+
+    f.write_char('\n')
+}
+
+/// The fields of the text format in the `details` group.
+fn write_details<W: Write + ?Sized>(entry: &LogEntry, f: &mut W) -> fmt::Result {
+    let dur = |d| StyledDuration(d, entry.config.duration_style);
+    if let Some(mw) = entry.middleware {
+        write!(f, " mw={}", dur(mw))?;
+    }
+    if let Some(sent) = entry.sent_duration {
+        write!(f, " sent_duration={}", dur(sent))?;
+    }
+    if let Some(n) = entry.header_bytes_in {
+        write!(f, " hdr_in={n}")?;
+    }
+    if let Some(n) = entry.header_bytes_out {
+        write!(f, " hdr_out={n}")?;
+    }
+    if let Some(conn) = &entry.conn {
+        write!(f, " conn={conn}")?;
+    }
+    if let Some(setup) = entry.setup {
+        write!(f, " setup={setup}")?;
+    }
+    if let Some(tls) = &entry.tls {
+        write!(f, "{tls}")?;
+    }
+    if let Some(upgrade) = entry.upgraded_to() {
+        write!(f, " upgrade={}", Escaped::from(upgrade))?;
+    }
+    if let Some(negotiation) = &entry.negotiation {
+        write!(f, " accept={negotiation}")?;
+    }
+    if let Some(content_type) = &entry.content_type {
+        write!(f, " content_type={}", header(entry, content_type))?;
+    }
+    if let Some(encoding) = &entry.content_encoding {
+        write!(f, " content_encoding={}", header(entry, encoding))?;
+    }
+    if let Some(accept) = &entry.accept {
+        write!(f, " accept_header={}", header(entry, accept))?;
+    }
+    if let (Some(_), Some(proxy)) = (entry.real_ip, entry.remote) {
+        write!(f, " proxy={}", Remote(proxy))?;
+    }
+    if let Some(real_ip) = &entry.real_ip_mismatch {
+        write!(f, " real_ip_mismatch={}", Escaped::from(real_ip))?;
+    }
+    if let Some(authority) = &entry.authority_mismatch {
+        write!(f, " authority_mismatch={}", Escaped::from(authority))?;
+    }
+    if let Some(proto) = &entry.fwd_proto {
+        write!(f, " fwd_proto={}", Escaped::from(proto))?;
+    }
+    if let Some(host) = &entry.fwd_host {
+        write!(f, " fwd_host={}", Escaped::from(host))?;
+    }
+    if let Some(name) = &entry.remote_host {
+        write!(f, " remote_host={}", Escaped::from(name))?;
+    }
+    if let Some(geo) = &entry.geo {
+        write!(f, " geo={geo}")?;
+        if let Some(asn) = geo.asn() {
+            write!(f, " asn={asn}")?;
+        }
+    }
+    #[cfg(feature = "fingerprint")]
+    if let Some(fp) = &entry.fingerprint {
+        write!(f, " fp={fp}")?;
+    }
+    if let Some(state) = &entry.trace_state {
+        write!(f, " tracestate={}", Escaped::from(state))?;
+    }
+    for (name, value) in &entry.headers {
+        write!(f, " {}={}", header_key(name), header(entry, value))?;
+    }
+    for (name, value) in &entry.response_headers {
+        write!(f, " resp.{name}={}", header(entry, value))?;
+    }
+    Ok(())
+}
+
+/// A header value as the text and pretty formats log it: escaped, in ASCII if
+/// [LogConfig::ascii_headers](crate::LogConfig::ascii_headers) is enabled, and cut to
+/// [LogConfig::max_header_len](crate::LogConfig::max_header_len).
+fn header<'a>(entry: &LogEntry, value: impl Into<Escaped<'a>>) -> Escaped<'a> {
+    let mut escaped = value.into();
+    if entry.config.ascii_headers {
+        escaped = escaped.ascii_only();
+    }
+    match entry.config.max_header_len {
+        Some(max_len) => escaped.max_len(max_len),
+        None => escaped,
+    }
+}
+
+fn write_pretty<W: Write + ?Sized>(entry: &LogEntry, f: &mut W) -> fmt::Result {
+    const WIDTH: usize = "user_agent ".len();
+    let mut field = |key: &str, value: &dyn Display| -> fmt::Result {
+        let pad = WIDTH.saturating_sub(key.len()).max(1);
+        writeln!(f, "  {key}:{:pad$}{value}", "")
+    };
+
+    if let Some(timestamp) = entry.styled_timestamp() {
+        field("timestamp", &timestamp)?;
+    }
+    if let Some(id) = &entry.request_id {
+        field("request_id", &Escaped::from(id))?;
+    }
+    match &entry.trace_parent {
+        Some(TraceParent::Valid { trace_id, span_id }) => {
+            field("trace_id", trace_id)?;
+            field("span_id", span_id)?;
+        }
+        Some(TraceParent::Malformed(header)) => field("traceparent", &Escaped::from(header))?,
+        None => {}
+    }
+    if let Some(act) = &entry.action {
+        field("action", &Escaped::from(act))?;
+    }
+    field("status", &Status(entry, Placeholder::Verbose))?;
+    field("outcome", &entry.outcome())?;
+    if let Some(cache) = &entry.cache_status {
+        field("cache_status", &Escaped::from(cache.as_str()))?;
+    }
+    let fields = entry.field_set();
+    if let Some(user) = entry.user.as_ref().filter(|_| fields.user()) {
+        field("user", &Escaped::from(&**user))?;
+    }
+    if fields.remote() {
+        match (entry.remote, entry.client) {
+            (Some(remote), false) => field("remote", &Remote(remote))?,
+            (Some(peer), true) => field("peer", &Remote(peer))?,
+            (None, false) => field("remote", &"<unknown-remote>")?,
+            (None, true) => field("peer", &"<unknown-peer>")?,
+        }
+        if let Some(ip) = entry.real_ip {
+            field("client_ip", &ip)?;
+        }
+    }
+    if let Some(name) = &entry.remote_host {
+        field("remote_host", &Escaped::from(name))?;
+    }
+    if let Some(geo) = &entry.geo {
+        field("geo", geo)?;
+        if let Some(asn) = geo.asn() {
+            field("asn", &asn)?;
+        }
+    }
+    #[cfg(feature = "fingerprint")]
+    if let Some(fp) = &entry.fingerprint {
+        field("fp", fp)?;
+    }
+    if let Some(state) = &entry.trace_state {
+        field("tracestate", &Escaped::from(state))?;
+    }
+    if !entry.fwd.is_empty() && fields.forwarded() {
+        field("forwarded", &Forwarded(entry))?;
+    }
+    if let Some(real_ip) = &entry.real_ip_mismatch {
+        field("real_ip_mismatch", &Escaped::from(real_ip))?;
+    }
+    if let Some(proto) = &entry.fwd_proto {
+        field("forwarded_proto", &Escaped::from(proto))?;
+    }
+    if let Some(host) = &entry.fwd_host {
+        field("forwarded_host", &Escaped::from(host))?;
+    }
+    if fields.host() {
+        field("host", &header(entry, entry.host.as_ref()))?;
+    }
+    if let Some(authority) = &entry.authority_mismatch {
+        field("authority_mismatch", &Escaped::from(authority))?;
+    }
+    if let Some(name) = &entry.server_name {
+        field("server", &Escaped::from(name))?;
+    }
+    field(
+        "method",
+        &LogMethod(&entry.method, entry.config.method_allowlist),
+    )?;
+    field("uri", &LogUri(entry))?;
+    if fields.version() {
+        field("version", &http_version(entry.version))?;
+    }
+    if fields.user_agent() {
+        field("user_agent", &header(entry, entry.user_agent.as_ref()))?;
+    }
+    if fields.referer() {
+        field("referer", &header(entry, entry.referer.as_ref()))?;
+    }
+    for (name, value) in &entry.headers {
+        field(&header_key(name), &header(entry, value))?;
+    }
+    for (name, value) in &entry.response_headers {
+        field(&format!("resp.{name}"), &header(entry, value))?;
+    }
+    let dur = |d| StyledDuration(d, entry.config.duration_style);
+    if let Some(handler) = entry.handler {
+        field("handler", &dur(handler))?;
+    }
+    field("duration", &dur(entry.duration))?;
+    if let Some(bytes) = entry.request_bytes {
+        field("bytes_in", &bytes)?;
+    }
+    if let Some(bytes) = entry.response_bytes {
+        field("bytes", &bytes)?;
+    }
+    if entry.body_complete() == Some(false) {
+        field("body", &"incomplete")?;
+    }
+    if let Some(kind) = &entry.error_kind {
+        field("err_kind", kind)?;
+    }
+    if let Some(err) = &entry.error {
+        field("err", &Escaped::from(err))?;
+    }
+    if let Some(age) = entry.stuck_after {
+        field(
+            "late",
+            &format_args!("+{}", dur(entry.duration.saturating_sub(age))),
+        )?;
+    }
+    if let (Some(budget), Some(over)) = (entry.budget, entry.over_budget()) {
+        field("budget", &dur(budget))?;
+        field("over", &format_args!("+{}", dur(over)))?;
+    }
+    if let Some(threshold) = entry.slow_threshold_exceeded() {
+        field("slow", &format_args!(">{}", dur(threshold)))?;
+    }
+    if let Some(upstream) = entry.upstream {
+        field("upstream", &dur(upstream))?;
+    }
+    if let Some(mw) = entry.middleware {
+        field("mw", &dur(mw))?;
+    }
+    if let Some(sent) = entry.sent_duration {
+        field("sent_duration", &dur(sent))?;
+    }
+    if let Some(n) = entry.header_bytes_in {
+        field("hdr_in", &n)?;
+    }
+    if let Some(n) = entry.header_bytes_out {
+        field("hdr_out", &n)?;
+    }
+    if let Some(conn) = &entry.conn {
+        field("conn", conn)?;
+    }
+    if let Some(setup) = &entry.setup {
+        field("setup", setup)?;
+    }
+    if let Some(tls) = &entry.tls {
+        if let Some(version) = &tls.version {
+            field("tls", &Escaped::from(version))?;
+        }
+        if let Some(cipher) = &tls.cipher {
+            field("cipher", &Escaped::from(cipher))?;
+        }
+        if let Some(sni) = &tls.sni {
+            field("sni", &Escaped::from(sni))?;
+        }
+        if let Some(alpn) = &tls.alpn {
+            field("alpn", &Escaped::from(&alpn[..]))?;
+        }
+        if let Some(cn) = &tls.client_cert_cn {
+            field("client_cn", &Escaped::from(cn))?;
+        }
+    }
+    if let Some(upgrade) = entry.upgraded_to() {
+        field("upgrade", &Escaped::from(upgrade))?;
+    }
+    if let Some(negotiation) = &entry.negotiation {
+        field("accept", negotiation)?;
+    }
+    if let Some(content_type) = &entry.content_type {
+        field("content_type", &header(entry, content_type))?;
+    }
+    if let Some(encoding) = &entry.content_encoding {
+        field("content_encoding", &header(entry, encoding))?;
+    }
+    if let Some(accept) = &entry.accept {
+        field("accept_header", &header(entry, accept))?;
+    }
+    for (key, value) in &entry.extras {
+        field(&Escaped::from(key.as_ref()).to_string(), value)?;
+    }
+    if entry.extras_dropped != 0 {
+        field("extras_dropped", &entry.extras_dropped)?;
+    }
+    f.write_char('\n')
+}
+
+/// Writes a column of the text format which is `-` when elided, as [column] does, with `""` as
+/// its [Placeholder::Verbose] text.
+fn elidable_column<W: Write + ?Sized>(
+    f: &mut W,
+    placeholder: Placeholder,
+    value: Option<&impl Display>,
+    elided: bool,
+) -> fmt::Result {
+    match elided {
+        true => f.write_str(" -"),
+        false => column(f, placeholder, value, Some("\"\"")),
+    }
+}
+
+/// Writes a space and an optional column of the text format, or the placeholder for it if it's
+/// missing. `verbose` is the [Placeholder::Verbose] text, or `None` if that omits the column.
+fn column<W: Write + ?Sized>(
+    f: &mut W,
+    placeholder: Placeholder,
+    value: Option<&impl Display>,
+    verbose: Option<&str>,
+) -> fmt::Result {
+    match (value, placeholder) {
+        (Some(value), _) => write!(f, " {value}"),
+        (None, Placeholder::Verbose) => match verbose {
+            Some(text) => write!(f, " {text}"),
+            None => Ok(()),
+        },
+        (None, Placeholder::Dash) => f.write_str(" -"),
+        (None, Placeholder::Omit) => Ok(()),
+    }
+}
+
+/// Renders the status, or a placeholder explaining why there isn't one.
+struct Status<'a>(&'a LogEntry, Placeholder);
+
+impl Display for Status<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.0.status, self.0.lifecycle) {
+            (Some(status), lifecycle) => {
+                write!(f, "{status}")?;
+                if let Some(reason) = self.0.status_reason() {
+                    write!(f, " {reason}")?;
+                }
+                if let Some(grpc) = self.0.grpc_status {
+                    write!(f, "/grpc={grpc}")?;
+                }
+                match lifecycle {
+                    Lifecycle::Panicked => f.write_str("!panic"),
+                    _ => Ok(()),
+                }
+            }
+            (None, Lifecycle::Cancelled) => f.write_str("!cancelled"),
+            (None, Lifecycle::Panicked) => f.write_str("!panic"),
+            (_, Lifecycle::Stuck) => f.write_str("!stuck"),
+            (None, _) => match self.1 {
+                Placeholder::Verbose => f.write_str("???"),
+                Placeholder::Dash => f.write_str("-"),
+                Placeholder::Omit => Ok(()),
+            },
+        }
+    }
+}
+
+/// Renders a remote address, with IPv4-mapped IPv6 addresses shown as IPv4, and without the
+/// port if it is 0, as it is when
+/// [IpAnonymization::MaskAndDropPort](crate::IpAnonymization::MaskAndDropPort) drops it.
+pub(crate) struct Remote(pub SocketAddr);
+
+impl Display for Remote {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", RemoteIp(self.0.ip()))?;
+        match self.0.port() {
+            0 => Ok(()),
+            port => write!(f, ":{port}"),
+        }
+    }
+}
+
+/// Renders the IP of a remote address, with IPv4-mapped IPv6 addresses shown as IPv4.
+pub(crate) struct RemoteIp(pub IpAddr);
+
+impl Display for RemoteIp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            IpAddr::V4(v4) => write!(f, "{v4}"),
+            IpAddr::V6(v6) => {
+                // TODO: use to_ipv4_mapped() once it's stable
+                match v6.octets() {
+                    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                        write!(f, "{a}.{b}.{c}.{d}")
+                    }
+                    _ => write!(f, "{v6}"),
+                }
+            }
+        }
+    }
+}
+
+/// Renders the forwarded chain of an entry, with the number of hops left out after the first
+/// one, if any, and marked if it didn't come from a trusted proxy.
+struct Forwarded<'a>(&'a LogEntry);
+
+impl Display for Forwarded<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let entry = self.0;
+        if entry.fwd_untrusted {
+            f.write_str("!untrusted:")?;
+        }
+        let chain = Chain(&entry.fwd, entry.fwd_elided, entry.config.ascii_headers);
+        write!(f, "{chain}")
+    }
+}
+
+/// The canonical name of an HTTP version, as it is logged: `HTTP/0.9`, `HTTP/1.0`, `HTTP/1.1`,
+/// `HTTP/2`, or `HTTP/3`, or `HTTP/?` for a version this crate doesn't know about.
+///
+/// ```
+/// # use http::Version;
+/// assert_eq!(hyper_req_log::http_version(Version::HTTP_11), "HTTP/1.1");
+/// assert_eq!(hyper_req_log::http_version(Version::HTTP_2), "HTTP/2");
+/// ```
+pub fn http_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_11 => "HTTP/1.1",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/?",
+    }
+}
+
+/// Renders a request method. The standard methods are written as-is; extension methods are
+/// escaped, or replaced by `OTHER(method)` if the allowlist is enabled.
+pub(crate) struct LogMethod<'a>(pub(crate) &'a Method, pub(crate) bool);
+
+impl Display for LogMethod<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let standard = matches!(
+            *self.0,
+            Method::GET
+                | Method::HEAD
+                | Method::POST
+                | Method::PUT
+                | Method::DELETE
+                | Method::CONNECT
+                | Method::OPTIONS
+                | Method::TRACE
+                | Method::PATCH
+        );
+        if standard {
+            f.write_str(self.0.as_str())
+        } else if self.1 {
+            write!(f, "OTHER({})", Escaped::from(self.0.as_str()))
+        } else {
+            write!(f, "{}", Escaped::from(self.0.as_str()))
+        }
+    }
+}
+
+/// Compare a rendering of the [fixture entry](crate::entry::fixture_entry), or a description of a
+/// schema, with the golden file of the given name in `testdata/golden`. Run the tests with
+/// `UPDATE_GOLDEN=1` to accept a change by rewriting the file instead; if a field changed rather
+/// than being added, bump [SCHEMA_VERSION] too.
+#[cfg(test)]
+pub(crate) fn assert_golden(name: &str, actual: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("reading {}: {e}", path.display()));
+    assert!(
+        actual == expected,
+        "{name} changed; if this is intended, run with UPDATE_GOLDEN=1\n\
+         expected:\n{expected}\nactual:\n{actual}"
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::HeaderValue;
+
+    use crate::config::LogConfig;
+    use crate::entry::test_entry;
+    use crate::field_set::FieldSet;
+    use crate::time::{DurationStyle, Timestamp, TimestampStyle, Timezone};
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_golden() {
+        let entry = crate::entry::fixture_entry();
+        for format in [
+            Format::Text,
+            Format::Pretty,
+            Format::Json,
+            Format::Combined,
+            Format::Logfmt,
+        ] {
+            // Adding a format fails to compile here until it has a golden file.
+            let name = match format {
+                Format::Text => "text.txt",
+                Format::Pretty => "pretty.txt",
+                Format::Json => "json.txt",
+                Format::Combined => "combined.txt",
+                Format::Logfmt => "logfmt.txt",
+            };
+            let mut out = String::new();
+            write_entry(&entry, format, &mut out).unwrap();
+            assert_golden(name, &out);
+        }
+    }
+
+    #[test]
+    fn test_max_line_len() {
+        let capped = |len| {
+            let mut entry = crate::entry::fixture_entry();
+            entry.config = Arc::new(LogConfig::new().max_line_len(len));
+            entry.to_string()
+        };
+        let full = crate::entry::fixture_entry().to_string();
+        assert_eq!(capped(full.len() - 1), full);
+
+        let line = capped(full.len() - 2);
+        assert!(
+            line.ends_with(" hdr_out=22 geo=NZ/AUK asn=64496 elided=extras\n"),
+            "{line}"
+        );
+
+        let line = capped(289);
+        assert!(line.len() <= 290, "{line}");
+        assert!(line.contains(" HTTP/1.1 - - 4.567/12.345 "), "{line}");
+        assert!(line.ends_with(" elided=extras,ua,referer\n"), "{line}");
+
+        // The most important fields survive even when nothing fits.
+        assert_eq!(
+            capped(0),
+            "2023-11-14T22:13:20.000Z request: [create:201] alice 192.0.2.10:51234 - \
+             POST /api/items/42?expand=owner HTTP/1.1 - - 4.567/12.345 bytes_in=512 bytes=2048 \
+             budget=10.000 over=+2.345 elided=extras,ua,referer,details,fwd,host\n"
+        );
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let bare = |placeholder, style| {
+            let req = http::Request::get("/").body(()).unwrap();
+            let mut entry = test_entry(&req);
+            entry.config = Arc::new(
+                LogConfig::new()
+                    .placeholder(placeholder)
+                    .status_style(style)
+                    .timestamp_style(TimestampStyle::None),
+            );
+            entry.duration = Duration::from_millis(5);
+            entry
+        };
+        use Placeholder::*;
+        use StatusStyle::*;
+        let cases = [
+            (
+                Verbose,
+                Numeric,
+                "request: [???] <unknown-remote> \"\" GET / HTTP/1.1 \"\" \"\" 5.000\n",
+            ),
+            (
+                Dash,
+                Numeric,
+                "request: [-] - - - GET / HTTP/1.1 - - 5.000\n",
+            ),
+            (Omit, Numeric, "request: [] GET / HTTP/1.1 5.000\n"),
+            (
+                Dash,
+                Both,
+                "request: [-:incomplete] - - - GET / HTTP/1.1 - - 5.000\n",
+            ),
+            (Omit, Both, "request: [incomplete] GET / HTTP/1.1 5.000\n"),
+        ];
+        for (placeholder, style, expected) in cases {
+            assert_eq!(bare(placeholder, style).to_string(), expected);
+        }
+
+        let mut entry = bare(Omit, Numeric);
+        entry.action = Some("get".to_owned());
+        assert!(entry.to_string().starts_with("request: [get] GET "));
+        entry.lifecycle = Lifecycle::Cancelled;
+        assert!(entry
+            .to_string()
+            .starts_with("request: [get:!cancelled] GET "));
+
+        // Present fields are the same whatever the policy.
+        let full = crate::entry::fixture_entry();
+        for placeholder in [Verbose, Dash, Omit] {
+            let mut entry = full.clone();
+            entry.config = Arc::new(LogConfig::new().header_sizes(true).placeholder(placeholder));
+            assert_eq!(entry.to_string(), full.to_string());
+        }
+    }
+
+    #[test]
+    fn test_method() {
+        let method =
+            |m: &[u8], allowlist| LogMethod(&Method::from_bytes(m).unwrap(), allowlist).to_string();
+        assert_eq!(method(b"GET", false), "GET");
+        assert_eq!(method(b"PATCH", true), "PATCH");
+        assert_eq!(method(b"FOO!#$%", false), "FOO!#$%");
+        assert_eq!(method(b"FOO!#$%", true), "OTHER(FOO!#$%)");
+        assert_eq!(method(b"PROPFIND", true), "OTHER(PROPFIND)");
+
+        let req = http::Request::builder()
+            .method(Method::from_bytes(b"BREW").unwrap())
+            .body(())
+            .unwrap();
+        let config = Arc::new(LogConfig::new().method_allowlist(true));
+        let log = crate::LogRequest::<&str>::from_request_with_config(&req, config);
+        let line = log.entry().to_string();
+        log.discard();
+        assert!(line.contains(" OTHER(BREW) /"), "{line}");
+    }
+
+    #[test]
+    fn test_max_header_len() {
+        let mut entry = crate::entry::fixture_entry();
+        entry.user_agent = Some(HeaderValue::from_str(&"A".repeat(5000)).unwrap());
+        entry.config = Arc::new(
+            LogConfig::new()
+                .timestamp_style(TimestampStyle::None)
+                .max_header_len(16),
+        );
+        let mut line = String::new();
+        write_entry(&entry, Format::Text, &mut line).unwrap();
+        assert!(line.contains(" AAAAAAAAAAAAAAAA…(+4984) "), "{line}");
+        // Short values are left alone.
+        assert!(line.contains(" api.example.com POST "), "{line}");
+        assert!(line.contains(" https://www.exam…(+13) "), "{line}");
+        let mut pretty = String::new();
+        write_entry(&entry, Format::Pretty, &mut pretty).unwrap();
+        assert!(pretty.contains(" AAAAAAAAAAAAAAAA…(+4984)\n"), "{pretty}");
+    }
+
+    #[test]
+    fn test_ascii_headers() {
+        let mut entry = crate::entry::fixture_entry();
+        entry.user_agent = Some(HeaderValue::from_bytes("agent/1.0 (café)".as_bytes()).unwrap());
+        let text = |config: LogConfig| {
+            let mut entry = entry.clone();
+            entry.config = Arc::new(config.timestamp_style(TimestampStyle::None));
+            let mut line = String::new();
+            write_entry(&entry, Format::Text, &mut line).unwrap();
+            line
+        };
+        let line = text(LogConfig::new());
+        assert!(line.contains(r#" "agent/1.0 (café)" "#), "{line}");
+        let line = text(LogConfig::new().ascii_headers(true));
+        assert!(line.contains(r#" "agent/1.0 (caf\u{e9})" "#), "{line}");
+        assert!(line.is_ascii(), "{line}");
+    }
+
+    #[test]
+    fn test_field_set() {
+        let render = |fields: FieldSet, format| {
+            let req = http::Request::get("/a?b=1")
+                .header("host", "example.com")
+                .header("user-agent", "curl/8.0")
+                .header("referer", "https://example.com/")
+                .header("x-forwarded-for", "203.0.113.9")
+                .body(())
+                .unwrap();
+            let mut entry = test_entry(&req);
+            entry.config = Arc::new(
+                LogConfig::new()
+                    .field_set(fields)
+                    .timestamp_style(TimestampStyle::None),
+            );
+            entry.status = Some(200);
+            entry.lifecycle = Lifecycle::Responded;
+            entry.user = Some("alice".into());
+            entry.remote = Some("192.0.2.1:4000".parse().unwrap());
+            entry.duration = Duration::from_millis(5);
+            let mut line = String::new();
+            write_entry(&entry, format, &mut line).unwrap();
+            line
+        };
+        let cases = [
+            (
+                FieldSet::ALL,
+                "request: [200] alice 192.0.2.1:4000/203.0.113.9 example.com GET /a?b=1 HTTP/1.1 \
+                 curl/8.0 https://example.com/ 5.000\n",
+            ),
+            (
+                FieldSet::default().without_user_agent().without_referer(),
+                "request: [200] alice 192.0.2.1:4000/203.0.113.9 example.com GET /a?b=1 HTTP/1.1 \
+                 5.000\n",
+            ),
+            (
+                FieldSet::default().without_forwarded().without_host(),
+                "request: [200] alice 192.0.2.1:4000 GET /a?b=1 HTTP/1.1 curl/8.0 \
+                 https://example.com/ 5.000\n",
+            ),
+            (
+                FieldSet::default().without_remote().without_user(),
+                "request: [200] example.com GET /a?b=1 HTTP/1.1 curl/8.0 https://example.com/ \
+                 5.000\n",
+            ),
+            (FieldSet::MINIMAL, "request: [200] GET /a?b=1 5.000\n"),
+            (
+                FieldSet::MINIMAL.with_user_agent(),
+                "request: [200] GET /a?b=1 curl/8.0 5.000\n",
+            ),
+        ];
+        for (fields, expected) in cases {
+            assert_eq!(render(fields, Format::Text), expected, "{fields:?}");
+        }
+
+        assert_eq!(
+            render(FieldSet::MINIMAL, Format::Json),
+            concat!(
+                r#"{"schema":1,"status":200,"outcome":"ok","method":"GET","uri":"/a?b=1","#,
+                r#""duration_ms":5}"#,
+                "\n"
+            )
+        );
+        let json = render(FieldSet::default().without_user_agent(), Format::Json);
+        assert!(
+            json.contains(r#""referer":"https://example.com/""#),
+            "{json}"
+        );
+        assert!(!json.contains("user_agent"), "{json}");
+        let pretty = render(FieldSet::MINIMAL.with_host(), Format::Pretty);
+        assert!(pretty.contains("  host:"), "{pretty}");
+        for key in [
+            "user:",
+            "remote:",
+            "forwarded:",
+            "version:",
+            "user_agent:",
+            "referer:",
+        ] {
+            assert!(!pretty.contains(key), "{pretty}");
+        }
+    }
+
+    #[test]
+    fn test_status_reason() {
+        let mut entry = crate::entry::fixture_entry();
+        entry.status = Some(404);
+        let mut plain = String::new();
+        write_entry(&entry, Format::Text, &mut plain).unwrap();
+        assert!(plain.contains(":404]"), "{plain}");
+        assert!(!plain.contains("Not Found"), "{plain}");
+
+        entry.config = std::sync::Arc::new(LogConfig::new().status_reason(true));
+        let mut text = String::new();
+        write_entry(&entry, Format::Text, &mut text).unwrap();
+        assert!(text.contains(":404 Not Found]"), "{text}");
+        let mut json = String::new();
+        write_entry(&entry, Format::Json, &mut json).unwrap();
+        assert!(
+            json.contains(r#""status":404,"status_text":"Not Found""#),
+            "{json}"
+        );
+
+        entry.status = Some(599);
+        let mut text = String::new();
+        write_entry(&entry, Format::Text, &mut text).unwrap();
+        assert!(text.contains(":599]"), "{text}");
+        let mut json = String::new();
+        write_entry(&entry, Format::Json, &mut json).unwrap();
+        assert!(!json.contains("status_text"), "{json}");
+    }
+
+    #[test]
+    fn test_http_version() {
+        let cases = [
+            (Version::HTTP_09, "HTTP/0.9"),
+            (Version::HTTP_10, "HTTP/1.0"),
+            (Version::HTTP_11, "HTTP/1.1"),
+            (Version::HTTP_2, "HTTP/2"),
+            (Version::HTTP_3, "HTTP/3"),
+        ];
+        for (version, expected) in cases {
+            assert_eq!(http_version(version), expected);
+            let mut entry = crate::entry::fixture_entry();
+            entry.version = version;
+            for format in [Format::Text, Format::Combined, Format::Logfmt] {
+                let mut out = String::new();
+                write_entry(&entry, format, &mut out).unwrap();
+                // Followed by a separator, so "HTTP/1" doesn't pass for "HTTP/1.1".
+                assert!(
+                    out.contains(&format!("{expected} ")) || out.contains(&format!("{expected}\"")),
+                    "{format:?}: {out}"
+                );
+            }
+            let mut json = String::new();
+            write_entry(&entry, Format::Json, &mut json).unwrap();
+            assert!(
+                json.contains(&format!(r#""version":"{expected}""#)),
+                "{json}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut entry = test_entry(&http::Request::new(()));
+        entry.duration = Duration::from_millis(287);
+        assert!(!entry.to_string().contains("budget="));
+
+        entry.budget = Some(Duration::from_millis(300));
+        assert_eq!(entry.over_budget(), None);
+        assert!(entry.to_string().ends_with(" 287.000\n"));
+
+        entry.budget = Some(Duration::from_millis(287));
+        assert_eq!(entry.over_budget(), None);
+
+        entry.budget = Some(Duration::from_millis(250));
+        assert_eq!(entry.over_budget(), Some(Duration::from_millis(37)));
+        assert!(entry
+            .to_string()
+            .ends_with(" 287.000 budget=250.000 over=+37.000\n"));
+
+        let styled = |style| {
+            let mut entry = entry.clone();
+            entry.config = Arc::new(LogConfig::new().duration_style(style));
+            entry.to_string()
+        };
+        assert!(styled(DurationStyle::HumanDebug).ends_with(" 287ms budget=250ms over=+37ms\n"));
+        assert!(styled(DurationStyle::Micros).ends_with(" 287000 budget=250000 over=+37000\n"));
+        assert!(
+            styled(DurationStyle::Seconds).ends_with(" 0.287000 budget=0.250000 over=+0.037000\n")
+        );
+    }
+
+    #[test]
+    fn test_slow() {
+        let mut entry = test_entry(&http::Request::new(()));
+        entry.duration = Duration::from_millis(500);
+        assert!(!entry.to_string().contains("SLOW"));
+
+        entry.config = Arc::new(LogConfig::new().slow_threshold(Duration::from_millis(500)));
+        assert!(!entry.is_slow());
+        assert!(entry.to_string().ends_with(" 500.000\n"));
+        let mut json = String::new();
+        write_entry(&entry, Format::Json, &mut json).unwrap();
+        assert!(!json.contains("slow"), "{json}");
+
+        entry.duration = Duration::from_micros(500_001);
+        assert_eq!(
+            entry.slow_threshold_exceeded(),
+            Some(Duration::from_millis(500))
+        );
+        assert!(entry.to_string().ends_with(" 500.001 SLOW>500.000\n"));
+        json.clear();
+        write_entry(&entry, Format::Json, &mut json).unwrap();
+        assert!(
+            json.contains(r#","slow":true,"slow_threshold_ms":500"#),
+            "{json}"
+        );
+        let mut logfmt = String::new();
+        write_entry(&entry, Format::Logfmt, &mut logfmt).unwrap();
+        assert!(
+            logfmt.contains(" slow=true slow_threshold_ms=500"),
+            "{logfmt}"
+        );
+
+        // The request's threshold replaces the config's.
+        entry.slow_threshold = Some(Duration::from_secs(1));
+        assert!(!entry.is_slow());
+        entry.slow_threshold = Some(Duration::from_millis(100));
+        assert!(entry.to_string().ends_with(" SLOW>100.000\n"));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let req = http::Request::get("/").body(()).unwrap();
+        let line = test_entry(&req).to_string();
+        let (timestamp, rest) = line.split_once(' ').unwrap();
+        assert!(rest.starts_with("request: [???] "), "{line}");
+        // The shape of `%Y-%m-%dT%H:%M:%S%.3fZ`, whatever the time.
+        let shape = timestamp
+            .chars()
+            .map(|c| if c.is_ascii_digit() { '9' } else { c })
+            .collect::<String>();
+        assert_eq!(shape, "9999-99-99T99:99:99.999Z", "{line}");
+
+        let mut entry = test_entry(&req);
+        entry.config = Arc::new(LogConfig::new().timestamp_style(TimestampStyle::None));
+        assert!(entry.to_string().starts_with("request: [???] "));
+        entry.config = Arc::new(
+            LogConfig::new()
+                .format(Format::Pretty)
+                .timestamp_style(TimestampStyle::None),
+        );
+        assert!(!entry.to_string().contains("timestamp:"));
+        entry.config = Arc::new(
+            LogConfig::new()
+                .format(Format::Json)
+                .timestamp_style(TimestampStyle::None),
+        );
+        assert!(!entry.to_string().contains("timestamp"));
+        assert_eq!(entry.start_time_utc().system_time(), entry.start_time(),);
+    }
+
+    #[test]
+    fn test_timestamp_style() {
+        let mut entry = crate::entry::fixture_entry();
+        entry.start_time += Duration::from_millis(789);
+        let render = |entry: &LogEntry, style: Option<TimestampStyle>, format: Format| {
+            let mut entry = entry.clone();
+            entry.timestamp_style = style;
+            let mut out = String::new();
+            write_entry(&entry, format, &mut out).unwrap();
+            out
+        };
+        let local = Timestamp::new(entry.start_time, Timezone::Local).to_string();
+        let cases = [
+            (TimestampStyle::Rfc3339Utc, "2023-11-14T22:13:20.789Z"),
+            (TimestampStyle::Rfc3339Local, local.as_str()),
+            (TimestampStyle::EpochMillis, "1700000000789"),
+            (TimestampStyle::Clf, "14/Nov/2023:22:13:20 +0000"),
+        ];
+        for (style, expected) in cases {
+            let line = render(&entry, Some(style), Format::Text);
+            assert!(line.starts_with(&format!("{expected} request: ")), "{line}");
+            let pretty = render(&entry, Some(style), Format::Pretty);
+            assert!(
+                pretty.starts_with(&format!("  timestamp:  {expected}\n")),
+                "{pretty}"
+            );
+            let logfmt = render(&entry, Some(style), Format::Logfmt);
+            assert!(
+                logfmt.contains(&format!("=\"{expected}\" "))
+                    || logfmt.contains(&format!("={expected} ")),
+                "{logfmt}"
+            );
+        }
+        let json = render(&entry, Some(TimestampStyle::EpochMillis), Format::Json);
+        assert!(json.starts_with("{\"timestamp\":1700000000789,"), "{json}");
+        let json = render(&entry, Some(TimestampStyle::Clf), Format::Json);
+        assert!(
+            json.starts_with("{\"timestamp\":\"14/Nov/2023:22:13:20 +0000\","),
+            "{json}"
+        );
+        for format in [Format::Text, Format::Json, Format::Logfmt] {
+            let out = render(&entry, Some(TimestampStyle::None), format);
+            assert!(!out.contains("2023") && !out.contains("timestamp"), "{out}");
+        }
+
+        // The request's style wins over the config's.
+        entry.config = Arc::new(
+            LogConfig::new()
+                .timestamp_style(TimestampStyle::Clf)
+                .timezone(Timezone::FixedOffset(-7 * 3600)),
+        );
+        let line = render(&entry, None, Format::Text);
+        assert!(
+            line.starts_with("14/Nov/2023:15:13:20 -0700 request: "),
+            "{line}"
+        );
+        entry.config = Arc::new(LogConfig::new().timestamp_style(TimestampStyle::EpochMillis));
+        let line = render(&entry, None, Format::Text);
+        assert!(line.starts_with("1700000000789 request: "), "{line}");
+        let line = render(&entry, Some(TimestampStyle::Rfc3339Utc), Format::Text);
+        assert!(
+            line.starts_with("2023-11-14T22:13:20.789Z request: "),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn test_pretty() {
+        let req = http::Request::get("/index.html")
+            .header("host", "example.com")
+            .header("user-agent", "curl/8.0 (x86_64)")
+            .body(())
+            .unwrap();
+        let mut entry = test_entry(&req);
+        entry.action = Some("get".to_owned());
+        entry.status = Some(200);
+        entry.remote = Some((Ipv4Addr::new(10, 1, 2, 3), 4567).into());
+        entry.duration = Duration::from_micros(1500);
+        entry.config = Arc::new(
+            LogConfig::new()
+                .format(Format::Pretty)
+                .timestamp_style(TimestampStyle::None),
+        );
+        assert_eq!(
+            entry.to_string(),
+            "  action:     get\n\
+             \x20 status:     200\n\
+             \x20 outcome:    ok\n\
+             \x20 remote:     10.1.2.3:4567\n\
+             \x20 host:       example.com\n\
+             \x20 method:     GET\n\
+             \x20 uri:        /index.html\n\
+             \x20 version:    HTTP/1.1\n\
+             \x20 user_agent: \"curl/8.0 (x86_64)\"\n\
+             \x20 referer:    \"\"\n\
+             \x20 duration:   1.500\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn test_redact_query() {
+        let req = http::Request::get("/login?user=alice&token=abc&TOKEN=def&next")
+            .body(())
+            .unwrap();
+        let mut entry = test_entry(&req);
+        entry.config = Arc::new(LogConfig::new().redact_query_params(["token", "password"]));
+        let redacted = "/login?user=alice&token=[REDACTED]&TOKEN=[REDACTED]&next";
+        for format in [
+            Format::Text,
+            Format::Pretty,
+            Format::Json,
+            Format::Logfmt,
+            Format::Combined,
+        ] {
+            let mut out = String::new();
+            write_entry(&entry, format, &mut out).unwrap();
+            assert!(out.contains(redacted), "{format:?}: {out}");
+            assert!(!out.contains("abc") && !out.contains("def"), "{out}");
+        }
+        // The entry itself keeps the URI as it was received.
+        assert_eq!(entry.query(), Some("user=alice&token=abc&TOKEN=def&next"));
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt::{self, Debug, Formatter, Write};
+use std::sync::{Arc, RwLock};
+
+use crate::entry::LogEntry;
+use crate::format::{self, Format};
+
+/// [LogFormatter] renders entries in a format of the application's own, in place of one of the
+/// built-in [Format]s, which implement it too.
+///
+/// The formatter reads the entry through its accessors, and writes the whole rendering, including
+/// any trailing newline; it is written as it is wherever the entry goes, such as stderr or a
+/// [LogSink](crate::LogSink) which writes lines. The URI is best logged with
+/// [logged_uri](LogEntry::logged_uri), which is redacted as the config says, rather than
+/// [uri](LogEntry::uri), which is the URI as it was sent.
+///
+/// ```
+/// # use std::fmt::{self, Write};
+/// # use std::sync::Arc;
+/// use hyper_req_log::{LogConfig, LogEntry, LogFormatter, SimpleLogRequest};
+///
+/// struct Short;
+///
+/// impl LogFormatter for Short {
+///     fn format(&self, entry: &LogEntry, f: &mut dyn Write) -> fmt::Result {
+///         let status = entry.status().map_or("-".to_owned(), |s| s.to_string());
+///         writeln!(f, "{} {} {status}", entry.method(), entry.logged_uri())
+///     }
+/// }
+///
+/// # let req = http::Request::get("/items?token=abc").body(()).unwrap();
+/// let config = Arc::new(LogConfig::new().redact_query_params(["token"]));
+/// let mut log = SimpleLogRequest::from_request_with_config(&req, config);
+/// log.set_formatter(Arc::new(Short)).set_status(200);
+/// assert_eq!(log.render(), "GET /items?token=[REDACTED] 200\n");
+/// # log.discard();
+/// ```
+///
+/// A formatter can be set for a request with
+/// [LogRequest::set_formatter](crate::LogRequest::set_formatter), for the requests using a config
+/// with [LogConfig::formatter](crate::LogConfig::formatter), or for the whole process with
+/// [set_default_formatter], in that order of precedence. A [Format] set with
+/// [LogConfig::format](crate::LogConfig::format) takes precedence over the process default.
+pub trait LogFormatter: Send + Sync {
+    /// Render the entry.
+    fn format(&self, entry: &LogEntry, f: &mut dyn Write) -> fmt::Result;
+}
+
+impl LogFormatter for Format {
+    fn format(&self, entry: &LogEntry, f: &mut dyn Write) -> fmt::Result {
+        format::write_entry(entry, *self, f)
+    }
+}
+
+static DEFAULT_FORMATTER: RwLock<Option<Arc<dyn LogFormatter>>> = RwLock::new(None);
+
+/// Set the formatter entries are rendered with when neither their request nor their config has
+/// a formatter or [Format] of its own, in place of [Format::Text]. It applies to every entry
+/// rendered afterwards.
+pub fn set_default_formatter(formatter: Arc<dyn LogFormatter>) {
+    *DEFAULT_FORMATTER.write().unwrap_or_else(|e| e.into_inner()) = Some(formatter);
+}
+
+/// Go back to rendering entries in [Format::Text] by default, undoing [set_default_formatter].
+pub fn clear_default_formatter() {
+    *DEFAULT_FORMATTER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// The formatter set with [set_default_formatter], if any.
+pub(crate) fn default_formatter() -> Option<Arc<dyn LogFormatter>> {
+    DEFAULT_FORMATTER
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// A formatter set with [LogConfig::formatter](crate::LogConfig::formatter), so the config can
+/// still be `Debug`.
+#[derive(Clone)]
+pub(crate) struct ConfigFormatter(pub(crate) Arc<dyn LogFormatter>);
+
+impl Debug for ConfigFormatter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("LogFormatter")
+    }
+}
+
+/// Adapts a `fmt::Write` which may be unsized for passing as `&mut dyn Write`.
+pub(crate) struct WriteAdapter<'a, W: ?Sized>(pub(crate) &'a mut W);
+
+impl<W: Write + ?Sized> Write for WriteAdapter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.0.write_char(c)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::LogConfig;
+    use crate::LogRequest;
+
+    struct Fixed(&'static str);
+
+    impl LogFormatter for Fixed {
+        fn format(&self, _: &LogEntry, f: &mut dyn Write) -> fmt::Result {
+            writeln!(f, "{}", self.0)
+        }
+    }
+
+    #[test]
+    fn test_builtin() {
+        let entry = crate::entry::fixture_entry();
+        for format in [Format::Text, Format::Json, Format::Combined, Format::Logfmt] {
+            let (mut direct, mut formatted) = (String::new(), String::new());
+            format::write_entry(&entry, format, &mut direct).unwrap();
+            format.format(&entry, &mut formatted).unwrap();
+            assert_eq!(formatted, direct, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let req = http::Request::get("/").body(()).unwrap();
+        let rendered = |config: LogConfig, formatter: Option<&'static str>| {
+            let mut log = LogRequest::<&str>::from_request_with_config(&req, Arc::new(config));
+            if let Some(name) = formatter {
+                log.set_formatter(Arc::new(Fixed(name)));
+            }
+            let line = log.render();
+            log.discard();
+            line
+        };
+        let config = || LogConfig::new().formatter(Arc::new(Fixed("config")));
+        assert_eq!(rendered(config(), Some("request")), "request\n");
+        assert_eq!(rendered(config(), None), "config\n");
+        assert!(rendered(LogConfig::new(), None).contains("request: ["));
+    }
+
+    #[test]
+    fn test_failing() {
+        struct Failing;
+
+        impl LogFormatter for Failing {
+            fn format(&self, _: &LogEntry, f: &mut dyn Write) -> fmt::Result {
+                f.write_str("partial")?;
+                Err(fmt::Error)
+            }
+        }
+
+        let out = Arc::new(std::sync::Mutex::new(Vec::<u8>::new()));
+        let config = Arc::new(
+            LogConfig::new()
+                .formatter(Arc::new(Failing))
+                .sink(out.clone()),
+        );
+        let req = http::Request::get("/").body(()).unwrap();
+        let log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+        assert_eq!(log.render(), "partial");
+        assert!(log.render_into(Vec::new()).is_err());
+        assert!(log.write(Vec::new()).is_err());
+
+        // Debug output doesn't panic either.
+        let log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+        assert_eq!(format!("{log:?}"), r#"LogRequest("<formatter error>")"#);
+        assert_eq!(
+            format!("{:?}", log.entry()),
+            r#"LogEntry("<formatter error>")"#
+        );
+        log.discard();
+
+        // Dropping the request fails the write rather than panicking.
+        drop(LogRequest::<&str>::from_request_with_config(&req, config));
+        assert!(out.lock().unwrap().is_empty());
+    }
+}
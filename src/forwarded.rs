@@ -0,0 +1,345 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+
+use http::header::{GetAll, HeaderValue};
+
+use crate::escaped::Escaped;
+
+/// What is taken from a request's RFC 7239 `Forwarded` headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Forwarded {
+    /// The `for` parameter of each element which has one, joined with commas in the same order,
+    /// the way `X-Forwarded-For` lists them.
+    pub(crate) chain: Option<HeaderValue>,
+    /// The `proto` parameter of the first element: the scheme the client used.
+    pub(crate) proto: Option<HeaderValue>,
+    /// The `host` parameter of the first element: the `Host` the client sent.
+    pub(crate) host: Option<HeaderValue>,
+}
+
+impl Forwarded {
+    /// Parse every `Forwarded` header of a request, as one list of elements.
+    ///
+    /// Parameter values are unquoted, so `for="[2001:db8::1]:4711"` gives `[2001:db8::1]:4711`,
+    /// but otherwise logged as they are, including obfuscated identifiers like `_hidden` and
+    /// `unknown`. Nothing in them is trusted: they are escaped when they are written, like the
+    /// other header values.
+    pub(crate) fn parse(headers: GetAll<'_, HeaderValue>) -> Self {
+        let mut chain = Vec::new();
+        let (mut proto, mut host) = (None, None);
+        for (i, element) in headers
+            .iter()
+            .flat_map(|header| split_quoted(header.as_bytes(), b','))
+            .enumerate()
+        {
+            for pair in split_quoted(element, b';') {
+                let Some(eq) = pair.iter().position(|&b| b == b'=') else {
+                    continue;
+                };
+                let (key, value) = (pair[..eq].trim_ascii(), unquote(&pair[eq + 1..]));
+                if key.eq_ignore_ascii_case(b"for") && !value.is_empty() {
+                    if !chain.is_empty() {
+                        chain.push(b',');
+                    }
+                    chain.extend_from_slice(&value);
+                } else if i == 0 && key.eq_ignore_ascii_case(b"proto") {
+                    proto = HeaderValue::from_bytes(&value).ok();
+                } else if i == 0 && key.eq_ignore_ascii_case(b"host") {
+                    host = HeaderValue::from_bytes(&value).ok();
+                }
+            }
+        }
+        Self {
+            chain: match chain.is_empty() {
+                true => None,
+                false => HeaderValue::from_bytes(&chain).ok(),
+            },
+            proto,
+            host,
+        }
+    }
+}
+
+/// An entry in a chain of forwarded addresses, from `X-Forwarded-For`, the `for` parameters of
+/// `Forwarded`, or `X-Real-IP`, as it is logged. IPv4-mapped IPv6 addresses are given as the IPv4
+/// address they map, so `::ffff:192.0.2.1` is logged as `192.0.2.1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForwardedEntry {
+    /// An address, optionally written in brackets for IPv6.
+    Ip(IpAddr),
+    /// An address with a port, like `192.0.2.1:4000` or `[2001:db8::1]:4711`.
+    Socket(SocketAddr),
+    /// `unknown`: RFC 7239's word for a hop whose address isn't known.
+    Unknown,
+    /// An obfuscated identifier, like `_hidden`, which RFC 7239 allows in place of an address.
+    Obfuscated(String),
+    /// Anything else. It is logged escaped, and marked as `!invalid:`.
+    Invalid(Vec<u8>),
+}
+
+impl ForwardedEntry {
+    /// Parse one entry of a chain, already trimmed of whitespace.
+    pub(crate) fn parse(hop: &[u8]) -> Self {
+        let Ok(text) = std::str::from_utf8(hop) else {
+            return Self::Invalid(hop.to_vec());
+        };
+        if let Ok(ip) = text.parse::<IpAddr>() {
+            return Self::Ip(ip.to_canonical());
+        }
+        if let Ok(addr) = text.parse::<SocketAddr>() {
+            return Self::Socket((addr.ip().to_canonical(), addr.port()).into());
+        }
+        let bracketed = text.strip_prefix('[').and_then(|h| h.strip_suffix(']'));
+        if let Some(Ok(ip)) = bracketed.map(str::parse::<IpAddr>) {
+            return Self::Ip(ip.to_canonical());
+        }
+        if text.eq_ignore_ascii_case("unknown") {
+            return Self::Unknown;
+        }
+        let obfuscated = text.strip_prefix('_').is_some_and(|rest| {
+            !rest.is_empty()
+                && rest
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+        });
+        match obfuscated {
+            true => Self::Obfuscated(text.to_owned()),
+            false => Self::Invalid(hop.to_vec()),
+        }
+    }
+
+    /// The entry's address, if it has one.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Self::Ip(ip) => Some(*ip),
+            Self::Socket(addr) => Some(addr.ip()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ForwardedEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Socket(addr) => write!(f, "{addr}"),
+            Self::Unknown => f.write_str("unknown"),
+            Self::Obfuscated(id) => f.write_str(id),
+            Self::Invalid(bytes) => write!(f, "!invalid:{}", Escaped::from(bytes)),
+        }
+    }
+}
+
+/// Parse a comma-separated chain of forwarded addresses, ignoring whitespace around each entry
+/// and empty entries.
+pub(crate) fn parse_chain(chain: &[u8]) -> Vec<ForwardedEntry> {
+    chain
+        .split(|&b| b == b',')
+        .map(<[u8]>::trim_ascii)
+        .filter(|hop| !hop.is_empty())
+        .map(ForwardedEntry::parse)
+        .collect()
+}
+
+/// Renders a parsed chain joined with commas, with the number of entries left out after the
+/// first one, if any, marked with `\u{2026}` instead of `…` if the third field is set.
+pub(crate) struct Chain<'a>(
+    pub(crate) &'a [ForwardedEntry],
+    pub(crate) usize,
+    pub(crate) bool,
+);
+
+impl Display for Chain<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, entry) in self.0.iter().enumerate() {
+            match i {
+                0 => write!(f, "{entry}")?,
+                1 if self.1 != 0 => {
+                    let marker = match self.2 {
+                        true => "\\u{2026}",
+                        false => "…",
+                    };
+                    write!(f, ",{marker}(+{}),{entry}", self.1)?
+                }
+                _ => write!(f, ",{entry}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split on a separator which isn't inside a quoted string, skipping empty parts.
+fn split_quoted(bytes: &[u8], sep: u8) -> impl Iterator<Item = &[u8]> {
+    let (mut quoted, mut escaped) = (false, false);
+    bytes
+        .split(move |&b| {
+            match (escaped, quoted, b) {
+                (true, _, _) => escaped = false,
+                (false, true, b'\\') => escaped = true,
+                (false, _, b'"') => quoted = !quoted,
+                _ => (),
+            }
+            b == sep && !quoted
+        })
+        .map(<[u8]>::trim_ascii)
+        .filter(|part| !part.is_empty())
+}
+
+/// A parameter value without surrounding whitespace, and without its quotes and the
+/// backslashes of its quoted pairs if it is a quoted string.
+fn unquote(value: &[u8]) -> Vec<u8> {
+    let value = value.trim_ascii();
+    let Some(inner) = value
+        .strip_prefix(b"\"")
+        .and_then(|value| value.strip_suffix(b"\""))
+    else {
+        return value.to_vec();
+    };
+    let mut out = Vec::with_capacity(inner.len());
+    let mut bytes = inner.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\\' => out.extend(bytes.next()),
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::header::{HeaderMap, FORWARDED};
+
+    fn parse(values: &[&str]) -> Forwarded {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(FORWARDED, HeaderValue::from_str(value).unwrap());
+        }
+        Forwarded::parse(headers.get_all(FORWARDED))
+    }
+
+    fn chain(values: &[&str]) -> Option<String> {
+        parse(values)
+            .chain
+            .map(|chain| chain.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn test_parse() {
+        let fwd = parse(&["for=192.0.2.60;proto=https;by=203.0.113.43;host=example.com"]);
+        assert_eq!(
+            fwd,
+            Forwarded {
+                chain: Some(HeaderValue::from_static("192.0.2.60")),
+                proto: Some(HeaderValue::from_static("https")),
+                host: Some(HeaderValue::from_static("example.com")),
+            }
+        );
+
+        assert_eq!(
+            chain(&[r#"For="[2001:db8:cafe::17]:4711", for=192.0.2.43"#]).as_deref(),
+            Some("[2001:db8:cafe::17]:4711,192.0.2.43")
+        );
+        assert_eq!(
+            chain(&["for=_hidden, for=unknown", "for=198.51.100.17;proto=http"]).as_deref(),
+            Some("_hidden,unknown,198.51.100.17")
+        );
+        // Only the first element's proto and host describe what the client sent.
+        let fwd = parse(&[
+            "for=192.0.2.60",
+            "for=198.51.100.17;proto=http;host=internal",
+        ]);
+        assert_eq!((fwd.proto, fwd.host), (None, None));
+        assert_eq!(parse(&["proto=https;by=203.0.113.43"]).chain, None);
+        assert_eq!(parse(&[""]), Forwarded::default());
+    }
+
+    #[test]
+    fn test_quoting() {
+        // Separators inside quoted strings don't split elements or pairs.
+        assert_eq!(
+            chain(&[r#"for="a,b;c", for=d"#]).as_deref(),
+            Some("a,b;c,d")
+        );
+        assert_eq!(chain(&[r#"for="x\"y\\z""#]).as_deref(), Some(r#"x"y\z"#));
+        // Garbage is kept as it is, to be escaped when it is written.
+        assert_eq!(
+            chain(&["for=not an address"]).as_deref(),
+            Some("not an address")
+        );
+        assert_eq!(
+            chain(&[r#"for="unterminated"#]).as_deref(),
+            Some(r#""unterminated"#)
+        );
+    }
+
+    #[test]
+    fn test_entry() {
+        use ForwardedEntry::*;
+        let ip = |s: &str| Ip(s.parse().unwrap());
+        let socket = |s: &str| Socket(s.parse().unwrap());
+        assert_eq!(ForwardedEntry::parse(b"192.0.2.1"), ip("192.0.2.1"));
+        assert_eq!(ForwardedEntry::parse(b"::ffff:192.0.2.1"), ip("192.0.2.1"));
+        assert_eq!(ForwardedEntry::parse(b"2001:db8::1"), ip("2001:db8::1"));
+        assert_eq!(ForwardedEntry::parse(b"[2001:db8::1]"), ip("2001:db8::1"));
+        assert_eq!(
+            ForwardedEntry::parse(b"192.0.2.1:4000"),
+            socket("192.0.2.1:4000")
+        );
+        assert_eq!(
+            ForwardedEntry::parse(b"[::ffff:192.0.2.1]:4000"),
+            socket("192.0.2.1:4000")
+        );
+        assert_eq!(
+            ForwardedEntry::parse(b"[2001:db8::1]:4711"),
+            socket("[2001:db8::1]:4711")
+        );
+        assert_eq!(ForwardedEntry::parse(b"UNKNOWN"), Unknown);
+        assert_eq!(
+            ForwardedEntry::parse(b"_hidden.1"),
+            Obfuscated("_hidden.1".to_owned())
+        );
+        for garbage in [&b"_"[..], b"_no way", b"<script>", b"192.0.2.1.5", b"\xff"] {
+            assert_eq!(
+                ForwardedEntry::parse(garbage),
+                Invalid(garbage.to_vec()),
+                "{}",
+                garbage.escape_ascii()
+            );
+        }
+        assert_eq!(ip("192.0.2.1").ip(), Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(
+            socket("[2001:db8::1]:1").ip(),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(Unknown.ip(), None);
+    }
+
+    #[test]
+    fn test_chain() {
+        let render = |chain: &[u8], elided| Chain(&parse_chain(chain), elided, false).to_string();
+        assert_eq!(
+            render(b"192.0.2.1, ::ffff:198.51.100.2,2001:db8::1", 0),
+            "192.0.2.1,198.51.100.2,2001:db8::1"
+        );
+        assert_eq!(
+            render(b" \t192.0.2.1 ,, [2001:db8::1]:4711\t, unknown ", 0),
+            "192.0.2.1,[2001:db8::1]:4711,unknown"
+        );
+        assert_eq!(
+            render(b"1.2.3.4, ::ffff:5.6.7.8, <script>", 0),
+            "1.2.3.4,5.6.7.8,!invalid:<script>"
+        );
+        assert_eq!(
+            render(b"_hidden, not an address, \"q\\", 0),
+            "_hidden,!invalid:\"not an address\",!invalid:\"\\\"q\\\\\""
+        );
+        assert_eq!(
+            render(b"192.0.2.1, 192.0.2.9", 3),
+            "192.0.2.1,…(+3),192.0.2.9"
+        );
+        assert_eq!(render(b"192.0.2.1", 3), "192.0.2.1");
+        assert_eq!(render(b" , ", 0), "");
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::{self, Display, Formatter};
+
+/// [Geo] is where a request came from, as found by a GeoIP lookup, for
+/// [LogRequest::set_geo](crate::LogRequest::set_geo). It is logged as `geo=DE/BY asn=3320`.
+///
+/// It can only be created from a valid country code, so that a failed lookup leaves the field
+/// out rather than logging a placeholder country.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Geo {
+    country: [u8; 2],
+    subdivision: Option<String>,
+    asn: Option<u32>,
+}
+
+impl Geo {
+    /// The given ISO 3166-1 alpha-2 country code, in either case. Returns `None` unless it is two
+    /// ASCII letters, or if it is one of the codes used for an unknown country (`XX` and `ZZ`).
+    pub fn new(country: &str) -> Option<Self> {
+        let country: [u8; 2] = country.as_bytes().try_into().ok()?;
+        if !country.iter().all(u8::is_ascii_alphabetic) {
+            return None;
+        }
+        let country = country.map(|b| b.to_ascii_uppercase());
+        if matches!(&country, b"XX" | b"ZZ") {
+            return None;
+        }
+        Some(Self {
+            country,
+            subdivision: None,
+            asn: None,
+        })
+    }
+
+    /// Add the subdivision, as the part of its ISO 3166-2 code after the country: `BY` for
+    /// `DE-BY`. Codes which aren't one to three ASCII letters or digits are ignored.
+    pub fn with_subdivision(mut self, code: &str) -> Self {
+        if (1..=3).contains(&code.len()) && code.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            self.subdivision = Some(code.to_ascii_uppercase());
+        }
+        self
+    }
+
+    /// Add the number of the autonomous system the address belongs to.
+    pub fn with_asn(mut self, asn: u32) -> Self {
+        self.asn = Some(asn);
+        self
+    }
+
+    /// The country code, in upper case.
+    pub fn country(&self) -> &str {
+        // Only ever ASCII letters.
+        std::str::from_utf8(&self.country).unwrap()
+    }
+
+    /// The subdivision code, without the country, in upper case.
+    pub fn subdivision(&self) -> Option<&str> {
+        self.subdivision.as_deref()
+    }
+
+    /// The autonomous system number.
+    pub fn asn(&self) -> Option<u32> {
+        self.asn
+    }
+}
+
+/// The country and subdivision, as `DE/BY`, or just `DE`.
+impl Display for Geo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.country())?;
+        if let Some(subdivision) = &self.subdivision {
+            write!(f, "/{subdivision}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_geo() {
+        let geo = Geo::new("de")
+            .unwrap()
+            .with_subdivision("by")
+            .with_asn(3320);
+        assert_eq!(geo.country(), "DE");
+        assert_eq!(geo.subdivision(), Some("BY"));
+        assert_eq!(geo.asn(), Some(3320));
+        assert_eq!(geo.to_string(), "DE/BY");
+        assert_eq!(Geo::new("NZ").unwrap().to_string(), "NZ");
+
+        for bad in ["", "D", "DEU", "D1", "XX", "zz", "é"] {
+            assert_eq!(Geo::new(bad), None, "{bad}");
+        }
+        for bad in ["", "ABCD", "B-Y", "B Y"] {
+            assert_eq!(
+                Geo::new("DE").unwrap().with_subdivision(bad).subdivision(),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use http::HeaderValue;
+
+/// The header, or trailer, a gRPC response's status is sent in.
+pub(crate) const STATUS: &str = "grpc-status";
+
+/// Whether a `Content-Type` is one of gRPC's: `application/grpc`, optionally followed by a
+/// subtype like `+proto`, or parameters.
+pub(crate) fn is_grpc(content_type: &HeaderValue) -> bool {
+    let value = content_type.as_bytes();
+    let Some(rest) = value.get(b"application/grpc".len()..) else {
+        return false;
+    };
+    value[..value.len() - rest.len()].eq_ignore_ascii_case(b"application/grpc")
+        && matches!(rest.first(), None | Some(b'+' | b';'))
+}
+
+/// The status in a `grpc-status` header: a non-negative decimal number. Anything else is
+/// ignored rather than logged as some status it isn't.
+pub(crate) fn status(value: &HeaderValue) -> Option<i32> {
+    let value = value.to_str().ok()?;
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// The service and method of a gRPC request's path, which is always `/package.Service/Method`.
+pub(crate) fn method(path: &str) -> Option<(&str, &str)> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if service.is_empty() || method.is_empty() || method.contains('/') {
+        return None;
+    }
+    Some((service, method))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_grpc() {
+        for value in [
+            "application/grpc",
+            "application/grpc+proto",
+            "Application/gRPC+json",
+            "application/grpc;charset=utf-8",
+        ] {
+            assert!(is_grpc(&HeaderValue::from_static(value)), "{value}");
+        }
+        for value in [
+            "application/grpc-web",
+            "application/json",
+            "application/grp",
+            "",
+        ] {
+            assert!(!is_grpc(&HeaderValue::from_static(value)), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let status = |s: &'static str| status(&HeaderValue::from_static(s));
+        assert_eq!(status("0"), Some(0));
+        assert_eq!(status("14"), Some(14));
+        assert_eq!(status(""), None);
+        assert_eq!(status("-1"), None);
+        assert_eq!(status("+1"), None);
+        assert_eq!(status(" 1"), None);
+        assert_eq!(status("99999999999"), None);
+    }
+
+    #[test]
+    fn test_method() {
+        assert_eq!(
+            method("/pkg.Service/Method"),
+            Some(("pkg.Service", "Method"))
+        );
+        assert_eq!(method("/Service/Method"), Some(("Service", "Method")));
+        assert_eq!(method("/pkg.Service/"), None);
+        assert_eq!(method("//Method"), None);
+        assert_eq!(method("/pkg.Service"), None);
+        assert_eq!(method("/a/b/c"), None);
+        assert_eq!(method("pkg.Service/Method"), None);
+    }
+}
@@ -0,0 +1,401 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use http::{HeaderValue, Method, Uri, Version};
+
+use crate::config::{default_config, LogConfig};
+use crate::entry::{Extra, Lifecycle, LogEntry};
+use crate::format::{self, Format};
+use crate::request::{cap_hops, push_extra};
+
+/// A request described with plain owned values rather than hyper types, for rendering lines for
+/// requests which were handled somewhere else, such as by a sidecar written in another language.
+///
+/// An input is rendered by the same code as a [LogRequest](crate::LogRequest), so a request
+/// described the same way produces the same line, byte for byte. The values are logged as given:
+/// only the limits on the number of forwarded hops and on extra fields are applied, not options
+/// which change what is captured, like [normalize_host](LogConfig::normalize_host).
+///
+/// ```
+/// # use hyper_req_log::LogEntryInput;
+/// let input = LogEntryInput {
+///     action: Some("get".to_owned()),
+///     status: Some(200),
+///     remote: Some("192.0.2.1:4000".to_owned()),
+///     uri: "/hello".to_owned(),
+///     start_unix_nanos: 1_700_000_000_000_000_000,
+///     duration_nanos: 1_500_000,
+///     ..Default::default()
+/// };
+/// let mut line = String::new();
+/// input.render(&mut line).unwrap();
+/// assert_eq!(
+///     line,
+///     "2023-11-14T22:13:20.000Z request: [get:200] 192.0.2.1:4000 \"\" GET /hello HTTP/1.1 \
+///      \"\" \"\" 1.500\n"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntryInput {
+    /// Whether this is an outbound request made by a client, as if by
+    /// [LogRequest::from_client_request](crate::LogRequest::from_client_request).
+    pub client: bool,
+    /// The request's ID.
+    pub request_id: Option<String>,
+    /// The action.
+    pub action: Option<String>,
+    /// The response status. A request without one is logged as still in flight.
+    pub status: Option<u16>,
+    /// The user.
+    pub user: Option<String>,
+    /// The remote address, or the peer for a client request, as `192.0.2.1:4000` or
+    /// `[2001:db8::1]:443`.
+    pub remote: Option<String>,
+    /// The `X-Forwarded-For` header.
+    pub forwarded_for: Option<String>,
+    /// The `Host` header.
+    pub host: Option<String>,
+    /// The name of the site which handled the request.
+    pub server_name: Option<String>,
+    /// The method. Defaults to `GET`.
+    pub method: String,
+    /// The request target. Defaults to `/`.
+    pub uri: String,
+    /// The HTTP version, as `HTTP/1.1`. Defaults to `HTTP/1.1`.
+    pub version: String,
+    /// The `User-Agent` header.
+    pub user_agent: Option<String>,
+    /// The `Referer` header.
+    pub referer: Option<String>,
+    /// How long the request took, in nanoseconds.
+    pub duration_nanos: u64,
+    /// How long the handler took to produce the response, in nanoseconds, if that is known.
+    pub handler_nanos: Option<u64>,
+    /// When the request started, in nanoseconds since the Unix epoch, which is the timestamp the
+    /// line is logged with.
+    pub start_unix_nanos: u64,
+    /// The size of the response body in bytes.
+    pub response_bytes: Option<u64>,
+    /// A description of why the request failed.
+    pub error: Option<String>,
+    /// Extra fields, as if added by [add_field_bytes](crate::LogRequest::add_field_bytes)
+    /// without a length limit.
+    pub extras: Vec<(String, String)>,
+}
+
+impl Default for LogEntryInput {
+    fn default() -> Self {
+        Self {
+            client: false,
+            request_id: None,
+            action: None,
+            status: None,
+            user: None,
+            remote: None,
+            forwarded_for: None,
+            host: None,
+            server_name: None,
+            method: "GET".to_owned(),
+            uri: "/".to_owned(),
+            version: "HTTP/1.1".to_owned(),
+            user_agent: None,
+            referer: None,
+            duration_nanos: 0,
+            handler_nanos: None,
+            start_unix_nanos: 0,
+            response_bytes: None,
+            error: None,
+            extras: vec![],
+        }
+    }
+}
+
+impl LogEntryInput {
+    /// Render the input in the text format with the default configuration, appending the line
+    /// to the given buffer.
+    pub fn render(&self, buf: &mut String) -> Result<(), InputError> {
+        let entry = self.to_entry(default_config())?;
+        format::write_entry(&entry, Format::Text, buf).expect("writing to a String can't fail");
+        Ok(())
+    }
+
+    /// Convert the input to a [LogEntry] with the given configuration, which can be rendered in
+    /// any format or passed to a [LogSink](crate::LogSink).
+    pub fn to_entry(&self, config: Arc<LogConfig>) -> Result<LogEntry, InputError> {
+        let header = |field, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|v| HeaderValue::try_from(v).map_err(|_| InputError::Header(field)))
+                .transpose()
+        };
+        let (fwd, fwd_elided) = match header("forwarded_for", &self.forwarded_for)? {
+            Some(fwd) if !self.client => cap_hops(&fwd, config.max_forwarded_hops),
+            _ => (None, 0),
+        };
+        let mut extras = vec![];
+        let (mut extras_bytes, mut extras_dropped) = (0, 0);
+        for (key, value) in &self.extras {
+            push_extra(
+                &mut extras,
+                &mut extras_bytes,
+                &mut extras_dropped,
+                &config,
+                Cow::Owned(key.clone()),
+                Extra::Bytes {
+                    value: Bytes::from(value.clone()),
+                    max_len: usize::MAX,
+                },
+            );
+        }
+        let anon = config.anonymize_ips;
+        Ok(LogEntry {
+            start_time: SystemTime::UNIX_EPOCH + Duration::from_nanos(self.start_unix_nanos),
+            timestamp_style: None,
+            log_query: None,
+            field_set: None,
+            request_id: self.request_id.clone(),
+            trace_parent: None,
+            trace_state: None,
+            user: self.user.clone().map(Bytes::from),
+            client: self.client,
+            remote: self
+                .remote
+                .as_deref()
+                .map(|r| r.parse::<SocketAddr>().map_err(|_| InputError::Remote))
+                .transpose()?
+                .map(|remote| anon.addr(remote)),
+            remote_host: None,
+            geo: None,
+            fwd: fwd.map_or_else(Vec::new, |fwd| anon.chain(fwd.as_bytes())),
+            fwd_elided,
+            fwd_proto: None,
+            fwd_host: None,
+            real_ip: None,
+            fwd_untrusted: false,
+            real_ip_mismatch: None,
+            host: header("host", &self.host)?,
+            authority_mismatch: None,
+            server_name: self.server_name.clone(),
+            method: Method::from_bytes(self.method.as_bytes()).map_err(|_| InputError::Method)?,
+            uri: self.uri.parse::<Uri>().map_err(|_| InputError::Uri)?,
+            version: parse_version(&self.version).ok_or(InputError::Version)?,
+            user_agent: header("user_agent", &self.user_agent)?
+                .map(|ua| config.privacy.user_agent(&ua)),
+            referer: header("referer", &self.referer)?.and_then(|r| config.privacy.referer(&r)),
+            headers: vec![],
+            response_headers: vec![],
+            upgrade: None,
+            negotiation: None,
+            content_type: None,
+            content_encoding: None,
+            accept: None,
+            dump: None,
+            #[cfg(feature = "fingerprint")]
+            fingerprint: None,
+            formatter: None,
+            action: self.action.clone(),
+            action_chain: Vec::new(),
+            status: self.status,
+            grpc_status: None,
+            cache_status: None,
+            budget: None,
+            slow_threshold: None,
+            middleware: None,
+            handler: self.handler_nanos.map(Duration::from_nanos),
+            upstream: None,
+            sent_duration: None,
+            header_bytes_in: None,
+            header_bytes_out: None,
+            request_bytes: None,
+            response_bytes: self.response_bytes,
+            body_end: None,
+            setup: None,
+            conn: None,
+            tls: None,
+            extras,
+            extras_dropped,
+            error_kind: None,
+            error: self.error.clone(),
+            sample_rate: None,
+            lifecycle: match self.status {
+                Some(_) => Lifecycle::Responded,
+                None => Lifecycle::InFlight,
+            },
+            duration: Duration::from_nanos(self.duration_nanos),
+            stuck_after: None,
+            config,
+        })
+    }
+}
+
+fn parse_version(version: &str) -> Option<Version> {
+    Some(match version {
+        "HTTP/0.9" => Version::HTTP_09,
+        "HTTP/1.0" => Version::HTTP_10,
+        "HTTP/1.1" => Version::HTTP_11,
+        "HTTP/2.0" | "HTTP/2" => Version::HTTP_2,
+        "HTTP/3.0" | "HTTP/3" => Version::HTTP_3,
+        _ => return None,
+    })
+}
+
+/// Why a [LogEntryInput] couldn't be rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InputError {
+    /// The remote address isn't a socket address.
+    Remote,
+    /// The method isn't a valid HTTP method.
+    Method,
+    /// The URI isn't a valid request target.
+    Uri,
+    /// The version isn't one of `HTTP/0.9`, `HTTP/1.0`, `HTTP/1.1`, `HTTP/2`, or `HTTP/3`, or
+    /// `HTTP/2.0` or `HTTP/3.0`.
+    Version,
+    /// The named field has characters which can't be in a header value, such as a newline.
+    Header(&'static str),
+}
+
+impl Display for InputError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Remote => f.write_str("invalid remote address"),
+            Self::Method => f.write_str("invalid method"),
+            Self::Uri => f.write_str("invalid URI"),
+            Self::Version => f.write_str("invalid HTTP version"),
+            Self::Header(field) => write!(f, "invalid header value for {field}"),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::LogRequest;
+
+    #[test]
+    fn test_same_as_request() {
+        let req = http::Request::put("/api/items/42?draft=1")
+            .header("host", "api.example.com")
+            .header("user-agent", "sidecar/2.0 \"beta\"")
+            .header("referer", "https://www.example.com/")
+            .header("x-forwarded-for", "203.0.113.7, 10.0.0.1")
+            .body(())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        log.set_remote("[2001:db8::1]:51234".parse().unwrap())
+            .set_user("alice".to_owned())
+            .set_action("update")
+            .set_response(&http::Response::builder().status(204).body(()).unwrap())
+            .set_response_size(0)
+            .add_field_bytes("trace", Bytes::from_static(b"4bf92f35 77b3"), usize::MAX);
+        let mut entry = log.entry();
+        log.discard();
+        entry.duration = Duration::from_nanos(12_345_678);
+        entry.handler = Some(Duration::from_nanos(4_567_000));
+        entry.start_time = SystemTime::UNIX_EPOCH;
+
+        let input = LogEntryInput {
+            action: Some("update".to_owned()),
+            status: Some(204),
+            user: Some("alice".to_owned()),
+            remote: Some("[2001:db8::1]:51234".to_owned()),
+            forwarded_for: Some("203.0.113.7, 10.0.0.1".to_owned()),
+            host: Some("api.example.com".to_owned()),
+            method: "PUT".to_owned(),
+            uri: "/api/items/42?draft=1".to_owned(),
+            user_agent: Some("sidecar/2.0 \"beta\"".to_owned()),
+            referer: Some("https://www.example.com/".to_owned()),
+            duration_nanos: 12_345_678,
+            handler_nanos: Some(4_567_000),
+            response_bytes: Some(0),
+            extras: vec![("trace".to_owned(), "4bf92f35 77b3".to_owned())],
+            ..Default::default()
+        };
+        let mut line = String::new();
+        input.render(&mut line).unwrap();
+        assert_eq!(line, entry.to_string());
+
+        // And for a request which is still in flight.
+        let log = LogRequest::<&str>::from_request(&http::Request::new(()));
+        let mut entry = log.entry();
+        log.discard();
+        entry.duration = Duration::ZERO;
+        entry.start_time = SystemTime::UNIX_EPOCH;
+        let mut line = String::new();
+        LogEntryInput::default().render(&mut line).unwrap();
+        assert_eq!(line, entry.to_string());
+    }
+
+    #[test]
+    fn test_limits() {
+        let input = LogEntryInput {
+            forwarded_for: Some("1.1.1.1, 2.2.2.2, 3.3.3.3, 4.4.4.4".to_owned()),
+            extras: vec![
+                ("a".to_owned(), "1".to_owned()),
+                ("b".to_owned(), "2".to_owned()),
+            ],
+            ..Default::default()
+        };
+        let config = Arc::new(LogConfig::new().max_forwarded_hops(2).max_extras(1));
+        let line = input.to_entry(config).unwrap().to_string();
+        assert!(line.contains("/1.1.1.1,…(+2),4.4.4.4 "), "{line}");
+        assert!(line.ends_with(" a=1 extras_dropped=1\n"), "{line}");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let render = |input: LogEntryInput| input.render(&mut String::new());
+        let cases = [
+            (
+                LogEntryInput {
+                    remote: Some("192.0.2.1".to_owned()),
+                    ..Default::default()
+                },
+                InputError::Remote,
+            ),
+            (
+                LogEntryInput {
+                    method: "GE T".to_owned(),
+                    ..Default::default()
+                },
+                InputError::Method,
+            ),
+            (
+                LogEntryInput {
+                    uri: "/a b".to_owned(),
+                    ..Default::default()
+                },
+                InputError::Uri,
+            ),
+            (
+                LogEntryInput {
+                    version: "HTTP/1.2".to_owned(),
+                    ..Default::default()
+                },
+                InputError::Version,
+            ),
+            (
+                LogEntryInput {
+                    user_agent: Some("a\nb".to_owned()),
+                    ..Default::default()
+                },
+                InputError::Header("user_agent"),
+            ),
+        ];
+        for (input, err) in cases {
+            assert_eq!(render(input), Err(err));
+        }
+        assert_eq!(
+            InputError::Header("host").to_string(),
+            "invalid header value for host"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Write};
+
+use crate::entry::LogEntry;
+use crate::escaped::Escaped;
+use crate::fields::{write_fields, Fields};
+
+/// Render an entry as a single-line JSON object, for [Format::Json](crate::Format::Json).
+pub(crate) fn write_json<W: Write + ?Sized>(entry: &LogEntry, f: &mut W) -> fmt::Result {
+    let mut obj = Object::new(f)?;
+    write_fields(entry, &mut obj)?;
+    obj.end()?;
+    f.write_char('\n')
+}
+
+/// Writes the members of a JSON object.
+struct Object<'a, W: Write + ?Sized> {
+    f: &'a mut W,
+    first: bool,
+}
+
+impl<'a, W: Write + ?Sized> Object<'a, W> {
+    fn new(f: &'a mut W) -> Result<Self, fmt::Error> {
+        f.write_char('{')?;
+        Ok(Self { f, first: true })
+    }
+
+    /// Write a member, given its already encoded value.
+    fn member(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        if !self.first {
+            self.f.write_char(',')?;
+        }
+        self.first = false;
+        write!(self.f, "{}:{value}", Escaped::json(key))
+    }
+
+    fn end(self) -> fmt::Result {
+        self.f.write_char('}')
+    }
+}
+
+impl<W: Write + ?Sized> Fields for Object<'_, W> {
+    fn string(&mut self, key: &str, value: &[u8]) -> fmt::Result {
+        self.member(key, &Escaped::json(value))
+    }
+
+    fn number(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        self.member(key, value)
+    }
+
+    fn strings(&mut self, key: &str, values: &[String]) -> fmt::Result {
+        let mut array = String::from("[");
+        for (i, value) in values.iter().enumerate() {
+            if i != 0 {
+                array.push(',');
+            }
+            write!(array, "{}", Escaped::json(value))?;
+        }
+        array.push(']');
+        self.member(key, &array)
+    }
+
+    fn extras(&mut self, extras: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result {
+        let mut inner = String::new();
+        let mut obj = Object::new(&mut inner)?;
+        for (key, value) in extras {
+            obj.string(key, value)?;
+        }
+        obj.end()?;
+        self.member("extras", &inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use http::HeaderValue;
+    use serde_json::{json, Value};
+
+    use crate::config::LogConfig;
+    use crate::fields::FieldNames;
+    use crate::format::Format;
+
+    fn parse(entry: &LogEntry) -> Value {
+        let mut line = String::new();
+        write_json(entry, &mut line).unwrap();
+        assert!(line.ends_with("}\n") && !line[..line.len() - 1].contains('\n'));
+        serde_json::from_str(&line).unwrap_or_else(|e| panic!("{e}: {line}"))
+    }
+
+    #[test]
+    fn test_fixture() {
+        let value = parse(&crate::entry::fixture_entry());
+        assert_eq!(value["status"], 201);
+        assert_eq!(value["action"], "create");
+        assert_eq!(value["duration_ms"], 12.345);
+        assert_eq!(value["extras"], json!({"trace": "4bf92f3577b34da6"}));
+    }
+
+    #[test]
+    fn test_weird_bytes() {
+        let req = http::Request::get("/")
+            .header(
+                "user-agent",
+                HeaderValue::from_bytes(b"bad \xff\xfe utf-8 \"quoted\" back\\slash\t").unwrap(),
+            )
+            .header(
+                "referer",
+                HeaderValue::from_bytes("caf\u{e9} \u{2028}".as_bytes()).unwrap(),
+            )
+            .body(())
+            .unwrap();
+        let mut entry = crate::entry::test_entry(&req);
+        entry.user = Some("line\nbreak\u{1}\u{7f}".into());
+        let value = parse(&entry);
+        assert_eq!(
+            value["user_agent"],
+            "bad \u{fffd}\u{fffd} utf-8 \"quoted\" back\\slash\t"
+        );
+        assert_eq!(value["referer"], "caf\u{e9} \u{2028}");
+        assert_eq!(value["user"], "line\nbreak\u{1}\u{7f}");
+
+        let mut line = String::new();
+        write_json(&entry, &mut line).unwrap();
+        assert!(
+            line.contains(r#""user":"line\nbreak\u0001\u007f""#),
+            "{line}"
+        );
+        assert!(line.contains(r#"back\\slash\t""#), "{line}");
+        assert!(line.contains("\"caf\u{e9} \\u2028\""), "{line}");
+    }
+
+    #[test]
+    fn test_missing() {
+        let value = parse(&crate::entry::test_entry(&http::Request::new(())));
+        let keys = value.as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "duration_ms",
+                "lifecycle",
+                "method",
+                "outcome",
+                "schema",
+                "timestamp",
+                "uri",
+                "version"
+            ]
+        );
+        assert_eq!(value["lifecycle"], "in-flight");
+    }
+
+    #[test]
+    fn test_field_names() {
+        let line = |names| {
+            let config = LogConfig::new()
+                .field_names(names)
+                .capture_header(http::header::HeaderName::from_static("x-tenant"));
+            let req = http::Request::get("/items?id=7")
+                .header("host", "example.com")
+                .header("user-agent", "curl/8.0")
+                .header("x-tenant", "acme")
+                .body(())
+                .unwrap();
+            let mut log =
+                crate::LogRequest::<&str>::from_request_with_config(&req, Arc::new(config));
+            let clock = crate::clock::MockClock::default();
+            log.set_clock(clock.clone());
+            log.set_remote("[::ffff:192.0.2.1]:4000".parse().unwrap())
+                .set_action("items")
+                .set_user("alice");
+            clock.advance(std::time::Duration::from_micros(12_345));
+            log.set_response(&http::Response::builder().status(404).body(()).unwrap());
+            let mut line = String::new();
+            write_json(&log.entry(), &mut line).unwrap();
+            log.discard();
+            assert_eq!(line.pop(), Some('\n'));
+            line
+        };
+        assert_eq!(
+            line(FieldNames::Native),
+            concat!(
+                r#"{"timestamp":"1970-01-01T00:00:00.000Z","schema":1,"status":404,"#,
+                r#""outcome":"client-error","action":"items","user":"alice","#,
+                r#""remote":"192.0.2.1:4000","host":"example.com","method":"GET","#,
+                r#""uri":"/items?id=7","version":"HTTP/1.1","user_agent":"curl/8.0","#,
+                r#""x-tenant":"acme","handler_ms":12.345,"duration_ms":12.345}"#,
+            )
+        );
+        assert_eq!(
+            line(FieldNames::Otel),
+            concat!(
+                r#"{"timestamp":"1970-01-01T00:00:00.000Z","schema":1,"#,
+                r#""http.response.status_code":404,"outcome":"client-error","http.route":"items","#,
+                r#""user.name":"alice","client.address":"192.0.2.1","client.port":4000,"#,
+                r#""server.address":"example.com","http.request.method":"GET","#,
+                r#""url.path":"/items","#,
+                r#""url.query":"id=7","network.protocol.version":"1.1","#,
+                r#""user_agent.original":"curl/8.0","http.request.header.x-tenant":"acme","#,
+                r#""handler_ms":12.345,"http.server.request.duration":0.012345}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_header_collisions() {
+        use http::header::{ACCEPT, HOST};
+
+        let config = LogConfig::new()
+            .capture_header(HOST)
+            .capture_header(ACCEPT)
+            .capture_header(http::header::HeaderName::from_static("traceparent"))
+            .capture_header(http::header::HeaderName::from_static("x-tenant"));
+        let req = http::Request::get("/")
+            .header("host", "example.com")
+            .header("accept", "text/html")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .header("x-tenant", "acme")
+            .body(())
+            .unwrap();
+        let log = crate::LogRequest::<&str>::from_request_with_config(&req, Arc::new(config));
+        let entry = log.entry();
+        log.discard();
+        let mut line = String::new();
+        write_json(&entry, &mut line).unwrap();
+
+        // Every key is written once, so none is lost by a parser which keeps the last value.
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        let keys = value.as_object().unwrap().len();
+        assert_eq!(keys, line.matches("\":").count(), "{line}");
+        assert_eq!(value["host"], "example.com");
+        assert_eq!(value["req.host"], "example.com");
+        assert_eq!(value["req.accept"], "text/html");
+        assert!(value["req.traceparent"]
+            .as_str()
+            .unwrap()
+            .starts_with("00-4bf9"));
+        assert_eq!(value["x-tenant"], "acme");
+
+        let mut text = String::new();
+        crate::format::write_entry(&entry, crate::Format::Logfmt, &mut text).unwrap();
+        assert!(text.contains(" req.host=example.com "), "{text}");
+        assert_eq!(text.matches(" host=").count(), 1, "{text}");
+    }
+
+    #[test]
+    fn test_max_header_len() {
+        let config = LogConfig::new()
+            .max_header_len(8)
+            .capture_header(http::header::HeaderName::from_static("x-tenant"));
+        let req = http::Request::get("/")
+            .header("user-agent", "A".repeat(5000))
+            .header("referer", "caf\u{e9}/menu")
+            .header("x-tenant", "acme")
+            .body(())
+            .unwrap();
+        let log = crate::LogRequest::<&str>::from_request_with_config(&req, Arc::new(config));
+        let value = parse(&log.entry());
+        log.discard();
+        assert_eq!(value["user_agent"], "AAAAAAAA…(+4992)");
+        assert_eq!(value["referer"], "caf\u{e9}/men…(+1)");
+        assert_eq!(value["x-tenant"], "acme");
+    }
+
+    #[test]
+    fn test_ascii_headers() {
+        let config = LogConfig::new().ascii_headers(true).max_header_len(12);
+        let req = http::Request::get("/")
+            .header("user-agent", HeaderValue::from_bytes(b"bad \xff").unwrap())
+            .header("referer", "caf\u{e9}/men\u{fc}")
+            .body(())
+            .unwrap();
+        let log = crate::LogRequest::<&str>::from_request_with_config(&req, Arc::new(config));
+        let mut line = String::new();
+        write_json(&log.entry(), &mut line).unwrap();
+        log.discard();
+        assert!(line.is_ascii(), "{line}");
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["user_agent"], r"bad \xff");
+        assert_eq!(value["referer"], r"caf\u{e9}/me\u{2026}(+3)");
+    }
+
+    #[test]
+    fn test_config() {
+        let out = Arc::new(Mutex::new(vec![]));
+        let config = Arc::new(LogConfig::new().format(Format::Json));
+        let req = http::Request::get("/json").body(()).unwrap();
+        let mut log = crate::LogRequest::<&str>::from_request_with_config(&req, config);
+        log.set_remote("[::ffff:192.0.2.1]:4000".parse().unwrap())
+            .set_response(&http::Response::new(()))
+            .also_write_to(out.clone());
+        let mut written = vec![];
+        log.write(&mut written).unwrap();
+        assert_eq!(written, *out.lock().unwrap());
+        let value: Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(value["remote"], "192.0.2.1:4000");
+        assert_eq!(value["uri"], "/json");
+    }
+}
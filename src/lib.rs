@@ -1,6 +1,213 @@
+//! Access logging for Hyper servers, or anything else built on the `http` crate's types.
+//!
+//! Create a [LogRequest] from each incoming request, fill in what the handler learns along the
+//! way, and it writes a log entry when it is dropped:
+//!
+//! ```
+//! use hyper_req_log::LogRequest;
+//!
+//! let req = http::Request::get("/hello").body(()).unwrap();
+//! let mut log = LogRequest::<&str>::from_request(&req);
+//! log.set_remote("192.0.2.1:4000".parse().unwrap());
+//! log.set_action("hello");
+//! log.set_response(&http::Response::new(()));
+//! log.write(std::io::stderr()).unwrap();
+//! ```
+//!
+//! # Features
+//!
+//! The request capture, the text formats, and the stderr and `io::Write` sinks are always
+//! available. Everything else is behind a feature:
+//!
+//! - `aggregate` (default): [AggregatingSink].
+//! - `connection` (default): [ConnectionLog], for logging connections which never finish a
+//!   request. Implies `hyper`.
+//! - `correlate` (default): [CorrelatedSink].
+//! - `dedup` (default): [DedupSink].
+//! - `hyper` (default): `ErrorKind::from_hyper_error`. [LogRequest] itself only needs the `http`
+//!   crate's `Request` and `Response`, so this can be turned off for other servers.
+//! - `identity` (default): [IdentitySource], for filling in the user from credentials, using
+//!   `sha2`.
+//! - `rdns` (default): [ReverseDns], and [LogRequest::resolve_remote]. Implies `hyper`, for its
+//!   `Timer`.
+//! - `watchdog` (default): [LogConfig::max_age], for logging requests which never finish.
+//! - `ffi`: [ffi], a C interface for rendering [LogEntryInput]s.
+//! - `fingerprint`: [Fingerprint], for anonymous per-visitor IDs, using `sha2`.
+//! - `local-time`: the local timezone for [Timezone::Local], using `chrono`.
+//! - `log`: `LogFacadeSink`, and `LogRequest::emit_log`, for passing entries to the `log`
+//!   facade.
+//! - `parquet`: `ParquetSink`, using the `parquet` and `arrow` crates.
+//! - `prometheus`: `PrometheusMetrics`, a [MetricsSink] which keeps request counters and latency
+//!   histograms, and renders them in the Prometheus text format.
+//! - `serde`: `serde::Serialize` for [LogEntry] and [LogRequest], with the fields of
+//!   [Format::Json].
+//! - `test-util`: `MockClock`, and `LogRequest::set_clock`, for rendering entries with fixed
+//!   times in tests.
+//! - `tls`: creating a [TlsConnectionInfo] from a rustls `ServerConnection`.
+//! - `tokio`: `LogRequest::write_async`, for writing entries to tokio `AsyncWrite` streams.
+//! - `tower`: `LogLayer`, a `tower` middleware which logs each request.
+//! - `tracing`: `TracingSink`, and `LogRequest::emit_tracing`, for emitting entries as
+//!   `tracing` events.
+//!
+//! Building with `default-features = false` gives just the core.
+
+#![cfg_attr(docsrs, feature(doc_auto_cfg))]
+
+#[cfg(feature = "aggregate")]
+mod aggregate;
+mod annotations;
+mod anonymize;
+mod background;
+mod basic_auth;
+mod body;
+mod cache;
+mod clf;
+mod client;
+mod clock;
+mod config;
+#[cfg(feature = "connection")]
+mod connection;
+#[cfg(feature = "correlate")]
+mod correlate;
+#[cfg(feature = "dedup")]
+mod dedup;
+mod diagnostics;
 mod display;
+mod dump;
+mod entry;
+mod error;
 mod escaped;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod field_set;
+mod fields;
+mod file_sink;
+#[cfg(feature = "fingerprint")]
+mod fingerprint;
+mod format;
+mod formatter;
+mod forwarded;
+mod geo;
+mod grpc;
+mod head;
+#[cfg(feature = "identity")]
+mod identity;
+mod input;
+mod json;
+#[cfg(feature = "log")]
+mod log_facade;
+mod logfmt;
+mod logged;
+mod metrics;
+mod negotiation;
+mod normalize;
+#[cfg(feature = "parquet")]
+mod parquet_sink;
+mod policy;
+mod privacy;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod proxies;
+mod query;
+mod remote;
 mod request;
+mod request_id;
+#[cfg(feature = "rdns")]
+mod resolve;
+mod ring;
+mod route;
+mod sample;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "tower")]
+mod service;
+mod shutdown;
+mod sink;
+mod stats;
+mod syslog;
+mod time;
+mod tls;
+mod trace_context;
+#[cfg(feature = "tracing")]
+mod tracing_event;
+mod w3c;
+#[cfg(feature = "watchdog")]
+mod watchdog;
+mod write_errors;
+
+#[cfg(feature = "aggregate")]
+pub use aggregate::AggregatingSink;
+pub use annotations::LogAnnotations;
+pub use anonymize::IpAnonymization;
+pub use background::{BackgroundLogger, Overflow};
+pub use body::CountingBody;
+pub use cache::CacheStatus;
+#[cfg(any(test, feature = "test-util"))]
+pub use clock::MockClock;
+pub use config::{ConfigError, LogConfig, Placeholder, StatusStyle};
+#[cfg(feature = "connection")]
+pub use connection::{ConnectionLog, CountingIo};
+#[cfg(feature = "correlate")]
+pub use correlate::CorrelatedSink;
+#[cfg(feature = "dedup")]
+pub use dedup::DedupSink;
+pub use display::{LogDisplay, ViaDisplay};
+pub use dump::DumpPolicy;
+pub use entry::{Lifecycle, LogEntry, Outcome, StatusClass};
+pub use error::ErrorKind;
+pub use escaped::{escape_to_string, Escaped};
+pub use field_set::{set_default_field_set, FieldSet};
+pub use fields::FieldNames;
+pub use file_sink::FileSink;
+#[cfg(feature = "fingerprint")]
+pub use fingerprint::{Fingerprint, FingerprintField};
+pub use format::{http_version, Format, SCHEMA_VERSION};
+pub use formatter::{clear_default_formatter, set_default_formatter, LogFormatter};
+pub use forwarded::ForwardedEntry;
+pub use geo::Geo;
+pub use head::RequestHead;
+#[cfg(feature = "identity")]
+pub use identity::{EdgeIdentity, IdentitySource, TrustedIdentityHeader};
+pub use input::{InputError, LogEntryInput};
+#[cfg(feature = "log")]
+pub use log_facade::LogFacadeSink;
+pub use logged::{logged_service, LoggedFuture};
+pub use metrics::{clear_default_metrics_sink, set_default_metrics_sink, MetricsSink};
+#[cfg(feature = "parquet")]
+pub use parquet_sink::ParquetSink;
+pub use policy::LogPolicy;
+pub use privacy::Privacy;
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusMetrics;
+pub use proxies::{TrustedProxies, TrustedProxiesError};
+pub use query::{set_default_log_query, TargetForm};
+pub use remote::RemoteAddr;
+pub use request::{DynLogRequest, LogRequest, SimpleLogRequest};
+#[cfg(feature = "rdns")]
+pub use resolve::{ResolveFuture, Resolver, ReverseDns};
+pub use ring::RingBufferSink;
+pub use route::{Route, RouteRules};
+pub use sample::{SamplePolicy, SamplingDecision};
+#[cfg(feature = "tower")]
+pub use service::{LogFuture, LogHandle, LogLayer, LogService};
+pub use shutdown::{is_shut_down, shutdown};
+pub use sink::{clear_default_sink, set_default_sink, DualFormat, LogSink, MultiSink, StderrSink};
+pub use stats::{Stats, StatsReporter};
+pub use syslog::{Facility, Severity, SyslogConfig, SyslogSink};
+pub use time::{set_default_timestamp_style, DurationStyle, Timestamp, TimestampStyle, Timezone};
+pub use tls::TlsConnectionInfo;
+#[cfg(feature = "tracing")]
+pub use tracing_event::TracingSink;
+pub use w3c::{W3cField, W3cSink};
+pub use write_errors::{clear_write_error_handler, failed_writes, set_write_error_handler};
+
+/// Entry points for the benchmarks. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    use std::fmt::{self, Write};
 
-pub use display::LogDisplay;
-pub use request::LogRequest;
+    /// Write bytes the way header values are escaped in the text format.
+    pub fn write_escaped<W: Write>(bytes: &[u8], out: &mut W) -> fmt::Result {
+        crate::Escaped::from(bytes).write_to(out)
+    }
+}
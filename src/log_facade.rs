@@ -0,0 +1,200 @@
+use std::io;
+use std::sync::Arc;
+
+use crate::entry::LogEntry;
+use crate::sink::LogSink;
+
+type LevelFn = dyn Fn(&LogEntry) -> log::Level + Send + Sync;
+
+/// [LogFacadeSink] is a sink which passes each entry to the `log` facade, so it is handled by
+/// whichever logger the application has installed, such as `env_logger`, with its filtering and
+/// output handling, rather than being written to stderr.
+///
+/// Entries are rendered in the [Format](crate::Format) of their config, without the trailing
+/// newline. The level is `Error` for 5xx responses, `Warn` for 4xx responses, and `Info`
+/// otherwise, unless another mapping is set with [level](Self::level). Lines which aren't
+/// request entries are logged at `Info`.
+///
+/// To have dropped requests go to the `log` facade rather than stderr, set it as the
+/// [sink](crate::LogConfig::sink) of the config:
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{LogConfig, LogFacadeSink};
+/// let config = Arc::new(LogConfig::new().sink(Arc::new(LogFacadeSink::new().target("access"))));
+/// ```
+#[derive(Clone)]
+pub struct LogFacadeSink {
+    target: String,
+    level: Option<Arc<LevelFn>>,
+}
+
+impl Default for LogFacadeSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogFacadeSink {
+    /// Create a sink which logs with the target `hyper_req_log`.
+    pub fn new() -> Self {
+        Self {
+            target: "hyper_req_log".to_owned(),
+            level: None,
+        }
+    }
+
+    /// The target of every record.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Choose the level of each entry's record, instead of going by its status.
+    pub fn level(
+        mut self,
+        level: impl Fn(&LogEntry) -> log::Level + Send + Sync + 'static,
+    ) -> Self {
+        self.level = Some(Arc::new(level));
+        self
+    }
+
+    fn level_of(&self, entry: &LogEntry) -> log::Level {
+        if let Some(level) = &self.level {
+            return level(entry);
+        }
+        match entry.status {
+            Some(500..) => log::Level::Error,
+            Some(400..) => log::Level::Warn,
+            _ => log::Level::Info,
+        }
+    }
+}
+
+impl LogSink for LogFacadeSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        log::log!(target: &self.target, log::Level::Info, "{}", line.trim_end_matches('\n'));
+        Ok(())
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        let level = self.level_of(entry);
+        if !log::log_enabled!(target: &self.target, level) {
+            return Ok(());
+        }
+        entry.with_rendered(|line| {
+            log::log!(target: &self.target, level, "{}", line.trim_end_matches('\n'));
+            Ok(())
+        })
+    }
+
+    fn as_log_facade(&self) -> Option<&LogFacadeSink> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::untimed;
+    use std::sync::Mutex;
+
+    use log::{Level, Log, Metadata, Record};
+
+    use crate::LogRequest;
+
+    /// The records logged so far, as level, target, and message. `log` only allows one logger to
+    /// be installed per process, so the tests share it.
+    static RECORDS: Mutex<Vec<(Level, String, String)>> = Mutex::new(vec![]);
+
+    struct Recorder;
+
+    impl Log for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            RECORDS.lock().unwrap().push((
+                record.level(),
+                record.target().to_owned(),
+                record.args().to_string(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn records(target: &str) -> Vec<(Level, String)> {
+        let _ = log::set_logger(&Recorder);
+        log::set_max_level(log::LevelFilter::Trace);
+        RECORDS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, t, _)| t == target)
+            .map(|(level, _, message)| (*level, message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_emit_log() {
+        records("");
+        let req = http::Request::get("/items").body(()).unwrap();
+        for status in [503, 404, 200] {
+            let mut log = LogRequest::<&str>::from_request(&req);
+            log.set_remote("192.0.2.1:4000".parse().unwrap())
+                .set_response(&http::Response::builder().status(status).body(()).unwrap());
+            log.emit_log();
+        }
+        let records = records("hyper_req_log");
+        let levels = records.iter().map(|(level, _)| *level).collect::<Vec<_>>();
+        assert_eq!(levels, [Level::Error, Level::Warn, Level::Info]);
+        assert!(
+            untimed(&records[0].1).starts_with("request: [503] 192.0.2.1:4000 "),
+            "{}",
+            records[0].1
+        );
+        assert!(!records[0].1.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_config_sink() {
+        records("");
+        let sink = LogFacadeSink::new()
+            .target("access")
+            .level(|entry| match entry.status() {
+                Some(404) => Level::Debug,
+                _ => Level::Trace,
+            });
+        let config = Arc::new(crate::LogConfig::new().sink(Arc::new(sink)));
+        let req = http::Request::get("/missing").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .set_response(&http::Response::builder().status(404).body(()).unwrap());
+        drop(log);
+        let records = records("access");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, Level::Debug);
+        assert!(records[0].1.contains(" GET /missing "), "{}", records[0].1);
+    }
+
+    #[test]
+    fn test_emit_log_config() {
+        records("");
+        let sink = LogFacadeSink::new()
+            .target("emitted")
+            .level(|_| Level::Debug);
+        let config = Arc::new(crate::LogConfig::new().sink(Arc::new(sink)));
+        let req = http::Request::get("/emitted").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+        log.set_response(&http::Response::builder().status(500).body(()).unwrap());
+        let written = crate::Stats::get().written;
+        log.emit_log();
+        assert!(crate::Stats::get().written > written);
+        let records = records("emitted");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, Level::Debug);
+        assert!(records[0].1.contains(" GET /emitted "), "{}", records[0].1);
+    }
+}
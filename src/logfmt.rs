@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::entry::LogEntry;
+use crate::fields::{is_key, write_fields, Fields};
+
+/// Render an entry as a line of logfmt `key=value` pairs, for
+/// [Format::Logfmt](crate::Format::Logfmt).
+pub(crate) fn write_logfmt<W: Write + ?Sized>(entry: &LogEntry, f: &mut W) -> fmt::Result {
+    write_fields(entry, &mut Pairs { f, first: true })?;
+    f.write_char('\n')
+}
+
+/// Writes space-separated pairs.
+struct Pairs<'a, W: Write + ?Sized> {
+    f: &'a mut W,
+    first: bool,
+}
+
+impl<W: Write + ?Sized> Pairs<'_, W> {
+    /// Write a pair, given its already encoded value.
+    fn pair(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        if !self.first {
+            self.f.write_char(' ')?;
+        }
+        self.first = false;
+        write!(self.f, "{}={value}", LogfmtKey(key))
+    }
+}
+
+impl<W: Write + ?Sized> Fields for Pairs<'_, W> {
+    fn string(&mut self, key: &str, value: &[u8]) -> fmt::Result {
+        self.pair(key, &LogfmtValue(value))
+    }
+
+    fn number(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        self.pair(key, value)
+    }
+
+    fn extras(&mut self, extras: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result {
+        for (key, value) in extras {
+            match is_key(key) {
+                true => self.string(&format!("extra.{key}"), value)?,
+                false => self.string(key, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders a key, with any characters which can't be in a logfmt key replaced with `_`.
+struct LogfmtKey<'a>(&'a str);
+
+impl Display for LogfmtKey<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_char('_');
+        }
+        for c in self.0.chars() {
+            match c {
+                ' ' | '=' | '"' => f.write_char('_')?,
+                c if c.is_control() => f.write_char('_')?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders a value, quoted if it is empty or has spaces, `=`, quotes, backslashes, or control
+/// characters in it. Quoted values escape quotes and backslashes with a backslash, and control
+/// characters as `\n`, `\t`, or `\u00XX`. Sequences which aren't valid UTF-8 are replaced with
+/// U+FFFD, as by [String::from_utf8_lossy].
+struct LogfmtValue<'a>(&'a [u8]);
+
+impl Display for LogfmtValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let bare = !self.0.is_empty()
+            && self
+                .0
+                .iter()
+                .all(|&b| b > b' ' && b != b'=' && b != b'"' && b != b'\\' && b != 0x7f);
+        if let (true, Ok(s)) = (bare, std::str::from_utf8(self.0)) {
+            if !s.chars().any(char::is_control) {
+                return f.write_str(s);
+            }
+        }
+        f.write_char('"')?;
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '"' => f.write_str("\\\"")?,
+                    '\\' => f.write_str("\\\\")?,
+                    '\n' => f.write_str("\\n")?,
+                    '\r' => f.write_str("\\r")?,
+                    '\t' => f.write_str("\\t")?,
+                    c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                    c => f.write_char(c)?,
+                }
+            }
+            if !chunk.invalid().is_empty() {
+                f.write_char('\u{fffd}')?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fields::{FieldNames, KEYS};
+
+    use http::HeaderValue;
+
+    fn value(bytes: &[u8]) -> String {
+        LogfmtValue(bytes).to_string()
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(value(b"alice@example.com"), "alice@example.com");
+        assert_eq!(value(b""), "\"\"");
+        assert_eq!(value(b"two words"), "\"two words\"");
+        assert_eq!(value(b"a=b"), "\"a=b\"");
+        assert_eq!(value(b"say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(value(b"back\\slash"), "\"back\\\\slash\"");
+        assert_eq!(value(b"tab\there\n\x01"), "\"tab\\there\\n\\u0001\"");
+        assert_eq!(value("caf\u{e9}".as_bytes()), "caf\u{e9}");
+        assert_eq!(value(b"bad\xff\xfe"), "\"bad\u{fffd}\u{fffd}\"");
+        assert_eq!(value("\u{85}".as_bytes()), "\"\\u0085\"");
+        assert_eq!(LogfmtKey("my key=\"x\"").to_string(), "my_key__x_");
+        assert_eq!(LogfmtKey("").to_string(), "_");
+    }
+
+    #[test]
+    fn test_entry() {
+        let req = http::Request::get("/foo?a=1")
+            .header(
+                "user-agent",
+                HeaderValue::from_bytes(b"agent \xff").unwrap(),
+            )
+            .body(())
+            .unwrap();
+        let mut entry = crate::entry::test_entry(&req);
+        entry.status = Some(200);
+        entry.lifecycle = crate::entry::Lifecycle::Responded;
+        entry.action = Some("get".to_owned());
+        entry.user = Some("alice@example.com".into());
+        entry.remote = Some("1.2.3.4:5678".parse().unwrap());
+        entry.duration = std::time::Duration::from_micros(1230);
+        let mut line = String::new();
+        write_logfmt(&entry, &mut line).unwrap();
+        let (_, rest) = line.split_once(' ').unwrap();
+        assert!(line.starts_with("timestamp="), "{line}");
+        assert_eq!(
+            rest,
+            "schema=1 status=200 outcome=ok action=get user=alice@example.com remote=1.2.3.4:5678 \
+             method=GET uri=\"/foo?a=1\" version=HTTP/1.1 user_agent=\"agent \u{fffd}\" \
+             duration_ms=1.23\n"
+        );
+    }
+
+    #[test]
+    fn test_extra_collisions() {
+        let mut entry = crate::entry::fixture_entry();
+        entry.config = std::sync::Arc::new(
+            crate::LogConfig::new().timestamp_style(crate::TimestampStyle::None),
+        );
+        let req = http::Request::new(());
+        let mut log = crate::LogRequest::<&str>::from_request(&req);
+        log.set_field("status", "cached")
+            .set_field("tenant", 42)
+            .set_field("extras_dropped", "x");
+        entry.extras = log.entry().extras;
+        log.discard();
+        let mut line = String::new();
+        write_logfmt(&entry, &mut line).unwrap();
+        assert!(line.starts_with("schema=1 status=201 "), "{line}");
+        assert!(
+            line.ends_with(" extra.status=cached tenant=42 extra.extras_dropped=x\n"),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        // Every key written for a fully populated entry is one extra fields are kept off.
+        struct Keys(Vec<String>);
+        impl Fields for Keys {
+            fn string(&mut self, key: &str, _: &[u8]) -> fmt::Result {
+                self.0.push(key.to_owned());
+                Ok(())
+            }
+            fn number(&mut self, key: &str, _: &dyn Display) -> fmt::Result {
+                self.0.push(key.to_owned());
+                Ok(())
+            }
+            fn extras(&mut self, _: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result {
+                Ok(())
+            }
+        }
+        let mut entry = crate::entry::fixture_entry();
+        entry.error_kind = Some(crate::ErrorKind::Timeout);
+        entry.error = Some("timed out".to_owned());
+        for names in [FieldNames::Native, FieldNames::Otel] {
+            entry.config = std::sync::Arc::new((*entry.config).clone().field_names(names));
+            let mut keys = Keys(vec![]);
+            write_fields(&entry, &mut keys).unwrap();
+            assert!(keys.0.len() > 20, "{:?}", keys.0);
+            for key in &keys.0 {
+                assert!(is_key(key), "{key}");
+                if names == FieldNames::Native {
+                    assert!(KEYS.contains(&key.as_str()), "{key}");
+                }
+            }
+        }
+    }
+}
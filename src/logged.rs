@@ -0,0 +1,185 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use http::{Request, Response};
+use http_body::Body;
+
+use crate::body::CountingBody;
+use crate::display::LogDisplay;
+use crate::error::ErrorKind;
+use crate::request::LogRequest;
+
+/// The future returned by a handler given to [logged_service], and by the function it returns.
+pub type LoggedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Wrap a handler so that each request it handles is logged, for use with hyper's
+/// `service_fn`.
+///
+/// For each request, a [LogRequest] is created from it, with the remote address set, and passed
+/// to the handler along with the request. The handler can fill it in as it goes, including
+/// after awaiting. When the handler returns a response, it is passed to
+/// [set_response](LogRequest::set_response), and its body is wrapped with
+/// [count_response_body](LogRequest::count_response_body), so the request is logged once the
+/// body has been sent. If the handler returns an error, the request is logged straight away, with
+/// `err_kind=internal`.
+///
+/// The handler returns a boxed future, so that it can borrow the [LogRequest]:
+///
+/// ```
+/// # use std::convert::Infallible;
+/// let remote = "192.0.2.1:4000".parse().unwrap();
+/// let service = hyper::service::service_fn(hyper_req_log::logged_service(
+///     remote,
+///     |req, log| {
+///         Box::pin(async move {
+///             log.set_action("hello");
+///             let body = format!("hello from {}", req.uri().path());
+///             Ok::<_, Infallible>(http::Response::new(body))
+///         })
+///     },
+/// ));
+/// # fn check<S: hyper::service::Service<http::Request<String>>>(_: S) {}
+/// # check(service);
+/// ```
+pub fn logged_service<A, B, R, E, F>(
+    remote: SocketAddr,
+    handler: F,
+) -> impl Fn(Request<B>) -> LoggedFuture<'static, Result<Response<CountingBody<R>>, E>> + Clone
+where
+    A: LogDisplay + Send + 'static,
+    B: Send + 'static,
+    R: Body + Send + 'static,
+    E: Send + 'static,
+    F: for<'a> Fn(Request<B>, &'a mut LogRequest<A>) -> LoggedFuture<'a, Result<Response<R>, E>>
+        + Send
+        + Sync
+        + 'static,
+{
+    logged_with(
+        move |req| {
+            let mut log = LogRequest::from_request(req);
+            log.set_remote(remote);
+            log
+        },
+        handler,
+    )
+}
+
+/// Like [logged_service], but each [LogRequest] is created by `new_log`.
+pub(crate) fn logged_with<A, B, R, E, F, N>(
+    new_log: N,
+    handler: F,
+) -> impl Fn(Request<B>) -> LoggedFuture<'static, Result<Response<CountingBody<R>>, E>> + Clone
+where
+    A: LogDisplay + Send + 'static,
+    B: Send + 'static,
+    R: Body + Send + 'static,
+    E: Send + 'static,
+    F: for<'a> Fn(Request<B>, &'a mut LogRequest<A>) -> LoggedFuture<'a, Result<Response<R>, E>>
+        + Send
+        + Sync
+        + 'static,
+    N: Fn(&Request<B>) -> LogRequest<A> + Send + Sync + 'static,
+{
+    let shared = Arc::new((new_log, handler));
+    move |req| {
+        let shared = shared.clone();
+        Box::pin(async move {
+            let (new_log, handler) = &*shared;
+            let mut log = new_log(&req);
+            match handler(req, &mut log).await {
+                Ok(resp) => {
+                    log.set_response(&resp);
+                    Ok(resp.map(|body| log.count_response_body(body)))
+                }
+                Err(e) => {
+                    log.set_error_kind(ErrorKind::Internal);
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::untimed;
+    use std::convert::Infallible;
+    use std::io;
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    use crate::config::LogConfig;
+
+    #[tokio::test]
+    async fn test_logged_service() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let config = Arc::new(LogConfig::new().sink(out.clone()));
+        let service = logged_with(
+            move |req| {
+                let mut log = LogRequest::from_request_with_config(req, config.clone());
+                log.set_remote("192.0.2.1:4000".parse().unwrap());
+                log
+            },
+            |req: Request<()>, log: &mut LogRequest<&str>| {
+                Box::pin(async move {
+                    log.set_action("start");
+                    tokio::task::yield_now().await;
+                    if req.uri().path() == "/fail" {
+                        return Err(io::Error::other("broken"));
+                    }
+                    log.set_action("done");
+                    Ok(Response::new(Full::new(Bytes::from_static(b"hello"))))
+                })
+            },
+        );
+        let text = || String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap();
+
+        let resp = service(Request::get("/ok").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(text(), "", "logged before the body was sent");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+        let line = text();
+        assert!(
+            untimed(&line).starts_with("request: [done:200] 192.0.2.1:4000 \"\" GET /ok "),
+            "{line}"
+        );
+        assert!(line.contains(" bytes=5"), "{line}");
+
+        assert!(service(Request::get("/fail").body(()).unwrap())
+            .await
+            .is_err());
+        let line = text();
+        assert!(
+            untimed(&line).starts_with("request: [start:???] 192.0.2.1:4000 "),
+            "{line}"
+        );
+        assert!(line.contains(" err_kind=internal"), "{line}");
+    }
+
+    #[test]
+    fn test_service_fn() {
+        use hyper::service::Service;
+
+        fn is_service<S: Service<Request<Full<Bytes>>>>(_: &S) {}
+        fn is_send<T: Send + 'static>(_: &T) {}
+        let service = hyper::service::service_fn(logged_service(
+            "192.0.2.1:4000".parse().unwrap(),
+            |_, log: &mut LogRequest<&str>| {
+                Box::pin(async move {
+                    log.set_action("hello");
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::new())))
+                })
+            },
+        ));
+        is_service(&service);
+        is_send(&service.call(Request::new(Full::new(Bytes::new()))));
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, RwLock};
+
+use crate::entry::LogEntry;
+
+/// Something which records metrics about requests, such as a counter or latency histogram in a
+/// metrics registry, so that requests don't have to be instrumented twice.
+///
+/// A request's metrics sink is called once, with its entry, when the [LogRequest] is dropped,
+/// whether or not the entry was written, was dropped by sampling, or was
+/// [discarded](crate::LogRequest::discard), so that every request is counted. Requests excluded
+/// by their [route](crate::Route::exclude) aren't observed. The sink is the one set with
+/// [LogRequest::set_metrics_sink], or else the one in its
+/// [LogConfig](crate::LogConfig::metrics_sink), or else the process default from
+/// [set_default_metrics_sink].
+///
+/// [LogRequest]: crate::LogRequest
+/// [LogRequest::set_metrics_sink]: crate::LogRequest::set_metrics_sink
+pub trait MetricsSink: Send + Sync {
+    /// Record a finished request.
+    fn observe(&self, entry: &LogEntry);
+}
+
+impl<T: MetricsSink + ?Sized> MetricsSink for Arc<T> {
+    fn observe(&self, entry: &LogEntry) {
+        (**self).observe(entry)
+    }
+}
+
+static DEFAULT_METRICS_SINK: RwLock<Option<Arc<dyn MetricsSink>>> = RwLock::new(None);
+
+/// Set the metrics sink for requests which don't have one of their own, or in their config. It
+/// applies to every request dropped afterwards, including ones already in progress.
+pub fn set_default_metrics_sink(sink: Arc<dyn MetricsSink>) {
+    *DEFAULT_METRICS_SINK
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = Some(sink);
+}
+
+/// Stop passing requests to the metrics sink set with [set_default_metrics_sink].
+pub fn clear_default_metrics_sink() {
+    *DEFAULT_METRICS_SINK
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// The metrics sink set with [set_default_metrics_sink], if any.
+pub(crate) fn default_metrics_sink() -> Option<Arc<dyn MetricsSink>> {
+    DEFAULT_METRICS_SINK
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// A metrics sink set with [LogConfig::metrics_sink](crate::LogConfig::metrics_sink), so the
+/// config can still be `Debug`.
+#[derive(Clone)]
+pub(crate) struct ConfigMetricsSink(pub(crate) Arc<dyn MetricsSink>);
+
+impl Debug for ConfigMetricsSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("MetricsSink")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::{LogConfig, LogRequest, Route, RouteRules};
+
+    /// Records the URI and whether the entry had a status.
+    #[derive(Default)]
+    struct Observed(Mutex<Vec<String>>);
+
+    impl MetricsSink for Observed {
+        fn observe(&self, entry: &LogEntry) {
+            let status = entry.status().map_or("none".to_owned(), |s| s.to_string());
+            let line = format!("{} {status}", entry.uri());
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    #[test]
+    fn test_observe() {
+        let config_sink = Arc::new(Observed::default());
+        let request_sink = Arc::new(Observed::default());
+        let config = Arc::new(
+            LogConfig::new()
+                .metrics_sink(config_sink.clone())
+                .routes(RouteRules::new().prefix("/health", Route::new().exclude())),
+        );
+        let log = |path: &str| {
+            let req = http::Request::get(path).body(()).unwrap();
+            LogRequest::<&str>::from_request_with_config(&req, config.clone())
+        };
+
+        let mut written = log("/written");
+        written.set_response(&http::Response::new(()));
+        written.write(std::io::sink()).unwrap();
+        log("/discarded").discard();
+        let mut own = log("/own");
+        own.set_metrics_sink(request_sink.clone());
+        own.discard();
+        log("/health").discard();
+        let mut reused = log("/first");
+        reused.write_in_place(std::io::sink()).unwrap();
+        reused.reset(&http::Request::get("/second").body(()).unwrap());
+        reused.discard();
+
+        assert_eq!(
+            *config_sink.0.lock().unwrap(),
+            [
+                "/written 200",
+                "/discarded none",
+                "/first none",
+                "/second none"
+            ]
+        );
+        assert_eq!(*request_sink.0.lock().unwrap(), ["/own none"]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt::{self, Display, Formatter};
+
+use http::HeaderValue;
+
+use crate::escaped::Escaped;
+
+/// The `Accept` header of a request and the `Content-Type` of its response, for
+/// [LogConfig::negotiation](crate::LogConfig::negotiation).
+#[derive(Debug, Clone)]
+pub(crate) struct Negotiation {
+    pub(crate) accept: HeaderValue,
+    pub(crate) served: Option<HeaderValue>,
+}
+
+impl Negotiation {
+    /// The media range the client prefers: the first one with the highest quality.
+    pub(crate) fn preferred(&self) -> Option<&str> {
+        let mut best: Option<(&str, f32)> = None;
+        for (range, q) in media_ranges(self.accept.to_str().ok()?) {
+            if q > 0. && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((range, q));
+            }
+        }
+        best.map(|(range, _)| range)
+    }
+
+    /// Whether the served type is acceptable, or `None` if there is no served type.
+    pub(crate) fn acceptable(&self) -> Option<bool> {
+        let served = self.served.as_ref()?.to_str().ok();
+        Some(served.is_some_and(|served| acceptable(self.accept.to_str().unwrap_or(""), served)))
+    }
+}
+
+/// Renders as `application/json -> text/html !mismatch`.
+impl Display for Negotiation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.preferred() {
+            Some(range) => write!(f, "{}", Escaped::from(range))?,
+            None => f.write_str("-")?,
+        }
+        f.write_str(" -> ")?;
+        match &self.served {
+            Some(served) => write!(f, "{}", Escaped::from(essence(served.as_bytes())))?,
+            None => f.write_str("-")?,
+        }
+        if self.acceptable() == Some(false) {
+            f.write_str(" !mismatch")?;
+        }
+        Ok(())
+    }
+}
+
+/// The media ranges in an `Accept` header, with their quality values. Ranges without a valid
+/// `q` parameter have a quality of 1.
+fn media_ranges(accept: &str) -> impl Iterator<Item = (&str, f32)> {
+    accept.split(',').filter_map(|item| {
+        let mut parts = item.split(';');
+        let range = parts.next()?.trim();
+        if range.is_empty() {
+            return None;
+        }
+        let q = parts
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.);
+        Some((range, q))
+    })
+}
+
+/// A media type without its parameters.
+fn essence(media_type: &[u8]) -> &[u8] {
+    let end = media_type
+        .iter()
+        .position(|&b| b == b';')
+        .unwrap_or(media_type.len());
+    media_type[..end].trim_ascii()
+}
+
+/// Whether the served media type is matched by a range in the `Accept` header with a non-zero
+/// quality. The most specific matching range decides, so `text/*;q=0` excludes `text/html` even
+/// alongside `*/*`, but `text/html` is still accepted by `text/html, text/*;q=0`.
+fn acceptable(accept: &str, served: &str) -> bool {
+    let served = std::str::from_utf8(essence(served.as_bytes())).unwrap_or("");
+    let Some((served_type, _)) = served.split_once('/') else {
+        return false;
+    };
+    let mut best: Option<(u8, f32)> = None;
+    for (range, q) in media_ranges(accept) {
+        let specificity = if range == "*/*" {
+            0
+        } else if range.eq_ignore_ascii_case(served) {
+            2
+        } else if range
+            .strip_suffix("/*")
+            .is_some_and(|t| t.eq_ignore_ascii_case(served_type))
+        {
+            1
+        } else {
+            continue;
+        };
+        if best.is_none_or(|(s, _)| specificity > s) {
+            best = Some((specificity, q));
+        }
+    }
+    best.is_some_and(|(_, q)| q > 0.)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn negotiation(accept: &'static str, served: Option<&'static str>) -> Negotiation {
+        Negotiation {
+            accept: HeaderValue::from_static(accept),
+            served: served.map(HeaderValue::from_static),
+        }
+    }
+
+    #[test]
+    fn test_preferred() {
+        let preferred = |accept| negotiation(accept, None).preferred().map(str::to_owned);
+        assert_eq!(
+            preferred("application/json").as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(
+            preferred("text/html;q=0.5, application/json;q=0.9, */*;q=0.1").as_deref(),
+            Some("application/json")
+        );
+        // Ties go to the first.
+        assert_eq!(
+            preferred("application/msgpack, application/json").as_deref(),
+            Some("application/msgpack")
+        );
+        assert_eq!(
+            preferred("text/html;level=1;q=0.2, text/plain; q=0.3").as_deref(),
+            Some("text/plain")
+        );
+        assert_eq!(preferred("text/html;q=0").as_deref(), None);
+    }
+
+    #[test]
+    fn test_acceptable() {
+        assert!(acceptable("*/*", "application/json"));
+        assert!(acceptable(
+            "application/*",
+            "application/json; charset=utf-8"
+        ));
+        assert!(!acceptable("application/*", "text/html"));
+        assert!(acceptable("Application/JSON", "application/json"));
+        assert!(!acceptable("application/json", "text/html"));
+        assert!(!acceptable("text/*;q=0, */*", "text/html"));
+        assert!(acceptable("text/html, text/*;q=0", "text/html"));
+        assert!(!acceptable("text/html;q=0, */*", "text/html"));
+        assert!(acceptable("text/html;q=0, */*", "text/plain"));
+        assert!(!acceptable("*/*", "not-a-media-type"));
+    }
+
+    #[test]
+    fn test_display() {
+        let n = negotiation("application/json, */*;q=0.1", Some("application/json"));
+        assert_eq!(n.to_string(), "application/json -> application/json");
+        let n = negotiation("application/json", Some("text/html; charset=utf-8"));
+        assert_eq!(n.to_string(), "application/json -> text/html !mismatch");
+        let n = negotiation("application/json", None);
+        assert_eq!(n.to_string(), "application/json -> -");
+    }
+
+    #[test]
+    fn test_logged() {
+        use std::sync::Arc;
+
+        let logged = |config: crate::LogConfig, accept: Option<&str>| {
+            let mut req = http::Request::get("/items/7");
+            if let Some(accept) = accept {
+                req = req.header("accept", accept);
+            }
+            let req = req.body(()).unwrap();
+            let mut log =
+                crate::LogRequest::<&str>::from_request_with_config(&req, Arc::new(config));
+            log.set_response(
+                &http::Response::builder()
+                    .header("content-type", "text/html; charset=utf-8")
+                    .body(())
+                    .unwrap(),
+            );
+            let line = log.to_string();
+            log.discard();
+            line
+        };
+        let on = || crate::LogConfig::new().negotiation(true);
+        let line = logged(on(), Some("application/json"));
+        assert!(
+            line.ends_with(" accept=application/json -> text/html !mismatch\n"),
+            "{line}"
+        );
+        let line = logged(on(), Some("text/*, application/json;q=0.5"));
+        assert!(line.ends_with(" accept=text/* -> text/html\n"), "{line}");
+        assert!(!logged(on(), None).contains("accept="));
+        assert!(!logged(crate::LogConfig::new(), Some("application/json")).contains("accept="));
+    }
+}
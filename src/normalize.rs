@@ -0,0 +1,103 @@
+use http::uri::Scheme;
+use http::HeaderValue;
+
+/// Normalize a `Host` header, for [LogConfig::normalize_host](crate::LogConfig::normalize_host):
+/// lower-case it, strip a trailing dot from the name, and strip the port if it is the default one
+/// for the scheme. Hosts with anything other than ASCII in them, such as raw UTF-8 names, are left
+/// as they are; punycode names are ASCII, and are only lower-cased.
+pub(crate) fn host(host: &HeaderValue, scheme: Option<&Scheme>) -> HeaderValue {
+    let Some(s) = host.to_str().ok().filter(|s| s.is_ascii()) else {
+        return host.clone();
+    };
+    let (name, port) = split_port(s);
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let default_port = match scheme {
+        Some(scheme) if *scheme == Scheme::HTTP => Some("80"),
+        Some(scheme) if *scheme == Scheme::HTTPS => Some("443"),
+        _ => None,
+    };
+    let mut normalized = name.to_ascii_lowercase();
+    if let Some(port) = port.filter(|port| Some(*port) != default_port) {
+        normalized.push(':');
+        normalized.push_str(port);
+    }
+    HeaderValue::try_from(normalized).unwrap_or_else(|_| host.clone())
+}
+
+/// Split a host into its name and port, if it has one. IPv6 addresses are bracketed in hosts, so
+/// their colons are inside the brackets.
+fn split_port(host: &str) -> (&str, Option<&str>) {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !port.is_empty()
+                && port.bytes().all(|b| b.is_ascii_digit())
+                && (!name.starts_with('[') || name.ends_with(']')) =>
+        {
+            (name, Some(port))
+        }
+        _ => (host, None),
+    }
+}
+
+/// Lower-case a header value, for
+/// [LogConfig::normalize_content_type](crate::LogConfig::normalize_content_type).
+pub(crate) fn lowercase(value: &HeaderValue) -> HeaderValue {
+    if !value.as_bytes().iter().any(u8::is_ascii_uppercase) {
+        return value.clone();
+    }
+    let mut lowered = HeaderValue::from_bytes(&value.as_bytes().to_ascii_lowercase())
+        .unwrap_or_else(|_| value.clone());
+    lowered.set_sensitive(value.is_sensitive());
+    lowered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        let norm = |h: &'static str, scheme: Option<&Scheme>| {
+            host(&HeaderValue::from_static(h), scheme)
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+        let https = Some(&Scheme::HTTPS);
+        for h in [
+            "Example.COM",
+            "example.com.",
+            "example.com:443",
+            "EXAMPLE.com.:443",
+        ] {
+            assert_eq!(norm(h, https), "example.com", "{h}");
+        }
+        assert_eq!(norm("example.com:80", https), "example.com:80");
+        assert_eq!(norm("example.com:80", Some(&Scheme::HTTP)), "example.com");
+        // Without a scheme, the default port isn't known.
+        assert_eq!(norm("example.com:443", None), "example.com:443");
+        assert_eq!(norm("[2001:DB8::1]:443", https), "[2001:db8::1]");
+        assert_eq!(norm("[2001:db8::1]", https), "[2001:db8::1]");
+        assert_eq!(norm("[2001:db8::1]:8443", https), "[2001:db8::1]:8443");
+
+        // IDNs: punycode is only lower-cased, and raw UTF-8 is left alone.
+        assert_eq!(
+            norm("xn--bcher-kva.example", https),
+            "xn--bcher-kva.example"
+        );
+        assert_eq!(
+            norm("XN--BCHER-KVA.example:443", https),
+            "xn--bcher-kva.example"
+        );
+        let raw = HeaderValue::from_bytes("BÜCHER.example:443".as_bytes()).unwrap();
+        assert_eq!(host(&raw, https), raw);
+    }
+
+    #[test]
+    fn test_lowercase() {
+        let value = HeaderValue::from_static("Text/HTML; Charset=UTF-8");
+        assert_eq!(lowercase(&value), "text/html; charset=utf-8");
+        let value = HeaderValue::from_static("gzip");
+        assert_eq!(lowercase(&value), "gzip");
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use arrow_array::builder::{
+    Float64Builder, MapBuilder, StringBuilder, TimestampMicrosecondBuilder, UInt16Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+
+use crate::entry::LogEntry;
+use crate::format::{Remote, SCHEMA_VERSION};
+use crate::query::LogUri;
+use crate::sink::LogSink;
+
+/// [ParquetSink] is a sink which writes entries to an Apache Parquet file, for loading into
+/// columnar storage. Requires the `parquet` feature.
+///
+/// Entries are buffered in memory and written as a row group once the row group size (10,000 by
+/// default) is reached, or when an entry is written after the flush interval (one minute by
+/// default) has passed since the first buffered entry. Lines which aren't request entries are
+/// ignored. The file is only readable once it has been finalized with [close](Self::close),
+/// which also happens when the sink is dropped.
+///
+/// # Schema
+///
+/// | column        | type                                 | nullable |
+/// |---------------|--------------------------------------|----------|
+/// | `timestamp`   | timestamp, microseconds, UTC         | no       |
+/// | `status`      | uint16                               | yes      |
+/// | `method`      | utf8                                 | no       |
+/// | `uri`         | utf8                                 | no       |
+/// | `duration_ms` | float64                              | no       |
+/// | `remote`      | utf8                                 | yes      |
+/// | `user`        | utf8                                 | yes      |
+/// | `action`      | utf8                                 | yes      |
+/// | `extras`      | map of utf8 to utf8                  | no       |
+///
+/// Values are the same as the ones in the text format, except that strings are not escaped
+/// (other than extras, whose values are rendered as in the text format), the timestamp is the
+/// request's start time, and a missing status is null rather than a placeholder.
+///
+/// The schema evolves only by appending new nullable columns: existing columns keep their name,
+/// type, and meaning, and are never removed. Readers should select columns by name. The
+/// [SCHEMA_VERSION](crate::SCHEMA_VERSION) is recorded in the file's metadata, under the key
+/// `hyper_req_log.schema`.
+pub struct ParquetSink {
+    state: Mutex<State>,
+    row_group_size: usize,
+    flush_interval: Duration,
+}
+
+struct State {
+    writer: Option<ArrowWriter<Box<dyn Write + Send>>>,
+    buffered: Vec<LogEntry>,
+    first_buffered: Option<Instant>,
+}
+
+impl ParquetSink {
+    /// Create a sink which writes to a new file at the given path, replacing any existing file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Create a sink which writes to the given writer.
+    pub fn new(writer: impl Write + Send + 'static) -> io::Result<Self> {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        let writer = ArrowWriter::try_new(writer, schema(), None).map_err(io::Error::other)?;
+        Ok(Self {
+            state: Mutex::new(State {
+                writer: Some(writer),
+                buffered: vec![],
+                first_buffered: None,
+            }),
+            row_group_size: 10_000,
+            flush_interval: Duration::from_secs(60),
+        })
+    }
+
+    /// The number of entries written in each row group.
+    pub fn row_group_size(mut self, rows: usize) -> Self {
+        self.row_group_size = rows.max(1);
+        self
+    }
+
+    /// The longest time entries are buffered before being written, checked whenever an entry is
+    /// written.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Write any buffered entries as a row group.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.flush()
+    }
+
+    /// Write any buffered entries and finalize the file. Entries written afterwards are
+    /// discarded.
+    pub fn close(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.flush()?;
+        if let Some(writer) = state.writer.take() {
+            writer.close().map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    fn flush(&mut self) -> io::Result<()> {
+        self.first_buffered = None;
+        let Some(writer) = &mut self.writer else {
+            self.buffered.clear();
+            return Ok(());
+        };
+        if self.buffered.is_empty() {
+            return Ok(());
+        }
+        let batch = record_batch(&self.buffered)?;
+        self.buffered.clear();
+        writer.write(&batch).map_err(io::Error::other)?;
+        writer.flush().map_err(io::Error::other)
+    }
+}
+
+fn schema() -> SchemaRef {
+    let metadata = HashMap::from([(
+        "hyper_req_log.schema".to_owned(),
+        SCHEMA_VERSION.to_string(),
+    )]);
+    Arc::new(Schema::new_with_metadata(
+        vec![
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new("status", DataType::UInt16, true),
+            Field::new("method", DataType::Utf8, false),
+            Field::new("uri", DataType::Utf8, false),
+            Field::new("duration_ms", DataType::Float64, false),
+            Field::new("remote", DataType::Utf8, true),
+            Field::new("user", DataType::Utf8, true),
+            Field::new("action", DataType::Utf8, true),
+            Field::new_map(
+                "extras",
+                "entries",
+                Field::new("key", DataType::Utf8, false),
+                Field::new("value", DataType::Utf8, true),
+                false,
+                false,
+            ),
+        ],
+        metadata,
+    ))
+}
+
+fn record_batch(entries: &[LogEntry]) -> io::Result<RecordBatch> {
+    let mut timestamp = TimestampMicrosecondBuilder::new().with_timezone("UTC");
+    let mut status = UInt16Builder::new();
+    let mut method = StringBuilder::new();
+    let mut uri = StringBuilder::new();
+    let mut duration_ms = Float64Builder::new();
+    let mut remote = StringBuilder::new();
+    let mut user = StringBuilder::new();
+    let mut action = StringBuilder::new();
+    let mut extras = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+
+    for entry in entries {
+        let micros = match entry.start_time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_micros() as i64,
+            Err(e) => -(e.duration().as_micros() as i64),
+        };
+        timestamp.append_value(micros);
+        status.append_option(entry.status);
+        method.append_value(entry.method.as_str());
+        uri.append_value(LogUri(entry).to_string());
+        duration_ms.append_value(entry.duration_ms());
+        remote.append_option(entry.remote.map(|r| Remote(r).to_string()));
+        user.append_option(entry.user.as_deref().map(String::from_utf8_lossy));
+        action.append_option(entry.action.as_deref());
+        for (key, value) in &entry.extras {
+            extras.keys().append_value(key);
+            extras.values().append_value(value.to_string());
+        }
+        extras.append(true).map_err(io::Error::other)?;
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(timestamp.finish()),
+        Arc::new(status.finish()),
+        Arc::new(method.finish()),
+        Arc::new(uri.finish()),
+        Arc::new(duration_ms.finish()),
+        Arc::new(remote.finish()),
+        Arc::new(user.finish()),
+        Arc::new(action.finish()),
+        Arc::new(extras.finish()),
+    ];
+    RecordBatch::try_new(schema(), columns).map_err(io::Error::other)
+}
+
+impl LogSink for ParquetSink {
+    fn write_line(&self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.writer.is_none() {
+            return Ok(());
+        }
+        state.buffered.push(entry.clone());
+        let first = *state.first_buffered.get_or_insert_with(Instant::now);
+        if state.buffered.len() >= self.row_group_size || first.elapsed() >= self.flush_interval {
+            state.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::test_entry;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, UInt16Type};
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn test_golden() {
+        let schema = schema();
+        let mut out = String::new();
+        for (key, value) in schema.metadata() {
+            out += &format!("metadata {key}={value}\n");
+        }
+        for field in schema.fields() {
+            out += &format!(
+                "{} {} nullable={}\n",
+                field.name(),
+                field.data_type(),
+                field.is_nullable()
+            );
+        }
+        crate::format::assert_golden("parquet.txt", &out);
+    }
+
+    #[test]
+    fn test_parquet() {
+        let path =
+            std::env::temp_dir().join(format!("hyper-req-log-{}.parquet", std::process::id()));
+        let sink = ParquetSink::create(&path).unwrap().row_group_size(2);
+        for i in 0..5 {
+            let req = http::Request::get(format!("/item/{i}")).body(()).unwrap();
+            let mut entry = test_entry(&req);
+            entry.status = (i != 3).then_some(200 + i);
+            entry.duration = Duration::from_micros(1500 * u64::from(i));
+            entry.action = Some("get".to_owned());
+            entry.extras.push((
+                "n".into(),
+                crate::entry::Extra::Bytes {
+                    value: i.to_string().into(),
+                    max_len: 10,
+                },
+            ));
+            sink.write_entry(&entry).unwrap();
+        }
+        drop(sink);
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 3);
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(
+            builder.schema().metadata()["hyper_req_log.schema"],
+            SCHEMA_VERSION.to_string()
+        );
+        let batches = builder
+            .with_batch_size(10)
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.schema().fields(), schema().fields());
+        assert_eq!(batch.num_rows(), 5);
+
+        let status = batch.column(1).as_primitive::<UInt16Type>();
+        assert_eq!(status.value(4), 204);
+        assert!(status.is_null(3));
+        let uri = batch.column(3).as_string::<i32>();
+        assert_eq!(uri.value(2), "/item/2");
+        let duration = batch.column(4).as_primitive::<Float64Type>();
+        assert_eq!(duration.value(3), 4.5);
+        let extras = batch.column(8).as_map();
+        assert_eq!(extras.value(1).column(1).as_string::<i32>().value(0), "1");
+    }
+}
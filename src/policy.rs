@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+/// Which requests are logged, decided when each is logged, from its final status and how long it
+/// took, for services where only errors and slow requests are worth a line. Requests which match
+/// none of its conditions are dropped, as if they had been discarded.
+///
+/// A policy without any conditions logs every request. With some, a request is logged if it
+/// matches any of them:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use hyper_req_log::{LogPolicy, SimpleLogRequest};
+/// # let req = http::Request::get("/").body(()).unwrap();
+/// let mut log = SimpleLogRequest::from_request(&req);
+/// log.set_policy(
+///     LogPolicy::default()
+///         .min_status(400)
+///         .or_slower_than(Duration::from_millis(500)),
+/// );
+/// log.set_status(200);
+/// // A fast 200: not logged when dropped.
+/// ```
+///
+/// A request without a status, because the handler never responded, is logged whatever the
+/// conditions, since that's rarely something to leave out; see
+/// [log_missing_status](Self::log_missing_status). Set a policy for a request with
+/// [LogRequest::set_policy](crate::LogRequest::set_policy), or for every request using a config
+/// with [LogConfig::policy](crate::LogConfig::policy). A request can still be logged regardless
+/// of it with [force_log](crate::LogRequest::force_log).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogPolicy {
+    min_status: Option<u16>,
+    slower_than: Option<Duration>,
+    missing_status: bool,
+}
+
+impl Default for LogPolicy {
+    fn default() -> Self {
+        Self {
+            min_status: None,
+            slower_than: None,
+            missing_status: true,
+        }
+    }
+}
+
+impl LogPolicy {
+    /// Log requests whose status is at least the given one, such as 400 for every client and
+    /// server error.
+    pub fn min_status(mut self, status: u16) -> Self {
+        self.min_status = Some(status);
+        self
+    }
+
+    /// Log requests which took longer than the given time, from when the request was created
+    /// until it is logged.
+    pub fn or_slower_than(mut self, duration: Duration) -> Self {
+        self.slower_than = Some(duration);
+        self
+    }
+
+    /// Whether to log requests without a status, whatever the other conditions. Enabled by
+    /// default.
+    pub fn log_missing_status(mut self, enable: bool) -> Self {
+        self.missing_status = enable;
+        self
+    }
+
+    /// Whether a request with the given status, which took the given time, is logged.
+    pub(crate) fn keeps(&self, status: Option<u16>, duration: Duration) -> bool {
+        if self.min_status.is_none() && self.slower_than.is_none() {
+            return true;
+        }
+        let Some(status) = status else {
+            return self.missing_status || self.slower_than.is_some_and(|slow| duration > slow);
+        };
+        self.min_status.is_some_and(|min| status >= min)
+            || self.slower_than.is_some_and(|slow| duration > slow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keeps() {
+        let ms = Duration::from_millis;
+        let policy = LogPolicy::default().min_status(400).or_slower_than(ms(500));
+        assert!(!policy.keeps(Some(200), ms(10)));
+        assert!(!policy.keeps(Some(399), ms(500)));
+        assert!(policy.keeps(Some(200), ms(501)));
+        assert!(policy.keeps(Some(400), ms(10)));
+        assert!(policy.keeps(Some(503), ms(10)));
+        assert!(policy.keeps(None, ms(10)));
+
+        let policy = policy.log_missing_status(false);
+        assert!(!policy.keeps(None, ms(10)));
+        assert!(policy.keeps(None, ms(501)));
+
+        assert!(LogPolicy::default().keeps(Some(200), ms(0)));
+        assert!(LogPolicy::default()
+            .log_missing_status(false)
+            .keeps(None, ms(0)));
+        let slow = LogPolicy::default().or_slower_than(ms(100));
+        assert!(!slow.keeps(Some(500), ms(100)));
+        assert!(slow.keeps(Some(200), ms(101)));
+    }
+}
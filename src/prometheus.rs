@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use http::Method;
+
+use crate::entry::{LogEntry, StatusClass};
+use crate::metrics::MetricsSink;
+
+/// The upper bounds of the latency buckets, in seconds, unless others are given with
+/// [PrometheusMetrics::with_buckets]. These are the Prometheus client libraries' defaults.
+const DEFAULT_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10.];
+
+/// The method, status class, and action of a group of requests.
+type Key = (&'static str, Option<StatusClass>, Option<String>);
+
+struct Group {
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+    /// How many requests fell in each bucket, and not an earlier one.
+    buckets: Vec<AtomicU64>,
+}
+
+/// [PrometheusMetrics] is a [MetricsSink] which keeps a request counter and a latency histogram
+/// for each combination of method, status class, and action, and renders them in the Prometheus
+/// text exposition format, for serving from a `/metrics` endpoint:
+///
+/// ```text
+/// # HELP http_requests_total Requests handled, by method, status class, and action.
+/// # TYPE http_requests_total counter
+/// http_requests_total{method="GET",status="2xx",action="items"} 2
+/// # HELP http_request_duration_seconds How long requests took, by method, status class, and action.
+/// # TYPE http_request_duration_seconds histogram
+/// http_request_duration_seconds_bucket{method="GET",status="2xx",action="items",le="0.005"} 1
+/// ...
+/// http_request_duration_seconds_bucket{method="GET",status="2xx",action="items",le="+Inf"} 2
+/// http_request_duration_seconds_sum{method="GET",status="2xx",action="items"} 0.0123
+/// http_request_duration_seconds_count{method="GET",status="2xx",action="items"} 2
+/// ```
+///
+/// Requests with no status are counted as `status="none"`, and those with no action as
+/// `action=""`. Methods other than the standard ones are counted together as `method="OTHER"`,
+/// so that clients can't create new series at will. The counters are never reset, as Prometheus
+/// expects.
+///
+/// This is only available with the `prometheus` feature.
+pub struct PrometheusMetrics {
+    bounds: Vec<f64>,
+    groups: RwLock<BTreeMap<Key, Arc<Group>>>,
+}
+
+impl PrometheusMetrics {
+    /// Create metrics with the default latency buckets, from 5ms to 10s.
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+
+    /// Create metrics with latency buckets with the given upper bounds, in seconds. They are
+    /// sorted, and a `+Inf` bucket is always added.
+    pub fn with_buckets(mut bounds: Vec<f64>) -> Self {
+        bounds.retain(|b| b.is_finite());
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        Self {
+            bounds,
+            groups: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    fn group(&self, key: Key) -> Arc<Group> {
+        let group = self
+            .groups
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .cloned();
+        match group {
+            Some(group) => group,
+            None => self
+                .groups
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(key)
+                .or_insert_with(|| {
+                    Arc::new(Group {
+                        count: AtomicU64::new(0),
+                        sum_micros: AtomicU64::new(0),
+                        buckets: self.bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+                    })
+                })
+                .clone(),
+        }
+    }
+
+    /// Render every metric in the Prometheus text exposition format. Groups are written in
+    /// sorted order.
+    pub fn render(&self) -> String {
+        let groups = self.groups.read().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        out.push_str(
+            "# HELP http_requests_total Requests handled, by method, status class, and action.\n\
+             # TYPE http_requests_total counter\n",
+        );
+        for (key, group) in groups.iter() {
+            let count = group.count.load(Ordering::Relaxed);
+            writeln!(out, "http_requests_total{{{}}} {count}", Labels(key)).unwrap();
+        }
+        out.push_str(
+            "# HELP http_request_duration_seconds How long requests took, by method, status \
+             class, and action.\n\
+             # TYPE http_request_duration_seconds histogram\n",
+        );
+        for (key, group) in groups.iter() {
+            let labels = Labels(key);
+            let mut cumulative = 0;
+            for (bound, bucket) in self.bounds.iter().zip(&group.buckets) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+                )
+                .unwrap();
+            }
+            let count = group.count.load(Ordering::Relaxed);
+            let sum = group.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {count}\n\
+                 http_request_duration_seconds_sum{{{labels}}} {sum}\n\
+                 http_request_duration_seconds_count{{{labels}}} {count}"
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsSink for PrometheusMetrics {
+    fn observe(&self, entry: &LogEntry) {
+        let key = (
+            method(entry.method()),
+            entry.status_class(),
+            entry.action.clone(),
+        );
+        let group = self.group(key);
+        let seconds = entry.duration.as_secs_f64();
+        let micros = u64::try_from(entry.duration.as_micros()).unwrap_or(u64::MAX);
+        group.count.fetch_add(1, Ordering::Relaxed);
+        group.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        if let Some(i) = self.bounds.iter().position(|&bound| seconds <= bound) {
+            group.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The method's label: its name if it's a standard one, or `OTHER`.
+fn method(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "OTHER",
+    }
+}
+
+/// Renders the labels of a group, without the braces.
+struct Labels<'a>(&'a Key);
+
+impl Display for Labels<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (method, class, action) = self.0;
+        write!(f, "method=\"{method}\",status=\"")?;
+        match class {
+            Some(class) => write!(f, "{class}")?,
+            None => f.write_str("none")?,
+        }
+        f.write_str("\",action=\"")?;
+        // The exposition format's escapes.
+        for c in action.as_deref().unwrap_or_default().chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::clock::MockClock;
+    use crate::{LogConfig, LogRequest};
+
+    #[test]
+    fn test_render() {
+        let metrics = Arc::new(PrometheusMetrics::with_buckets(vec![0.1, 0.01, 1.]));
+        let config = Arc::new(LogConfig::new().metrics_sink(metrics.clone()));
+        let request = |method: &str, status: u16, action: Option<&'static str>, millis| {
+            let req = http::Request::builder()
+                .method(method)
+                .uri("/")
+                .body(())
+                .unwrap();
+            let mut log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+            let clock = MockClock::default();
+            log.set_clock(clock.clone());
+            if let Some(action) = action {
+                log.set_action(action);
+            }
+            clock.advance(Duration::from_millis(millis));
+            log.set_status(status);
+            log.discard();
+        };
+        request("GET", 200, Some("items"), 5);
+        request("GET", 204, Some("items"), 50);
+        request("GET", 200, Some("items"), 2000);
+        request("POST", 500, Some("say \"hi\""), 10);
+        request("BREW", 418, None, 1);
+
+        assert_eq!(
+            metrics.render(),
+            r#"# HELP http_requests_total Requests handled, by method, status class, and action.
+# TYPE http_requests_total counter
+http_requests_total{method="GET",status="2xx",action="items"} 3
+http_requests_total{method="OTHER",status="4xx",action=""} 1
+http_requests_total{method="POST",status="5xx",action="say \"hi\""} 1
+# HELP http_request_duration_seconds How long requests took, by method, status class, and action.
+# TYPE http_request_duration_seconds histogram
+http_request_duration_seconds_bucket{method="GET",status="2xx",action="items",le="0.01"} 1
+http_request_duration_seconds_bucket{method="GET",status="2xx",action="items",le="0.1"} 2
+http_request_duration_seconds_bucket{method="GET",status="2xx",action="items",le="1"} 2
+http_request_duration_seconds_bucket{method="GET",status="2xx",action="items",le="+Inf"} 3
+http_request_duration_seconds_sum{method="GET",status="2xx",action="items"} 2.055
+http_request_duration_seconds_count{method="GET",status="2xx",action="items"} 3
+http_request_duration_seconds_bucket{method="OTHER",status="4xx",action="",le="0.01"} 1
+http_request_duration_seconds_bucket{method="OTHER",status="4xx",action="",le="0.1"} 1
+http_request_duration_seconds_bucket{method="OTHER",status="4xx",action="",le="1"} 1
+http_request_duration_seconds_bucket{method="OTHER",status="4xx",action="",le="+Inf"} 1
+http_request_duration_seconds_sum{method="OTHER",status="4xx",action=""} 0.001
+http_request_duration_seconds_count{method="OTHER",status="4xx",action=""} 1
+http_request_duration_seconds_bucket{method="POST",status="5xx",action="say \"hi\"",le="0.01"} 1
+http_request_duration_seconds_bucket{method="POST",status="5xx",action="say \"hi\"",le="0.1"} 1
+http_request_duration_seconds_bucket{method="POST",status="5xx",action="say \"hi\"",le="1"} 1
+http_request_duration_seconds_bucket{method="POST",status="5xx",action="say \"hi\"",le="+Inf"} 1
+http_request_duration_seconds_sum{method="POST",status="5xx",action="say \"hi\""} 0.01
+http_request_duration_seconds_count{method="POST",status="5xx",action="say \"hi\""} 1
+"#
+        );
+    }
+
+    #[test]
+    fn test_missing_status() {
+        let metrics = PrometheusMetrics::new();
+        metrics.observe(&crate::entry::test_entry(&http::Request::new(())));
+        let text = metrics.render();
+        assert!(
+            text.contains("http_requests_total{method=\"GET\",status=\"none\",action=\"\"} 1\n"),
+            "{text}"
+        );
+        assert!(text.contains(",le=\"0.005\"} "), "{text}");
+        assert!(text.contains(",le=\"10\"} "), "{text}");
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use http::HeaderValue;
+
+use crate::forwarded::ForwardedEntry;
+
+/// [TrustedProxies] is the set of addresses of the reverse proxies in front of a server, for
+/// [LogConfig::trusted_proxies](crate::LogConfig::trusted_proxies), which decides whose
+/// `X-Forwarded-For` (or `Forwarded`) header is believed.
+///
+/// When a request comes from one of these proxies, the header's chain of addresses is walked
+/// from the right, skipping the proxies, and the first address which isn't one is taken to be
+/// the real client. That is logged in place of the remote address, with the proxy the request
+/// came from logged as `proxy=...`. When a request doesn't come from a trusted proxy, anyone
+/// could have written the header, so it is logged after `!untrusted:` and the remote address is
+/// logged as usual.
+///
+/// They can be parsed from a list of addresses and CIDR blocks, separated by commas or
+/// whitespace:
+///
+/// ```
+/// # use hyper_req_log::TrustedProxies;
+/// let proxies: TrustedProxies = "10.0.0.0/8, 192.0.2.1 2001:db8::/32".parse().unwrap();
+/// assert!(proxies.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!proxies.contains("192.0.2.2".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    nets: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// An empty set, which trusts no one.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust a single address.
+    pub fn ip(self, ip: IpAddr) -> Self {
+        let len = match ip {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        self.cidr(ip, len)
+    }
+
+    /// Trust every address in a CIDR block, such as `10.0.0.0` with a prefix length of 8. A
+    /// prefix length longer than the address is treated as the whole address.
+    pub fn cidr(mut self, addr: IpAddr, prefix_len: u8) -> Self {
+        let addr = addr.to_canonical();
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        self.nets.push((addr, prefix_len.min(max)));
+        self
+    }
+
+    /// Whether the address is one of the trusted proxies. IPv4-mapped IPv6 addresses match the
+    /// IPv4 address they map.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.nets.iter().any(|&(net, len)| match (net, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(u32::from(net).into(), u32::from(ip).into(), 32, len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_eq(net.into(), ip.into(), 128, len),
+            _ => false,
+        })
+    }
+
+    /// The real client in a chain of forwarded addresses, as listed by `X-Forwarded-For`: the
+    /// rightmost address which isn't a trusted proxy, or the leftmost one if they all are.
+    /// `None` if the walk reaches an entry which isn't an address, such as `unknown`, since
+    /// whoever is to the left of it can't be vouched for.
+    pub(crate) fn client_in_chain(&self, chain: &[u8]) -> Option<IpAddr> {
+        let mut client = None;
+        for hop in chain.rsplit(|&b| b == b',') {
+            let ip = parse_hop(hop.trim_ascii())?;
+            client = Some(ip);
+            if !self.contains(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Whether the first `len` of `bits` bits of two addresses are the same.
+fn prefix_eq(a: u128, b: u128, bits: u32, len: u8) -> bool {
+    let shift = bits - u32::from(len);
+    a.checked_shr(shift).unwrap_or(0) == b.checked_shr(shift).unwrap_or(0)
+}
+
+/// The first address in a forwarded chain, if it is one.
+pub(crate) fn first_hop(chain: &HeaderValue) -> Option<IpAddr> {
+    let chain = chain.as_bytes();
+    parse_hop(chain.split(|&b| b == b',').next()?.trim_ascii())
+}
+
+/// An address in a forwarded chain: an IP address, optionally with a port, and with IPv6
+/// addresses optionally in brackets.
+pub(crate) fn parse_hop(hop: &[u8]) -> Option<IpAddr> {
+    ForwardedEntry::parse(hop).ip()
+}
+
+impl FromStr for TrustedProxies {
+    type Err = TrustedProxiesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut proxies = Self::new();
+        for entry in s.split(|c: char| c == ',' || c.is_whitespace()) {
+            if entry.is_empty() {
+                continue;
+            }
+            let invalid = || TrustedProxiesError(entry.to_owned());
+            proxies = match entry.split_once('/') {
+                Some((addr, len)) => {
+                    let addr = addr.parse().map_err(|_| invalid())?;
+                    let len = len.parse::<u8>().map_err(|_| invalid())?;
+                    let max = match addr {
+                        IpAddr::V4(_) => 32,
+                        IpAddr::V6(_) => 128,
+                    };
+                    if len > max {
+                        return Err(invalid());
+                    }
+                    proxies.cidr(addr, len)
+                }
+                None => proxies.ip(entry.parse().map_err(|_| invalid())?),
+            };
+        }
+        Ok(proxies)
+    }
+}
+
+/// An entry in a list of [TrustedProxies] which isn't an address or a CIDR block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedProxiesError(String);
+
+impl Display for TrustedProxiesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid trusted proxy {:?}", self.0)
+    }
+}
+
+impl std::error::Error for TrustedProxiesError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn proxies() -> TrustedProxies {
+        "10.0.0.0/8, 192.0.2.1, 2001:db8::/32".parse().unwrap()
+    }
+
+    #[test]
+    fn test_contains() {
+        let proxies = proxies();
+        for ip in [
+            "10.0.0.1",
+            "10.255.255.255",
+            "192.0.2.1",
+            "2001:db8::1",
+            "::ffff:10.1.1.1",
+        ] {
+            assert!(proxies.contains(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["11.0.0.1", "192.0.2.2", "2001:db9::1", "::1"] {
+            assert!(!proxies.contains(ip.parse().unwrap()), "{ip}");
+        }
+        let everyone = TrustedProxies::new().cidr("0.0.0.0".parse().unwrap(), 0);
+        assert!(everyone.contains("203.0.113.7".parse().unwrap()));
+        assert!(!everyone.contains("2001:db8::1".parse().unwrap()));
+        assert!(!TrustedProxies::new().contains("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("".parse(), Ok(TrustedProxies::new()));
+        for bad in [
+            "10.0.0.0/33",
+            "2001:db8::/129",
+            "10.0.0.0/x",
+            "proxy.local",
+            "10.0.0.1:80",
+        ] {
+            let err = TrustedProxies::from_str(bad).unwrap_err();
+            assert_eq!(err, TrustedProxiesError(bad.to_owned()));
+        }
+        assert_eq!(
+            TrustedProxies::from_str("10.0.0.1, nope")
+                .unwrap_err()
+                .to_string(),
+            "invalid trusted proxy \"nope\""
+        );
+    }
+
+    #[test]
+    fn test_client_in_chain() {
+        let proxies = proxies();
+        let client = |chain: &str| proxies.client_in_chain(chain.as_bytes());
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+        assert_eq!(client("203.0.113.7"), ip("203.0.113.7"));
+        assert_eq!(client("203.0.113.7, 10.0.0.2, 10.0.0.3"), ip("203.0.113.7"));
+        // Only the rightmost untrusted address counts; anything to its left could be made up.
+        assert_eq!(client("1.1.1.1, 203.0.113.7, 10.0.0.2"), ip("203.0.113.7"));
+        assert_eq!(client("10.0.0.4, 192.0.2.1"), ip("10.0.0.4"));
+        assert_eq!(
+            client("[2001:db8:cafe::17]:4711, 2001:db8::2"),
+            ip("2001:db8:cafe::17")
+        );
+        assert_eq!(client("2001:db9::1,[2001:db8::2]"), ip("2001:db9::1"));
+        assert_eq!(
+            client("203.0.113.7:5000,::ffff:10.0.0.2"),
+            ip("203.0.113.7")
+        );
+        assert_eq!(client("garbage, 203.0.113.7"), ip("203.0.113.7"));
+        assert_eq!(client("203.0.113.7, unknown, 10.0.0.2"), None);
+        assert_eq!(client(""), None);
+    }
+}
@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use http::Uri;
+
+use crate::entry::LogEntry;
+
+/// What the values of redacted query parameters are replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+static DEFAULT_LOG_QUERY: AtomicBool = AtomicBool::new(true);
+
+/// Set whether query strings are logged for entries whose request and config don't say, in
+/// place of logging them. It applies to every entry rendered afterwards.
+pub fn set_default_log_query(enable: bool) {
+    DEFAULT_LOG_QUERY.store(enable, Ordering::Relaxed);
+}
+
+/// The setting made with [set_default_log_query].
+pub(crate) fn default_log_query() -> bool {
+    DEFAULT_LOG_QUERY.load(Ordering::Relaxed)
+}
+
+/// The form of a request target, as defined in RFC 9112 section 3.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TargetForm {
+    /// A path and query, such as `/search?q=rust`: the usual form.
+    Origin,
+    /// A whole URI, such as `http://example.com/search?q=rust`, as sent to proxies.
+    Absolute,
+    /// A host and port, such as `example.com:443`, as sent in CONNECT requests.
+    Authority,
+    /// `*`, as sent in `OPTIONS *` requests.
+    Asterisk,
+}
+
+impl TargetForm {
+    pub(crate) fn of(uri: &Uri) -> Self {
+        match (uri.scheme(), uri.authority()) {
+            (Some(_), _) => Self::Absolute,
+            (None, Some(_)) => Self::Authority,
+            (None, None) if uri.path() == "*" => Self::Asterisk,
+            (None, None) => Self::Origin,
+        }
+    }
+}
+
+/// Renders an entry's request URI the way it is logged: without its scheme and authority if it's
+/// absolute-form and [LogConfig::origin_form](crate::LogConfig::origin_form) is enabled, without
+/// its query if the entry's [log_query](LogEntry::log_query) is off, and otherwise with the
+/// values of the parameters named in
+/// [LogConfig::redact_query_params](crate::LogConfig::redact_query_params) replaced with
+/// `[REDACTED]`.
+pub(crate) struct LogUri<'a>(pub(crate) &'a LogEntry);
+
+impl Display for LogUri<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (uri, names) = (&self.0.uri, &self.0.config.redact_query_params);
+        let origin_form = self.0.config.origin_form && TargetForm::of(uri) == TargetForm::Absolute;
+        let log_query = self.0.log_query();
+        if uri.query().is_none() || (log_query && names.is_empty()) {
+            return match origin_form {
+                true => f.write_str(self.0.path_and_query()),
+                false => write!(f, "{uri}"),
+            };
+        }
+        // Neither the authority nor the path can contain a `?`, so the first one starts the query.
+        let uri = match origin_form {
+            true => Cow::Borrowed(self.0.path_and_query()),
+            false => Cow::Owned(uri.to_string()),
+        };
+        let (head, query) = uri.split_once('?').unwrap_or((&uri, ""));
+        match log_query {
+            true => write!(f, "{head}?{}", redact(query, names)),
+            false => f.write_str(head),
+        }
+    }
+}
+
+/// The query of an entry's request URI the way it is logged, without the `?`, if it has one
+/// and it is logged.
+pub(crate) fn log_query(entry: &LogEntry) -> Option<Cow<'_, str>> {
+    let query = entry.uri.query().filter(|_| entry.log_query())?;
+    Some(redact(query, &entry.config.redact_query_params))
+}
+
+/// A query string with the values of the named parameters replaced. Names are matched after
+/// [decoding](decode_key) and without regard to ASCII case, every occurrence of a repeated
+/// parameter is redacted, and parameters without a value are left as they are, since they have
+/// nothing to hide.
+fn redact<'a>(query: &'a str, names: &[String]) -> Cow<'a, str> {
+    let secret = |param: &str| {
+        param.split_once('=').is_some_and(|(key, _)| {
+            let key = decode_key(key);
+            names
+                .iter()
+                .any(|name| key.eq_ignore_ascii_case(name.as_bytes()))
+        })
+    };
+    if !query.split('&').any(secret) {
+        return Cow::Borrowed(query);
+    }
+    let mut out = String::with_capacity(query.len());
+    for (i, param) in query.split('&').enumerate() {
+        if i != 0 {
+            out.push('&');
+        }
+        match param.split_once('=') {
+            Some((key, _)) if secret(param) => {
+                out.push_str(key);
+                out.push('=');
+                out.push_str(REDACTED);
+            }
+            _ => out.push_str(param),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// A query parameter's key as the application sees it, with `+` as a space and percent-escapes
+/// decoded, so `api%5Fkey` can't get past a redaction of `api_key`. A `%` which doesn't start an
+/// escape is left as it is.
+fn decode_key(key: &str) -> Cow<'_, [u8]> {
+    if !key.contains(['%', '+']) {
+        return Cow::Borrowed(key.as_bytes());
+    }
+    let hex = |b: Option<&u8>| (*b? as char).to_digit(16);
+    let (key, mut out, mut i) = (key.as_bytes(), Vec::with_capacity(key.len()), 0);
+    while i < key.len() {
+        match (key[i], hex(key.get(i + 1)), hex(key.get(i + 2))) {
+            (b'+', _, _) => out.push(b' '),
+            (b'%', Some(high), Some(low)) => {
+                out.push((high * 16 + low) as u8);
+                i += 2;
+            }
+            (b, _, _) => out.push(b),
+        }
+        i += 1;
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::LogConfig;
+    use std::sync::Arc;
+
+    fn names() -> Vec<String> {
+        ["token", "api_key", "password"].map(str::to_owned).to_vec()
+    }
+
+    #[test]
+    fn test_redact() {
+        let names = names();
+        assert_eq!(redact("q=rust&page=2", &names), "q=rust&page=2");
+        assert!(matches!(redact("q=rust", &names), Cow::Borrowed(_)));
+        assert_eq!(
+            redact("q=rust&token=abc123&page=2", &names),
+            "q=rust&token=[REDACTED]&page=2"
+        );
+        assert_eq!(
+            redact("Token=a&API_KEY=b&password=", &names),
+            "Token=[REDACTED]&API_KEY=[REDACTED]&password=[REDACTED]"
+        );
+        assert_eq!(
+            redact("token=a&token=b&x=1", &names),
+            "token=[REDACTED]&token=[REDACTED]&x=1"
+        );
+        // A parameter without a value has nothing to redact.
+        assert_eq!(redact("token&q=1", &names), "token&q=1");
+        assert_eq!(redact("tokens=a&my_token=b", &names), "tokens=a&my_token=b");
+        assert_eq!(redact("", &names), "");
+        assert_eq!(redact("token=a", &[]), "token=a");
+
+        // Keys are compared as the application decodes them.
+        assert_eq!(
+            redact("api%5Fkey=a&API%5fKEY=b&api+key=c&%74oken=d", &names),
+            "api%5Fkey=[REDACTED]&API%5fKEY=[REDACTED]&api+key=c&%74oken=[REDACTED]"
+        );
+        let names = vec!["api key".to_owned()];
+        assert_eq!(
+            redact("api+key=a&api%20key=b", &names),
+            "api+key=[REDACTED]&api%20key=[REDACTED]"
+        );
+        assert_eq!(redact("api%2key=a&%=b", &names), "api%2key=a&%=b");
+    }
+
+    fn entry(uri: &str, config: LogConfig) -> LogEntry {
+        let mut entry = crate::entry::fixture_entry();
+        entry.uri = uri.parse().unwrap();
+        entry.config = Arc::new(config);
+        entry
+    }
+
+    #[test]
+    fn test_log_uri() {
+        let config = LogConfig::new().redact_query_params(["token"]);
+        let uri = |s: &str| LogUri(&entry(s, config.clone())).to_string();
+        assert_eq!(uri("/a?token=secret&b=1"), "/a?token=[REDACTED]&b=1");
+        assert_eq!(uri("/a?b=1"), "/a?b=1");
+        assert_eq!(uri("/a"), "/a");
+        assert_eq!(
+            uri("http://example.com:8080/a?token=secret"),
+            "http://example.com:8080/a?token=[REDACTED]"
+        );
+        assert_eq!(uri("*"), "*");
+    }
+
+    #[test]
+    fn test_target_forms() {
+        let cases = [
+            ("/a/b?c=1", TargetForm::Origin, "/a/b?c=1"),
+            (
+                "http://example.com:8080/a?c=1",
+                TargetForm::Absolute,
+                "/a?c=1",
+            ),
+            ("http://example.com", TargetForm::Absolute, "/"),
+            ("example.com:443", TargetForm::Authority, ""),
+            ("*", TargetForm::Asterisk, "*"),
+        ];
+        for (uri, form, path_and_query) in cases {
+            let entry = entry(uri, LogConfig::new());
+            assert_eq!(entry.target_form(), form, "{uri}");
+            assert_eq!(entry.path_and_query(), path_and_query, "{uri}");
+        }
+
+        let config = LogConfig::new()
+            .origin_form(true)
+            .redact_query_params(["token"]);
+        let uri = |s: &str| LogUri(&entry(s, config.clone())).to_string();
+        assert_eq!(uri("/a/b?c=1"), "/a/b?c=1");
+        assert_eq!(uri("http://example.com:8080/a?c=1"), "/a?c=1");
+        assert_eq!(
+            uri("http://example.com/a?token=secret"),
+            "/a?token=[REDACTED]"
+        );
+        assert_eq!(uri("http://example.com"), "/");
+        assert_eq!(uri("example.com:443"), "example.com:443");
+        assert_eq!(uri("*"), "*");
+        let off = config.log_query(false);
+        assert_eq!(
+            LogUri(&entry("http://example.com/a?c=1", off)).to_string(),
+            "/a"
+        );
+    }
+
+    #[test]
+    fn test_omit_query() {
+        let omitted = |uri: &str, config: LogConfig| {
+            let entry = entry(uri, config);
+            (
+                LogUri(&entry).to_string(),
+                log_query(&entry).map(Cow::into_owned),
+            )
+        };
+        let off = || LogConfig::new().log_query(false);
+        // The fragment never makes it into the `Uri`, so only the path survives.
+        assert_eq!(
+            omitted("/search?q=secret#frag", off()),
+            ("/search".to_owned(), None)
+        );
+        assert_eq!(omitted("/search?", off()), ("/search".to_owned(), None));
+        assert_eq!(omitted("/search", off()), ("/search".to_owned(), None));
+        // Proxied requests keep their scheme and authority.
+        assert_eq!(
+            omitted("http://example.com:8080/a/b?key=value", off()),
+            ("http://example.com:8080/a/b".to_owned(), None)
+        );
+        assert_eq!(
+            omitted("example.com:443", off()),
+            ("example.com:443".to_owned(), None)
+        );
+        assert_eq!(
+            omitted("/search?q=secret#frag", LogConfig::new()),
+            ("/search?q=secret".to_owned(), Some("q=secret".to_owned()))
+        );
+
+        // The request's setting wins over the config's.
+        assert_eq!(omitted("/a?b", LogConfig::new().log_query(false)).0, "/a");
+        let mut entry = entry("/a?b", LogConfig::new().log_query(true));
+        entry.log_query = Some(false);
+        assert_eq!(LogUri(&entry).to_string(), "/a");
+        entry.log_query = None;
+        assert_eq!(LogUri(&entry).to_string(), "/a?b");
+    }
+}
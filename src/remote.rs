@@ -0,0 +1,21 @@
+use std::net::SocketAddr;
+
+/// The address of a request's peer, for server glue to insert into the request's extensions
+/// where it knows the connection the request came in on.
+/// [from_request](crate::LogRequest::from_request) and the other server-side constructors read
+/// it, as if [set_remote](crate::LogRequest::set_remote) had been called with it; calling that
+/// afterwards still overrides it.
+///
+/// ```
+/// # use hyper_req_log::{LogRequest, RemoteAddr};
+/// let mut req = http::Request::get("/").body(()).unwrap();
+/// req.extensions_mut()
+///     .insert(RemoteAddr("192.0.2.1:4000".parse().unwrap()));
+///
+/// // Later, wherever the request is logged:
+/// let log = LogRequest::<&str>::from_request(&req);
+/// assert_eq!(log.remote(), Some("192.0.2.1:4000".parse().unwrap()));
+/// # log.discard();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RemoteAddr(pub SocketAddr);
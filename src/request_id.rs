@@ -0,0 +1,106 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use http::{HeaderValue, Method};
+
+/// The header request IDs are read from and returned in.
+pub(crate) const HEADER: &str = "x-request-id";
+
+/// The longest request ID taken from a request's header. Longer ones are ignored, so a client
+/// can't make every line it causes arbitrarily long.
+const MAX_LEN: usize = 128;
+
+/// A new request ID: 128 random bits, as 32 lowercase hex digits.
+///
+/// Like [random_unit](crate::sample::random_unit), this hashes a counter with std's randomly
+/// keyed hasher rather than pulling in a random number crate. The counter makes IDs unique within
+/// the process, and the random keys make them unpredictable and unique across processes.
+pub(crate) fn generate() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let state = RandomState::new();
+    let half = |n: u64| {
+        let mut hasher = state.build_hasher();
+        hasher.write_u64(count);
+        hasher.write_u64(n);
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(0), half(1))
+}
+
+/// A key identifying a request by when it started, to the millisecond, and its method and path,
+/// for matching it up with other records of it, such as an application's traces: 16 lowercase hex
+/// digits of the 64-bit FNV-1a hash of them.
+///
+/// Unlike [generate], this is the same for the same request in any process, and for any version
+/// of Rust, so it is hashed with FNV-1a rather than std's hasher, whose algorithm may change.
+pub(crate) fn correlation_key(start_unix_millis: u64, method: &Method, path: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let bytes = start_unix_millis.to_be_bytes();
+    // Methods can't contain spaces, so this keeps the method and path apart.
+    let parts: [&[u8]; 4] = [&bytes, method.as_str().as_bytes(), b" ", path.as_bytes()];
+    for &b in parts.iter().copied().flatten() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// The request ID in a header value, if it is one which can be logged and returned as it is:
+/// non-empty, no longer than [MAX_LEN], and only visible ASCII.
+pub(crate) fn from_header(value: &HeaderValue) -> Option<String> {
+    let bytes = value.as_bytes();
+    if bytes.is_empty() || bytes.len() > MAX_LEN || !bytes.iter().all(u8::is_ascii_graphic) {
+        return None;
+    }
+    value.to_str().ok().map(str::to_owned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_generate() {
+        let ids = (0..1000).map(|_| generate()).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), 1000);
+        for id in &ids {
+            assert_eq!(id.len(), 32, "{id}");
+            assert!(id.bytes().all(|b| b.is_ascii_hexdigit()), "{id}");
+        }
+    }
+
+    #[test]
+    fn test_correlation_key() {
+        let at = 1_700_000_000_000;
+        assert_eq!(
+            correlation_key(at, &Method::GET, "/items"),
+            "eabd5f3bea7f74e6"
+        );
+        assert_eq!(
+            correlation_key(at, &Method::POST, "/items"),
+            "3294aa57a207f866"
+        );
+        assert_eq!(
+            correlation_key(at + 1, &Method::GET, "/items"),
+            "f5d970dce6d9441d"
+        );
+        assert_eq!(correlation_key(0, &Method::GET, "/"), "b9787e5091c5f64e");
+    }
+
+    #[test]
+    fn test_from_header() {
+        let id = |s: &str| from_header(&HeaderValue::from_str(s).unwrap());
+        assert_eq!(id("abc-123").as_deref(), Some("abc-123"));
+        assert_eq!(id(""), None);
+        assert_eq!(id("two words"), None);
+        assert_eq!(id(&"x".repeat(MAX_LEN)).map(|s| s.len()), Some(MAX_LEN));
+        assert_eq!(id(&"x".repeat(MAX_LEN + 1)), None);
+        assert_eq!(
+            from_header(&HeaderValue::from_bytes(b"caf\xc3\xa9").unwrap()),
+            None
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::{poll_fn, Future};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+
+use hyper::rt::Timer;
+
+/// The future returned by [Resolver::resolve].
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = Option<String>> + Send + 'a>>;
+
+/// Something which can look up the hostname of an IP address, for [ReverseDns].
+///
+/// Implement this to plug in a DNS resolver library. A `HashMap<IpAddr, String>` can be used as a
+/// static hosts map.
+pub trait Resolver: Send + Sync {
+    /// Look up the hostname for the given address, or `None` if it doesn't have one.
+    fn resolve(&self, ip: IpAddr) -> ResolveFuture<'_>;
+}
+
+impl Resolver for HashMap<IpAddr, String> {
+    fn resolve(&self, ip: IpAddr) -> ResolveFuture<'_> {
+        Box::pin(std::future::ready(self.get(&ip).cloned()))
+    }
+}
+
+/// [ReverseDns] resolves client addresses to hostnames, with a bounded cache and a strict
+/// timeout, for use with [LogRequest::resolve_remote](crate::LogRequest::resolve_remote).
+///
+/// This is opt-in and meant for internal tools where a readable log is worth more than the cost
+/// of a lookup. It is not suitable for internet-facing or high-QPS services: each uncached
+/// client costs a DNS query, anyone who controls the reverse zone for their own address chooses
+/// what gets logged, and a client population larger than the cache makes it useless.
+///
+/// Lookups which don't finish within the timeout (100ms by default) leave the hostname unset, so
+/// only the IP address is logged, and are retried the next time the address is seen. Completed
+/// lookups, including ones which found no hostname, are cached; once the cache is full (1024
+/// addresses by default) the oldest address is evicted.
+pub struct ReverseDns {
+    resolver: Arc<dyn Resolver>,
+    timer: Arc<dyn Timer + Send + Sync>,
+    timeout: Duration,
+    cache: Mutex<Cache>,
+}
+
+struct Cache {
+    names: HashMap<IpAddr, Option<String>>,
+    order: VecDeque<IpAddr>,
+    capacity: usize,
+}
+
+impl ReverseDns {
+    /// Create a new reverse resolver using the given resolver, and the given timer for timeouts
+    /// (for example, `hyper_util::rt::TokioTimer`).
+    pub fn new(
+        resolver: impl Resolver + 'static,
+        timer: impl Timer + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            timer: Arc::new(timer),
+            timeout: Duration::from_millis(100),
+            cache: Mutex::new(Cache {
+                names: HashMap::new(),
+                order: VecDeque::new(),
+                capacity: 1024,
+            }),
+        }
+    }
+
+    /// The longest time to wait for a lookup.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The number of addresses to keep in the cache.
+    pub fn cache_size(mut self, capacity: usize) -> Self {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .capacity = capacity;
+        self
+    }
+
+    /// Look up the hostname for the given address, from the cache if possible. Returns `None` if
+    /// the address has no hostname or the lookup timed out.
+    pub async fn lookup(&self, ip: IpAddr) -> Option<String> {
+        if let Some(name) = self
+            .cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .names
+            .get(&ip)
+        {
+            return name.clone();
+        }
+
+        let mut resolve = self.resolver.resolve(ip);
+        let mut sleep = self.timer.sleep(self.timeout);
+        let result = poll_fn(|cx| {
+            if let Poll::Ready(name) = resolve.as_mut().poll(cx) {
+                return Poll::Ready(Some(name));
+            }
+            sleep.as_mut().poll(cx).map(|()| None)
+        })
+        .await;
+
+        let name = result?;
+        self.cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(ip, name.clone());
+        name
+    }
+}
+
+impl Cache {
+    fn insert(&mut self, ip: IpAddr, name: Option<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.names.insert(ip, name).is_none() {
+            self.order.push_back(ip);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(old) = self.order.pop_front() {
+                self.names.remove(&old);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use hyper_util::rt::TokioTimer;
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Resolver for Counting {
+        fn resolve(&self, ip: IpAddr) -> ResolveFuture<'_> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async move { Some(format!("host-{ip}")) })
+        }
+    }
+
+    struct Hang;
+
+    impl Resolver for Hang {
+        fn resolve(&self, _ip: IpAddr) -> ResolveFuture<'_> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_static_hosts() {
+        let hosts = HashMap::from([(
+            "10.3.2.17".parse().unwrap(),
+            "build-agent-07.corp.example".to_owned(),
+        )]);
+        let dns = ReverseDns::new(hosts, TokioTimer::new());
+        assert_eq!(
+            dns.lookup("10.3.2.17".parse().unwrap()).await.as_deref(),
+            Some("build-agent-07.corp.example")
+        );
+        assert_eq!(dns.lookup("10.3.2.18".parse().unwrap()).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let dns = ReverseDns::new(Hang, TokioTimer::new()).timeout(Duration::from_millis(10));
+        let ip = "10.0.0.1".parse().unwrap();
+        assert_eq!(dns.lookup(ip).await, None);
+        // Timeouts aren't cached.
+        assert!(dns.cache.lock().unwrap().names.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let dns = ReverseDns::new(Counting(lookups.clone()), TokioTimer::new()).cache_size(2);
+        let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "10.0.0.3"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let count = || lookups.load(Ordering::Relaxed);
+
+        assert_eq!(dns.lookup(ips[0]).await.as_deref(), Some("host-10.0.0.1"));
+        assert_eq!(dns.lookup(ips[0]).await.as_deref(), Some("host-10.0.0.1"));
+        assert_eq!(count(), 1);
+
+        dns.lookup(ips[1]).await;
+        dns.lookup(ips[2]).await;
+        assert_eq!(count(), 3);
+        assert_eq!(dns.cache.lock().unwrap().names.len(), 2);
+
+        // The oldest entry was evicted.
+        dns.lookup(ips[0]).await;
+        assert_eq!(count(), 4);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Mutex;
+
+use crate::entry::{LogEntry, StatusClass};
+use crate::sink::LogSink;
+
+/// [RingBufferSink] is a sink which keeps the most recent entries in memory, for a debug endpoint
+/// which shows what the server has been doing without going through its log files.
+///
+/// Once it holds `capacity` entries, each new entry replaces the oldest. Lines which aren't
+/// request entries are ignored. It is meant to run alongside the real log, by adding it with
+/// [LogRequest::also_write_to](crate::LogRequest::also_write_to) or
+/// [Route::also_write_to](crate::Route::also_write_to).
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{LogRequest, RingBufferSink, StatusClass};
+/// let recent = Arc::new(RingBufferSink::new(200));
+///
+/// let req = http::Request::get("/hello").body(()).unwrap();
+/// let mut log = LogRequest::<&str>::from_request(&req);
+/// log.also_write_to(recent.clone());
+/// log.set_response(&http::Response::new(()));
+/// log.write(std::io::sink()).unwrap();
+///
+/// assert_eq!(recent.entries().len(), 1);
+/// assert!(recent.entries_with_status(StatusClass::ServerError).is_empty());
+/// ```
+#[derive(Debug)]
+pub struct RingBufferSink {
+    capacity: usize,
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+impl RingBufferSink {
+    /// Create a sink which keeps up to `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// A copy of the entries currently held, oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// A copy of the entries currently held whose status is in the given class, oldest first.
+    pub fn entries_with_status(&self, class: StatusClass) -> Vec<LogEntry> {
+        self.lock()
+            .iter()
+            .filter(|entry| entry.status_class() == Some(class))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove all the entries.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<LogEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl LogSink for RingBufferSink {
+    fn write_line(&self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut entries = self.lock();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::test_entry;
+
+    #[test]
+    fn test_ring_buffer() {
+        let sink = RingBufferSink::new(3);
+        for i in 0..5u16 {
+            let req = http::Request::get(format!("/{i}")).body(()).unwrap();
+            let mut entry = test_entry(&req);
+            entry.status = Some(if i % 2 == 0 { 200 } else { 500 });
+            sink.write_entry(&entry).unwrap();
+        }
+        sink.write_line("not an entry\n").unwrap();
+
+        let uris = |entries: Vec<LogEntry>| {
+            entries
+                .iter()
+                .map(|e| e.uri().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(uris(sink.entries()), ["/2", "/3", "/4"]);
+        assert_eq!(
+            uris(sink.entries_with_status(StatusClass::Success)),
+            ["/2", "/4"]
+        );
+        assert_eq!(
+            uris(sink.entries_with_status(StatusClass::ServerError)),
+            ["/3"]
+        );
+
+        sink.clear();
+        assert!(sink.entries().is_empty());
+
+        let empty = RingBufferSink::new(0);
+        empty
+            .write_entry(&test_entry(&http::Request::new(())))
+            .unwrap();
+        assert!(empty.entries().is_empty());
+    }
+}
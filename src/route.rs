@@ -0,0 +1,220 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use crate::config::{ConfigError, LogConfig};
+use crate::entry::LogEntry;
+use crate::head::RequestHead;
+use crate::sample::SamplePolicy;
+use crate::sink::LogSink;
+
+type Predicate = dyn Fn(&RequestHead<'_>) -> bool + Send + Sync;
+
+/// A filter which suppresses entries it returns true for.
+pub(crate) type Suppress = dyn Fn(&LogEntry) -> bool + Send + Sync;
+
+/// How requests matching a route in [RouteRules] are logged, in place of the defaults.
+#[derive(Clone, Default)]
+pub struct Route {
+    pub(crate) config: Option<Arc<LogConfig>>,
+    pub(crate) exclude: bool,
+    pub(crate) sample: Option<SamplePolicy>,
+    pub(crate) suppress: Option<Arc<Suppress>>,
+    pub(crate) sinks: Vec<Arc<dyn LogSink>>,
+}
+
+impl Route {
+    /// A route which logs requests the same way as any other.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the given configuration for matching requests, instead of the one the request was
+    /// created with. Route rules in it are not consulted.
+    pub fn config(mut self, config: Arc<LogConfig>) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Don't log matching requests at all, as if they were discarded.
+    pub fn exclude(mut self) -> Self {
+        self.exclude = true;
+        self
+    }
+
+    /// Don't log matching requests whose entries the filter returns true for, as if by
+    /// [LogRequest::suppress_if](crate::LogRequest::suppress_if). Unlike
+    /// [exclude](Self::exclude), this is decided when the request is logged, so it can depend on
+    /// the status:
+    ///
+    /// ```
+    /// # use hyper_req_log::{Route, RouteRules};
+    /// // Log health checks only when they fail.
+    /// let routes = RouteRules::new()
+    ///     .prefix("/healthz", Route::new().suppress_if(|entry| entry.status() == Some(200)));
+    /// ```
+    pub fn suppress_if(
+        mut self,
+        filter: impl Fn(&LogEntry) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.suppress = Some(Arc::new(filter));
+        self
+    }
+
+    /// Sample matching requests with the given policy, as if by
+    /// [LogRequest::sample](crate::LogRequest::sample).
+    pub fn sample(mut self, policy: SamplePolicy) -> Self {
+        self.sample = Some(policy);
+        self
+    }
+
+    /// Also write matching requests to the given sink, as if by
+    /// [LogRequest::also_write_to](crate::LogRequest::also_write_to). This can be called multiple
+    /// times to add several sinks.
+    pub fn also_write_to(mut self, sink: Arc<dyn LogSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    fn check(&self, path: &str, errors: &mut Vec<ConfigError>) {
+        if let Some(sample) = self.sample {
+            sample.check(format!("{path}.sample"), errors);
+        }
+        if let Some(config) = &self.config {
+            config.check(&format!("{path}.config."), errors);
+        }
+    }
+}
+
+impl Debug for Route {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("config", &self.config)
+            .field("exclude", &self.exclude)
+            .field("sample", &self.sample)
+            .field("suppress", &self.suppress.is_some())
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
+}
+
+/// [RouteRules] is a table of per-route overrides for
+/// [LogConfig::routes](crate::LogConfig::routes), which is consulted once when each request is
+/// created, before anything is captured from it.
+///
+/// Routes are matched by path prefix or by a predicate on the request. Predicates are tried
+/// first, in the order they were added, and the first match wins; otherwise the longest matching
+/// prefix wins. Prefixes match whole path segments, so `/admin` matches `/admin` and
+/// `/admin/users` but not `/administrator`.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{LogConfig, Route, RouteRules, SamplePolicy};
+/// let config = LogConfig::new().routes(
+///     RouteRules::new()
+///         .prefix("/metrics", Route::new().exclude())
+///         .prefix("/static", Route::new().sample(SamplePolicy::Ratio(0.01)))
+///         .prefix("/upload", Route::new().config(Arc::new(LogConfig::new().header_sizes(true)))),
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct RouteRules {
+    prefixes: Vec<(String, Route)>,
+    predicates: Vec<(Arc<Predicate>, Route)>,
+}
+
+impl RouteRules {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a route for requests whose path starts with the given prefix.
+    pub fn prefix(mut self, prefix: impl Into<String>, route: Route) -> Self {
+        let mut prefix = prefix.into();
+        while prefix.len() > 1 && prefix.ends_with('/') {
+            prefix.pop();
+        }
+        self.prefixes.push((prefix, route));
+        self
+    }
+
+    /// Add a route for requests which the given predicate accepts.
+    pub fn when(
+        mut self,
+        predicate: impl Fn(&RequestHead<'_>) -> bool + Send + Sync + 'static,
+        route: Route,
+    ) -> Self {
+        self.predicates.push((Arc::new(predicate), route));
+        self
+    }
+
+    pub(crate) fn check(&self, path: &str, errors: &mut Vec<ConfigError>) {
+        for (i, (prefix, route)) in self.prefixes.iter().enumerate() {
+            let option = format!("{path}[{prefix:?}]");
+            if !prefix.starts_with('/') {
+                errors.push(ConfigError::RoutePrefix {
+                    option: option.clone(),
+                    prefix: prefix.clone(),
+                });
+            }
+            if self.prefixes[i + 1..].iter().any(|(p, _)| p == prefix) {
+                errors.push(ConfigError::DuplicateRoute {
+                    option: option.clone(),
+                    prefix: prefix.clone(),
+                });
+            }
+            route.check(&option, errors);
+        }
+        for (i, (_, route)) in self.predicates.iter().enumerate() {
+            route.check(&format!("{path}[when #{i}]"), errors);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.prefixes.is_empty() && self.predicates.is_empty()
+    }
+
+    /// Find the route which applies to the request, if any.
+    pub(crate) fn resolve(&self, head: &RequestHead<'_>) -> Option<&Route> {
+        if let Some((_, route)) = self.predicates.iter().find(|(p, _)| p(head)) {
+            return Some(route);
+        }
+        let path = head.uri().path();
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| prefix_matches(prefix, path))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, route)| route)
+    }
+}
+
+fn prefix_matches(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix == "/" || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+impl Debug for RouteRules {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteRules")
+            .field("prefixes", &self.prefixes)
+            .field("predicates", &self.predicates.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prefix_matches() {
+        assert!(prefix_matches("/admin", "/admin"));
+        assert!(prefix_matches("/admin", "/admin/"));
+        assert!(prefix_matches("/admin", "/admin/users"));
+        assert!(!prefix_matches("/admin", "/administrator"));
+        assert!(!prefix_matches("/admin", "/"));
+        assert!(prefix_matches("/", "/anything"));
+    }
+}
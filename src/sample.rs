@@ -0,0 +1,155 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Display, Formatter};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use http::HeaderValue;
+
+use crate::config::ConfigError;
+
+/// How a request is chosen to be logged or not, for
+/// [LogRequest::sample](crate::LogRequest::sample).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplePolicy {
+    /// Keep each request with the given probability, from 0 (keep nothing) to 1 (keep
+    /// everything).
+    Ratio(f64),
+    /// Keep requests with the given probability, like [Ratio](Self::Ratio), but decide by the
+    /// request ID, so that every service which logs a request with the same ID makes the same
+    /// decision for it. A request is kept if the 64-bit FNV-1a hash of its ID, divided by 2^64,
+    /// is below the ratio. Requests without an ID are decided at random.
+    Keyed(f64),
+}
+
+impl SamplePolicy {
+    /// The probability of keeping a request.
+    pub fn ratio(self) -> f64 {
+        match self {
+            SamplePolicy::Ratio(ratio) | SamplePolicy::Keyed(ratio) => ratio,
+        }
+    }
+
+    pub(crate) fn check(self, option: String, errors: &mut Vec<ConfigError>) {
+        let ratio = self.ratio();
+        if !(0. ..=1.).contains(&ratio) {
+            errors.push(ConfigError::Ratio {
+                option,
+                value: ratio,
+            });
+        }
+    }
+
+    pub(crate) fn decide(self, request_id: Option<&str>) -> SamplingDecision {
+        let unit = match (self, request_id) {
+            (SamplePolicy::Keyed(_), Some(id)) => keyed_unit(id),
+            _ => random_unit(),
+        };
+        if unit < self.ratio() {
+            SamplingDecision::Kept
+        } else {
+            SamplingDecision::Dropped
+        }
+    }
+}
+
+/// Whether a sampled request will be logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SamplingDecision {
+    /// The request will be logged.
+    Kept,
+    /// The request will not be logged, as if it had been discarded.
+    Dropped,
+}
+
+impl SamplingDecision {
+    /// The name of the response header which
+    /// [LogRequest::apply_sampling_header](crate::LogRequest::apply_sampling_header) sets.
+    pub const HEADER: &'static str = "x-log-sampled";
+
+    /// The value of the response header: `1` if kept, `0` if dropped.
+    pub fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            SamplingDecision::Kept => "1",
+            SamplingDecision::Dropped => "0",
+        })
+    }
+}
+
+impl Display for SamplingDecision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SamplingDecision::Kept => "kept",
+            SamplingDecision::Dropped => "dropped",
+        })
+    }
+}
+
+/// A uniformly distributed number in `[0, 1)`. This only needs to be good enough to spread
+/// requests evenly, so it hashes a counter with std's randomly keyed hasher rather than pulling in
+/// a random number crate.
+pub(crate) fn random_unit() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A number in `[0, 1)` which depends only on the key: its 64-bit FNV-1a hash, divided by 2^64.
+/// FNV-1a is simple enough to reimplement in any language, so other services can match it.
+fn keyed_unit(key: &str) -> f64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in key.bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ratio() {
+        let decide = |policy: SamplePolicy| policy.decide(None);
+        assert!((0..1000).all(|_| decide(SamplePolicy::Ratio(1.)) == SamplingDecision::Kept));
+        assert!((0..1000).all(|_| decide(SamplePolicy::Ratio(0.)) == SamplingDecision::Dropped));
+        let kept = (0..10_000)
+            .filter(|_| decide(SamplePolicy::Ratio(0.25)) == SamplingDecision::Kept)
+            .count();
+        assert!((2000..3000).contains(&kept), "{kept}");
+    }
+
+    #[test]
+    fn test_keyed() {
+        // FNV-1a test vectors.
+        let unit = |hash: u64| (hash >> 11) as f64 / 2f64.powi(53);
+        assert_eq!(keyed_unit(""), unit(0xcbf2_9ce4_8422_2325));
+        assert_eq!(keyed_unit("a"), unit(0xaf63_dc4c_8601_ec8c));
+
+        let policy = SamplePolicy::Keyed(0.25);
+        let ids = (0..10_000)
+            .map(|_| crate::request_id::generate())
+            .collect::<Vec<_>>();
+        let decisions = ids
+            .iter()
+            .map(|id| policy.decide(Some(id)))
+            .collect::<Vec<_>>();
+        let again = ids
+            .iter()
+            .map(|id| policy.decide(Some(id)))
+            .collect::<Vec<_>>();
+        assert_eq!(decisions, again);
+        let kept = decisions
+            .iter()
+            .filter(|d| **d == SamplingDecision::Kept)
+            .count();
+        assert!((2000..3000).contains(&kept), "{kept}");
+        // Anything kept at a lower ratio is kept at a higher one.
+        let wider = SamplePolicy::Keyed(0.5);
+        assert!(ids
+            .iter()
+            .filter(|id| policy.decide(Some(id)) == SamplingDecision::Kept)
+            .all(|id| wider.decide(Some(id)) == SamplingDecision::Kept));
+    }
+}
@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+
+use serde::ser::{Error, Serialize, SerializeMap, Serializer};
+
+use crate::display::LogDisplay;
+use crate::entry::LogEntry;
+use crate::fields::{write_fields, Fields};
+use crate::request::LogRequest;
+
+/// An entry serializes as a map with the same keys and values as
+/// [Format::Json](crate::Format::Json): strings which aren't valid UTF-8 have the invalid sequences
+/// replaced with U+FFFD, the remote address is a string, durations are numbers of milliseconds, the
+/// action is rendered, and the extra fields are a map under `extras`.
+impl Serialize for LogEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = Map {
+            map: serializer.serialize_map(None)?,
+            error: None,
+        };
+        if write_fields(self, &mut map).is_err() {
+            return Err(map
+                .error
+                .unwrap_or_else(|| S::Error::custom("failed to render a field")));
+        }
+        map.map.end()
+    }
+}
+
+/// A request serializes as its [entry](LogRequest::entry), as it is at the time. Serializing it
+/// doesn't mark it as logged.
+impl<A: LogDisplay> Serialize for LogRequest<A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.entry().serialize(serializer)
+    }
+}
+
+/// Writes fields to a serde map, keeping the serializer's error, since [Fields] can only return
+/// `fmt::Error`.
+struct Map<M: SerializeMap> {
+    map: M,
+    error: Option<M::Error>,
+}
+
+impl<M: SerializeMap> Map<M> {
+    fn entry<V: Serialize + ?Sized>(&mut self, key: &str, value: &V) -> fmt::Result {
+        self.map.serialize_entry(key, value).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+impl<M: SerializeMap> Fields for Map<M> {
+    fn string(&mut self, key: &str, value: &[u8]) -> fmt::Result {
+        self.entry(key, &String::from_utf8_lossy(value))
+    }
+
+    fn number(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        self.entry(key, &Number(value.to_string()))
+    }
+
+    fn strings(&mut self, key: &str, values: &[String]) -> fmt::Result {
+        self.entry(key, values)
+    }
+
+    fn extras(&mut self, extras: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result {
+        self.entry("extras", &Extras(extras))
+    }
+}
+
+/// A number, or `bool`, as rendered by its `Display` impl, serialized as the narrowest type
+/// which parses it.
+struct Number(String);
+
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Ok(n) = self.0.parse::<u64>() {
+            serializer.serialize_u64(n)
+        } else if let Ok(n) = self.0.parse::<i64>() {
+            serializer.serialize_i64(n)
+        } else if let Ok(n) = self.0.parse::<f64>() {
+            serializer.serialize_f64(n)
+        } else if let Ok(b) = self.0.parse::<bool>() {
+            serializer.serialize_bool(b)
+        } else {
+            serializer.serialize_str(&self.0)
+        }
+    }
+}
+
+struct Extras<'a>(&'a [(&'a str, Cow<'a, [u8]>)]);
+
+impl Serialize for Extras<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            map.serialize_entry(key, &String::from_utf8_lossy(value))?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{write_entry, Format};
+
+    #[test]
+    fn test_matches_json() {
+        let mut entry = crate::entry::fixture_entry();
+        entry.action_chain = vec!["auth".to_owned(), "get".to_owned()];
+        let mut json = String::new();
+        write_entry(&entry, Format::Json, &mut json).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_value(&entry).unwrap(), expected);
+        assert!(expected["extras"].is_object(), "{json}");
+        assert!(expected["duration_ms"].is_number(), "{json}");
+    }
+
+    #[test]
+    fn test_request() {
+        let req = http::Request::get("/a?b=1")
+            .header(
+                "user-agent",
+                http::HeaderValue::from_bytes(b"x\xff").unwrap(),
+            )
+            .body(())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .set_action("get")
+            .set_status(200);
+        let value = serde_json::to_value(&log).unwrap();
+        assert!(!log.is_logged());
+        assert_eq!(value["remote"], "192.0.2.1:4000");
+        assert_eq!(value["action"], "get");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["uri"], "/a?b=1");
+        assert_eq!(value["user_agent"], "x\u{fffd}");
+        assert!(value["duration_ms"].as_f64().is_some());
+        log.discard();
+    }
+}
@@ -0,0 +1,328 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use http::{Request, Response};
+
+use crate::annotations::LogAnnotations;
+use crate::config::{default_config, LogConfig};
+use crate::error::ErrorKind;
+use crate::request::LogRequest;
+
+/// [LogLayer] is a `tower` layer which logs every request passing through the service it wraps,
+/// managing the [LogRequest] so the service doesn't have to.
+///
+/// A [LogRequest] is created from each request, using the layer's config, and a [LogHandle] for
+/// it is put in the request's extensions, for the handler to set the user, action, and extra
+/// fields. When the inner service responds, the response is passed to
+/// [set_response](LogRequest::set_response), and the request is logged when the response future
+/// finishes. If the inner service fails, the request is logged with `err_kind=internal`, and no
+/// status unless one is set with [error_status](Self::error_status). If the response future is
+/// dropped before it finishes, the request is logged as cancelled.
+///
+/// The remote address isn't part of the request, so it has to be given to the layer. With
+/// hyper, the service stack is usually built for each connection, which is where the address is
+/// known.
+///
+/// ```
+/// # use hyper_req_log::{LogHandle, LogLayer};
+/// # use tower::{Layer, Service};
+/// # async fn handle(req: http::Request<()>) -> Result<http::Response<()>, std::io::Error> {
+/// #     Ok(http::Response::new(()))
+/// # }
+/// let remote = "192.0.2.1:4000".parse().unwrap();
+/// let service = LogLayer::new().remote(remote).layer(tower::service_fn(
+///     |req: http::Request<()>| async move {
+///         if let Some(log) = req.extensions().get::<LogHandle>() {
+///             log.set_action("hello");
+///         }
+///         handle(req).await
+///     },
+/// ));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogLayer {
+    config: Arc<LogConfig>,
+    remote: Option<SocketAddr>,
+    error_status: Option<u16>,
+}
+
+impl Default for LogLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogLayer {
+    /// Create a layer which logs requests with the default config.
+    pub fn new() -> Self {
+        Self {
+            config: default_config(),
+            remote: None,
+            error_status: None,
+        }
+    }
+
+    /// The config requests are logged with.
+    pub fn config(mut self, config: Arc<LogConfig>) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The remote address of the connection the requests came in on.
+    pub fn remote(mut self, remote: SocketAddr) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// The status to log for requests where the inner service fails, instead of none.
+    pub fn error_status(mut self, status: u16) -> Self {
+        self.error_status = Some(status);
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for LogLayer {
+    type Service = LogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LogService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// [LogService] is the service made by [LogLayer].
+#[derive(Debug, Clone)]
+pub struct LogService<S> {
+    inner: S,
+    layer: LogLayer,
+}
+
+impl<S> LogService<S> {
+    /// The wrapped service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get the wrapped service back.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B, R> tower::Service<Request<B>> for LogService<S>
+where
+    S: tower::Service<Request<B>, Response = Response<R>>,
+{
+    type Response = Response<R>;
+    type Error = S::Error;
+    type Future = LogFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let mut log = LogRequest::from_request_with_config(&req, self.layer.config.clone());
+        if let Some(remote) = self.layer.remote {
+            log.set_remote(remote);
+        }
+        let handle = LogHandle::default();
+        req.extensions_mut().insert(handle.clone());
+        LogFuture {
+            inner: Box::pin(self.inner.call(req)),
+            log: Some(log),
+            handle,
+            error_status: self.layer.error_status,
+        }
+    }
+}
+
+/// The response future of [LogService].
+pub struct LogFuture<F> {
+    inner: Pin<Box<F>>,
+    log: Option<LogRequest<&'static str>>,
+    handle: LogHandle,
+    error_status: Option<u16>,
+}
+
+impl<F, R, E> Future for LogFuture<F>
+where
+    F: Future<Output = Result<Response<R>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = std::task::ready!(self.inner.as_mut().poll(cx));
+        let this = &mut *self;
+        if let Some(mut log) = this.log.take() {
+            log.annotate(&this.handle.take());
+            match &result {
+                Ok(resp) => {
+                    log.set_response(resp);
+                }
+                Err(_) => {
+                    log.set_error_kind(ErrorKind::Internal);
+                    if let Some(status) = this.error_status {
+                        let resp = Response::builder().status(status).body(());
+                        if let Ok(resp) = resp {
+                            log.set_response(&resp);
+                        }
+                    }
+                }
+            }
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl<F> Drop for LogFuture<F> {
+    fn drop(&mut self) {
+        if let Some(mut log) = self.log.take() {
+            log.annotate(&self.handle.take());
+            log.mark_cancelled();
+        }
+    }
+}
+
+/// [LogHandle] is put in the extensions of requests passing through a [LogService], for the
+/// handler to add to the request's log entry.
+///
+/// What is set here is logged in place of anything in [LogAnnotations] on the response.
+#[derive(Debug, Clone, Default)]
+pub struct LogHandle(Arc<Mutex<LogAnnotations>>);
+
+impl LogHandle {
+    /// Set the action, as with [LogRequest::set_action].
+    pub fn set_action(&self, action: impl Into<String>) -> &Self {
+        self.lock().action = Some(action.into());
+        self
+    }
+
+    /// Set the user, as with [LogRequest::set_user].
+    pub fn set_user(&self, user: impl Into<String>) -> &Self {
+        self.lock().user = Some(user.into());
+        self
+    }
+
+    /// Add an extra field, logged as `key=value`. A field with the same key replaces it.
+    pub fn add_field(&self, key: impl Into<String>, value: impl Into<String>) -> &Self {
+        let (key, value) = (key.into(), value.into());
+        let mut annotations = self.lock();
+        annotations.fields.retain(|(k, _)| *k != key);
+        annotations.fields.push((key, value));
+        self
+    }
+
+    fn take(&self) -> LogAnnotations {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LogAnnotations> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::untimed;
+    use std::io;
+
+    use tower::{Layer, Service};
+
+    async fn call<S>(service: &mut S, req: Request<()>) -> Result<Response<()>, S::Error>
+    where
+        S: Service<Request<()>, Response = Response<()>>,
+    {
+        std::future::poll_fn(|cx| service.poll_ready(cx)).await?;
+        service.call(req).await
+    }
+
+    fn layer(out: &Arc<Mutex<Vec<u8>>>) -> LogLayer {
+        let config = LogConfig::new().sink(out.clone());
+        LogLayer::new()
+            .config(Arc::new(config))
+            .remote("192.0.2.1:4000".parse().unwrap())
+    }
+
+    fn text(out: &Mutex<Vec<u8>>) -> String {
+        String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_success() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let mut service = layer(&out).layer(tower::service_fn(|req: Request<()>| async move {
+            let log = req.extensions().get::<LogHandle>().unwrap();
+            log.set_user("alice")
+                .set_action("create")
+                .add_field("items", "3");
+            Ok::<_, io::Error>(Response::builder().status(201).body(()).unwrap())
+        }));
+        let req = Request::post("/things").body(()).unwrap();
+        let resp = call(&mut service, req).await.unwrap();
+        assert_eq!(resp.status(), 201);
+        let line = text(&out);
+        assert!(
+            untimed(&line)
+                .starts_with("request: [create:201] alice 192.0.2.1:4000 \"\" POST /things "),
+            "{line}"
+        );
+        assert!(line.ends_with(" items=3\n"), "{line}");
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let failing = tower::service_fn(|_: Request<()>| async move {
+            Err::<Response<()>, _>(io::Error::other("broken"))
+        });
+        let mut service = layer(&out).layer(failing);
+        let req = Request::get("/fails").body(()).unwrap();
+        assert!(call(&mut service, req).await.is_err());
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with("request: [???] 192.0.2.1:4000 "),
+            "{line}"
+        );
+        assert!(line.contains(" err_kind=internal"), "{line}");
+
+        let mut service = layer(&out).error_status(500).layer(failing);
+        let req = Request::get("/fails").body(()).unwrap();
+        assert!(call(&mut service, req).await.is_err());
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with("request: [500] 192.0.2.1:4000 "),
+            "{line}"
+        );
+        assert!(line.contains(" err_kind=internal"), "{line}");
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let mut service = layer(&out).layer(tower::service_fn(|req: Request<()>| async move {
+            req.extensions()
+                .get::<LogHandle>()
+                .unwrap()
+                .set_action("slow");
+            std::future::pending::<Result<Response<()>, io::Error>>().await
+        }));
+        let mut future = service.call(Request::get("/slow").body(()).unwrap());
+        let waker = std::task::Waker::noop();
+        assert!(Pin::new(&mut future)
+            .poll(&mut Context::from_waker(waker))
+            .is_pending());
+        drop(future);
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with("request: [slow:!cancelled] 192.0.2.1:4000 "),
+            "{line}"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::entry::LogEntry;
+use crate::format;
+
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Switch requests which are logged when dropped to a best-effort path, for when the process is
+/// about to exit.
+///
+/// Once this has been called, a [LogRequest](crate::LogRequest) which is dropped without being
+/// written renders a minimal line (the text format without any of the fields
+/// [max_line_len](crate::LogConfig::max_line_len) can leave out) and writes it to stderr with a
+/// single write call, without taking any locks. It isn't written to any other sinks, since they
+/// may buffer or may already be gone. Requests written explicitly, with
+/// [write](crate::LogRequest::write), are unaffected.
+///
+/// This can't be undone. The recommended order for shutting down a server is:
+///
+/// 1. Stop accepting connections.
+/// 2. Drain the connections being served, with hyper's graceful shutdown, so that requests which
+///    finish are logged normally.
+/// 3. Flush or close any sinks which buffer, such as `DedupSink` or `ParquetSink`.
+/// 4. Call [shutdown].
+/// 5. Drop the runtime. Requests still held by tasks it cancels are logged by the best-effort
+///    path.
+pub fn shutdown() {
+    SHUT_DOWN.store(true, Ordering::Release);
+}
+
+/// Whether [shutdown] has been called.
+pub fn is_shut_down() -> bool {
+    SHUT_DOWN.load(Ordering::Acquire)
+}
+
+/// Write the entry by the best-effort path.
+pub(crate) fn write_late(entry: &LogEntry) {
+    let mut line = String::new();
+    if format::write_minimal(entry, &mut line).is_ok() {
+        let _ = write_stderr(line.as_bytes());
+    }
+}
+
+#[cfg(unix)]
+fn write_stderr(bytes: &[u8]) -> io::Result<usize> {
+    use std::os::fd::FromRawFd;
+    // SAFETY: fd 2 stays open for the life of the process unless the program closes it itself.
+    // It is only borrowed: ManuallyDrop keeps the File from closing it.
+    let file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(2) });
+    (&*file).write(bytes)
+}
+
+#[cfg(not(unix))]
+fn write_stderr(bytes: &[u8]) -> io::Result<usize> {
+    io::stderr().write(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_minimal_line() {
+        let entry = crate::entry::fixture_entry();
+        let mut line = String::new();
+        format::write_minimal(&entry, &mut line).unwrap();
+        assert_eq!(
+            line,
+            "2023-11-14T22:13:20.000Z request: [create:201] alice 192.0.2.10:51234 - \
+             POST /api/items/42?expand=owner HTTP/1.1 - - 4.567/12.345 bytes_in=512 bytes=2048 \
+             budget=10.000 over=+2.345 elided=extras,ua,referer,details,fwd,host\n"
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::entry::LogEntry;
+use crate::format::Format;
+
+/// A destination for log entries.
+///
+/// Sinks which write to a byte stream only need to implement [write_line](Self::write_line);
+/// sinks which inspect or transform entries (aggregating, filtering, buffering) can override
+/// [write_entry](Self::write_entry) as well.
+pub trait LogSink: Send + Sync {
+    /// Write a single pre-formatted line. The line includes its trailing newline.
+    fn write_line(&self, line: &str) -> io::Result<()>;
+
+    /// Write a log entry. By default this renders the entry in the text format and passes it to
+    /// [write_line](Self::write_line).
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        entry.with_rendered(|line| self.write_line(line))
+    }
+
+    /// The sink, if it's a [LogFacadeSink](crate::LogFacadeSink), so that
+    /// [emit_log](crate::LogRequest::emit_log) logs with its target and levels. Not part of the
+    /// public API.
+    #[cfg(feature = "log")]
+    #[doc(hidden)]
+    fn as_log_facade(&self) -> Option<&crate::LogFacadeSink> {
+        None
+    }
+}
+
+/// A sink which writes to the process's standard error stream.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        io::stderr().lock().write_all(line.as_bytes())
+    }
+}
+
+impl<W: io::Write + Send> LogSink for Mutex<W> {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut w = self.lock().unwrap_or_else(|e| e.into_inner());
+        w.write_all(line.as_bytes())
+    }
+}
+
+static DEFAULT_SINK: RwLock<Option<Arc<dyn LogSink>>> = RwLock::new(None);
+
+/// Set the sink entries are written to when they aren't written explicitly, such as when a
+/// [LogRequest](crate::LogRequest) is dropped, in place of stderr, for requests whose config
+/// doesn't have a [sink](crate::LogConfig::sink) of its own. It applies to every request written
+/// afterwards, including ones already in progress.
+///
+/// Errors writing to the sink are counted in [failed_writes](crate::failed_writes), as they are
+/// for stderr.
+pub fn set_default_sink(sink: Arc<dyn LogSink>) {
+    *DEFAULT_SINK.write().unwrap_or_else(|e| e.into_inner()) = Some(sink);
+}
+
+/// Go back to writing entries to stderr when they aren't written explicitly, undoing
+/// [set_default_sink].
+pub fn clear_default_sink() {
+    *DEFAULT_SINK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// The sink set with [set_default_sink], if any.
+pub(crate) fn default_sink() -> Option<Arc<dyn LogSink>> {
+    DEFAULT_SINK
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// A sink set with [LogConfig::sink](crate::LogConfig::sink), so the config can still be `Debug`.
+#[derive(Clone)]
+pub(crate) struct ConfigSink(pub(crate) Arc<dyn LogSink>);
+
+impl Debug for ConfigSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("LogSink")
+    }
+}
+
+/// [DualFormat] is a sink which renders each entry in two formats, writing each rendering to its
+/// own sink. This is useful while migrating from one format to another, so both can be emitted
+/// for the same requests during a transition period.
+///
+/// The entry is rendered from the same snapshot for both, so the two outputs always describe the
+/// same request. The formats given here take the place of the one in the entry's
+/// [LogConfig](crate::LogConfig). Lines which aren't request entries are written to both sinks.
+pub struct DualFormat {
+    first: (Format, Arc<dyn LogSink>),
+    second: (Format, Arc<dyn LogSink>),
+    buf: Mutex<String>,
+}
+
+impl DualFormat {
+    /// Create a sink which writes entries rendered in `first_format` to `first`, and rendered in
+    /// `second_format` to `second`.
+    pub fn new(
+        first_format: Format,
+        first: Arc<dyn LogSink>,
+        second_format: Format,
+        second: Arc<dyn LogSink>,
+    ) -> Self {
+        Self {
+            first: (first_format, first),
+            second: (second_format, second),
+            buf: Mutex::new(String::new()),
+        }
+    }
+}
+
+impl LogSink for DualFormat {
+    /// Errors from the first sink are returned in preference to the second, but both sinks are
+    /// always written to.
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let first = self.first.1.write_line(line);
+        let second = self.second.1.write_line(line);
+        first.and(second)
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        // Reuse one render buffer, but don't hold the lock while writing to the sinks.
+        let mut buf = std::mem::take(&mut *self.buf.lock().unwrap_or_else(|e| e.into_inner()));
+        let mut result = Ok(());
+        for (format, sink) in [&self.first, &self.second] {
+            buf.clear();
+            let _ = entry.write_as(*format, &mut buf);
+            let r = sink.write_line(&buf);
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        buf.clear();
+        *self.buf.lock().unwrap_or_else(|e| e.into_inner()) = buf;
+        result
+    }
+}
+
+/// [MultiSink] is a sink which writes each entry to several destinations, such as stderr for
+/// `kubectl logs` and a [FileSink](crate::FileSink):
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{Format, LogConfig, MultiSink, StderrSink};
+/// let file = Arc::new(std::sync::Mutex::new(Vec::<u8>::new()));
+/// let sink = MultiSink::new()
+///     .sink(Arc::new(StderrSink))
+///     .sink_as(Format::Json, file);
+/// let config = LogConfig::new().sink(Arc::new(sink));
+/// ```
+///
+/// Destinations added with [sink](Self::sink) are passed the entry itself, so they render it in
+/// the format of its [LogConfig](crate::LogConfig), or handle it however they like. Those added
+/// with [sink_as](Self::sink_as) are passed it rendered in the format given; it is rendered once
+/// for each format, however many destinations use it. Lines which aren't request entries are
+/// written to every destination as they are.
+///
+/// Every destination is written to, even if some of them fail. The first error is returned, so
+/// when a request is dropped it is counted by [failed_writes](crate::failed_writes) and passed to
+/// the [handler](crate::set_write_error_handler) like any other.
+#[derive(Default)]
+pub struct MultiSink {
+    destinations: Vec<(Option<usize>, Arc<dyn LogSink>)>,
+    /// The distinct formats of the destinations added with [sink_as](Self::sink_as).
+    formats: Vec<Format>,
+    /// A render buffer for each format.
+    bufs: Mutex<Vec<String>>,
+}
+
+impl MultiSink {
+    /// Create a sink with no destinations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a destination which is passed each entry to render for itself.
+    pub fn sink(mut self, sink: Arc<dyn LogSink>) -> Self {
+        self.destinations.push((None, sink));
+        self
+    }
+
+    /// Add a destination which is passed each entry rendered in `format`.
+    pub fn sink_as(mut self, format: Format, sink: Arc<dyn LogSink>) -> Self {
+        let index = match self.formats.iter().position(|&f| f == format) {
+            Some(index) => index,
+            None => {
+                self.formats.push(format);
+                self.formats.len() - 1
+            }
+        };
+        self.destinations.push((Some(index), sink));
+        self
+    }
+}
+
+impl LogSink for MultiSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut result = Ok(());
+        for (_, sink) in &self.destinations {
+            let r = sink.write_line(line);
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        result
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        // Reuse the render buffers, but don't hold the lock while writing to the destinations,
+        // which may write entries of their own. A nested write renders into fresh ones.
+        let mut bufs = std::mem::take(&mut *self.bufs.lock().unwrap_or_else(|e| e.into_inner()));
+        bufs.resize_with(self.formats.len(), String::new);
+        for (format, buf) in self.formats.iter().zip(&mut bufs) {
+            buf.clear();
+            let _ = entry.write_as(*format, buf);
+        }
+        let mut result = Ok(());
+        for (format, sink) in &self.destinations {
+            let r = match format {
+                Some(index) => sink.write_line(&bufs[*index]),
+                None => sink.write_entry(entry),
+            };
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        for buf in &mut bufs {
+            buf.clear();
+        }
+        *self.bufs.lock().unwrap_or_else(|e| e.into_inner()) = bufs;
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entry::test_entry;
+    use crate::entry::untimed;
+    use crate::LogRequest;
+
+    fn text(out: &Mutex<Vec<u8>>) -> String {
+        String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_request_sink() {
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let req = http::Request::get("/with-sink").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_request_with_sink(&req, out.clone());
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .set_response(&http::Response::new(()));
+        drop(log);
+        let line = text(&out);
+        assert!(
+            untimed(&line).starts_with("request: [200] 192.0.2.1:4000 "),
+            "{line}"
+        );
+        assert!(line.contains(" GET /with-sink "), "{line}");
+
+        // An explicit write goes where it's told, and still reports errors.
+        let mut log = LogRequest::<&str>::from_request_with_sink(&req, out.clone());
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .set_response(&http::Response::new(()));
+        let mut written = vec![];
+        log.write(&mut written).unwrap();
+        assert!(!written.is_empty());
+        assert_eq!(text(&out), "");
+    }
+
+    #[test]
+    fn test_nested_write() {
+        /// Writes a second entry from inside the first one's write.
+        struct Nested {
+            inner: LogEntry,
+            lines: Mutex<Vec<String>>,
+        }
+
+        impl LogSink for Nested {
+            fn write_line(&self, line: &str) -> io::Result<()> {
+                let first = self.lines.lock().unwrap().is_empty();
+                self.lines.lock().unwrap().push(line.to_owned());
+                if first {
+                    self.write_entry(&self.inner)?;
+                }
+                Ok(())
+            }
+        }
+
+        let outer = test_entry(&http::Request::get("/outer").body(()).unwrap());
+        let inner = test_entry(&http::Request::get("/inner").body(()).unwrap());
+        let sink = Nested {
+            inner: inner.clone(),
+            lines: Mutex::new(vec![]),
+        };
+        sink.write_entry(&outer).unwrap();
+        sink.write_entry(&outer).unwrap();
+        assert_eq!(
+            *sink.lines.lock().unwrap(),
+            [outer.to_string(), inner.to_string(), outer.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_dual_format() {
+        let text = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let pretty = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sink = DualFormat::new(Format::Text, text.clone(), Format::Pretty, pretty.clone());
+
+        let req = http::Request::post("/upload?id=7").body(()).unwrap();
+        let mut entry = test_entry(&req);
+        entry.status = Some(201);
+        entry.action = Some("upload".to_owned());
+        sink.write_entry(&entry).unwrap();
+
+        let text = String::from_utf8(text.lock().unwrap().clone()).unwrap();
+        let pretty = String::from_utf8(pretty.lock().unwrap().clone()).unwrap();
+        assert_eq!(text, entry.to_string());
+        assert!(
+            untimed(&text).starts_with("request: [upload:201] "),
+            "{text}"
+        );
+        let mut expected = String::new();
+        entry.write_as(Format::Pretty, &mut expected).unwrap();
+        assert_eq!(pretty, expected);
+        for field in ["upload", "201", "POST", "/upload?id=7"] {
+            assert!(text.contains(field), "{text}");
+            assert!(pretty.contains(field), "{pretty}");
+        }
+    }
+
+    #[test]
+    fn test_multi_sink() {
+        struct Failing;
+
+        impl LogSink for Failing {
+            fn write_line(&self, _line: &str) -> io::Result<()> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "sink is gone"))
+            }
+        }
+
+        let plain = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let json = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let also_json = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sink = Arc::new(
+            MultiSink::new()
+                .sink_as(Format::Json, json.clone())
+                .sink(Arc::new(Failing))
+                .sink(plain.clone())
+                .sink_as(Format::Json, also_json.clone()),
+        );
+
+        let req = http::Request::get("/multi").body(()).unwrap();
+        let entry = test_entry(&req);
+        let err = sink.write_entry(&entry).unwrap_err();
+        assert_eq!(err.to_string(), "sink is gone");
+        let mut expected = String::new();
+        entry.write_as(Format::Json, &mut expected).unwrap();
+        assert_eq!(text(&json), expected);
+        assert_eq!(text(&also_json), expected);
+        assert_eq!(text(&plain), entry.to_string());
+
+        assert!(sink.write_line("other\n").is_err());
+        for out in [&json, &plain, &also_json] {
+            assert_eq!(text(out), "other\n");
+        }
+
+        // The other sinks are written when a request using it is dropped.
+        let config = Arc::new(crate::LogConfig::new().sink(sink));
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+        log.set_response(&http::Response::new(()));
+        drop(log);
+        assert!(text(&json).contains(r#""uri":"/multi""#));
+        assert!(text(&plain).contains(" GET /multi "));
+    }
+}
@@ -0,0 +1,195 @@
+use std::io;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::sink::LogSink;
+
+/// Process-wide counters of what [LogRequest](crate::LogRequest) has done.
+pub(crate) struct Counters {
+    written: AtomicU64,
+    dropped: AtomicU64,
+    errors: AtomicU64,
+    inflight: AtomicI64,
+}
+
+pub(crate) static COUNTERS: Counters = Counters::new();
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            written: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            inflight: AtomicI64::new(0),
+        }
+    }
+
+    pub(crate) fn started(&self) {
+        self.inflight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn finished(&self) {
+        self.inflight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn written(&self, result: &io::Result<()>) {
+        match result {
+            Ok(()) => &self.written,
+            Err(_) => &self.errors,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Stats {
+        Stats {
+            written: self.written.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            inflight: self.inflight.load(Ordering::Relaxed).max(0) as u64,
+        }
+    }
+}
+
+/// A snapshot of the process-wide logging counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Entries successfully written to their primary stream.
+    pub written: u64,
+    /// Entries which were not written because sampling dropped them.
+    pub dropped: u64,
+    /// Entries which failed to be written to their primary stream, and writes to
+    /// [extra sinks](crate::LogRequest::also_write_to) which failed.
+    pub errors: u64,
+    /// [LogRequest](crate::LogRequest) instances which currently exist.
+    pub inflight: u64,
+}
+
+impl Stats {
+    /// The current values of the counters, counted since the process started.
+    pub fn get() -> Self {
+        COUNTERS.snapshot()
+    }
+}
+
+/// [StatsReporter] writes the process-wide logging counters to a sink as a single summary line,
+/// when [report](Self::report) is called or periodically from a background thread.
+///
+/// Summary lines look like:
+///
+/// `hyper-req-log stats: written=10233 dropped=12 errors=1 inflight=3`
+///
+/// and never start with `request:`, so parsers of request lines can skip them. By default each
+/// line counts what happened since the previous report; with [cumulative](Self::cumulative) the
+/// counts are totals since the process started. `inflight` is always the current value.
+pub struct StatsReporter {
+    sink: Arc<dyn LogSink>,
+    cumulative: bool,
+    counters: &'static Counters,
+    last: Mutex<Stats>,
+}
+
+impl StatsReporter {
+    /// Create a reporter which writes to the given sink.
+    pub fn new(sink: Arc<dyn LogSink>) -> Self {
+        Self::with_counters(sink, &COUNTERS)
+    }
+
+    fn with_counters(sink: Arc<dyn LogSink>, counters: &'static Counters) -> Self {
+        Self {
+            sink,
+            cumulative: false,
+            counters,
+            last: Mutex::new(Stats::default()),
+        }
+    }
+
+    /// If enabled, report totals since the process started instead of counts since the previous
+    /// report.
+    ///
+    /// Disabled by default.
+    pub fn cumulative(mut self, enable: bool) -> Self {
+        self.cumulative = enable;
+        self
+    }
+
+    /// Write a summary line to the sink.
+    pub fn report(&self) -> io::Result<()> {
+        let now = self.counters.snapshot();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let shown = if self.cumulative {
+            now
+        } else {
+            Stats {
+                written: now.written - last.written,
+                dropped: now.dropped - last.dropped,
+                errors: now.errors - last.errors,
+                inflight: now.inflight,
+            }
+        };
+        *last = now;
+        drop(last);
+        self.sink.write_line(&format!(
+            "hyper-req-log stats: written={} dropped={} errors={} inflight={}\n",
+            shown.written, shown.dropped, shown.errors, shown.inflight,
+        ))
+    }
+
+    /// Start a background thread which calls [report](Self::report) on the given interval. The
+    /// thread exits after the reporter is dropped.
+    pub fn spawn(self: &Arc<Self>, interval: Duration) -> thread::JoinHandle<()> {
+        let weak: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match weak.upgrade() {
+                Some(reporter) => {
+                    let _ = reporter.report();
+                }
+                None => return,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let counters: &'static Counters = Box::leak(Box::new(Counters::new()));
+        let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let take = || String::from_utf8(std::mem::take(&mut *out.lock().unwrap())).unwrap();
+        let delta = StatsReporter::with_counters(out.clone(), counters);
+        let total = StatsReporter::with_counters(out.clone(), counters).cumulative(true);
+
+        for _ in 0..3 {
+            counters.started();
+        }
+        counters.written(&Ok(()));
+        counters.finished();
+        counters.written(&Err(io::ErrorKind::Other.into()));
+        counters.finished();
+        counters.dropped();
+        delta.report().unwrap();
+        assert_eq!(
+            take(),
+            "hyper-req-log stats: written=1 dropped=1 errors=1 inflight=1\n"
+        );
+
+        counters.written(&Ok(()));
+        counters.finished();
+        delta.report().unwrap();
+        total.report().unwrap();
+        assert_eq!(
+            take(),
+            "hyper-req-log stats: written=1 dropped=0 errors=0 inflight=0\n\
+             hyper-req-log stats: written=2 dropped=1 errors=1 inflight=0\n"
+        );
+    }
+}
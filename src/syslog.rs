@@ -0,0 +1,471 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write as _};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::entry::LogEntry;
+use crate::fields::{write_fields, Fields};
+use crate::sink::LogSink;
+
+type SeverityFn = dyn Fn(&LogEntry) -> Severity + Send + Sync;
+
+/// A syslog facility, which says what kind of program a message is from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Ntp = 12,
+    Audit = 13,
+    Alert = 14,
+    Clock = 15,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// A syslog severity, from most to least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(missing_docs)]
+pub enum Severity {
+    Emergency = 0,
+    Alert = 1,
+    Critical = 2,
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Informational = 6,
+    Debug = 7,
+}
+
+/// The settings for a [SyslogSink].
+#[derive(Clone)]
+pub struct SyslogConfig {
+    facility: Facility,
+    hostname: Option<String>,
+    app_name: String,
+    enterprise_id: u32,
+    severity: Option<Arc<SeverityFn>>,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyslogConfig {
+    /// The default settings: the `daemon` facility, no hostname, and an app-name of
+    /// `hyper-req-log`.
+    pub fn new() -> Self {
+        Self {
+            facility: Facility::Daemon,
+            hostname: None,
+            app_name: "hyper-req-log".to_owned(),
+            enterprise_id: 32473,
+            severity: None,
+        }
+    }
+
+    /// The facility of every message.
+    pub fn facility(mut self, facility: Facility) -> Self {
+        self.facility = facility;
+        self
+    }
+
+    /// The HOSTNAME of every message. Without one, it is the nil value `-`, which local syslog
+    /// daemons fill in themselves.
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// The APP-NAME of every message.
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.app_name = app_name.into();
+        self
+    }
+
+    /// The private enterprise number in the IDs of the structured data elements, as in
+    /// `request@32473`. Defaults to 32473, which is reserved for documentation.
+    pub fn enterprise_id(mut self, id: u32) -> Self {
+        self.enterprise_id = id;
+        self
+    }
+
+    /// Choose the severity of each entry's message, instead of going by its status: `err` for
+    /// 5xx responses, `warning` for 4xx responses, and `info` otherwise.
+    pub fn severity(
+        mut self,
+        severity: impl Fn(&LogEntry) -> Severity + Send + Sync + 'static,
+    ) -> Self {
+        self.severity = Some(Arc::new(severity));
+        self
+    }
+
+    fn severity_of(&self, entry: &LogEntry) -> Severity {
+        if let Some(severity) = &self.severity {
+            return severity(entry);
+        }
+        match entry.status {
+            Some(500..) => Severity::Error,
+            Some(400..) => Severity::Warning,
+            _ => Severity::Informational,
+        }
+    }
+
+    /// Render an RFC 5424 message, without any framing.
+    fn write_message(
+        &self,
+        severity: Severity,
+        msgid: &str,
+        entry: Option<&LogEntry>,
+        msg: &str,
+        f: &mut String,
+    ) -> fmt::Result {
+        write!(f, "<{}>1 ", (self.facility as u8) * 8 + severity as u8)?;
+        match entry {
+            Some(entry) => write!(f, "{}", entry.timestamp())?,
+            None => f.write_char('-')?,
+        }
+        write!(
+            f,
+            " {} {} {} {} ",
+            HeaderField(self.hostname.as_deref().unwrap_or(""), 255),
+            HeaderField(&self.app_name, 48),
+            std::process::id(),
+            HeaderField(msgid, 32),
+        )?;
+        match entry {
+            Some(entry) => {
+                let mut element = Element {
+                    f,
+                    enterprise_id: self.enterprise_id,
+                    extras: String::new(),
+                };
+                write!(element.f, "[request@{}", self.enterprise_id)?;
+                write_fields(entry, &mut element)?;
+                element.f.write_char(']')?;
+                let extras = element.extras;
+                f.push_str(&extras);
+            }
+            None => f.write_char('-')?,
+        }
+        if !msg.is_empty() {
+            write!(f, " {msg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// [SyslogSink] is a sink which writes entries as RFC 5424 syslog messages, to a Unix datagram
+/// socket such as `/dev/log`, or to any writer with a newline after each message.
+///
+/// Each message's structured data has a `request` element with the fields of the entry, under
+/// the same names as in [Format::Json](crate::Format::Json), and an `extras` element with its
+/// extra fields, if it has any. The message itself is the entry in the text format. The
+/// timestamp is when the request started, in the [timezone](crate::LogConfig::timezone) of the
+/// entry's config. Lines which aren't request entries are written as messages with the
+/// `informational` severity and no structured data.
+///
+/// ```
+/// # use hyper_req_log::{Facility, SyslogConfig, SyslogSink};
+/// let config = SyslogConfig::new()
+///     .facility(Facility::Local3)
+///     .hostname("web1")
+///     .app_name("api");
+/// # #[cfg(unix)]
+/// # if std::path::Path::new("/dev/log").exists() {
+/// let sink = SyslogSink::unix_datagram("/dev/log", config)?;
+/// # }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct SyslogSink {
+    config: SyslogConfig,
+    target: Target,
+}
+
+enum Target {
+    Writer(Mutex<Box<dyn Write + Send>>),
+    #[cfg(unix)]
+    Datagram(UnixDatagram),
+}
+
+impl SyslogSink {
+    /// Create a sink which writes messages to the given writer, each followed by a newline.
+    pub fn new(writer: impl Write + Send + 'static, config: SyslogConfig) -> Self {
+        Self {
+            config,
+            target: Target::Writer(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Create a sink which sends each message as a datagram to the Unix socket at the given
+    /// path, such as `/dev/log`.
+    #[cfg(unix)]
+    pub fn unix_datagram(path: impl AsRef<Path>, config: SyslogConfig) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self {
+            config,
+            target: Target::Datagram(socket),
+        })
+    }
+
+    fn send(&self, mut message: String) -> io::Result<()> {
+        match &self.target {
+            Target::Writer(writer) => {
+                message.push('\n');
+                let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                writer.write_all(message.as_bytes())
+            }
+            #[cfg(unix)]
+            Target::Datagram(socket) => socket.send(message.as_bytes()).map(|_| ()),
+        }
+    }
+}
+
+impl LogSink for SyslogSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut message = String::new();
+        let _ = self.config.write_message(
+            Severity::Informational,
+            "-",
+            None,
+            line.trim_end_matches('\n'),
+            &mut message,
+        );
+        self.send(message)
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        let line = entry.to_string();
+        let msgid = match entry.client {
+            false => "request",
+            true => "client-request",
+        };
+        let mut message = String::new();
+        let _ = self.config.write_message(
+            self.config.severity_of(entry),
+            msgid,
+            Some(entry),
+            line.trim_end_matches('\n'),
+            &mut message,
+        );
+        self.send(message)
+    }
+}
+
+/// Writes the parameters of the `request` element, and collects the `extras` element.
+struct Element<'a> {
+    f: &'a mut String,
+    enterprise_id: u32,
+    extras: String,
+}
+
+impl Fields for Element<'_> {
+    fn string(&mut self, key: &str, value: &[u8]) -> fmt::Result {
+        write!(self.f, " {}=\"{}\"", ParamName(key), ParamValue(value))
+    }
+
+    fn number(&mut self, key: &str, value: &dyn Display) -> fmt::Result {
+        write!(self.f, " {}=\"{value}\"", ParamName(key))
+    }
+
+    fn extras(&mut self, extras: &[(&str, Cow<'_, [u8]>)]) -> fmt::Result {
+        write!(self.extras, "[extras@{}", self.enterprise_id)?;
+        for (key, value) in extras {
+            write!(self.extras, " {}=\"{}\"", ParamName(key), ParamValue(value))?;
+        }
+        self.extras.write_char(']')
+    }
+}
+
+/// Renders a header field as printable ASCII of at most `max` bytes, with other characters
+/// replaced with `_`, or as the nil value `-` if it's empty.
+struct HeaderField<'a>(&'a str, usize);
+
+impl Display for HeaderField<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_char('-');
+        }
+        for c in self.0.chars().take(self.1) {
+            f.write_char(if c.is_ascii_graphic() { c } else { '_' })?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a parameter name, which is at most 32 printable ASCII characters other than `=`,
+/// `]`, and `"`, with other characters replaced with `_`.
+struct ParamName<'a>(&'a str);
+
+impl Display for ParamName<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_char('_');
+        }
+        for c in self.0.chars().take(32) {
+            match c {
+                '=' | ']' | '"' => f.write_char('_')?,
+                c if c.is_ascii_graphic() => f.write_char(c)?,
+                _ => f.write_char('_')?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders a parameter value, with `"`, `\`, and `]` escaped with a backslash. Sequences which
+/// aren't valid UTF-8 are replaced with U+FFFD, as by [String::from_utf8_lossy].
+struct ParamValue<'a>(&'a [u8]);
+
+impl Display for ParamValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                if matches!(c, '"' | '\\' | ']') {
+                    f.write_char('\\')?;
+                }
+                f.write_char(c)?;
+            }
+            if !chunk.invalid().is_empty() {
+                f.write_char('\u{fffd}')?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    use crate::entry::test_entry;
+
+    /// A writer whose output the test can still read after handing it to a sink.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_messages() {
+        let out = Shared::default();
+        let config = SyslogConfig::new()
+            .facility(Facility::Local3)
+            .hostname("web 1")
+            .app_name("api");
+        let sink = SyslogSink::new(out.clone(), config);
+        let pid = std::process::id();
+
+        let req = http::Request::get("/items?id=1").body(()).unwrap();
+        let mut entry = test_entry(&req);
+        entry.start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(971_211_336);
+        entry.duration = Duration::from_micros(1500);
+        entry.status = Some(503);
+        entry.lifecycle = crate::Lifecycle::Responded;
+        entry.user = Some("a \"b\" [c]\\".into());
+        entry.extras = vec![(
+            "trace id".into(),
+            crate::entry::Extra::Bytes {
+                value: "x]".into(),
+                max_len: usize::MAX,
+            },
+        )];
+        sink.write_entry(&entry).unwrap();
+        let line = out.take();
+        let expected = format!(
+            "<155>1 2000-10-10T20:55:36.000Z web_1 api {pid} request \
+             [request@32473 timestamp=\"2000-10-10T20:55:36.000Z\" schema=\"1\" status=\"503\" \
+             outcome=\"server-error\" user=\"a \\\"b\\\" [c\\]\\\\\" method=\"GET\" \
+             uri=\"/items?id=1\" version=\"HTTP/1.1\" duration_ms=\"1.5\"]\
+             [extras@32473 trace_id=\"x\\]\"] {}",
+            entry
+        );
+        assert_eq!(line, expected);
+
+        entry.status = Some(404);
+        entry.extras.clear();
+        sink.write_entry(&entry).unwrap();
+        assert!(out.take().starts_with("<156>1 "));
+        entry.status = Some(200);
+        sink.write_entry(&entry).unwrap();
+        assert!(out.take().starts_with("<158>1 "));
+
+        sink.write_line("connection: closed\n").unwrap();
+        assert_eq!(
+            out.take(),
+            format!("<158>1 - web_1 api {pid} - - connection: closed\n")
+        );
+    }
+
+    #[test]
+    fn test_severity_override() {
+        let out = Shared::default();
+        let config = SyslogConfig::new().severity(|entry| match entry.status() {
+            Some(429) => Severity::Notice,
+            _ => Severity::Debug,
+        });
+        let sink = SyslogSink::new(out.clone(), config);
+        let mut entry = test_entry(&http::Request::new(()));
+        entry.status = Some(429);
+        sink.write_entry(&entry).unwrap();
+        let line = out.take();
+        assert!(line.starts_with("<29>1 "), "{line}");
+        assert!(line.contains(" - hyper-req-log "), "{line}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_datagram() {
+        let dir = std::env::temp_dir().join(format!("hrl-syslog-{}", std::process::id()));
+        let _ = std::fs::remove_file(&dir);
+        let server = UnixDatagram::bind(&dir).unwrap();
+        let sink = SyslogSink::unix_datagram(&dir, SyslogConfig::new()).unwrap();
+        sink.write_line("hello\n").unwrap();
+        let mut buf = [0; 512];
+        let n = server.recv(&mut buf).unwrap();
+        let pid = std::process::id();
+        assert_eq!(
+            std::str::from_utf8(&buf[..n]).unwrap(),
+            format!("<30>1 - - hyper-req-log {pid} - - hello")
+        );
+        std::fs::remove_file(&dir).unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The timezone wall-clock timestamps are rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timezone {
+    /// Coordinated Universal Time.
+    #[default]
+    Utc,
+    /// The system's local timezone. Looking up the offset requires the `local-time` feature;
+    /// without it, timestamps are rendered in UTC.
+    Local,
+    /// A fixed offset from UTC, in seconds east of UTC.
+    FixedOffset(i32),
+}
+
+impl Timezone {
+    /// The offset from UTC, in seconds, at the given time.
+    fn offset_at(self, time: SystemTime) -> i32 {
+        match self {
+            Self::Utc => 0,
+            Self::FixedOffset(secs) => secs,
+            #[cfg(feature = "local-time")]
+            Self::Local => {
+                use chrono::Offset;
+                chrono::DateTime::<chrono::Local>::from(time)
+                    .offset()
+                    .fix()
+                    .local_minus_utc()
+            }
+            #[cfg(not(feature = "local-time"))]
+            Self::Local => {
+                let _ = time;
+                0
+            }
+        }
+    }
+}
+
+/// How the time a request started is shown in log entries.
+///
+/// This applies to the text and pretty formats, which start with it, and to the `timestamp`
+/// field of the JSON and logfmt formats and of `tracing` events. Formats which define their own
+/// timestamps, such as [Format::Combined](crate::Format::Combined) and the W3C and syslog sinks,
+/// always use those.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampStyle {
+    /// RFC 3339 in UTC, with millisecond precision, e.g. `2000-10-10T20:55:36.789Z`.
+    #[default]
+    Rfc3339Utc,
+    /// RFC 3339 in the system's local timezone, with millisecond precision, e.g.
+    /// `2000-10-10T13:55:36.789-07:00`. Looking up the offset requires the `local-time` feature;
+    /// without it, times are rendered in UTC, with an offset of `+00:00`.
+    Rfc3339Local,
+    /// The number of milliseconds since the Unix epoch, e.g. `971211336789`. It is a number in
+    /// JSON.
+    EpochMillis,
+    /// The style of the Apache `%t` log field, without the brackets, in the configured
+    /// [timezone](crate::LogConfig::timezone), e.g. `10/Oct/2000:13:55:36 -0700`.
+    Clf,
+    /// No timestamp at all, for logs which go somewhere that adds its own, such as journald or
+    /// syslog.
+    None,
+}
+
+/// How durations, such as how long a request took, are shown in log entries.
+///
+/// This applies to the text and pretty formats. The structured formats always have numbers of
+/// milliseconds, under keys ending in `_ms`, or seconds for the
+/// [FieldNames::Otel](crate::FieldNames::Otel) duration, and
+/// [LogEntry::duration](crate::LogEntry::duration) gives the duration itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationStyle {
+    /// Milliseconds, with three decimal places, e.g. `1.235` for 1234.567µs.
+    #[default]
+    Millis,
+    /// Whole microseconds, e.g. `1234` for 1234.567µs.
+    Micros,
+    /// Seconds, with six decimal places, e.g. `0.001235` for 1234.567µs.
+    Seconds,
+    /// The `Debug` representation of the `Duration`, whose unit depends on its size, e.g.
+    /// `1.234567ms`, `567.8µs`, or `2.3s`.
+    HumanDebug,
+}
+
+/// Renders a duration in a [DurationStyle].
+pub(crate) struct StyledDuration(pub(crate) Duration, pub(crate) DurationStyle);
+
+impl Display for StyledDuration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            DurationStyle::Millis => write!(f, "{:.3}", self.0.as_secs_f64() * 1000.),
+            DurationStyle::Micros => write!(f, "{}", self.0.as_micros()),
+            DurationStyle::Seconds => write!(f, "{:.6}", self.0.as_secs_f64()),
+            DurationStyle::HumanDebug => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+static DEFAULT_STYLE: AtomicU8 = AtomicU8::new(0);
+
+const STYLES: [TimestampStyle; 5] = [
+    TimestampStyle::Rfc3339Utc,
+    TimestampStyle::Rfc3339Local,
+    TimestampStyle::EpochMillis,
+    TimestampStyle::Clf,
+    TimestampStyle::None,
+];
+
+/// Set the [TimestampStyle] of entries whose request and config don't set one, in place of
+/// [TimestampStyle::Rfc3339Utc]. It applies to every entry rendered afterwards.
+pub fn set_default_timestamp_style(style: TimestampStyle) {
+    let i = STYLES.iter().position(|s| *s == style).unwrap() as u8;
+    DEFAULT_STYLE.store(i, Ordering::Relaxed);
+}
+
+/// The style set with [set_default_timestamp_style].
+pub(crate) fn default_timestamp_style() -> TimestampStyle {
+    let i = DEFAULT_STYLE.load(Ordering::Relaxed);
+    STYLES[usize::from(i)]
+}
+
+/// A wall-clock time in a particular timezone, for rendering in logs.
+///
+/// The `Display` impl renders it in RFC 3339 format with millisecond precision, e.g.
+/// `2024-05-01T12:34:56.789Z` or `2024-05-01T14:34:56.789+02:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    time: SystemTime,
+    timezone: Timezone,
+}
+
+impl Timestamp {
+    /// Create a new timestamp for the given time, to be rendered in the given timezone.
+    pub fn new(time: SystemTime, timezone: Timezone) -> Self {
+        Self { time, timezone }
+    }
+
+    /// The time this timestamp represents.
+    pub fn system_time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// The timezone this timestamp is rendered in.
+    pub fn timezone(&self) -> Timezone {
+        self.timezone
+    }
+
+    /// Render the timestamp in the style of the Apache `%t` log field (without the brackets),
+    /// e.g. `10/Oct/2000:13:55:36 -0700`.
+    pub fn apache(&self) -> impl Display + '_ {
+        Apache(self)
+    }
+
+    /// Render the timestamp in the given style, or `None` for [TimestampStyle::None]. The
+    /// timestamp's timezone is only used by [TimestampStyle::Clf].
+    pub fn styled(&self, style: TimestampStyle) -> Option<impl Display> {
+        (style != TimestampStyle::None).then_some(Styled(*self, style))
+    }
+
+    /// The number of milliseconds since the Unix epoch.
+    pub(crate) fn epoch_millis(&self) -> i128 {
+        match self.time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as i128,
+            Err(e) => -(e.duration().as_nanos().div_ceil(1_000_000) as i128),
+        }
+    }
+
+    /// The date, as `2000-10-10`, and the time of day, as `20:55:36`, in the timezone.
+    pub(crate) fn date_and_time(&self) -> (String, String) {
+        let (p, _) = self.parts();
+        (
+            format!("{:04}-{:02}-{:02}", p.year, p.month, p.day),
+            format!("{:02}:{:02}:{:02}", p.hour, p.minute, p.second),
+        )
+    }
+
+    /// Break the time down into the civil date and time in the timezone, and the offset used.
+    fn parts(&self) -> (Parts, i32) {
+        let offset = self.timezone.offset_at(self.time);
+        let (secs, nanos) = match self.time.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_nanos() {
+                    0 => (-(d.as_secs() as i64), 0),
+                    n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n),
+                }
+            }
+        };
+        (Parts::new(secs + i64::from(offset), nanos), offset)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (p, offset) = self.parts();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+            p.year,
+            p.month,
+            p.day,
+            p.hour,
+            p.minute,
+            p.second,
+            p.nanos / 1_000_000
+        )?;
+        if self.timezone == Timezone::Utc {
+            f.write_str("Z")
+        } else {
+            let (sign, hh, mm) = split_offset(offset);
+            write!(f, "{sign}{hh:02}:{mm:02}")
+        }
+    }
+}
+
+struct Styled(Timestamp, TimestampStyle);
+
+impl Display for Styled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let time = self.0.time;
+        match self.1 {
+            TimestampStyle::Rfc3339Utc => Timestamp::new(time, Timezone::Utc).fmt(f),
+            TimestampStyle::Rfc3339Local => Timestamp::new(time, Timezone::Local).fmt(f),
+            TimestampStyle::EpochMillis => self.0.epoch_millis().fmt(f),
+            TimestampStyle::Clf => self.0.apache().fmt(f),
+            TimestampStyle::None => Ok(()),
+        }
+    }
+}
+
+struct Apache<'a>(&'a Timestamp);
+
+impl Display for Apache<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        let (p, offset) = self.0.parts();
+        let (sign, hh, mm) = split_offset(offset);
+        write!(
+            f,
+            "{:02}/{}/{:04}:{:02}:{:02}:{:02} {sign}{hh:02}{mm:02}",
+            p.day,
+            MONTHS[p.month as usize - 1],
+            p.year,
+            p.hour,
+            p.minute,
+            p.second,
+        )
+    }
+}
+
+fn split_offset(offset: i32) -> (char, u32, u32) {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let abs = offset.unsigned_abs() / 60;
+    (sign, abs / 60, abs % 60)
+}
+
+struct Parts {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32,
+}
+
+impl Parts {
+    fn new(secs: i64, nanos: u32) -> Self {
+        let days = secs.div_euclid(86400);
+        let secs_of_day = secs.rem_euclid(86400) as u32;
+
+        // Civil-from-days algorithm from http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            minute: secs_of_day / 60 % 60,
+            second: secs_of_day % 60,
+            nanos,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64, millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(secs * 1000 + millis)
+    }
+
+    #[test]
+    fn test_utc() {
+        let ts = |t| Timestamp::new(t, Timezone::Utc);
+        assert_eq!(ts(at(0, 0)).to_string(), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            ts(at(971_211_336, 789)).to_string(),
+            "2000-10-10T20:55:36.789Z"
+        );
+        assert_eq!(
+            ts(at(1_709_164_800, 1)).to_string(),
+            "2024-02-29T00:00:00.001Z"
+        );
+        assert_eq!(
+            ts(UNIX_EPOCH - Duration::from_millis(1)).to_string(),
+            "1969-12-31T23:59:59.999Z"
+        );
+        assert_eq!(
+            ts(at(971_211_336, 0)).apache().to_string(),
+            "10/Oct/2000:20:55:36 +0000"
+        );
+    }
+
+    #[test]
+    fn test_fixed_offset() {
+        let pdt = Timestamp::new(at(971_211_336, 0), Timezone::FixedOffset(-7 * 3600));
+        assert_eq!(pdt.to_string(), "2000-10-10T13:55:36.000-07:00");
+        assert_eq!(pdt.apache().to_string(), "10/Oct/2000:13:55:36 -0700");
+
+        // crossing a day, month, and year boundary
+        let ist = Timestamp::new(at(1_704_066_000, 0), Timezone::FixedOffset(5 * 3600 + 1800));
+        assert_eq!(ist.to_string(), "2024-01-01T05:10:00.000+05:30");
+        assert_eq!(ist.apache().to_string(), "01/Jan/2024:05:10:00 +0530");
+        let nst = Timestamp::new(
+            at(1_704_067_200, 0),
+            Timezone::FixedOffset(-(3 * 3600 + 1800)),
+        );
+        assert_eq!(nst.to_string(), "2023-12-31T20:30:00.000-03:30");
+    }
+
+    #[test]
+    fn test_duration_style() {
+        let shown = |d: Duration, style| StyledDuration(d, style).to_string();
+        let cases = [
+            (
+                Duration::from_nanos(1_234_567),
+                ["1.235", "1234", "0.001235", "1.234567ms"],
+            ),
+            (
+                Duration::from_nanos(567_800),
+                ["0.568", "567", "0.000568", "567.8µs"],
+            ),
+            (
+                Duration::from_millis(2_300),
+                ["2300.000", "2300000", "2.300000", "2.3s"],
+            ),
+            (Duration::ZERO, ["0.000", "0", "0.000000", "0ns"]),
+        ];
+        let styles = [
+            DurationStyle::Millis,
+            DurationStyle::Micros,
+            DurationStyle::Seconds,
+            DurationStyle::HumanDebug,
+        ];
+        for (d, expected) in cases {
+            for (style, expected) in styles.into_iter().zip(expected) {
+                assert_eq!(shown(d, style), expected, "{d:?} {style:?}");
+            }
+        }
+    }
+}
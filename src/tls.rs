@@ -0,0 +1,176 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::escaped::Escaped;
+
+/// [TlsConnectionInfo] describes the TLS session a request came in on, for
+/// [LogRequest::set_tls](crate::LogRequest::set_tls) or
+/// [ConnectionLog::with_tls](crate::ConnectionLog::with_tls). It is logged as
+/// `tls=TLSv1_3 cipher=TLS13_AES_128_GCM_SHA256 sni=example.com alpn=h2 client_cn=...`, leaving
+/// out whatever isn't known. Nothing is logged for requests without it.
+///
+/// With the `tls` feature, it can be created from a rustls `ServerConnection` once the handshake
+/// has finished. With tokio-rustls, that is the second half of
+/// `TlsStream::get_ref()`:
+///
+/// ```no_run
+/// # #[cfg(all(feature = "tls", feature = "connection"))]
+/// # fn f(stream: &rustls::ServerConnection, remote: std::net::SocketAddr) {
+/// use hyper_req_log::{ConnectionLog, TlsConnectionInfo};
+///
+/// let conn = ConnectionLog::new(remote).with_tls(TlsConnectionInfo::from(stream));
+/// # }
+/// ```
+///
+/// rustls doesn't parse certificates, so the client certificate's common name has to be filled
+/// in by whatever verified it. It is logged as `client_cn`, and used by the
+/// [ClientCert](crate::IdentitySource::ClientCert) identity source for every request on the
+/// connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TlsConnectionInfo {
+    /// The protocol version, such as `TLSv1_3`.
+    pub version: Option<String>,
+    /// The cipher suite, such as `TLS13_AES_128_GCM_SHA256`.
+    pub cipher: Option<String>,
+    /// The server name the client asked for with SNI.
+    pub sni: Option<String>,
+    /// The application protocol agreed with ALPN, such as `h2`.
+    pub alpn: Option<Vec<u8>>,
+    /// The common name of the client certificate.
+    pub client_cert_cn: Option<String>,
+}
+
+impl TlsConnectionInfo {
+    /// Info with nothing filled in.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<&rustls::ServerConnection> for TlsConnectionInfo {
+    fn from(conn: &rustls::ServerConnection) -> Self {
+        Self {
+            version: conn
+                .protocol_version()
+                .map(|v| v.as_str().map_or_else(|| format!("{v:?}"), str::to_owned)),
+            cipher: conn.negotiated_cipher_suite().map(|suite| {
+                let suite = suite.suite();
+                suite
+                    .as_str()
+                    .map_or_else(|| format!("{suite:?}"), str::to_owned)
+            }),
+            sni: conn.server_name().map(str::to_owned),
+            alpn: conn.alpn_protocol().map(<[u8]>::to_vec),
+            client_cert_cn: None,
+        }
+    }
+}
+
+/// The fields of the text format, with a leading space.
+impl Display for TlsConnectionInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(version) = &self.version {
+            write!(f, " tls={}", Escaped::from(version))?;
+        }
+        if let Some(cipher) = &self.cipher {
+            write!(f, " cipher={}", Escaped::from(cipher))?;
+        }
+        if let Some(sni) = &self.sni {
+            write!(f, " sni={}", Escaped::from(sni))?;
+        }
+        if let Some(alpn) = &self.alpn {
+            write!(f, " alpn={}", Escaped::from(&alpn[..]))?;
+        }
+        if let Some(cn) = &self.client_cert_cn {
+            write!(f, " client_cn={}", Escaped::from(cn))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection};
+
+    fn handshake() -> ServerConnection {
+        let cert = rcgen::generate_simple_self_signed(["example.com".to_owned()]).unwrap();
+        let der = CertificateDer::from(cert.cert);
+        let key = PrivateKeyDer::try_from(cert.key_pair.serialize_der()).unwrap();
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut server = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![der.clone()], key)
+            .unwrap();
+        server.alpn_protocols = vec![b"h2".to_vec()];
+        let mut roots = RootCertStore::empty();
+        roots.add(der).unwrap();
+        let mut client = ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        let mut server = ServerConnection::new(Arc::new(server)).unwrap();
+        let name = ServerName::try_from("example.com").unwrap();
+        let mut client = ClientConnection::new(Arc::new(client), name).unwrap();
+        while server.is_handshaking() || client.is_handshaking() {
+            let mut buf = vec![];
+            client.write_tls(&mut buf).unwrap();
+            server.read_tls(&mut &buf[..]).unwrap();
+            server.process_new_packets().unwrap();
+            let mut buf = vec![];
+            server.write_tls(&mut buf).unwrap();
+            client.read_tls(&mut &buf[..]).unwrap();
+            client.process_new_packets().unwrap();
+        }
+        server
+    }
+
+    #[test]
+    fn test_from_server_connection() {
+        let info = TlsConnectionInfo::from(&handshake());
+        assert_eq!(info.version.as_deref(), Some("TLSv1_3"));
+        assert!(info.cipher.as_deref().unwrap().starts_with("TLS13_"));
+        assert_eq!(info.sni.as_deref(), Some("example.com"));
+        assert_eq!(info.alpn.as_deref(), Some(&b"h2"[..]));
+        assert_eq!(info.client_cert_cn, None);
+    }
+
+    #[cfg(feature = "connection")]
+    #[test]
+    fn test_connection() {
+        use crate::connection::ConnectionLog;
+        use crate::request::LogRequest;
+
+        let mut info = TlsConnectionInfo::from(&handshake());
+        info.cipher = Some("TLS13_AES_128_GCM_SHA256".to_owned());
+        info.client_cert_cn = Some("build-agent".to_owned());
+        let conn = ConnectionLog::new("192.0.2.1:4000".parse().unwrap()).with_tls(info.clone());
+
+        let req = http::Request::new(());
+        for _ in 0..2 {
+            let log: LogRequest<&str> = conn.request(&req);
+            let entry = log.entry();
+            log.discard();
+            assert_eq!(entry.tls(), Some(&info));
+            let line = entry.to_string();
+            assert!(
+                line.contains(
+                    " tls=TLSv1_3 cipher=TLS13_AES_128_GCM_SHA256 sni=example.com alpn=h2 \
+                     client_cn=build-agent"
+                ),
+                "{line}"
+            );
+        }
+        conn.finish(&Ok(()));
+    }
+}
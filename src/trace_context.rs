@@ -0,0 +1,139 @@
+use std::fmt::{self, Display, Formatter};
+
+use http::HeaderValue;
+
+use crate::escaped::Escaped;
+
+/// The `traceparent` header of a request, for
+/// [LogConfig::trace_context](crate::LogConfig::trace_context), as defined by
+/// [W3C Trace Context](https://www.w3.org/TR/trace-context/#traceparent-header).
+#[derive(Debug, Clone)]
+pub(crate) enum TraceParent {
+    /// A header which parsed, with its IDs as lowercase hex.
+    Valid {
+        trace_id: Box<str>,
+        span_id: Box<str>,
+    },
+    /// A header which didn't, kept as it was received.
+    Malformed(HeaderValue),
+}
+
+impl TraceParent {
+    pub(crate) fn new(header: &HeaderValue) -> Self {
+        match parse(header.as_bytes()) {
+            Some((trace_id, span_id)) => Self::Valid {
+                trace_id: trace_id.into(),
+                span_id: span_id.into(),
+            },
+            None => Self::Malformed(header.clone()),
+        }
+    }
+
+    /// The trace ID, if the header was valid.
+    pub(crate) fn trace_id(&self) -> Option<&str> {
+        match self {
+            Self::Valid { trace_id, .. } => Some(trace_id),
+            Self::Malformed(_) => None,
+        }
+    }
+
+    /// The ID of the caller's span, if the header was valid.
+    pub(crate) fn span_id(&self) -> Option<&str> {
+        match self {
+            Self::Valid { span_id, .. } => Some(span_id),
+            Self::Malformed(_) => None,
+        }
+    }
+}
+
+/// Renders as `trace_id=4bf92f3577b34da6a3ce929d0e0e4736 span_id=00f067aa0ba902b7`, or
+/// `traceparent=<the header, escaped>` if it was malformed.
+impl Display for TraceParent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valid { trace_id, span_id } => write!(f, "trace_id={trace_id} span_id={span_id}"),
+            Self::Malformed(header) => write!(f, "traceparent={}", Escaped::from(header)),
+        }
+    }
+}
+
+/// Parse a `traceparent` header into its trace ID and parent ID.
+///
+/// The header is `version-trace_id-parent_id-flags`, all in lowercase hex, with a version other
+/// than `ff`, and IDs which aren't all zeros. Version `00` has exactly those four fields; later
+/// versions may add more after another `-`, which are ignored.
+fn parse(header: &[u8]) -> Option<(&str, &str)> {
+    const LEN: usize = "00-".len() + 32 + 1 + 16 + "-00".len();
+    let header = std::str::from_utf8(header).ok()?;
+    let (head, rest) = header.split_at_checked(LEN)?;
+    let mut fields = head.split('-');
+    let version = fields.next()?;
+    let trace_id = fields.next()?;
+    let span_id = fields.next()?;
+    let flags = fields.next()?;
+    let hex =
+        |s: &str, len| s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    let valid = hex(version, 2)
+        && version != "ff"
+        && hex(trace_id, 32)
+        && hex(span_id, 16)
+        && hex(flags, 2)
+        && trace_id.bytes().any(|b| b != b'0')
+        && span_id.bytes().any(|b| b != b'0')
+        && match version {
+            "00" => rest.is_empty(),
+            _ => rest.is_empty() || rest.starts_with('-'),
+        };
+    valid.then_some((trace_id, span_id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN: &str = "00f067aa0ba902b7";
+
+    #[test]
+    fn test_parse() {
+        let ids = Some((TRACE, SPAN));
+        assert_eq!(parse(format!("00-{TRACE}-{SPAN}-01").as_bytes()), ids);
+        assert_eq!(parse(format!("00-{TRACE}-{SPAN}-00").as_bytes()), ids);
+        assert_eq!(parse(format!("01-{TRACE}-{SPAN}-01").as_bytes()), ids);
+        assert_eq!(parse(format!("01-{TRACE}-{SPAN}-01-extra").as_bytes()), ids);
+
+        for bad in [
+            String::new(),
+            format!("00-{TRACE}-{SPAN}-01-extra"),
+            format!("00-{TRACE}-{SPAN}-1"),
+            format!("ff-{TRACE}-{SPAN}-01"),
+            format!("0g-{TRACE}-{SPAN}-01"),
+            format!("00-{}-{SPAN}-01", TRACE.to_uppercase()),
+            format!("00-{}-{SPAN}-01", "0".repeat(32)),
+            format!("00-{TRACE}-{}-01", "0".repeat(16)),
+            format!("00-{TRACE}0-{}-01", &SPAN[1..]),
+            format!("00_{TRACE}_{SPAN}_01"),
+            format!("01-{TRACE}-{SPAN}-01extra"),
+        ] {
+            assert_eq!(parse(bad.as_bytes()), None, "{bad}");
+        }
+        assert_eq!(parse("00-é".as_bytes()), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let header = HeaderValue::try_from(format!("00-{TRACE}-{SPAN}-01")).unwrap();
+        let parent = TraceParent::new(&header);
+        assert_eq!(parent.trace_id(), Some(TRACE));
+        assert_eq!(parent.span_id(), Some(SPAN));
+        assert_eq!(
+            parent.to_string(),
+            format!("trace_id={TRACE} span_id={SPAN}")
+        );
+
+        let parent = TraceParent::new(&HeaderValue::from_static("not a traceparent"));
+        assert_eq!(parent.trace_id(), None);
+        assert_eq!(parent.span_id(), None);
+        assert_eq!(parent.to_string(), r#"traceparent="not a traceparent""#);
+    }
+}
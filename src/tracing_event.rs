@@ -0,0 +1,246 @@
+use std::io;
+
+use http::HeaderValue;
+
+use crate::entry::{Lifecycle, LogEntry};
+use crate::escaped::Escaped;
+use crate::fields::header_value;
+use crate::format::{http_version, LogMethod, Remote};
+use crate::forwarded::Chain;
+use crate::query::LogUri;
+use crate::sink::LogSink;
+
+/// [TracingSink] is a sink which emits each entry as a `tracing` event, so it goes through the
+/// application's subscriber, with its filtering and output handling, rather than being written
+/// as a line.
+///
+/// The event has the target `hyper_req_log`, a message of `request` (or `client-request` for a
+/// request made by a client), and the fields of the entry up to `err_kind` as fields of their
+/// own, under the same names as in [Format::Json](crate::Format::Json). Fields the entry doesn't
+/// have are left out of the event. Events can't have more than 32 fields, so the details which
+/// follow, such as the TLS parameters and the breakdowns of the duration, aren't included. The
+/// extra fields are rendered together, as in the text format, in an `extras` field. The level is
+/// `ERROR` for 5xx responses, `WARN` for 4xx responses, and `INFO` otherwise. Lines which aren't
+/// request entries are emitted as `INFO` events with the line as the message.
+///
+/// Use [LogRequest::emit_tracing](crate::LogRequest::emit_tracing) to emit a request's event
+/// instead of writing it to stderr, or set the sink as the [sink](crate::LogConfig::sink) of the
+/// config to do the same for every request which is dropped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingSink;
+
+impl LogSink for TracingSink {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        tracing::info!(target: "hyper_req_log", "{}", line.trim_end_matches('\n'));
+        Ok(())
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        event(entry);
+        Ok(())
+    }
+}
+
+/// Emit an entry as a `tracing` event.
+pub(crate) fn event(entry: &LogEntry) {
+    let header = |value: &Option<HeaderValue>| {
+        value
+            .as_ref()
+            .map(|v| String::from_utf8_lossy(&header_value(entry, v.as_bytes())).into_owned())
+    };
+    let lifecycle = match entry.lifecycle {
+        Lifecycle::Responded => None,
+        Lifecycle::InFlight => Some("in-flight"),
+        Lifecycle::Cancelled => Some("cancelled"),
+        Lifecycle::Panicked => Some("panicked"),
+        Lifecycle::Stuck => Some("stuck"),
+    };
+    let (remote, peer) = match entry.client {
+        false => (entry.remote.map(|r| Remote(r).to_string()), None),
+        true => (None, entry.remote.map(|r| Remote(r).to_string())),
+    };
+    let extras = (!entry.extras.is_empty()).then(|| {
+        let mut extras = String::new();
+        for (key, value) in &entry.extras {
+            if !extras.is_empty() {
+                extras.push(' ');
+            }
+            extras.push_str(&format!("{}={value}", Escaped::from(key.as_ref())));
+        }
+        extras
+    });
+    let message = match entry.client {
+        false => "request",
+        true => "client-request",
+    };
+    let fwd = (!entry.fwd.is_empty())
+        .then(|| Chain(&entry.fwd, entry.fwd_elided, entry.config.ascii_headers).to_string());
+
+    let timestamp = entry.styled_timestamp();
+    let user = entry.user.as_deref().map(String::from_utf8_lossy);
+
+    macro_rules! emit {
+        ($level:expr) => {
+            tracing::event!(
+                target: "hyper_req_log",
+                $level,
+                timestamp = timestamp.as_ref().map(tracing::field::display),
+                request_id = entry.request_id(),
+                trace_id = entry.trace_id(),
+                span_id = entry.span_id(),
+                status = entry.status,
+                outcome = %entry.outcome(),
+                lifecycle,
+                cache_status = entry.cache_status.as_ref().map(|cache| cache.as_str()),
+                action = entry.action.as_deref(),
+                user = user.as_deref(),
+                remote = remote.as_deref(),
+                peer = peer.as_deref(),
+                remote_host = entry.remote_host.as_deref(),
+                forwarded_for = fwd,
+                host = header(&entry.host).as_deref(),
+                server = entry.server_name.as_deref(),
+                method = %LogMethod(&entry.method, entry.config.method_allowlist),
+                uri = %LogUri(entry),
+                version = http_version(entry.version),
+                user_agent = header(&entry.user_agent).as_deref(),
+                referer = header(&entry.referer).as_deref(),
+                handler_ms = entry.handler.map(|d| d.as_micros() as f64 / 1000.),
+                duration_ms = entry.duration_ms(),
+                upstream_ms = entry.upstream.map(|d| d.as_micros() as f64 / 1000.),
+                bytes_in = entry.request_bytes,
+                bytes = entry.response_bytes,
+                body = (entry.body_complete() == Some(false)).then_some("incomplete"),
+                err_kind = entry.error_kind.map(|kind| kind.to_string()).as_deref(),
+                err = entry.error.as_deref(),
+                extras = extras.as_deref(),
+                extras_dropped = (entry.extras_dropped != 0).then_some(entry.extras_dropped),
+                "{message}"
+            )
+        };
+    }
+    match entry.status {
+        Some(500..) => emit!(tracing::Level::ERROR),
+        Some(400..) => emit!(tracing::Level::WARN),
+        _ => emit!(tracing::Level::INFO),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    use crate::LogRequest;
+
+    type Recorded = (Level, BTreeMap<String, String>);
+
+    /// A subscriber which keeps the level and fields of each event.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Recorded>>>);
+
+    struct Fields<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.insert(field.name().to_owned(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = BTreeMap::new();
+            event.record(&mut Fields(&mut fields));
+            let level = *event.metadata().level();
+            self.0.lock().unwrap().push((level, fields));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_emit_tracing() {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let req = http::Request::get("/items?id=1")
+            .header(
+                "user-agent",
+                HeaderValue::from_bytes(b"agent \xff").unwrap(),
+            )
+            .body(())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .set_action("list")
+            .set_response(&http::Response::builder().status(503).body(()).unwrap())
+            .add_field_bytes("trace", bytes::Bytes::from_static(b"abc"), usize::MAX);
+        log.emit_tracing();
+
+        let events = std::mem::take(&mut *recorder.0.lock().unwrap());
+        assert_eq!(events.len(), 1);
+        let (level, fields) = &events[0];
+        assert_eq!(*level, Level::ERROR);
+        let keys = fields.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "action",
+                "duration_ms",
+                "extras",
+                "handler_ms",
+                "message",
+                "method",
+                "outcome",
+                "remote",
+                "status",
+                "timestamp",
+                "uri",
+                "user_agent",
+                "version"
+            ]
+        );
+        assert_eq!(fields["message"], "request");
+        assert_eq!(fields["status"], "503");
+        assert_eq!(fields["remote"], "192.0.2.1:4000");
+        assert_eq!(fields["uri"], "/items?id=1");
+        assert_eq!(fields["user_agent"], "agent \u{fffd}");
+        assert_eq!(fields["extras"], "trace=abc");
+
+        for (status, expected) in [(404, Level::WARN), (200, Level::INFO)] {
+            let mut entry = crate::entry::test_entry(&req);
+            entry.status = Some(status);
+            TracingSink.write_entry(&entry).unwrap();
+            let events = std::mem::take(&mut *recorder.0.lock().unwrap());
+            assert_eq!(events[0].0, expected);
+        }
+
+        TracingSink.write_line("connection: closed\n").unwrap();
+        let events = std::mem::take(&mut *recorder.0.lock().unwrap());
+        assert_eq!(events[0].1["message"], "connection: closed");
+    }
+}
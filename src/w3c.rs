@@ -0,0 +1,329 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write as _};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use http::HeaderValue;
+
+use crate::clf::Host;
+use crate::entry::LogEntry;
+use crate::fields::header_value;
+use crate::format::{http_version, LogMethod};
+use crate::query::log_query;
+use crate::sink::LogSink;
+use crate::time::{Timestamp, Timezone};
+
+/// A field of the W3C extended log file format, for [W3cSink].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum W3cField {
+    /// `date`: the date the request started, in UTC.
+    Date,
+    /// `time`: the time of day the request started, in UTC.
+    Time,
+    /// `c-ip`: the remote address, without the port.
+    CIp,
+    /// `c-port`: the port of the remote address.
+    CPort,
+    /// `cs-username`: the user.
+    CsUsername,
+    /// `cs-method`: the method.
+    CsMethod,
+    /// `cs-uri-stem`: the path of the request target.
+    CsUriStem,
+    /// `cs-uri-query`: the query of the request target, without the `?`.
+    CsUriQuery,
+    /// `cs-version`: the HTTP version, as `HTTP/1.1`.
+    CsVersion,
+    /// `cs-host`: the `Host` header.
+    CsHost,
+    /// `cs(User-Agent)`: the `User-Agent` header.
+    CsUserAgent,
+    /// `cs(Referer)`: the `Referer` header.
+    CsReferer,
+    /// `sc-status`: the response status.
+    ScStatus,
+    /// `cs-bytes`: the size of the request body.
+    CsBytes,
+    /// `sc-bytes`: the size of the response body.
+    ScBytes,
+    /// `time-taken`: how long the request took, in whole milliseconds, as IIS logs it.
+    TimeTaken,
+    /// `x-action`: the action.
+    XAction,
+    /// `s-sitename`: the name of the site which handled the request, set with
+    /// [set_server_name](crate::LogRequest::set_server_name).
+    SSitename,
+}
+
+impl W3cField {
+    /// The name of the field in the `#Fields` directive.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Date => "date",
+            Self::Time => "time",
+            Self::CIp => "c-ip",
+            Self::CPort => "c-port",
+            Self::CsUsername => "cs-username",
+            Self::CsMethod => "cs-method",
+            Self::CsUriStem => "cs-uri-stem",
+            Self::CsUriQuery => "cs-uri-query",
+            Self::CsVersion => "cs-version",
+            Self::CsHost => "cs-host",
+            Self::CsUserAgent => "cs(User-Agent)",
+            Self::CsReferer => "cs(Referer)",
+            Self::ScStatus => "sc-status",
+            Self::CsBytes => "cs-bytes",
+            Self::ScBytes => "sc-bytes",
+            Self::TimeTaken => "time-taken",
+            Self::XAction => "x-action",
+            Self::SSitename => "s-sitename",
+        }
+    }
+
+    /// The value of the field for an entry, if it has one.
+    fn value(self, entry: &LogEntry) -> Option<Cow<'_, [u8]>> {
+        let string = |value: String| Some(Cow::Owned(value.into_bytes()));
+        match self {
+            Self::Date => string(utc(entry).0),
+            Self::Time => string(utc(entry).1),
+            Self::CIp => string(Host(entry.remote?).to_string()),
+            Self::CPort => match entry.remote?.port() {
+                0 => None,
+                port => string(port.to_string()),
+            },
+            Self::CsUsername => entry.user.as_deref().map(Cow::Borrowed),
+            Self::CsMethod => {
+                string(LogMethod(&entry.method, entry.config.method_allowlist).to_string())
+            }
+            Self::CsUriStem => string(entry.uri.path().to_owned()),
+            Self::CsUriQuery => string(log_query(entry)?.into_owned()),
+            Self::CsVersion => Some(Cow::Borrowed(http_version(entry.version).as_bytes())),
+            Self::CsHost => header(entry, &entry.host),
+            Self::CsUserAgent => header(entry, &entry.user_agent),
+            Self::CsReferer => header(entry, &entry.referer),
+            Self::ScStatus => string(entry.status?.to_string()),
+            Self::CsBytes => string(entry.request_bytes?.to_string()),
+            Self::ScBytes => string(entry.response_bytes?.to_string()),
+            Self::TimeTaken => string(entry.duration.as_millis().to_string()),
+            Self::XAction => entry.action.as_ref().map(|a| Cow::Borrowed(a.as_bytes())),
+            Self::SSitename => entry
+                .server_name
+                .as_ref()
+                .map(|s| Cow::Borrowed(s.as_bytes())),
+        }
+    }
+}
+
+fn header<'a>(entry: &LogEntry, value: &'a Option<HeaderValue>) -> Option<Cow<'a, [u8]>> {
+    value.as_ref().map(|v| header_value(entry, v.as_bytes()))
+}
+
+fn utc(entry: &LogEntry) -> (String, String) {
+    Timestamp::new(entry.start_time, Timezone::Utc).date_and_time()
+}
+
+/// [W3cSink] is a sink which writes entries in the W3C extended log file format, as used by IIS,
+/// with only the chosen fields, in the chosen order.
+///
+/// The `#Version`, `#Date`, and `#Fields` directives are written before the first entry, with
+/// the date being when that entry's request started. Each entry is then a line of its field
+/// values separated by spaces. A field the entry doesn't have is `-`. Spaces in values are
+/// written as `+`, and `+`, `%`, and any byte which isn't printable ASCII are percent-encoded,
+/// so a value never has a space in it and can always be decoded. Lines which aren't request
+/// entries are written as `#Remark` directives.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hyper_req_log::{LogRequest, W3cField, W3cSink};
+/// let fields = [W3cField::CIp, W3cField::CsMethod, W3cField::CsUriStem, W3cField::ScStatus];
+/// let sink = Arc::new(W3cSink::new(Vec::new(), &fields));
+///
+/// let req = http::Request::get("/hello").body(()).unwrap();
+/// let mut log = LogRequest::<&str>::from_request(&req);
+/// log.set_remote("192.0.2.1:4000".parse().unwrap())
+///     .set_response(&http::Response::new(()))
+///     .also_write_to(sink.clone());
+/// log.write(std::io::sink()).unwrap();
+///
+/// let out = Arc::into_inner(sink).unwrap().into_inner();
+/// let out = String::from_utf8(out).unwrap();
+/// let fields = "#Fields: c-ip cs-method cs-uri-stem sc-status\n";
+/// assert!(out.ends_with(&format!("\n{fields}192.0.2.1 GET /hello 200\n")));
+/// ```
+pub struct W3cSink<W> {
+    fields: Vec<W3cField>,
+    state: Mutex<State<W>>,
+}
+
+struct State<W> {
+    writer: W,
+    started: bool,
+}
+
+impl<W: Write + Send> W3cSink<W> {
+    /// Create a sink which writes the given fields of each entry to `writer`.
+    pub fn new(writer: W, fields: &[W3cField]) -> Self {
+        Self {
+            fields: fields.to_vec(),
+            state: Mutex::new(State {
+                writer,
+                started: false,
+            }),
+        }
+    }
+
+    /// The fields written for each entry.
+    pub fn fields(&self) -> &[W3cField] {
+        &self.fields
+    }
+
+    /// Get the writer back.
+    pub fn into_inner(self) -> W {
+        self.state
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .writer
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<W>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<W: Write + Send> LogSink for W3cSink<W> {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut state = self.lock();
+        for line in line.lines() {
+            writeln!(state.writer, "#Remark: {line}")?;
+        }
+        Ok(())
+    }
+
+    fn write_entry(&self, entry: &LogEntry) -> io::Result<()> {
+        let mut buf = String::new();
+        for (i, field) in self.fields.iter().enumerate() {
+            if i != 0 {
+                buf.push(' ');
+            }
+            let value = field.value(entry);
+            let _ = write!(buf, "{}", W3cValue(value.as_deref().unwrap_or_default()));
+        }
+        buf.push('\n');
+
+        let mut state = self.lock();
+        if !state.started {
+            let (date, time) = utc(entry);
+            let mut header = format!("#Version: 1.0\n#Date: {date} {time}\n#Fields:");
+            for field in &self.fields {
+                header.push(' ');
+                header.push_str(field.name());
+            }
+            header.push('\n');
+            state.writer.write_all(header.as_bytes())?;
+            state.started = true;
+        }
+        state.writer.write_all(buf.as_bytes())
+    }
+}
+
+/// Renders a field value with no spaces in it: spaces as `+`, and `+`, `%`, and bytes which
+/// aren't printable ASCII percent-encoded.
+struct W3cValue<'a>(&'a [u8]);
+
+impl Display for W3cValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_char('-');
+        }
+        for &b in self.0 {
+            match b {
+                b' ' => f.write_char('+')?,
+                b'+' | b'%' => write!(f, "%{b:02X}")?,
+                b'!'..=b'~' => f.write_char(b as char)?,
+                _ => write!(f, "%{b:02X}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    use http::HeaderValue;
+
+    use crate::entry::test_entry;
+
+    const ALL: [W3cField; 18] = [
+        W3cField::Date,
+        W3cField::Time,
+        W3cField::CIp,
+        W3cField::CPort,
+        W3cField::CsUsername,
+        W3cField::CsMethod,
+        W3cField::CsUriStem,
+        W3cField::CsUriQuery,
+        W3cField::CsVersion,
+        W3cField::CsHost,
+        W3cField::CsUserAgent,
+        W3cField::CsReferer,
+        W3cField::ScStatus,
+        W3cField::CsBytes,
+        W3cField::ScBytes,
+        W3cField::TimeTaken,
+        W3cField::XAction,
+        W3cField::SSitename,
+    ];
+
+    #[test]
+    fn test_sink() {
+        let sink = W3cSink::new(vec![], &ALL);
+        let req = http::Request::get("/a%20b?q=1+2")
+            .header("host", "example.com")
+            .header(
+                "user-agent",
+                HeaderValue::from_bytes(b"Mozilla/5.0 (X11) 100%\xff").unwrap(),
+            )
+            .body(())
+            .unwrap();
+        let mut entry = test_entry(&req);
+        entry.start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(971_211_336);
+        entry.remote = Some("[2001:db8::1]:443".parse().unwrap());
+        entry.user = Some("alice smith".into());
+        entry.status = Some(200);
+        entry.response_bytes = Some(6);
+        entry.duration = Duration::from_micros(12_700);
+        entry.action = Some("get".to_owned());
+        entry.server_name = Some("shop".to_owned());
+        sink.write_entry(&entry).unwrap();
+        sink.write_line("connection: closed\n").unwrap();
+        sink.write_entry(&test_entry(&http::Request::new(())))
+            .unwrap();
+
+        let out = String::from_utf8(sink.into_inner()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[..3],
+            [
+                "#Version: 1.0",
+                "#Date: 2000-10-10 20:55:36",
+                "#Fields: date time c-ip c-port cs-username cs-method cs-uri-stem cs-uri-query \
+                 cs-version cs-host cs(User-Agent) cs(Referer) sc-status cs-bytes sc-bytes \
+                 time-taken x-action s-sitename",
+            ]
+        );
+        assert_eq!(
+            lines[3],
+            "2000-10-10 20:55:36 2001:db8::1 443 alice+smith GET /a%2520b q=1%2B2 HTTP/1.1 \
+             example.com Mozilla/5.0+(X11)+100%25%FF - 200 - 6 12 get shop"
+        );
+        assert_eq!(lines[4], "#Remark: connection: closed");
+        let (_, rest) = lines[5].split_once(' ').unwrap();
+        let (_, rest) = rest.split_once(' ').unwrap();
+        assert_eq!(rest, "- - - GET / - HTTP/1.1 - - - - - - 0 - -");
+        assert_eq!(lines.len(), 6);
+    }
+}
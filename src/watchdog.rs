@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::entry::{Lifecycle, LogEntry};
+use crate::request::write_extra;
+use crate::sink::{default_sink, LogSink};
+use crate::write_errors;
+
+/// A request which hasn't been finished yet, with what was captured when it was registered.
+struct Pending {
+    entry: LogEntry,
+    sinks: Vec<Arc<dyn LogSink>>,
+    started: Instant,
+}
+
+/// The deadline of a registered request, plus a unique ID to break ties.
+type Key = (Instant, u64);
+
+/// The process-wide watchdog. A single thread, started the first time a request is registered,
+/// sleeps until the earliest deadline and writes a provisional entry for each request which
+/// reaches it.
+struct Watchdog {
+    pending: Mutex<BTreeMap<Key, Pending>>,
+    wake: Condvar,
+    next_id: AtomicU64,
+}
+
+fn watchdog() -> &'static Watchdog {
+    static WATCHDOG: OnceLock<Watchdog> = OnceLock::new();
+    WATCHDOG.get_or_init(|| {
+        thread::Builder::new()
+            .name("hyper-req-log-watchdog".to_owned())
+            .spawn(run)
+            .expect("failed to start watchdog thread");
+        Watchdog {
+            pending: Mutex::new(BTreeMap::new()),
+            wake: Condvar::new(),
+            next_id: AtomicU64::new(0),
+        }
+    })
+}
+
+fn run() {
+    let dog = watchdog();
+    let mut pending = dog.pending.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let Some(&(deadline, id)) = pending.keys().next() else {
+            pending = dog.wake.wait(pending).unwrap();
+            continue;
+        };
+        if deadline > now {
+            pending = dog.wake.wait_timeout(pending, deadline - now).unwrap().0;
+            continue;
+        }
+        let stuck = pending.remove(&(deadline, id)).unwrap();
+        drop(pending);
+        stuck.write(now);
+        pending = dog.pending.lock().unwrap();
+    }
+}
+
+impl Pending {
+    /// Write the request as stuck, as of `now`.
+    fn write(mut self, now: Instant) {
+        self.entry.lifecycle = Lifecycle::Stuck;
+        self.entry.duration = now.saturating_duration_since(self.started);
+        let sink = self.entry.config.sink.as_ref().map(|sink| sink.0.clone());
+        let result = match sink.or_else(default_sink) {
+            Some(sink) => sink.write_entry(&self.entry),
+            None => write!(io::stderr().lock(), "{}", self.entry),
+        };
+        if let Err(err) = result {
+            write_errors::report(&err);
+        }
+        write_extra(&self.entry, &self.sinks);
+    }
+}
+
+/// A request's registration with the watchdog, which must be [finished](Self::finish) when the
+/// request is logged or discarded.
+pub(crate) struct Watch {
+    key: Key,
+    max_age: Duration,
+}
+
+impl Watch {
+    /// Register a request, which will be written tagged as stuck with the given entry if it isn't
+    /// finished within `max_age`.
+    pub(crate) fn register(
+        entry: LogEntry,
+        sinks: Vec<Arc<dyn LogSink>>,
+        started: Instant,
+        max_age: Duration,
+    ) -> Self {
+        let dog = watchdog();
+        let key = (
+            started + max_age,
+            dog.next_id.fetch_add(1, Ordering::Relaxed),
+        );
+        let mut pending = dog.pending.lock().unwrap();
+        let earliest = pending.keys().next().is_none_or(|first| key < *first);
+        pending.insert(
+            key,
+            Pending {
+                entry,
+                sinks,
+                started,
+            },
+        );
+        drop(pending);
+        if earliest {
+            dog.wake.notify_one();
+        }
+        Self { key, max_age }
+    }
+
+    /// Update what will be written if the request gets stuck, unless it already has been.
+    pub(crate) fn update(&self, f: impl FnOnce(&mut LogEntry, &mut Vec<Arc<dyn LogSink>>)) {
+        if let Some(pending) = watchdog().pending.lock().unwrap().get_mut(&self.key) {
+            f(&mut pending.entry, &mut pending.sinks);
+        }
+    }
+
+    /// Cancel the registration. If the watchdog already wrote the request as stuck, returns the
+    /// age it did so at.
+    pub(crate) fn finish(self) -> Option<Duration> {
+        let removed = watchdog().pending.lock().unwrap().remove(&self.key);
+        match removed {
+            Some(_) => None,
+            None => Some(self.max_age),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::config::LogConfig;
+    use crate::entry::untimed;
+    use crate::request::LogRequest;
+
+    fn text(out: &Arc<Mutex<Vec<u8>>>) -> String {
+        String::from_utf8(out.lock().unwrap().clone()).unwrap()
+    }
+
+    /// Write every request whose deadline is at or before `now` as stuck, as the watchdog's
+    /// thread does when it reaches their deadlines.
+    fn write_due(now: Instant) {
+        let due = {
+            let mut pending = watchdog().pending.lock().unwrap();
+            let later = pending.split_off(&(now, u64::MAX));
+            std::mem::replace(&mut *pending, later)
+        };
+        for (_, stuck) in due {
+            stuck.write(now);
+        }
+    }
+
+    #[test]
+    fn test_stuck() {
+        // The deadlines are far enough away that the watchdog's thread never reaches them.
+        let max_age = Duration::from_secs(3600);
+        let out = Arc::new(Mutex::new(vec![]));
+        let config = Arc::new(LogConfig::new().max_age(max_age).sink(out.clone()));
+        let clock = MockClock::default();
+        let req = http::Request::get("/forever").body(()).unwrap();
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+        log.set_clock(clock.clone())
+            .set_remote("192.0.2.1:4000".parse().unwrap());
+        write_due(clock.now());
+        assert_eq!(text(&out), "");
+
+        clock.advance(max_age * 2);
+        write_due(clock.now());
+        let stuck = text(&out);
+        assert!(untimed(&stuck).starts_with("request: [!stuck] "), "{stuck}");
+        assert!(stuck.contains(" 192.0.2.1:4000 "), "{stuck}");
+        assert!(stuck.contains(" GET /forever "), "{stuck}");
+
+        log.set_response(&http::Response::new(()));
+        drop(log);
+        let lines = text(&out);
+        let late = lines.lines().nth(1).unwrap();
+        assert!(
+            untimed(late).starts_with("request: [200] 192.0.2.1:4000 "),
+            "{late}"
+        );
+        assert!(late.contains(" late=+"), "{late}");
+
+        // A request which finishes in time is only logged once, and not as late.
+        out.lock().unwrap().clear();
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+        log.set_clock(clock.clone())
+            .set_remote("192.0.2.1:4000".parse().unwrap())
+            .set_response(&http::Response::new(()));
+        drop(log);
+        clock.advance(max_age * 2);
+        write_due(clock.now());
+        let lines = text(&out);
+        assert_eq!(lines.lines().count(), 1, "{lines}");
+        assert!(!lines.contains("late="), "{lines}");
+    }
+}
@@ -0,0 +1,127 @@
+//! Reporting of entries which failed to be written where there is no caller to return the error
+//! to, such as when their request was dropped.
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The shortest time between calls to the handler.
+const INTERVAL: Duration = Duration::from_secs(10);
+
+/// Called with the error when an entry fails to be written.
+type Handler = fn(&io::Error);
+
+/// What has failed, and who to tell about it.
+struct Reporting {
+    failed: AtomicU64,
+    /// The handler, and when it was last called.
+    handler: Mutex<(Option<Handler>, Option<Instant>)>,
+}
+
+impl Reporting {
+    const fn new() -> Self {
+        Self {
+            failed: AtomicU64::new(0),
+            handler: Mutex::new((None, None)),
+        }
+    }
+
+    fn report(&self, err: &io::Error, now: Instant) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        let mut handler = self.handler.lock().unwrap_or_else(|e| e.into_inner());
+        let (Some(call), last) = *handler else {
+            return;
+        };
+        if last.is_some_and(|last| now.saturating_duration_since(last) < INTERVAL) {
+            return;
+        }
+        handler.1 = Some(now);
+        // The handler may log the error through this crate, so it isn't called with the lock held.
+        drop(handler);
+        call(err);
+    }
+
+    fn set_handler(&self, call: Option<Handler>) {
+        *self.handler.lock().unwrap_or_else(|e| e.into_inner()) = (call, None);
+    }
+}
+
+static REPORTING: Reporting = Reporting::new();
+
+/// Count an entry which failed to be written where the error can't be returned, and pass the
+/// error to the handler, if there is one and it hasn't been called too recently.
+pub(crate) fn report(err: &io::Error) {
+    REPORTING.report(err, Instant::now());
+}
+
+/// How many entries have failed to be written since the process started, when their
+/// [LogRequest](crate::LogRequest) was dropped or [reset](crate::LogRequest::reset), when the
+/// response body they were waiting for finished. Writes to
+/// [extra sinks](crate::LogRequest::also_write_to), by the thread of a
+/// [BackgroundLogger](crate::BackgroundLogger), and by the watchdog are counted too.
+///
+/// These are the failures which would otherwise go unnoticed, since there is no caller to return
+/// the error to. [LogRequest::write](crate::LogRequest::write) and the other explicit writes
+/// return their errors instead, and aren't counted here; [Stats::errors](crate::Stats::errors)
+/// counts both.
+pub fn failed_writes() -> u64 {
+    REPORTING.failed.load(Ordering::Relaxed)
+}
+
+/// Call `handler` with the error when an entry fails to be written, as counted by
+/// [failed_writes], so that a sink or stderr which has stopped working can be noticed.
+///
+/// To keep a broken sink from causing a storm of calls, the handler is called for the first
+/// failure, and then at most once every 10 seconds, with the failure which happens then; the
+/// rest are only counted. It is called on whichever thread the failure happened on, so it should
+/// be quick.
+pub fn set_write_error_handler(handler: fn(&io::Error)) {
+    REPORTING.set_handler(Some(handler));
+}
+
+/// Remove the handler set with [set_write_error_handler].
+pub fn clear_write_error_handler() {
+    REPORTING.set_handler(None);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static CALLS: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+    }
+
+    fn record(err: &io::Error) {
+        CALLS.with(|calls| calls.borrow_mut().push(err.to_string()));
+    }
+
+    fn calls() -> Vec<String> {
+        CALLS.with(|calls| calls.borrow().clone())
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let reporting = Reporting::new();
+        let err = |n: u32| io::Error::other(format!("failure {n}"));
+        let start = Instant::now();
+        reporting.report(&err(0), start);
+        reporting.set_handler(Some(record));
+        let times = [0, 1, 9, 10, 15, 19, 20, 45];
+        for (n, secs) in times.into_iter().enumerate() {
+            reporting.report(&err(n as u32 + 1), start + Duration::from_secs(secs));
+        }
+        assert_eq!(reporting.failed.load(Ordering::Relaxed), 9);
+        assert_eq!(
+            calls(),
+            ["failure 1", "failure 4", "failure 7", "failure 8"]
+        );
+
+        // A new handler hears about the next failure straight away.
+        reporting.set_handler(Some(record));
+        reporting.report(&err(9), start + Duration::from_secs(46));
+        assert_eq!(calls().last().unwrap(), "failure 9");
+    }
+}
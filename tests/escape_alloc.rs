@@ -0,0 +1,45 @@
+//! Checks that escaping plain values doesn't allocate. It counts allocations with a global
+//! allocator of its own, so it has a test binary of its own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fmt::Write;
+
+use hyper_req_log::Escaped;
+
+/// Counts heap allocations made by the current thread.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+#[test]
+fn test_escape_plain_no_alloc() {
+    let mut out = String::with_capacity(256);
+    let before = ALLOCATIONS.with(|n| n.get());
+    for value in ["plain-token", "Mozilla/5.0", "/api/v1/items?limit=50"] {
+        write!(out, "{}", Escaped::from(value)).unwrap();
+        Escaped::from(value).write_to(&mut out).unwrap();
+    }
+    let after = ALLOCATIONS.with(|n| n.get());
+    assert_eq!(
+        out,
+        "plain-tokenplain-tokenMozilla/5.0Mozilla/5.0/api/v1/items?limit=50/api/v1/items?limit=50"
+    );
+    assert_eq!(after - before, 0);
+}
@@ -0,0 +1,253 @@
+//! Tests of the process-wide defaults and write error reporting. Changing a default changes how
+//! every entry in the process is rendered, so these tests have a binary of their own, and take
+//! turns with [lock].
+
+use std::fmt::{self, Write};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use hyper_req_log::*;
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Keep the other tests from changing the defaults until the guard is dropped.
+fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Render a request with a fixed start time, with the given config and the process defaults.
+fn render(config: LogConfig) -> String {
+    let input = LogEntryInput {
+        status: Some(200),
+        uri: "/search?q=rust".to_owned(),
+        user_agent: Some("curl/8.0".to_owned()),
+        start_unix_nanos: 1_700_000_000_000_000_000,
+        ..Default::default()
+    };
+    let entry = input.to_entry(Arc::new(config)).unwrap();
+    let mut line = String::new();
+    entry.write_to(&mut line).unwrap();
+    line
+}
+
+fn text(out: &Mutex<Vec<u8>>) -> String {
+    String::from_utf8(out.lock().unwrap().clone()).unwrap()
+}
+
+struct Fixed(&'static str);
+
+impl LogFormatter for Fixed {
+    fn format(&self, _: &LogEntry, f: &mut dyn Write) -> fmt::Result {
+        writeln!(f, "{}", self.0)
+    }
+}
+
+struct Failing;
+
+impl LogSink for Failing {
+    fn write_line(&self, _line: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "sink is gone"))
+    }
+}
+
+impl io::Write for Failing {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "stream is gone"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+static CALLS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+fn record(err: &io::Error) {
+    CALLS.lock().unwrap().push(err.to_string());
+}
+
+fn calls() -> Vec<String> {
+    CALLS.lock().unwrap().clone()
+}
+
+#[test]
+fn test_default_formatter() {
+    let _lock = lock();
+    set_default_formatter(Arc::new(Fixed("default")));
+    assert_eq!(render(LogConfig::new()), "default\n");
+    let config = LogConfig::new().formatter(Arc::new(Fixed("config")));
+    assert_eq!(render(config), "config\n");
+    let json = render(LogConfig::new().format(Format::Json));
+    assert!(json.starts_with('{'), "{json}");
+    clear_default_formatter();
+    assert!(render(LogConfig::new()).contains(" request: [200] "));
+}
+
+#[test]
+fn test_default_log_query() {
+    let _lock = lock();
+    set_default_log_query(false);
+    assert!(render(LogConfig::new()).contains(" GET /search HTTP/1.1 "));
+    let line = render(LogConfig::new().log_query(true));
+    assert!(line.contains(" GET /search?q=rust "), "{line}");
+    set_default_log_query(true);
+    assert!(render(LogConfig::new()).contains(" GET /search?q=rust "));
+}
+
+#[test]
+fn test_default_field_set() {
+    let _lock = lock();
+    set_default_field_set(FieldSet::MINIMAL);
+    let line = render(LogConfig::new());
+    assert!(!line.contains("curl/8.0"), "{line}");
+    let line = render(LogConfig::new().field_set(FieldSet::ALL));
+    assert!(line.contains("curl/8.0"), "{line}");
+    set_default_field_set(FieldSet::ALL);
+    assert!(render(LogConfig::new()).contains("curl/8.0"));
+}
+
+#[test]
+fn test_default_timestamp_style() {
+    let _lock = lock();
+    set_default_timestamp_style(TimestampStyle::Clf);
+    let line = render(LogConfig::new());
+    assert!(
+        line.starts_with("14/Nov/2023:22:13:20 +0000 request: "),
+        "{line}"
+    );
+    let line = render(LogConfig::new().timestamp_style(TimestampStyle::EpochMillis));
+    assert!(line.starts_with("1700000000000 request: "), "{line}");
+    set_default_timestamp_style(TimestampStyle::Rfc3339Utc);
+    let line = render(LogConfig::new());
+    assert!(
+        line.starts_with("2023-11-14T22:13:20.000Z request: "),
+        "{line}"
+    );
+}
+
+#[test]
+fn test_default_sink() {
+    let _lock = lock();
+    let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+    set_default_sink(out.clone());
+    let req = http::Request::get("/default-sink").body(()).unwrap();
+    let mut log = LogRequest::<&str>::from_request(&req);
+    log.set_remote("192.0.2.1:4000".parse().unwrap())
+        .set_response(&http::Response::new(()));
+    drop(log);
+
+    // A config's own sink wins.
+    let other = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let config = Arc::new(LogConfig::new().sink(other.clone()));
+    drop(LogRequest::<&str>::from_request_with_config(&req, config));
+    clear_default_sink();
+
+    let lines = text(&out);
+    assert_eq!(lines.lines().count(), 1, "{lines}");
+    assert!(lines.contains(" GET /default-sink "), "{lines}");
+    assert_eq!(text(&other).lines().count(), 1);
+}
+
+#[test]
+fn test_failing_sink() {
+    let _lock = lock();
+    let config = Arc::new(LogConfig::new().sink(Arc::new(Failing)));
+    let req = http::Request::get("/").body(()).unwrap();
+    let before = failed_writes();
+    set_write_error_handler(record);
+    for _ in 0..3 {
+        let mut log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+        log.set_response(&http::Response::new(()));
+    }
+    assert_eq!(failed_writes() - before, 3);
+    // The failures came too close together for more than one call.
+    assert_eq!(calls(), ["sink is gone"]);
+
+    // Explicit writes return their errors instead.
+    let log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+    assert!(log.write(Failing).is_err());
+    assert_eq!(failed_writes() - before, 3);
+
+    clear_write_error_handler();
+    drop(LogRequest::<&str>::from_request_with_config(&req, config));
+    assert_eq!(failed_writes() - before, 4);
+    assert_eq!(calls().len(), 1);
+}
+
+#[test]
+fn test_failing_formatter() {
+    struct Broken;
+
+    impl LogFormatter for Broken {
+        fn format(&self, _: &LogEntry, _: &mut dyn Write) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+
+    let _lock = lock();
+    let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let config = Arc::new(
+        LogConfig::new()
+            .formatter(Arc::new(Broken))
+            .sink(out.clone()),
+    );
+    let req = http::Request::get("/").body(()).unwrap();
+    let before = failed_writes();
+    drop(LogRequest::<&str>::from_request_with_config(&req, config));
+    assert_eq!(failed_writes() - before, 1);
+    assert!(text(&out).is_empty());
+}
+
+#[test]
+fn test_failing_multi_sink() {
+    let _lock = lock();
+    let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let sink = MultiSink::new().sink(Arc::new(Failing)).sink(out.clone());
+    let config = Arc::new(LogConfig::new().sink(Arc::new(sink)));
+    let req = http::Request::get("/multi").body(()).unwrap();
+    let before = failed_writes();
+    let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+    log.set_response(&http::Response::new(()));
+    drop(log);
+    assert_eq!(failed_writes() - before, 1);
+    assert!(text(&out).contains(" GET /multi "));
+}
+
+#[test]
+fn test_failing_background_sink() {
+    let _lock = lock();
+    let logger = Arc::new(BackgroundLogger::new(Arc::new(Failing), 4, Overflow::Block));
+    let req = http::Request::get("/queued").body(()).unwrap();
+    let before = failed_writes();
+    for _ in 0..2 {
+        drop(logger.request::<&str, _>(&req));
+    }
+    // Queued lines are written on the logger's thread, so they can't return their errors either.
+    logger.write_line("connection: closed\n").unwrap();
+    logger.flush();
+    assert_eq!(failed_writes() - before, 3);
+}
+
+#[test]
+fn test_failing_extra_sink() {
+    let _lock = lock();
+    let out = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let config = Arc::new(LogConfig::new().sink(out.clone()));
+    let req = http::Request::get("/extra").body(()).unwrap();
+    let (before, stats) = (failed_writes(), Stats::get());
+    let mut log = LogRequest::<&str>::from_request_with_config(&req, config.clone());
+    log.also_write_to(Arc::new(Failing));
+    drop(log);
+    assert_eq!(failed_writes() - before, 1);
+    // The entry was written, and one write of it failed.
+    assert_eq!(Stats::get().written - stats.written, 1);
+    assert_eq!(Stats::get().errors - stats.errors, 1);
+
+    // The write itself succeeded, so an explicit write returns Ok, and the extra sink's failure
+    // is reported.
+    let mut log = LogRequest::<&str>::from_request_with_config(&req, config);
+    log.also_write_to(Arc::new(Failing));
+    log.write(io::sink()).unwrap();
+    assert_eq!(failed_writes() - before, 2);
+    assert_eq!(text(&out).lines().count(), 1);
+}
@@ -0,0 +1,69 @@
+//! The best-effort path after [shutdown]. It can't be undone, so it has a test binary of its own,
+//! and it writes straight to stderr, so the requests are dropped in a child process running this
+//! same test, whose stderr is checked.
+
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use hyper_req_log::{is_shut_down, shutdown, LogRequest};
+
+/// Set in the environment of the child process.
+const CHILD: &str = "HYPER_REQ_LOG_LATE_DROPS";
+
+#[test]
+fn test_late_drops() {
+    if std::env::var_os(CHILD).is_some() {
+        late_drops();
+        return;
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "test_late_drops", "--test-threads=1"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+    let lines = stderr
+        .lines()
+        .filter(|line| line.contains(" request: "))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{stderr}");
+    for line in &lines {
+        assert!(line.contains(" request: [???] 192.0.2.1:4000 "), "{line}");
+        // The minimal line leaves out the user agent.
+        assert!(!line.contains("test"), "{line}");
+    }
+    // The runtime drops its tasks in no particular order.
+    for i in 0..3 {
+        let path = format!(" GET /pending/{i} HTTP/1.1 ");
+        assert!(lines.iter().any(|line| line.contains(&path)), "{stderr}");
+    }
+}
+
+fn late_drops() {
+    let out = Arc::new(Mutex::new(vec![]));
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    for i in 0..3 {
+        let req = http::Request::get(format!("/pending/{i}"))
+            .header("user-agent", "test")
+            .body(())
+            .unwrap();
+        let mut log = LogRequest::<&str>::from_request(&req);
+        log.set_remote("192.0.2.1:4000".parse().unwrap())
+            .also_write_to(out.clone());
+        runtime.spawn(async move {
+            std::future::pending::<()>().await;
+            drop(log);
+        });
+    }
+    // Let the tasks start, so they hold their requests when the runtime is dropped.
+    runtime.block_on(tokio::task::yield_now());
+
+    assert!(!is_shut_down());
+    shutdown();
+    assert!(is_shut_down());
+    drop(runtime);
+    assert!(out.lock().unwrap().is_empty());
+}